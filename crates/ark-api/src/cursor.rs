@@ -0,0 +1,43 @@
+//! Opaque cursors of the paginated endpoints.
+//!
+//! A cursor only encodes the offset of the next page for now, clients
+//! must not rely on its content so it can evolve to keyset pagination.
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+
+use crate::error::{ApiError, ApiResult};
+
+const PREFIX: &str = "offset:";
+
+pub fn encode(offset: u64) -> String {
+    URL_SAFE_NO_PAD.encode(format!("{PREFIX}{offset}"))
+}
+
+pub fn decode(cursor: &str) -> ApiResult<u64> {
+    let invalid = || ApiError::BadRequest(format!("invalid cursor {cursor}"));
+
+    let bytes = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+    let decoded = String::from_utf8(bytes).map_err(|_| invalid())?;
+
+    decoded
+        .strip_prefix(PREFIX)
+        .and_then(|offset| offset.parse::<u64>().ok())
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_roundtrip() {
+        assert_eq!(decode(&encode(0)).unwrap(), 0);
+        assert_eq!(decode(&encode(150)).unwrap(), 150);
+    }
+
+    #[test]
+    fn test_invalid_cursor() {
+        assert!(decode("not a cursor").is_err());
+        assert!(decode(&URL_SAFE_NO_PAD.encode("other:12")).is_err());
+    }
+}
@@ -0,0 +1,55 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::json;
+use tracing::error;
+
+pub type ApiResult<T> = Result<T, ApiError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Internal(e) => {
+                // Storage errors are logged, but not exposed to the clients.
+                error!("Internal error: {}", e);
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Internal error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        (status, Json(json!({ "error": self.to_string() }))).into_response()
+    }
+}
+
+impl From<pontos::storage::types::StorageError> for ApiError {
+    fn from(e: pontos::storage::types::StorageError) -> Self {
+        match e {
+            pontos::storage::types::StorageError::NotFound(s) => ApiError::NotFound(s),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<sana::storage::types::StorageError> for ApiError {
+    fn from(e: sana::storage::types::StorageError) -> Self {
+        match e {
+            sana::storage::types::StorageError::NotFound(s) => ApiError::NotFound(s),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
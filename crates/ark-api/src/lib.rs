@@ -0,0 +1,47 @@
+//! HTTP/JSON API over the data indexed by Pontos or Sana.
+//!
+//! The routes only depend on the `IndexedData` trait, implemented
+//! on top of the `QueryStorage` of each indexer.
+pub mod cursor;
+pub mod error;
+pub mod models;
+pub mod routes;
+pub mod source;
+
+use utoipa::OpenApi;
+
+pub use routes::router;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "ArkProject indexer API"),
+    paths(
+        routes::status,
+        routes::list_collections,
+        routes::get_collection,
+        routes::list_collection_tokens,
+        routes::get_token,
+        routes::list_token_transfers,
+        routes::list_owner_tokens,
+        routes::list_sales,
+    ),
+    components(schemas(
+        models::Collection,
+        models::Token,
+        models::Transfer,
+        models::Sale,
+        models::IndexerStatus,
+        models::CollectionPage,
+        models::TokenPage,
+        models::TransferPage,
+        models::SalePage,
+    )),
+    tags(
+        (name = "status", description = "Indexer status"),
+        (name = "collections", description = "Indexed collections"),
+        (name = "tokens", description = "Tokens of the collections"),
+        (name = "owners", description = "Tokens by owner"),
+        (name = "activity", description = "Transfers and sales"),
+    )
+)]
+pub struct ApiDoc;
@@ -0,0 +1,77 @@
+use anyhow::Result;
+use ark_api::source::{IndexedData, PontosData, SanaData};
+use ark_api::ApiDoc;
+use clap::{Parser, ValueEnum};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tracing::info;
+use tracing_subscriber::EnvFilter;
+use utoipa::OpenApi;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Indexer {
+    Pontos,
+    Sana,
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Indexer that populated the database.
+    #[arg(long, value_enum, env = "ARK_API_INDEXER", default_value = "pontos")]
+    indexer: Indexer,
+
+    #[arg(long, env = "DATABASE_URL")]
+    database_url: Option<String>,
+
+    /// Chain of the data served.
+    #[arg(long, env = "ARK_API_CHAIN_ID", default_value = "0x534e5f4d41494e")]
+    chain_id: String,
+
+    #[arg(long, env = "ARK_API_BIND", default_value = "0.0.0.0:8080")]
+    bind: SocketAddr,
+
+    /// Prints the OpenAPI document and exits.
+    #[arg(long)]
+    print_openapi: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .init();
+
+    let args = Args::parse();
+
+    if args.print_openapi {
+        println!("{}", ApiDoc::openapi().to_pretty_json()?);
+        return Ok(());
+    }
+
+    let database_url = args
+        .database_url
+        .ok_or_else(|| anyhow::anyhow!("--database-url (or DATABASE_URL) is required"))?;
+
+    let data: Arc<dyn IndexedData> = match args.indexer {
+        Indexer::Pontos => {
+            let storage = pontos::storage::PostgresStorage::new(&database_url)
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            Arc::new(PontosData::new(Arc::new(storage), &args.chain_id))
+        }
+        Indexer::Sana => {
+            let storage = sana::storage::PostgresStorage::new(&database_url)
+                .await
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            Arc::new(SanaData::new(Arc::new(storage), &args.chain_id))
+        }
+    };
+
+    let listener = tokio::net::TcpListener::bind(args.bind).await?;
+    info!("Serving {:?} data on {}", args.indexer, args.bind);
+
+    axum::serve(listener, ark_api::router(data)).await?;
+
+    Ok(())
+}
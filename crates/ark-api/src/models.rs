@@ -0,0 +1,109 @@
+//! Response types of the API.
+//!
+//! Those types are decoupled from the storage types of Pontos and Sana,
+//! so the API output doesn't change with the indexer in use.
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[aliases(
+    CollectionPage = Page<Collection>,
+    TokenPage = Page<Token>,
+    TransferPage = Page<Transfer>,
+    SalePage = Page<Sale>
+)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Cursor of the next page, absent on the last page.
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Collection {
+    pub address: String,
+    pub chain_id: String,
+    pub contract_type: String,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub image: Option<String>,
+    /// Heuristic spam score from 0 to 100, if scored by the indexer.
+    pub spam_score: Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Token {
+    pub collection_address: String,
+    /// Decimal representation of the token id.
+    pub token_id: String,
+    pub token_id_hex: String,
+    pub owner: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Transfer {
+    pub event_id: String,
+    /// `MINT`, `BURN` or `TRANSFER`.
+    pub event_type: String,
+    pub from_address: String,
+    pub to_address: String,
+    pub transaction_hash: String,
+    pub block_timestamp: u64,
+    pub block_number: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Sale {
+    pub event_id: String,
+    pub collection_address: String,
+    pub token_id: String,
+    pub token_id_hex: String,
+    pub from_address: String,
+    pub to_address: String,
+    pub marketplace_address: Option<String>,
+    pub marketplace_name: Option<String>,
+    pub transaction_hash: String,
+    pub quantity: u64,
+    pub currency_address: Option<String>,
+    /// Raw price, hexadecimal representation of the u256 amount.
+    pub price: String,
+    /// Exact decimal representation of the price in the unit of the currency.
+    pub normalized_price: Option<String>,
+    pub price_usd: Option<f64>,
+    pub block_timestamp: u64,
+    /// Wash trade patterns detected on the sale (`SELF_TRADE`, `ROUND_TRIP`,
+    /// `SELLER_FUNDED`, `PRICE_OUTLIER`), empty if not analyzed.
+    pub wash_trade_flags: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct IndexerStatus {
+    pub chain_id: String,
+    pub indexer_identifier: Option<String>,
+    pub indexer_version: Option<String>,
+    /// Highest block known by the indexer, and its status.
+    pub last_block_number: Option<u64>,
+    pub last_block_status: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageParams {
+    /// Cursor returned by the previous page.
+    pub cursor: Option<String>,
+    /// Number of items, 50 by default and 500 at most.
+    pub limit: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SaleParams {
+    /// Address of the collection.
+    pub collection: Option<String>,
+    /// Token id, hexadecimal (`0x` prefixed) or decimal.
+    pub token_id: Option<String>,
+    /// Inclusive bounds on the block timestamp of the sales.
+    pub from_timestamp: Option<u64>,
+    pub to_timestamp: Option<u64>,
+    pub cursor: Option<String>,
+    pub limit: Option<u64>,
+}
@@ -0,0 +1,240 @@
+use ark_starknet::format::to_hex_str;
+use axum::extract::{Path, Query, State};
+use axum::routing::get;
+use axum::{Json, Router};
+use num_bigint::BigUint;
+use std::sync::Arc;
+use utoipa::OpenApi;
+
+use crate::cursor;
+use crate::error::{ApiError, ApiResult};
+use crate::models::*;
+use crate::source::{IndexedData, PageRequest, SaleQuery, Slice};
+use crate::ApiDoc;
+
+const DEFAULT_LIMIT: u64 = 50;
+const MAX_LIMIT: u64 = 500;
+
+pub type AppState = Arc<dyn IndexedData>;
+
+pub fn router(data: AppState) -> Router {
+    Router::new()
+        .route("/v1/status", get(status))
+        .route("/v1/collections", get(list_collections))
+        .route("/v1/collections/:address", get(get_collection))
+        .route(
+            "/v1/collections/:address/tokens",
+            get(list_collection_tokens),
+        )
+        .route("/v1/collections/:address/tokens/:token_id", get(get_token))
+        .route(
+            "/v1/collections/:address/tokens/:token_id/transfers",
+            get(list_token_transfers),
+        )
+        .route("/v1/owners/:address/tokens", get(list_owner_tokens))
+        .route("/v1/sales", get(list_sales))
+        .route("/openapi.json", get(openapi))
+        .with_state(data)
+}
+
+/// Addresses and token ids are accepted in hexadecimal or decimal,
+/// and normalized to the padded hexadecimal stored by the indexers.
+fn normalize(value: &str) -> ApiResult<String> {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => BigUint::parse_bytes(hex.as_bytes(), 16),
+        None => BigUint::parse_bytes(value.as_bytes(), 10),
+    };
+
+    match parsed {
+        Some(v) if v.bits() <= 256 => Ok(to_hex_str(&v)),
+        _ => Err(ApiError::BadRequest(format!("invalid value {value}"))),
+    }
+}
+
+fn page_request(cursor: &Option<String>, limit: Option<u64>) -> ApiResult<PageRequest> {
+    let offset = match cursor {
+        Some(c) => cursor::decode(c)?,
+        None => 0,
+    };
+
+    Ok(PageRequest {
+        offset,
+        limit: limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+    })
+}
+
+fn page<T>(slice: Slice<T>) -> Page<T> {
+    Page {
+        items: slice.items,
+        next_cursor: slice.next_offset.map(cursor::encode),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/status",
+    responses((status = 200, description = "Status of the indexer", body = IndexerStatus)),
+    tag = "status"
+)]
+pub async fn status(State(data): State<AppState>) -> ApiResult<Json<IndexerStatus>> {
+    Ok(Json(data.status().await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/collections",
+    params(PageParams),
+    responses((status = 200, description = "Collections ordered by address", body = CollectionPage)),
+    tag = "collections"
+)]
+pub async fn list_collections(
+    State(data): State<AppState>,
+    Query(params): Query<PageParams>,
+) -> ApiResult<Json<Page<Collection>>> {
+    let request = page_request(&params.cursor, params.limit)?;
+    Ok(Json(page(data.collections(request).await?)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/collections/{address}",
+    params(("address" = String, Path, description = "Address of the collection")),
+    responses(
+        (status = 200, description = "Collection", body = Collection),
+        (status = 404, description = "Unknown collection")
+    ),
+    tag = "collections"
+)]
+pub async fn get_collection(
+    State(data): State<AppState>,
+    Path(address): Path<String>,
+) -> ApiResult<Json<Collection>> {
+    Ok(Json(data.collection(&normalize(&address)?).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/collections/{address}/tokens",
+    params(("address" = String, Path, description = "Address of the collection"), PageParams),
+    responses((status = 200, description = "Tokens ordered by id", body = TokenPage)),
+    tag = "tokens"
+)]
+pub async fn list_collection_tokens(
+    State(data): State<AppState>,
+    Path(address): Path<String>,
+    Query(params): Query<PageParams>,
+) -> ApiResult<Json<Page<Token>>> {
+    let request = page_request(&params.cursor, params.limit)?;
+    let tokens = data
+        .collection_tokens(&normalize(&address)?, request)
+        .await?;
+
+    Ok(Json(page(tokens)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/collections/{address}/tokens/{token_id}",
+    params(
+        ("address" = String, Path, description = "Address of the collection"),
+        ("token_id" = String, Path, description = "Token id, hexadecimal or decimal")
+    ),
+    responses(
+        (status = 200, description = "Token", body = Token),
+        (status = 404, description = "Unknown token")
+    ),
+    tag = "tokens"
+)]
+pub async fn get_token(
+    State(data): State<AppState>,
+    Path((address, token_id)): Path<(String, String)>,
+) -> ApiResult<Json<Token>> {
+    let token = data
+        .token(&normalize(&address)?, &normalize(&token_id)?)
+        .await?;
+
+    Ok(Json(token))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/collections/{address}/tokens/{token_id}/transfers",
+    params(
+        ("address" = String, Path, description = "Address of the collection"),
+        ("token_id" = String, Path, description = "Token id, hexadecimal or decimal"),
+        PageParams
+    ),
+    responses((status = 200, description = "Transfers, most recent first", body = TransferPage)),
+    tag = "activity"
+)]
+pub async fn list_token_transfers(
+    State(data): State<AppState>,
+    Path((address, token_id)): Path<(String, String)>,
+    Query(params): Query<PageParams>,
+) -> ApiResult<Json<Page<Transfer>>> {
+    let request = page_request(&params.cursor, params.limit)?;
+    let transfers = data
+        .token_transfers(&normalize(&address)?, &normalize(&token_id)?, request)
+        .await?;
+
+    Ok(Json(page(transfers)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/owners/{address}/tokens",
+    params(("address" = String, Path, description = "Address of the owner"), PageParams),
+    responses((status = 200, description = "Tokens of the owner", body = TokenPage)),
+    tag = "owners"
+)]
+pub async fn list_owner_tokens(
+    State(data): State<AppState>,
+    Path(address): Path<String>,
+    Query(params): Query<PageParams>,
+) -> ApiResult<Json<Page<Token>>> {
+    let request = page_request(&params.cursor, params.limit)?;
+    let tokens = data.owner_tokens(&normalize(&address)?, request).await?;
+
+    Ok(Json(page(tokens)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/sales",
+    params(SaleParams),
+    responses((status = 200, description = "Sales, most recent first", body = SalePage)),
+    tag = "activity"
+)]
+pub async fn list_sales(
+    State(data): State<AppState>,
+    Query(params): Query<SaleParams>,
+) -> ApiResult<Json<Page<Sale>>> {
+    let request = page_request(&params.cursor, params.limit)?;
+    let query = SaleQuery {
+        collection_address: params.collection.as_deref().map(normalize).transpose()?,
+        token_id_hex: params.token_id.as_deref().map(normalize).transpose()?,
+        from_timestamp: params.from_timestamp,
+        to_timestamp: params.to_timestamp,
+    };
+
+    Ok(Json(page(data.sales(&query, request).await?)))
+}
+
+pub async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let one = format!("0x{:064x}", 1);
+        assert_eq!(normalize("0x1").unwrap(), one);
+        assert_eq!(normalize("1").unwrap(), one);
+        assert_eq!(normalize(&one).unwrap(), one);
+        assert!(normalize("0xzz").is_err());
+        assert!(normalize(&format!("0x1{}", "0".repeat(64))).is_err());
+    }
+}
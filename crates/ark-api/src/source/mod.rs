@@ -0,0 +1,72 @@
+//! Sources of the indexed data served by the API.
+//!
+//! Each indexer has its own storage types, the sources convert
+//! them into the API models.
+use async_trait::async_trait;
+
+use crate::error::ApiResult;
+use crate::models::{Collection, IndexerStatus, Sale, Token, Transfer};
+
+mod pontos_data;
+mod sana_data;
+
+pub use pontos_data::PontosData;
+pub use sana_data::SanaData;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageRequest {
+    pub offset: u64,
+    pub limit: u64,
+}
+
+/// Items of a page, with the offset of the next one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Slice<T> {
+    pub items: Vec<T>,
+    pub next_offset: Option<u64>,
+}
+
+impl<T> Slice<T> {
+    pub fn convert<U, F: FnMut(T) -> U>(items: Vec<T>, next_offset: Option<u64>, f: F) -> Slice<U> {
+        Slice {
+            items: items.into_iter().map(f).collect(),
+            next_offset,
+        }
+    }
+}
+
+/// Sale criteria, addresses and token id are normalized
+/// to their padded hexadecimal representation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SaleQuery {
+    pub collection_address: Option<String>,
+    pub token_id_hex: Option<String>,
+    pub from_timestamp: Option<u64>,
+    pub to_timestamp: Option<u64>,
+}
+
+#[async_trait]
+pub trait IndexedData: Send + Sync {
+    fn chain_id(&self) -> &str;
+
+    async fn collections(&self, page: PageRequest) -> ApiResult<Slice<Collection>>;
+
+    async fn collection(&self, address: &str) -> ApiResult<Collection>;
+
+    async fn collection_tokens(&self, address: &str, page: PageRequest) -> ApiResult<Slice<Token>>;
+
+    async fn token(&self, address: &str, token_id_hex: &str) -> ApiResult<Token>;
+
+    async fn token_transfers(
+        &self,
+        address: &str,
+        token_id_hex: &str,
+        page: PageRequest,
+    ) -> ApiResult<Slice<Transfer>>;
+
+    async fn owner_tokens(&self, owner: &str, page: PageRequest) -> ApiResult<Slice<Token>>;
+
+    async fn sales(&self, query: &SaleQuery, page: PageRequest) -> ApiResult<Slice<Sale>>;
+
+    async fn status(&self) -> ApiResult<IndexerStatus>;
+}
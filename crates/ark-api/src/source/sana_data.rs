@@ -0,0 +1,192 @@
+use async_trait::async_trait;
+use num_bigint::BigUint;
+use sana::storage::types::{
+    ContractInfo, Pagination, SaleFilter, TokenInfo, TokenSaleEvent, TokenTransferEvent,
+};
+use sana::storage::QueryStorage;
+use std::sync::Arc;
+
+use super::{IndexedData, PageRequest, SaleQuery, Slice};
+use crate::error::{ApiError, ApiResult};
+use crate::models::{Collection, IndexerStatus, Sale, Token, Transfer};
+
+/// Data indexed by Sana.
+///
+/// Sana identifies the tokens by their decimal id,
+/// the hexadecimal ids of the API are converted.
+pub struct SanaData<S> {
+    storage: Arc<S>,
+    chain_id: String,
+}
+
+impl<S> SanaData<S> {
+    pub fn new(storage: Arc<S>, chain_id: &str) -> Self {
+        Self {
+            storage,
+            chain_id: chain_id.to_string(),
+        }
+    }
+}
+
+fn pagination(page: PageRequest) -> Pagination {
+    Pagination::new(page.offset, page.limit)
+}
+
+fn to_decimal(token_id_hex: &str) -> ApiResult<String> {
+    BigUint::parse_bytes(token_id_hex.trim_start_matches("0x").as_bytes(), 16)
+        .map(|id| id.to_str_radix(10))
+        .ok_or_else(|| ApiError::BadRequest(format!("invalid token id {token_id_hex}")))
+}
+
+fn collection(info: ContractInfo) -> Collection {
+    Collection {
+        address: info.contract_address,
+        chain_id: info.chain_id,
+        contract_type: info.contract_type,
+        name: info.name,
+        symbol: info.symbol,
+        image: info.image,
+        spam_score: None,
+    }
+}
+
+fn token(info: TokenInfo) -> Token {
+    Token {
+        collection_address: info.contract_address,
+        token_id: info.token_id,
+        token_id_hex: info.token_id_hex,
+        owner: info.owner,
+    }
+}
+
+fn transfer(event: TokenTransferEvent) -> Transfer {
+    Transfer {
+        event_id: event.token_event_id,
+        event_type: event.event_type.map(|t| t.to_string()).unwrap_or_default(),
+        from_address: event.from_address,
+        to_address: event.to_address,
+        transaction_hash: event.transaction_hash,
+        block_timestamp: event.block_timestamp,
+        block_number: event.block_number,
+    }
+}
+
+/// The marketplace of a sale is not stored by Sana, and its sales are
+/// not analyzed for wash trades.
+fn sale(event: TokenSaleEvent) -> Sale {
+    let non_empty = |s: String| if s.is_empty() { None } else { Some(s) };
+
+    Sale {
+        event_id: event.token_event_id,
+        collection_address: event.nft_contract_address,
+        token_id: event.token_id,
+        token_id_hex: event.token_id_hex,
+        from_address: event.from_address,
+        to_address: event.to_address,
+        marketplace_address: non_empty(event.marketplace_contract_address),
+        marketplace_name: non_empty(event.marketplace_name),
+        transaction_hash: event.transaction_hash,
+        quantity: event.quantity,
+        currency_address: event.currency_address,
+        price: event.price,
+        normalized_price: event.normalized_price,
+        price_usd: event.price_usd,
+        block_timestamp: event.block_timestamp,
+        wash_trade_flags: vec![],
+    }
+}
+
+#[async_trait]
+impl<S> IndexedData for SanaData<S>
+where
+    S: QueryStorage + Send + Sync,
+{
+    fn chain_id(&self) -> &str {
+        &self.chain_id
+    }
+
+    async fn collections(&self, page: PageRequest) -> ApiResult<Slice<Collection>> {
+        let p = self
+            .storage
+            .contracts(&self.chain_id, pagination(page))
+            .await?;
+        Ok(Slice::convert(p.items, p.next_offset, collection))
+    }
+
+    async fn collection(&self, address: &str) -> ApiResult<Collection> {
+        let info = self
+            .storage
+            .get_contract_info(address, &self.chain_id)
+            .await?;
+        Ok(collection(info))
+    }
+
+    async fn collection_tokens(&self, address: &str, page: PageRequest) -> ApiResult<Slice<Token>> {
+        let p = self
+            .storage
+            .collection_tokens(address, &self.chain_id, pagination(page))
+            .await?;
+        Ok(Slice::convert(p.items, p.next_offset, token))
+    }
+
+    async fn token(&self, address: &str, token_id_hex: &str) -> ApiResult<Token> {
+        let info = self
+            .storage
+            .get_token(address, &self.chain_id, &to_decimal(token_id_hex)?)
+            .await?;
+        Ok(token(info))
+    }
+
+    async fn token_transfers(
+        &self,
+        address: &str,
+        token_id_hex: &str,
+        page: PageRequest,
+    ) -> ApiResult<Slice<Transfer>> {
+        let p = self
+            .storage
+            .token_history(
+                address,
+                &self.chain_id,
+                &to_decimal(token_id_hex)?,
+                pagination(page),
+            )
+            .await?;
+        Ok(Slice::convert(p.items, p.next_offset, transfer))
+    }
+
+    async fn owner_tokens(&self, owner: &str, page: PageRequest) -> ApiResult<Slice<Token>> {
+        let p = self
+            .storage
+            .tokens_by_owner(owner, &self.chain_id, pagination(page))
+            .await?;
+        Ok(Slice::convert(p.items, p.next_offset, token))
+    }
+
+    async fn sales(&self, query: &SaleQuery, page: PageRequest) -> ApiResult<Slice<Sale>> {
+        let filter = SaleFilter {
+            contract_address: query.collection_address.clone(),
+            token_id_hex: query.token_id_hex.clone(),
+            from_timestamp: query.from_timestamp,
+            to_timestamp: query.to_timestamp,
+        };
+
+        let p = self
+            .storage
+            .sales(&self.chain_id, &filter, pagination(page))
+            .await?;
+        Ok(Slice::convert(p.items, p.next_offset, sale))
+    }
+
+    async fn status(&self) -> ApiResult<IndexerStatus> {
+        let last_block = self.storage.last_block_info().await?;
+
+        Ok(IndexerStatus {
+            chain_id: self.chain_id.clone(),
+            indexer_identifier: last_block.as_ref().map(|b| b.indexer_identifier.clone()),
+            indexer_version: last_block.as_ref().map(|b| b.indexer_version.clone()),
+            last_block_number: last_block.as_ref().map(|b| b.block_number),
+            last_block_status: last_block.map(|b| b.block_status.to_string()),
+        })
+    }
+}
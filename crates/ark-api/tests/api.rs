@@ -0,0 +1,90 @@
+use ark_api::source::PontosData;
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use http_body_util::BodyExt;
+use pontos::storage::types::TokenInfo;
+use pontos::storage::{MemoryStorage, Storage};
+use serde_json::Value;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const CHAIN_ID: &str = "SN_TEST";
+
+fn hex(value: u64) -> String {
+    format!("0x{:064x}", value)
+}
+
+async fn app() -> axum::Router {
+    let storage = MemoryStorage::new();
+
+    for token_id in 1..=3u64 {
+        let token = TokenInfo {
+            contract_address: hex(0xc0),
+            token_id: token_id.to_string(),
+            chain_id: CHAIN_ID.to_string(),
+            token_id_hex: hex(token_id),
+            owner: hex(0xa),
+        };
+        storage.register_token(&token, 10).await.unwrap();
+    }
+
+    let data = PontosData::new(Arc::new(storage), CHAIN_ID);
+    ark_api::router(Arc::new(data))
+}
+
+async fn get(app: axum::Router, uri: &str) -> (StatusCode, Value) {
+    let response = app
+        .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn test_owner_tokens_cursor_pagination() {
+    let app = app().await;
+
+    let (status, page) = get(app.clone(), "/v1/owners/0xa/tokens?limit=2").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["items"].as_array().unwrap().len(), 2);
+    assert_eq!(page["items"][0]["token_id"], "1");
+
+    let cursor = page["next_cursor"].as_str().unwrap();
+    let (_, page) = get(
+        app.clone(),
+        &format!("/v1/owners/0xa/tokens?limit=2&cursor={cursor}"),
+    )
+    .await;
+    assert_eq!(page["items"].as_array().unwrap().len(), 1);
+    assert_eq!(page["items"][0]["token_id"], "3");
+    assert!(page["next_cursor"].is_null());
+
+    let (status, _) = get(app, "/v1/owners/0xa/tokens?cursor=invalid").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_get_token() {
+    let app = app().await;
+
+    // Decimal and hexadecimal ids are accepted.
+    let (status, token) = get(app.clone(), "/v1/collections/0xc0/tokens/2").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(token["token_id_hex"], hex(2));
+
+    let (status, _) = get(app, "/v1/collections/0xc0/tokens/0x10").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_openapi() {
+    let (status, doc) = get(app().await, "/openapi.json").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert!(doc["paths"]["/v1/owners/{address}/tokens"].is_object());
+    assert!(doc["components"]["schemas"]["TokenPage"].is_object());
+}
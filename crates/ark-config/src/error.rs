@@ -0,0 +1,29 @@
+use std::path::PathBuf;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Can't read config file {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Unsupported config file extension {0}, expected toml, yaml or yml")]
+    UnsupportedFormat(String),
+
+    #[error("Malformed config file: {0}")]
+    Malformed(String),
+
+    /// A value is missing, of the wrong type, or out of its bounds.
+    #[error("Invalid `{key}`: {message}")]
+    Invalid { key: String, message: String },
+}
+
+impl ConfigError {
+    pub fn invalid(key: impl Into<String>, message: impl Into<String>) -> Self {
+        ConfigError::Invalid {
+            key: key.into(),
+            message: message.into(),
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! Configuration of the ArkProject indexers.
+//!
+//! A single file (TOML or YAML) holds the tunables of Pontos, Sana,
+//! Diri and the metadata refresher, each in its own section.
+//! Every value has a default, and can be overridden by an environment
+//! variable named after its key: `ARK_PONTOS__RETRY_DELAY_SECS`
+//! for `pontos.retry_delay_secs`.
+//!
+//! The `chains` list configures the chains indexed by the same process,
+//! each with its own RPC urls and database schema.
+mod error;
+mod sources;
+mod validation;
+
+pub use ark_metadata::config::MetadataConfig;
+pub use diri::DiriConfig;
+pub use error::ConfigError;
+pub use pontos::PontosConfig;
+pub use sana::SanaConfig;
+pub use sources::ENV_PREFIX;
+
+use ark_starknet::client::DEFAULT_EVENTS_CHUNK_SIZE;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+/// 'SN_MAIN' cairo short string.
+const DEFAULT_CHAIN_ID: &str = "0x534e5f4d41494e";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArkConfig {
+    pub starknet: StarknetConfig,
+    pub database_url: Option<String>,
+    pub pontos: PontosConfig,
+    pub sana: SanaConfig,
+    pub diri: DiriConfig,
+    pub metadata: MetadataConfig,
+    pub chains: Vec<ChainConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StarknetConfig {
+    /// RPC endpoints, by order of preference.
+    pub rpc_urls: Vec<String>,
+    pub chain_id: String,
+    /// Number of events requested by `starknet_getEvents` call.
+    pub events_chunk_size: u64,
+}
+
+impl Default for StarknetConfig {
+    fn default() -> Self {
+        StarknetConfig {
+            rpc_urls: vec![],
+            chain_id: DEFAULT_CHAIN_ID.to_string(),
+            events_chunk_size: DEFAULT_EVENTS_CHUNK_SIZE,
+        }
+    }
+}
+
+/// Chain indexed alongside the others, overriding the `starknet`
+/// and `pontos` sections.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChainConfig {
+    pub chain_id: String,
+    /// RPC endpoints, by order of preference.
+    pub rpc_urls: Vec<String>,
+    /// Postgres schema holding the data of the chain,
+    /// defaults to the lowercased chain id.
+    pub database_schema: Option<String>,
+    /// Pontos configuration of the chain, defaults to the `pontos` section.
+    pub pontos: Option<PontosConfig>,
+}
+
+impl ChainConfig {
+    pub fn database_schema(&self) -> String {
+        self.database_schema
+            .clone()
+            .unwrap_or_else(|| self.chain_id.to_lowercase())
+    }
+}
+
+impl ArkConfig {
+    /// Loads the configuration from the file, if any, and the
+    /// environment variables of the process.
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let file = match path {
+            Some(path) => Some(sources::read_file(path)?),
+            None => None,
+        };
+
+        Self::from_sources(file, std::env::vars())
+    }
+
+    /// Parses the content of a configuration file, `format` being
+    /// its extension. The environment variables are not applied.
+    pub fn parse(content: &str, format: &str) -> Result<Self, ConfigError> {
+        Self::from_sources(Some(sources::parse(content, format)?), [])
+    }
+
+    fn from_sources<I>(file: Option<Value>, env: I) -> Result<Self, ConfigError>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut value = serde_json::to_value(ArkConfig::default())
+            .map_err(|e| ConfigError::Malformed(e.to_string()))?;
+
+        if let Some(file) = file {
+            sources::merge(&mut value, file);
+        }
+
+        sources::apply_env(&mut value, env);
+
+        let config: ArkConfig = serde_path_to_error::deserialize(value).map_err(|e| {
+            let key = e.path().to_string();
+            ConfigError::invalid(key, e.into_inner().to_string())
+        })?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        validation::validate(self)
+    }
+
+    /// Pontos configuration of the chain, the `pontos`
+    /// section unless the chain overrides it.
+    pub fn chain_pontos(&self, chain: &ChainConfig) -> PontosConfig {
+        chain.pontos.clone().unwrap_or_else(|| self.pontos.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = ArkConfig::parse("", "toml").unwrap();
+
+        assert_eq!(config, ArkConfig::default());
+        assert_eq!(config.pontos.pending_poll_interval_secs, 2);
+        assert_eq!(config.diri.events_chunk_size, 1000);
+    }
+
+    #[test]
+    fn test_toml_and_yaml() {
+        let toml = r#"
+            [starknet]
+            rpc_urls = ["http://localhost:5050"]
+            chain_id = "0x534e5f5345504f4c4941"
+
+            [pontos]
+            indexer_identifier = "pontos-1"
+            max_block_retries = 2
+        "#;
+
+        let yaml = "
+starknet:
+  rpc_urls: [\"http://localhost:5050\"]
+  chain_id: \"0x534e5f5345504f4c4941\"
+pontos:
+  indexer_identifier: pontos-1
+  max_block_retries: 2
+";
+
+        let from_toml = ArkConfig::parse(toml, "toml").unwrap();
+        assert_eq!(from_toml.pontos.indexer_identifier, "pontos-1");
+        assert_eq!(from_toml.pontos.max_block_retries, 2);
+        assert_eq!(from_toml.pontos.retry_delay_secs, 1);
+        assert_eq!(from_toml, ArkConfig::parse(yaml, "yaml").unwrap());
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        let file = sources::parse("[sana]\nretry_delay_secs = 3", "toml").unwrap();
+        let config = ArkConfig::from_sources(
+            Some(file),
+            env(&[
+                ("ARK_SANA__RETRY_DELAY_SECS", "4"),
+                ("ARK_METADATA__REQUEST_REFERRER", "https://example.com"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config.sana.retry_delay_secs, 4);
+        assert_eq!(config.metadata.request_referrer, "https://example.com");
+    }
+
+    #[test]
+    fn test_errors_name_the_key() {
+        let key = |result: Result<ArkConfig, ConfigError>| match result {
+            Err(ConfigError::Invalid { key, .. }) => key,
+            other => panic!("Unexpected result {:?}", other),
+        };
+
+        assert_eq!(
+            key(ArkConfig::parse(
+                "[pontos]\nmax_block_retries = \"a\"",
+                "toml"
+            )),
+            "pontos.max_block_retries"
+        );
+        assert_eq!(
+            key(ArkConfig::from_sources(
+                None,
+                env(&[("ARK_DIRI__EVENTS_CHUNK_SIZE", "0")])
+            )),
+            "diri.events_chunk_size"
+        );
+        assert_eq!(
+            key(ArkConfig::parse(
+                "[sana]\nmarketplace_addresses = [\"0x1\", \"zz\"]",
+                "toml"
+            )),
+            "sana.marketplace_addresses[1]"
+        );
+        assert!(ArkConfig::parse("[pontos]\nunknown = 1", "toml").is_err());
+    }
+
+    #[test]
+    fn test_chains() {
+        let config = ArkConfig::parse(
+            r#"
+            [pontos]
+            indexer_identifier = "pontos-main"
+
+            [[chains]]
+            chain_id = "SN_MAIN"
+            rpc_urls = ["http://mainnet"]
+
+            [[chains]]
+            chain_id = "SN_SEPOLIA"
+            rpc_urls = ["http://sepolia"]
+            database_schema = "sepolia"
+
+            [chains.pontos]
+            indexer_identifier = "pontos-sepolia"
+            "#,
+            "toml",
+        )
+        .unwrap();
+
+        let (main, sepolia) = (&config.chains[0], &config.chains[1]);
+        assert_eq!(main.database_schema(), "sn_main");
+        assert_eq!(sepolia.database_schema(), "sepolia");
+        assert_eq!(config.chain_pontos(main).indexer_identifier, "pontos-main");
+        assert_eq!(
+            config.chain_pontos(sepolia).indexer_identifier,
+            "pontos-sepolia"
+        );
+
+        let duplicated = "[[chains]]\nchain_id = \"SN_MAIN\"\n[[chains]]\nchain_id = \"SN_MAIN\"";
+        assert!(ArkConfig::parse(duplicated, "toml").is_err());
+
+        let invalid_schema = "[[chains]]\nchain_id = \"SN_MAIN\"\ndatabase_schema = \"a-b\"";
+        assert!(ArkConfig::parse(invalid_schema, "toml").is_err());
+    }
+}
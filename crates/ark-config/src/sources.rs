@@ -0,0 +1,144 @@
+//! Sources of the configuration: the file and the environment.
+//!
+//! Both are merged as JSON values on top of the default configuration,
+//! before being deserialized, so the errors can name the exact key.
+use serde_json::{Map, Value};
+use std::path::Path;
+
+use crate::error::ConfigError;
+
+/// Prefix of the environment variables overriding the configuration,
+/// `ARK_PONTOS__RETRY_DELAY_SECS` overrides `pontos.retry_delay_secs`.
+pub const ENV_PREFIX: &str = "ARK_";
+const ENV_SEPARATOR: &str = "__";
+
+pub fn read_file(path: &Path) -> Result<Value, ConfigError> {
+    let content = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+
+    parse(&content, extension)
+}
+
+pub fn parse(content: &str, format: &str) -> Result<Value, ConfigError> {
+    match format {
+        "toml" => {
+            let value: toml::Value =
+                toml::from_str(content).map_err(|e| ConfigError::Malformed(e.to_string()))?;
+            serde_json::to_value(value).map_err(|e| ConfigError::Malformed(e.to_string()))
+        }
+        "yaml" | "yml" => {
+            let value: serde_yaml::Value =
+                serde_yaml::from_str(content).map_err(|e| ConfigError::Malformed(e.to_string()))?;
+            serde_json::to_value(value).map_err(|e| ConfigError::Malformed(e.to_string()))
+        }
+        other => Err(ConfigError::UnsupportedFormat(other.to_string())),
+    }
+}
+
+/// Merges `overlay` into `base`, tables are merged key by key.
+pub fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Applies the variables starting with `ENV_PREFIX`.
+///
+/// The value keeps the type of the value it replaces when it's a string,
+/// otherwise it's parsed as JSON (numbers, booleans, lists), and
+/// falls back to a string.
+pub fn apply_env<I>(config: &mut Value, vars: I)
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    for (name, raw) in vars {
+        let path = match name.strip_prefix(ENV_PREFIX) {
+            Some(path) if !path.is_empty() => path.to_lowercase(),
+            _ => continue,
+        };
+
+        let keys: Vec<&str> = path.split(ENV_SEPARATOR).collect();
+        let target = slot(config, &keys);
+
+        *target = match target {
+            Value::String(_) => Value::String(raw),
+            _ => serde_json::from_str(&raw).unwrap_or(Value::String(raw)),
+        };
+    }
+}
+
+/// Returns the value at the given keys, creating the missing tables.
+fn slot<'a>(value: &'a mut Value, keys: &[&str]) -> &'a mut Value {
+    let mut current = value;
+
+    for key in keys {
+        if !current.is_object() {
+            *current = Value::Object(Map::new());
+        }
+
+        current = current
+            .as_object_mut()
+            .expect("Value is an object")
+            .entry(key.to_string())
+            .or_insert(Value::Null);
+    }
+
+    current
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge() {
+        let mut base = json!({"a": {"b": 1, "c": 2}, "d": [1, 2]});
+        merge(&mut base, json!({"a": {"c": 3}, "d": [4]}));
+
+        assert_eq!(base, json!({"a": {"b": 1, "c": 3}, "d": [4]}));
+    }
+
+    #[test]
+    fn test_apply_env() {
+        let mut config = json!({"pontos": {"indexer_version": "0.1.0", "retry_delay_secs": 1}});
+
+        apply_env(
+            &mut config,
+            [
+                ("ARK_PONTOS__INDEXER_VERSION", "2"),
+                ("ARK_PONTOS__RETRY_DELAY_SECS", "5"),
+                ("ARK_STARKNET__RPC_URLS", r#"["http://node"]"#),
+                ("ARK_DATABASE_URL", "postgres://localhost"),
+                ("OTHER", "ignored"),
+            ]
+            .map(|(k, v)| (k.to_string(), v.to_string())),
+        );
+
+        assert_eq!(
+            config,
+            json!({
+                "pontos": {"indexer_version": "2", "retry_delay_secs": 5},
+                "starknet": {"rpc_urls": ["http://node"]},
+                "database_url": "postgres://localhost"
+            })
+        );
+    }
+}
@@ -0,0 +1,195 @@
+//! Checks of the values that deserialize but can't be used.
+use starknet::core::types::FieldElement;
+use std::collections::HashSet;
+use url::Url;
+
+use crate::error::ConfigError;
+use crate::ArkConfig;
+
+pub fn validate(config: &ArkConfig) -> Result<(), ConfigError> {
+    for (i, url) in config.starknet.rpc_urls.iter().enumerate() {
+        url_value(&format!("starknet.rpc_urls[{i}]"), url)?;
+    }
+    not_empty("starknet.chain_id", &config.starknet.chain_id)?;
+    positive(
+        "starknet.events_chunk_size",
+        config.starknet.events_chunk_size,
+    )?;
+
+    let pontos = &config.pontos;
+    not_empty("pontos.indexer_version", &pontos.indexer_version)?;
+    not_empty("pontos.indexer_identifier", &pontos.indexer_identifier)?;
+    positive(
+        "pontos.pending_poll_interval_secs",
+        pontos.pending_poll_interval_secs,
+    )?;
+    addresses(
+        "pontos.marketplace_addresses",
+        &pontos.marketplace_addresses,
+    )?;
+    address(
+        "pontos.ventory_currency_address",
+        &pontos.ventory_currency_address,
+    )?;
+    addresses("pontos.udc_addresses", &pontos.udc_addresses)?;
+    positive(
+        "pontos.spam_airdrop_min_recipients",
+        pontos.spam_airdrop_min_recipients as u64,
+    )?;
+    positive(
+        "pontos.wash_trade_window_blocks",
+        pontos.wash_trade_window_blocks,
+    )?;
+    let ratio = pontos.wash_trade_price_ratio;
+    if ratio.is_nan() || ratio <= 1.0 {
+        return Err(ConfigError::invalid(
+            "pontos.wash_trade_price_ratio",
+            "must be greater than 1",
+        ));
+    }
+    positive(
+        "pontos.wash_trade_min_price_samples",
+        pontos.wash_trade_min_price_samples as u64,
+    )?;
+    for (key, values) in [
+        ("pontos.allowed_contracts", &pontos.allowed_contracts),
+        ("pontos.allowed_class_hashes", &pontos.allowed_class_hashes),
+        ("pontos.denied_contracts", &pontos.denied_contracts),
+        ("pontos.denied_class_hashes", &pontos.denied_class_hashes),
+    ] {
+        addresses(key, values)?;
+    }
+
+    let sana = &config.sana;
+    not_empty("sana.indexer_version", &sana.indexer_version)?;
+    not_empty("sana.indexer_identifier", &sana.indexer_identifier)?;
+    positive(
+        "sana.pending_poll_interval_secs",
+        sana.pending_poll_interval_secs,
+    )?;
+    addresses("sana.marketplace_addresses", &sana.marketplace_addresses)?;
+    address(
+        "sana.ventory_currency_address",
+        &sana.ventory_currency_address,
+    )?;
+
+    positive("diri.events_chunk_size", config.diri.events_chunk_size)?;
+
+    url_value(
+        "metadata.ipfs_gateway_uri",
+        &config.metadata.ipfs_gateway_uri,
+    )?;
+    positive(
+        "metadata.image_timeout_secs",
+        config.metadata.image_timeout_secs,
+    )?;
+
+    chains(config)
+}
+
+fn chains(config: &ArkConfig) -> Result<(), ConfigError> {
+    let mut chain_ids = HashSet::new();
+    let mut schemas = HashSet::new();
+
+    for (i, chain) in config.chains.iter().enumerate() {
+        let key = format!("chains[{i}]");
+
+        not_empty(&format!("{key}.chain_id"), &chain.chain_id)?;
+        if !chain_ids.insert(&chain.chain_id) {
+            return Err(ConfigError::invalid(
+                format!("{key}.chain_id"),
+                format!("chain {} is configured twice", chain.chain_id),
+            ));
+        }
+
+        for (j, url) in chain.rpc_urls.iter().enumerate() {
+            url_value(&format!("{key}.rpc_urls[{j}]"), url)?;
+        }
+
+        let schema = chain.database_schema();
+        if !is_schema_name(&schema) {
+            return Err(ConfigError::invalid(
+                format!("{key}.database_schema"),
+                format!("invalid schema name {schema}, only a-z, 0-9 and _ are allowed"),
+            ));
+        }
+        if !schemas.insert(schema.clone()) {
+            return Err(ConfigError::invalid(
+                format!("{key}.database_schema"),
+                format!("schema {schema} is used by another chain"),
+            ));
+        }
+
+        if let Some(pontos) = &chain.pontos {
+            not_empty(
+                &format!("{key}.pontos.indexer_version"),
+                &pontos.indexer_version,
+            )?;
+            not_empty(
+                &format!("{key}.pontos.indexer_identifier"),
+                &pontos.indexer_identifier,
+            )?;
+            addresses(
+                &format!("{key}.pontos.marketplace_addresses"),
+                &pontos.marketplace_addresses,
+            )?;
+            addresses(
+                &format!("{key}.pontos.udc_addresses"),
+                &pontos.udc_addresses,
+            )?;
+            for (name, values) in [
+                ("allowed_contracts", &pontos.allowed_contracts),
+                ("allowed_class_hashes", &pontos.allowed_class_hashes),
+                ("denied_contracts", &pontos.denied_contracts),
+                ("denied_class_hashes", &pontos.denied_class_hashes),
+            ] {
+                addresses(&format!("{key}.pontos.{name}"), values)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Same rule as `PostgresStorage::is_valid_schema_name`, pontos
+/// being built without the postgres feature here.
+fn is_schema_name(value: &str) -> bool {
+    !value.is_empty()
+        && !value.starts_with(|c: char| c.is_ascii_digit())
+        && value
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+fn not_empty(key: &str, value: &str) -> Result<(), ConfigError> {
+    if value.trim().is_empty() {
+        return Err(ConfigError::invalid(key, "must not be empty"));
+    }
+    Ok(())
+}
+
+fn positive(key: &str, value: u64) -> Result<(), ConfigError> {
+    if value == 0 {
+        return Err(ConfigError::invalid(key, "must be greater than 0"));
+    }
+    Ok(())
+}
+
+fn url_value(key: &str, value: &str) -> Result<(), ConfigError> {
+    Url::parse(value)
+        .map(|_| ())
+        .map_err(|e| ConfigError::invalid(key, format!("invalid url {value}: {e}")))
+}
+
+fn address(key: &str, value: &str) -> Result<(), ConfigError> {
+    FieldElement::from_hex_be(value)
+        .map(|_| ())
+        .map_err(|_| ConfigError::invalid(key, format!("invalid address {value}")))
+}
+
+fn addresses(key: &str, values: &[String]) -> Result<(), ConfigError> {
+    for (i, value) in values.iter().enumerate() {
+        address(&format!("{key}[{i}]"), value)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,28 @@
+use std::path::PathBuf;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error("Can't write export file {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Can't serialize record: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Can't build record batch: {0}")]
+    Arrow(#[from] arrow_schema::ArrowError),
+
+    #[error("Can't write Parquet file: {0}")]
+    Parquet(#[from] parquet::errors::ParquetError),
+}
+
+impl ExportError {
+    pub(crate) fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        ExportError::Io {
+            path: path.into(),
+            source,
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Event handler exporting the events registered by Pontos.
+use crate::error::ExportError;
+use crate::schema::ExportRecord;
+use crate::writer::{ExportConfig, ExportWriter};
+use async_trait::async_trait;
+use pontos::event_handler::{EventHandler, MintEvent};
+use pontos::storage::types::TokenEvent;
+use std::sync::Mutex;
+use tracing::error;
+
+/// Writes the transfers, sales and mints registered by Pontos
+/// to the export files.
+///
+/// Pending rows are written at the end of each indexed range,
+/// on `flush` and when the handler is dropped. An export error
+/// is logged and never stops the indexation.
+pub struct ExportEventHandler {
+    writer: Mutex<ExportWriter>,
+}
+
+impl ExportEventHandler {
+    pub fn new(config: ExportConfig) -> Self {
+        ExportEventHandler {
+            writer: Mutex::new(ExportWriter::new(config)),
+        }
+    }
+
+    pub fn flush(&self) -> Result<(), ExportError> {
+        self.writer
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .flush()
+    }
+
+    fn export(&self, record: ExportRecord) {
+        let result = self
+            .writer
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .write(&record);
+
+        if let Err(e) = result {
+            error!("Can't export {:?} record: {}", record.kind(), e);
+        }
+    }
+}
+
+impl Drop for ExportEventHandler {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            error!("Can't flush export files: {}", e);
+        }
+    }
+}
+
+#[async_trait]
+impl EventHandler for ExportEventHandler {
+    async fn on_indexation_range_completed(&self) {
+        if let Err(e) = self.flush() {
+            error!("Can't flush export files: {}", e);
+        }
+    }
+
+    async fn on_event_registered(&self, event: TokenEvent) {
+        self.export(ExportRecord::from(&event));
+    }
+
+    async fn on_mint_registered(&self, mint: MintEvent) {
+        self.export(ExportRecord::from(&mint));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::ExportFormat;
+    use pontos::storage::types::{EventType, TokenTransferEvent};
+
+    #[tokio::test]
+    async fn test_exports_registered_events() {
+        let root = std::env::temp_dir().join(format!("ark-export-handler-{}", std::process::id()));
+        std::fs::remove_dir_all(&root).ok();
+
+        let handler = ExportEventHandler::new(ExportConfig {
+            root: root.clone(),
+            formats: vec![ExportFormat::Parquet],
+            max_rows_per_file: 100,
+        });
+
+        handler
+            .on_event_registered(TokenEvent::Transfer(TokenTransferEvent {
+                chain_id: "SN_MAIN".to_string(),
+                event_type: EventType::Mint,
+                timestamp: 1_709_251_200,
+                ..Default::default()
+            }))
+            .await;
+
+        let file = root.join("v2/transfers/chain_id=SN_MAIN/date=2024-03-01/part-00000.parquet");
+        assert!(!file.exists());
+
+        handler.on_indexation_range_completed().await;
+        assert!(file.exists());
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+}
@@ -0,0 +1,17 @@
+//! Export of the transfers, sales and mints indexed by Pontos to
+//! NDJSON and Parquet files, for analytics outside of the database.
+//!
+//! Files are rotated once they reach the configured number of rows,
+//! and partitioned by record kind, chain and UTC day of the block.
+//! Every record carries the `SCHEMA_VERSION` it was written with.
+pub mod error;
+pub mod handler;
+pub mod schema;
+pub mod writer;
+
+pub use error::ExportError;
+pub use handler::ExportEventHandler;
+pub use schema::{
+    ExportRecord, MintRecord, RecordKind, SaleRecord, TransferRecord, SCHEMA_VERSION,
+};
+pub use writer::{ExportConfig, ExportFormat, ExportWriter};
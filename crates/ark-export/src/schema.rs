@@ -0,0 +1,405 @@
+//! Records written to the export files, and their columns.
+//!
+//! The columns of a kind are never renamed nor removed within a schema
+//! version: any such change bumps `SCHEMA_VERSION`, which is part of
+//! the path of the files and of each record.
+use arrow_schema::{DataType, Field, Schema};
+use pontos::event_handler::MintEvent;
+use pontos::storage::types::{TokenEvent, TokenSaleEvent, TokenTransferEvent};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Version 2: `normalized_price` of the sales is an exact decimal
+/// string (`Utf8`) instead of a `Float64`.
+pub const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordKind {
+    Transfer,
+    Sale,
+    Mint,
+}
+
+impl RecordKind {
+    /// Directory of the records of this kind.
+    pub fn dir_name(&self) -> &'static str {
+        match self {
+            RecordKind::Transfer => "transfers",
+            RecordKind::Sale => "sales",
+            RecordKind::Mint => "mints",
+        }
+    }
+
+    pub fn columns(&self) -> &'static [Column] {
+        match self {
+            RecordKind::Transfer => TRANSFER_COLUMNS,
+            RecordKind::Sale => SALE_COLUMNS,
+            RecordKind::Mint => MINT_COLUMNS,
+        }
+    }
+
+    pub fn arrow_schema(&self) -> Schema {
+        let fields: Vec<Field> = self
+            .columns()
+            .iter()
+            .map(|c| Field::new(c.name, c.data_type.arrow_type(), c.nullable))
+            .collect();
+
+        Schema::new(fields).with_metadata(HashMap::from([(
+            "ark.schema_version".to_string(),
+            SCHEMA_VERSION.to_string(),
+        )]))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Utf8,
+    UInt32,
+    UInt64,
+    Float64,
+}
+
+impl ColumnType {
+    fn arrow_type(&self) -> DataType {
+        match self {
+            ColumnType::Utf8 => DataType::Utf8,
+            ColumnType::UInt32 => DataType::UInt32,
+            ColumnType::UInt64 => DataType::UInt64,
+            ColumnType::Float64 => DataType::Float64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Column {
+    pub name: &'static str,
+    pub data_type: ColumnType,
+    pub nullable: bool,
+}
+
+const fn column(name: &'static str, data_type: ColumnType, nullable: bool) -> Column {
+    Column {
+        name,
+        data_type,
+        nullable,
+    }
+}
+
+const TRANSFER_COLUMNS: &[Column] = &[
+    column("schema_version", ColumnType::UInt32, false),
+    column("chain_id", ColumnType::Utf8, false),
+    column("block_number", ColumnType::UInt64, true),
+    column("timestamp", ColumnType::UInt64, false),
+    column("transaction_hash", ColumnType::Utf8, false),
+    column("event_id", ColumnType::Utf8, false),
+    column("event_type", ColumnType::Utf8, false),
+    column("contract_address", ColumnType::Utf8, false),
+    column("contract_type", ColumnType::Utf8, false),
+    column("token_id", ColumnType::Utf8, false),
+    column("token_id_hex", ColumnType::Utf8, false),
+    column("from_address", ColumnType::Utf8, false),
+    column("to_address", ColumnType::Utf8, false),
+];
+
+const SALE_COLUMNS: &[Column] = &[
+    column("schema_version", ColumnType::UInt32, false),
+    column("chain_id", ColumnType::Utf8, false),
+    column("block_number", ColumnType::UInt64, true),
+    column("timestamp", ColumnType::UInt64, false),
+    column("transaction_hash", ColumnType::Utf8, false),
+    column("event_id", ColumnType::Utf8, false),
+    column("marketplace_contract_address", ColumnType::Utf8, false),
+    column("marketplace_name", ColumnType::Utf8, false),
+    column("nft_contract_address", ColumnType::Utf8, false),
+    column("nft_type", ColumnType::Utf8, true),
+    column("token_id", ColumnType::Utf8, false),
+    column("token_id_hex", ColumnType::Utf8, false),
+    column("quantity", ColumnType::UInt64, false),
+    column("from_address", ColumnType::Utf8, false),
+    column("to_address", ColumnType::Utf8, false),
+    column("currency_address", ColumnType::Utf8, true),
+    column("currency_symbol", ColumnType::Utf8, true),
+    column("currency_decimals", ColumnType::UInt32, true),
+    column("price", ColumnType::Utf8, false),
+    column("normalized_price", ColumnType::Utf8, true),
+    column("price_usd", ColumnType::Float64, true),
+];
+
+const MINT_COLUMNS: &[Column] = &[
+    column("schema_version", ColumnType::UInt32, false),
+    column("chain_id", ColumnType::Utf8, false),
+    column("block_number", ColumnType::UInt64, true),
+    column("timestamp", ColumnType::UInt64, false),
+    column("transaction_hash", ColumnType::Utf8, false),
+    column("contract_address", ColumnType::Utf8, false),
+    column("token_id", ColumnType::Utf8, false),
+    column("token_id_hex", ColumnType::Utf8, false),
+    column("minter_address", ColumnType::Utf8, false),
+    column("price", ColumnType::Utf8, true),
+    column("currency_address", ColumnType::Utf8, true),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransferRecord {
+    pub schema_version: u32,
+    pub chain_id: String,
+    pub block_number: Option<u64>,
+    pub timestamp: u64,
+    pub transaction_hash: String,
+    pub event_id: String,
+    /// `mint`, `burn` or `transfer`.
+    pub event_type: String,
+    pub contract_address: String,
+    pub contract_type: String,
+    pub token_id: String,
+    pub token_id_hex: String,
+    pub from_address: String,
+    pub to_address: String,
+}
+
+impl From<&TokenTransferEvent> for TransferRecord {
+    fn from(event: &TokenTransferEvent) -> Self {
+        TransferRecord {
+            schema_version: SCHEMA_VERSION,
+            chain_id: event.chain_id.clone(),
+            block_number: event.block_number,
+            timestamp: event.timestamp,
+            transaction_hash: event.transaction_hash.clone(),
+            event_id: event.event_id.clone(),
+            event_type: event.event_type.to_string().to_lowercase(),
+            contract_address: event.contract_address.clone(),
+            contract_type: event.contract_type.clone(),
+            token_id: event.token_id.clone(),
+            token_id_hex: event.token_id_hex.clone(),
+            from_address: event.from_address.clone(),
+            to_address: event.to_address.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaleRecord {
+    pub schema_version: u32,
+    pub chain_id: String,
+    pub block_number: Option<u64>,
+    pub timestamp: u64,
+    pub transaction_hash: String,
+    pub event_id: String,
+    pub marketplace_contract_address: String,
+    pub marketplace_name: String,
+    pub nft_contract_address: String,
+    pub nft_type: Option<String>,
+    pub token_id: String,
+    pub token_id_hex: String,
+    pub quantity: u64,
+    pub from_address: String,
+    pub to_address: String,
+    pub currency_address: Option<String>,
+    pub currency_symbol: Option<String>,
+    pub currency_decimals: Option<u32>,
+    /// Hexadecimal representation of the raw u256 amount.
+    pub price: String,
+    /// Exact decimal representation, to be summed without loss.
+    pub normalized_price: Option<String>,
+    pub price_usd: Option<f64>,
+}
+
+impl From<&TokenSaleEvent> for SaleRecord {
+    fn from(event: &TokenSaleEvent) -> Self {
+        SaleRecord {
+            schema_version: SCHEMA_VERSION,
+            chain_id: event.chain_id.clone(),
+            block_number: event.block_number,
+            timestamp: event.timestamp,
+            transaction_hash: event.transaction_hash.clone(),
+            event_id: event.event_id.clone(),
+            marketplace_contract_address: event.marketplace_contract_address.clone(),
+            marketplace_name: event.marketplace_name.clone(),
+            nft_contract_address: event.nft_contract_address.clone(),
+            nft_type: event.nft_type.clone(),
+            token_id: event.token_id.clone(),
+            token_id_hex: event.token_id_hex.clone(),
+            quantity: event.quantity,
+            from_address: event.from_address.clone(),
+            to_address: event.to_address.clone(),
+            currency_address: event.currency_address.clone(),
+            currency_symbol: event.currency_symbol.clone(),
+            currency_decimals: event.currency_decimals.map(u32::from),
+            price: event.price.clone(),
+            normalized_price: event.normalized_price.clone(),
+            price_usd: event.price_usd,
+        }
+    }
+}
+
+/// A mint is exported once at the mint, and again with
+/// its price once found by the Pontos analyzers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MintRecord {
+    pub schema_version: u32,
+    pub chain_id: String,
+    pub block_number: Option<u64>,
+    pub timestamp: u64,
+    pub transaction_hash: String,
+    pub contract_address: String,
+    pub token_id: String,
+    pub token_id_hex: String,
+    pub minter_address: String,
+    pub price: Option<String>,
+    pub currency_address: Option<String>,
+}
+
+impl From<&MintEvent> for MintRecord {
+    fn from(event: &MintEvent) -> Self {
+        MintRecord {
+            schema_version: SCHEMA_VERSION,
+            chain_id: event.chain_id.clone(),
+            block_number: event.mint.block_number,
+            timestamp: event.mint.timestamp,
+            transaction_hash: event.mint.transaction_hash.clone(),
+            contract_address: event.contract_address.clone(),
+            token_id: event.token_id.clone(),
+            token_id_hex: event.token_id_hex.clone(),
+            minter_address: event.mint.address.clone(),
+            price: event.mint.price.clone(),
+            currency_address: event.mint.currency_address.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportRecord {
+    Transfer(TransferRecord),
+    Sale(SaleRecord),
+    Mint(MintRecord),
+}
+
+impl ExportRecord {
+    pub fn kind(&self) -> RecordKind {
+        match self {
+            ExportRecord::Transfer(_) => RecordKind::Transfer,
+            ExportRecord::Sale(_) => RecordKind::Sale,
+            ExportRecord::Mint(_) => RecordKind::Mint,
+        }
+    }
+
+    pub fn chain_id(&self) -> &str {
+        match self {
+            ExportRecord::Transfer(r) => &r.chain_id,
+            ExportRecord::Sale(r) => &r.chain_id,
+            ExportRecord::Mint(r) => &r.chain_id,
+        }
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        match self {
+            ExportRecord::Transfer(r) => r.timestamp,
+            ExportRecord::Sale(r) => r.timestamp,
+            ExportRecord::Mint(r) => r.timestamp,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<serde_json::Value, serde_json::Error> {
+        match self {
+            ExportRecord::Transfer(r) => serde_json::to_value(r),
+            ExportRecord::Sale(r) => serde_json::to_value(r),
+            ExportRecord::Mint(r) => serde_json::to_value(r),
+        }
+    }
+}
+
+impl From<&TokenEvent> for ExportRecord {
+    fn from(event: &TokenEvent) -> Self {
+        match event {
+            TokenEvent::Transfer(transfer) => ExportRecord::Transfer(transfer.into()),
+            TokenEvent::Sale(sale) => ExportRecord::Sale(sale.into()),
+        }
+    }
+}
+
+impl From<&MintEvent> for ExportRecord {
+    fn from(event: &MintEvent) -> Self {
+        ExportRecord::Mint(event.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pontos::storage::types::{EventType, TokenMintInfo};
+
+    fn keys(value: serde_json::Value) -> Vec<String> {
+        let mut keys: Vec<String> = value.as_object().unwrap().keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    fn column_names(kind: RecordKind) -> Vec<String> {
+        let mut names: Vec<String> = kind.columns().iter().map(|c| c.name.to_string()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_records_match_columns() {
+        let transfer = ExportRecord::from(&TokenEvent::Transfer(TokenTransferEvent {
+            event_type: EventType::Mint,
+            ..Default::default()
+        }));
+        let mint = ExportRecord::from(&MintEvent {
+            contract_address: "0x1".to_string(),
+            chain_id: "SN_MAIN".to_string(),
+            token_id: "1".to_string(),
+            token_id_hex: "0x1".to_string(),
+            mint: TokenMintInfo::default(),
+        });
+
+        assert_eq!(
+            keys(transfer.to_json().unwrap()),
+            column_names(RecordKind::Transfer)
+        );
+        assert_eq!(
+            keys(mint.to_json().unwrap()),
+            column_names(RecordKind::Mint)
+        );
+
+        match transfer {
+            ExportRecord::Transfer(t) => assert_eq!(t.event_type, "mint"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_sale_record_matches_columns() {
+        let record = SaleRecord {
+            schema_version: SCHEMA_VERSION,
+            chain_id: "SN_MAIN".to_string(),
+            block_number: None,
+            timestamp: 0,
+            transaction_hash: String::new(),
+            event_id: String::new(),
+            marketplace_contract_address: String::new(),
+            marketplace_name: String::new(),
+            nft_contract_address: String::new(),
+            nft_type: None,
+            token_id: String::new(),
+            token_id_hex: String::new(),
+            quantity: 1,
+            from_address: String::new(),
+            to_address: String::new(),
+            currency_address: None,
+            currency_symbol: None,
+            currency_decimals: None,
+            price: String::new(),
+            normalized_price: None,
+            price_usd: None,
+        };
+
+        assert_eq!(
+            keys(serde_json::to_value(record).unwrap()),
+            column_names(RecordKind::Sale)
+        );
+    }
+}
@@ -0,0 +1,402 @@
+//! Rotating export files, partitioned by record kind, chain and day.
+//!
+//! Layout of the files under the export root:
+//!
+//! ```text
+//! v2/transfers/chain_id=SN_MAIN/date=2024-03-01/part-00000.ndjson
+//! v2/transfers/chain_id=SN_MAIN/date=2024-03-01/part-00000.parquet
+//! ```
+use crate::error::ExportError;
+use crate::schema::{ColumnType, ExportRecord, RecordKind, SCHEMA_VERSION};
+use arrow_array::builder::{Float64Builder, StringBuilder, UInt32Builder, UInt64Builder};
+use arrow_array::{ArrayRef, RecordBatch};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::debug;
+
+/// Partitions kept open at the same time. Past this count, every
+/// pending file is written and closed before opening a new one.
+const MAX_OPEN_PARTITIONS: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Ndjson,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportConfig {
+    pub root: PathBuf,
+    pub formats: Vec<ExportFormat>,
+    /// Rows of a file before it is rotated.
+    pub max_rows_per_file: usize,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        ExportConfig {
+            root: PathBuf::from("export"),
+            formats: vec![ExportFormat::Ndjson, ExportFormat::Parquet],
+            max_rows_per_file: 100_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Partition {
+    kind: RecordKind,
+    chain_id: String,
+    date: String,
+}
+
+impl Partition {
+    fn of(record: &ExportRecord) -> Self {
+        Partition {
+            kind: record.kind(),
+            chain_id: record.chain_id().to_string(),
+            date: date_of(record.timestamp()),
+        }
+    }
+
+    fn dir(&self, root: &Path) -> PathBuf {
+        root.join(format!("v{}", SCHEMA_VERSION))
+            .join(self.kind.dir_name())
+            .join(format!("chain_id={}", sanitize(&self.chain_id)))
+            .join(format!("date={}", self.date))
+    }
+}
+
+struct NdjsonFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    rows: usize,
+}
+
+#[derive(Default)]
+struct OpenPartition {
+    ndjson: Option<NdjsonFile>,
+    parquet_rows: Vec<serde_json::Value>,
+}
+
+/// Writes the records to files of the configured formats.
+///
+/// NDJSON files are appended to as the records come. Parquet files
+/// can't be appended to: their rows are buffered, and written once
+/// the file is full or on `flush`.
+pub struct ExportWriter {
+    config: ExportConfig,
+    partitions: HashMap<Partition, OpenPartition>,
+}
+
+impl ExportWriter {
+    pub fn new(config: ExportConfig) -> Self {
+        ExportWriter {
+            config,
+            partitions: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &ExportConfig {
+        &self.config
+    }
+
+    pub fn write(&mut self, record: &ExportRecord) -> Result<(), ExportError> {
+        let partition = Partition::of(record);
+
+        if !self.partitions.contains_key(&partition) && self.partitions.len() >= MAX_OPEN_PARTITIONS
+        {
+            self.flush()?;
+        }
+
+        let value = record.to_json()?;
+        let dir = partition.dir(&self.config.root);
+        let max_rows = self.config.max_rows_per_file.max(1);
+        let formats = self.config.formats.clone();
+        let open = self.partitions.entry(partition.clone()).or_default();
+
+        for format in formats {
+            match format {
+                ExportFormat::Ndjson => {
+                    write_ndjson(open, &dir, &value, max_rows)?;
+                }
+                ExportFormat::Parquet => {
+                    open.parquet_rows.push(value.clone());
+                    if open.parquet_rows.len() >= max_rows {
+                        let rows = std::mem::take(&mut open.parquet_rows);
+                        write_parquet(partition.kind, &dir, &rows)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the pending Parquet rows and closes every open file.
+    pub fn flush(&mut self) -> Result<(), ExportError> {
+        for (partition, open) in self.partitions.drain() {
+            let dir = partition.dir(&self.config.root);
+
+            if let Some(mut file) = open.ndjson {
+                file.writer
+                    .flush()
+                    .map_err(|e| ExportError::io(&file.path, e))?;
+            }
+
+            if !open.parquet_rows.is_empty() {
+                write_parquet(partition.kind, &dir, &open.parquet_rows)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn write_ndjson(
+    open: &mut OpenPartition,
+    dir: &Path,
+    value: &serde_json::Value,
+    max_rows: usize,
+) -> Result<(), ExportError> {
+    if open.ndjson.as_ref().map_or(false, |f| f.rows >= max_rows) {
+        if let Some(mut file) = open.ndjson.take() {
+            file.writer
+                .flush()
+                .map_err(|e| ExportError::io(&file.path, e))?;
+        }
+    }
+
+    if open.ndjson.is_none() {
+        let path = next_part_path(dir, ExportFormat::Ndjson)?;
+        let file = OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(&path)
+            .map_err(|e| ExportError::io(&path, e))?;
+        debug!("Writing export file {}", path.display());
+
+        open.ndjson = Some(NdjsonFile {
+            path,
+            writer: BufWriter::new(file),
+            rows: 0,
+        });
+    }
+
+    if let Some(file) = open.ndjson.as_mut() {
+        let mut line = serde_json::to_vec(value)?;
+        line.push(b'\n');
+        file.writer
+            .write_all(&line)
+            .map_err(|e| ExportError::io(&file.path, e))?;
+        file.rows += 1;
+    }
+
+    Ok(())
+}
+
+fn write_parquet(
+    kind: RecordKind,
+    dir: &Path,
+    rows: &[serde_json::Value],
+) -> Result<(), ExportError> {
+    let path = next_part_path(dir, ExportFormat::Parquet)?;
+    // Readers never see a partial file: it is written aside, then renamed.
+    let tmp_path = path.with_extension("parquet.tmp");
+
+    let schema = Arc::new(kind.arrow_schema());
+    let columns: Vec<ArrayRef> = kind
+        .columns()
+        .iter()
+        .map(|c| column_array(c.data_type, c.name, rows))
+        .collect();
+    let batch = RecordBatch::try_new(Arc::clone(&schema), columns)?;
+
+    let file = File::create(&tmp_path).map_err(|e| ExportError::io(&tmp_path, e))?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = ArrowWriter::try_new(file, schema, Some(properties))?;
+    writer.write(&batch)?;
+    writer.close()?;
+
+    fs::rename(&tmp_path, &path).map_err(|e| ExportError::io(&path, e))?;
+    debug!("Wrote {} rows to {}", rows.len(), path.display());
+
+    Ok(())
+}
+
+fn column_array(data_type: ColumnType, name: &str, rows: &[serde_json::Value]) -> ArrayRef {
+    let values = rows.iter().map(|row| row.get(name));
+
+    match data_type {
+        ColumnType::Utf8 => {
+            let mut builder = StringBuilder::new();
+            values.for_each(|v| builder.append_option(v.and_then(|v| v.as_str())));
+            Arc::new(builder.finish())
+        }
+        ColumnType::UInt32 => {
+            let mut builder = UInt32Builder::new();
+            values
+                .for_each(|v| builder.append_option(v.and_then(|v| v.as_u64()).map(|v| v as u32)));
+            Arc::new(builder.finish())
+        }
+        ColumnType::UInt64 => {
+            let mut builder = UInt64Builder::new();
+            values.for_each(|v| builder.append_option(v.and_then(|v| v.as_u64())));
+            Arc::new(builder.finish())
+        }
+        ColumnType::Float64 => {
+            let mut builder = Float64Builder::new();
+            values.for_each(|v| builder.append_option(v.and_then(|v| v.as_f64())));
+            Arc::new(builder.finish())
+        }
+    }
+}
+
+/// Path of the next part of the given format in `dir`, after the
+/// parts written by a previous run.
+fn next_part_path(dir: &Path, format: ExportFormat) -> Result<PathBuf, ExportError> {
+    fs::create_dir_all(dir).map_err(|e| ExportError::io(dir, e))?;
+
+    let suffix = format!(".{}", format.extension());
+    let entries = fs::read_dir(dir).map_err(|e| ExportError::io(dir, e))?;
+    let next = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            name.strip_prefix("part-")?
+                .strip_suffix(&suffix)?
+                .parse::<u32>()
+                .ok()
+        })
+        .max()
+        .map_or(0, |last| last + 1);
+
+    Ok(dir.join(format!("part-{:05}{}", next, suffix)))
+}
+
+/// UTC day of a block timestamp, as `YYYY-MM-DD`.
+fn date_of(timestamp: u64) -> String {
+    chrono::DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|| "1970-01-01".to_string())
+}
+
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::TransferRecord;
+
+    fn transfer(chain_id: &str, timestamp: u64, token_id: &str) -> ExportRecord {
+        ExportRecord::Transfer(TransferRecord {
+            schema_version: SCHEMA_VERSION,
+            chain_id: chain_id.to_string(),
+            block_number: Some(1),
+            timestamp,
+            transaction_hash: "0x1".to_string(),
+            event_id: format!("0x{}", token_id),
+            event_type: "transfer".to_string(),
+            contract_address: "0xabc".to_string(),
+            contract_type: "ERC721".to_string(),
+            token_id: token_id.to_string(),
+            token_id_hex: format!("0x{}", token_id),
+            from_address: "0x0".to_string(),
+            to_address: "0x2".to_string(),
+        })
+    }
+
+    fn root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("ark-export-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&root).ok();
+        root
+    }
+
+    #[test]
+    fn test_date_of() {
+        assert_eq!(date_of(0), "1970-01-01");
+        assert_eq!(date_of(1_709_251_200), "2024-03-01");
+        assert_eq!(sanitize("SN_MAIN/../x"), "SN_MAIN____x");
+    }
+
+    #[test]
+    fn test_rotates_and_partitions() {
+        let root = root("rotate");
+        let mut writer = ExportWriter::new(ExportConfig {
+            root: root.clone(),
+            formats: vec![ExportFormat::Ndjson, ExportFormat::Parquet],
+            max_rows_per_file: 2,
+        });
+
+        for token_id in ["1", "2", "3"] {
+            writer
+                .write(&transfer("SN_MAIN", 1_709_251_200, token_id))
+                .unwrap();
+        }
+        writer
+            .write(&transfer("SN_SEPOLIA", 1_709_251_200, "4"))
+            .unwrap();
+        writer.flush().unwrap();
+
+        let dir = root.join("v2/transfers/chain_id=SN_MAIN/date=2024-03-01");
+        let first = fs::read_to_string(dir.join("part-00000.ndjson")).unwrap();
+        let second = fs::read_to_string(dir.join("part-00001.ndjson")).unwrap();
+        assert_eq!(first.lines().count(), 2);
+        assert_eq!(second.lines().count(), 1);
+        assert!(dir.join("part-00000.parquet").exists());
+        assert!(dir.join("part-00001.parquet").exists());
+        assert!(!dir.join("part-00002.parquet").exists());
+
+        let line: serde_json::Value = serde_json::from_str(second.trim()).unwrap();
+        assert_eq!(line["token_id"], "3");
+        assert_eq!(line["schema_version"], SCHEMA_VERSION);
+
+        assert!(root
+            .join("v2/transfers/chain_id=SN_SEPOLIA/date=2024-03-01/part-00000.ndjson")
+            .exists());
+
+        // A new run continues the numbering.
+        let mut writer = ExportWriter::new(ExportConfig {
+            root: root.clone(),
+            formats: vec![ExportFormat::Ndjson],
+            max_rows_per_file: 2,
+        });
+        writer
+            .write(&transfer("SN_MAIN", 1_709_251_200, "5"))
+            .unwrap();
+        writer.flush().unwrap();
+        assert!(dir.join("part-00002.ndjson").exists());
+
+        fs::remove_dir_all(&root).ok();
+    }
+}
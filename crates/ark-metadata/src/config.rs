@@ -0,0 +1,32 @@
+//! Tunables of the metadata refresher.
+use serde_derive::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Values given to `MetadataManager::refresh_token_metadata` and
+/// `MetadataManager::refresh_collection_token_metadata`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetadataConfig {
+    /// Prefix of the IPFS hashes found in the token URIs.
+    pub ipfs_gateway_uri: String,
+    /// Timeout of the metadata and media requests, in seconds.
+    pub image_timeout_secs: u64,
+    /// `Referer` header of the requests.
+    pub request_referrer: String,
+}
+
+impl Default for MetadataConfig {
+    fn default() -> Self {
+        MetadataConfig {
+            ipfs_gateway_uri: "https://ipfs.io/ipfs/".to_string(),
+            image_timeout_secs: 10,
+            request_referrer: "https://arkproject.dev".to_string(),
+        }
+    }
+}
+
+impl MetadataConfig {
+    pub fn image_timeout(&self) -> Duration {
+        Duration::from_secs(self.image_timeout_secs)
+    }
+}
@@ -1,3 +1,4 @@
+pub mod config;
 pub mod elasticsearch_manager;
 pub mod file_manager;
 pub mod metadata_manager;
@@ -4,6 +4,11 @@ use async_trait::async_trait;
 #[cfg(any(test, feature = "mock"))]
 use mockall::automock;
 
+#[cfg(any(test, feature = "memory"))]
+pub mod memory;
+#[cfg(any(test, feature = "memory"))]
+pub use memory::MemoryStorage;
+
 #[cfg_attr(any(test, feature = "mock"), automock)]
 #[async_trait]
 pub trait Storage {
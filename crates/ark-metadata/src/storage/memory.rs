@@ -0,0 +1,286 @@
+//! Metadata `Storage` refreshing tokens held in memory, without a database.
+//!
+//! Tokens and contracts are added with `add_token` and `add_contract`,
+//! the metadata and statuses registered by the `MetadataManager` can
+//! then be inspected.
+use crate::storage::Storage;
+use crate::types::{StorageError, TokenMetadata, TokenWithoutMetadata};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tracing::trace;
+
+/// Status set on a token once its metadata is registered.
+pub const METADATA_STATUS_OK: &str = "OK";
+
+#[derive(Debug, Clone, Default)]
+pub struct StoredToken {
+    pub metadata: Option<TokenMetadata>,
+    pub metadata_status: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StoredContract {
+    pub is_verified: bool,
+    pub save_images: bool,
+    pub is_refreshing: bool,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    /// Keyed by (contract_address, chain_id, token_id).
+    tokens: HashMap<(String, String, String), StoredToken>,
+    /// Keyed by (contract_address, chain_id).
+    contracts: HashMap<(String, String), StoredContract>,
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    state: RwLock<MemoryState>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, MemoryState> {
+        self.state.read().expect("memory storage lock poisoned")
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, MemoryState> {
+        self.state.write().expect("memory storage lock poisoned")
+    }
+
+    /// Adds a token without metadata, its contract is
+    /// added with default flags if unknown.
+    pub fn add_token(&self, contract_address: &str, token_id: &str, chain_id: &str) {
+        let mut state = self.write();
+        state
+            .contracts
+            .entry((contract_address.to_string(), chain_id.to_string()))
+            .or_default();
+        state
+            .tokens
+            .entry((
+                contract_address.to_string(),
+                chain_id.to_string(),
+                token_id.to_string(),
+            ))
+            .or_default();
+    }
+
+    pub fn add_contract(&self, contract_address: &str, chain_id: &str, contract: StoredContract) {
+        self.write().contracts.insert(
+            (contract_address.to_string(), chain_id.to_string()),
+            contract,
+        );
+    }
+
+    pub fn token(
+        &self,
+        contract_address: &str,
+        token_id: &str,
+        chain_id: &str,
+    ) -> Option<StoredToken> {
+        self.read()
+            .tokens
+            .get(&(
+                contract_address.to_string(),
+                chain_id.to_string(),
+                token_id.to_string(),
+            ))
+            .cloned()
+    }
+
+    pub fn contract(&self, contract_address: &str, chain_id: &str) -> Option<StoredContract> {
+        self.read()
+            .contracts
+            .get(&(contract_address.to_string(), chain_id.to_string()))
+            .cloned()
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn register_token_metadata(
+        &self,
+        contract_address: &str,
+        token_id: &str,
+        chain_id: &str,
+        metadata: TokenMetadata,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Registering metadata of token {} {} {}",
+            contract_address,
+            token_id,
+            chain_id
+        );
+
+        let key = (
+            contract_address.to_string(),
+            chain_id.to_string(),
+            token_id.to_string(),
+        );
+
+        match self.write().tokens.get_mut(&key) {
+            Some(token) => {
+                token.metadata = Some(metadata);
+                token.metadata_status = Some(METADATA_STATUS_OK.to_string());
+                Ok(())
+            }
+            None => Err(StorageError::NotFound(format!(
+                "Token {} {} not found",
+                contract_address, token_id
+            ))),
+        }
+    }
+
+    /// Tokens without metadata, or all the tokens of the
+    /// filtered collection if `refresh_collection` is set.
+    async fn find_tokens_without_metadata(
+        &self,
+        filter: Option<(String, String)>,
+        refresh_collection: bool,
+    ) -> Result<Vec<TokenWithoutMetadata>, StorageError> {
+        let state = self.read();
+
+        let tokens = state
+            .tokens
+            .iter()
+            .filter(|((contract_address, chain_id, _), token)| {
+                let in_filter = match &filter {
+                    Some((c, ch)) => c == contract_address && ch == chain_id,
+                    None => true,
+                };
+                let with_collection = refresh_collection && filter.is_some();
+                in_filter && (with_collection || token.metadata.is_none())
+            })
+            .map(|((contract_address, chain_id, token_id), _)| {
+                let contract = state
+                    .contracts
+                    .get(&(contract_address.clone(), chain_id.clone()))
+                    .cloned()
+                    .unwrap_or_default();
+
+                TokenWithoutMetadata {
+                    contract_address: contract_address.clone(),
+                    token_id: token_id.clone(),
+                    chain_id: chain_id.clone(),
+                    is_verified: contract.is_verified,
+                    save_images: contract.save_images,
+                }
+            })
+            .collect();
+
+        Ok(tokens)
+    }
+
+    async fn update_all_token_metadata_status(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        metadata_status: &str,
+    ) -> Result<(), StorageError> {
+        for ((c, ch, _), token) in self.write().tokens.iter_mut() {
+            if c == contract_address && ch == chain_id {
+                token.metadata_status = Some(metadata_status.to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn update_token_metadata_status(
+        &self,
+        contract_address: &str,
+        token_id: &str,
+        chain_id: &str,
+        status: &str,
+    ) -> Result<(), StorageError> {
+        let key = (
+            contract_address.to_string(),
+            chain_id.to_string(),
+            token_id.to_string(),
+        );
+
+        match self.write().tokens.get_mut(&key) {
+            Some(token) => {
+                token.metadata_status = Some(status.to_string());
+                Ok(())
+            }
+            None => Err(StorageError::NotFound(format!(
+                "Token {} {} not found",
+                contract_address, token_id
+            ))),
+        }
+    }
+
+    async fn set_contract_refreshing_status(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        is_refreshing: bool,
+    ) -> Result<(), StorageError> {
+        match self
+            .write()
+            .contracts
+            .get_mut(&(contract_address.to_string(), chain_id.to_string()))
+        {
+            Some(contract) => {
+                contract.is_refreshing = is_refreshing;
+                Ok(())
+            }
+            None => Err(StorageError::NotFound(format!(
+                "Contract {} not found",
+                contract_address
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_find_tokens_without_metadata() {
+        let storage = MemoryStorage::new();
+        storage.add_token("0x1", "1", "SN_TEST");
+        storage.add_token("0x1", "2", "SN_TEST");
+        storage.add_token("0x2", "1", "SN_TEST");
+
+        storage
+            .register_token_metadata("0x1", "1", "SN_TEST", TokenMetadata::default())
+            .await
+            .unwrap();
+
+        let filter = Some(("0x1".to_string(), "SN_TEST".to_string()));
+        let tokens = storage
+            .find_tokens_without_metadata(filter.clone(), false)
+            .await
+            .unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token_id, "2");
+
+        let tokens = storage
+            .find_tokens_without_metadata(filter, true)
+            .await
+            .unwrap();
+        assert_eq!(tokens.len(), 2);
+
+        let tokens = storage
+            .find_tokens_without_metadata(None, false)
+            .await
+            .unwrap();
+        assert_eq!(tokens.len(), 2);
+
+        assert_eq!(
+            storage
+                .token("0x1", "1", "SN_TEST")
+                .unwrap()
+                .metadata_status,
+            Some(METADATA_STATUS_OK.to_string())
+        );
+    }
+}
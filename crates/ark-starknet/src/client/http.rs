@@ -15,11 +15,23 @@ const INPUT_TOO_LONG: &str = "0x496e70757420746f6f206c6f6e6720666f7220617267756d
 const FAILED_DESERIALIZE: &str = "0x4661696c656420746f20646573657269616c697a6520706172616d202331";
 const ENTRYPOINT_NOT_FOUND: &str = "not found in contract";
 
+/// Number of events requested by `starknet_getEvents` call.
+pub const DEFAULT_EVENTS_CHUNK_SIZE: u64 = 1000;
+
 #[derive(Debug)]
 pub struct StarknetClientHttp {
     /// Provider is kept public to allow custom reuse of
     /// the raw provider elsewhere.
     pub provider: AnyProvider,
+    events_chunk_size: u64,
+}
+
+impl StarknetClientHttp {
+    /// Sets the number of events requested by `starknet_getEvents` call.
+    pub fn with_events_chunk_size(mut self, events_chunk_size: u64) -> Self {
+        self.events_chunk_size = events_chunk_size;
+        self
+    }
 }
 
 #[async_trait]
@@ -31,7 +43,10 @@ impl StarknetClient for StarknetClientHttp {
 
         let provider = AnyProvider::JsonRpcHttp(JsonRpcClient::new(HttpTransport::new(rpc_url)));
 
-        Ok(Self { provider })
+        Ok(Self {
+            provider,
+            events_chunk_size: DEFAULT_EVENTS_CHUNK_SIZE,
+        })
     }
 
     /// Transaction receipts don't have `EmittedEvent` but `Event` instead.
@@ -203,6 +218,43 @@ impl StarknetClient for StarknetClientHttp {
             .map_err(StarknetClientError::Provider)?)
     }
 
+    async fn block_state_diff(&self, block: BlockId) -> Result<StateDiff, StarknetClientError> {
+        let update = self
+            .provider
+            .get_state_update(block)
+            .await
+            .map_err(StarknetClientError::Provider)?;
+
+        let state_diff = match update {
+            MaybePendingStateUpdate::Update(update) => update.state_diff,
+            MaybePendingStateUpdate::PendingUpdate(update) => update.state_diff,
+        };
+
+        Ok(state_diff)
+    }
+
+    async fn class_hash_at(
+        &self,
+        contract_address: FieldElement,
+        block: BlockId,
+    ) -> Result<FieldElement, StarknetClientError> {
+        self.provider
+            .get_class_hash_at(block, contract_address)
+            .await
+            .map_err(StarknetClientError::Provider)
+    }
+
+    async fn class_by_hash(
+        &self,
+        class_hash: FieldElement,
+        block: BlockId,
+    ) -> Result<ContractClass, StarknetClientError> {
+        self.provider
+            .get_class(block, class_hash)
+            .await
+            .map_err(StarknetClientError::Provider)
+    }
+
     async fn fetch_events(
         &self,
         from_block: Option<BlockId>,
@@ -220,11 +272,9 @@ impl StarknetClient for StarknetClientHttp {
             keys,
         };
 
-        let chunk_size = 1000;
-
         let event_page = self
             .provider
-            .get_events(filter.clone(), continuation_token, chunk_size)
+            .get_events(filter.clone(), continuation_token, self.events_chunk_size)
             .await
             .map_err(StarknetClientError::Provider)?;
 
@@ -257,13 +307,12 @@ impl StarknetClient for StarknetClientHttp {
             keys,
         };
 
-        let chunk_size = 1000;
         let mut continuation_token: Option<String> = None;
 
         loop {
             let event_page = self
                 .provider
-                .get_events(filter.clone(), continuation_token, chunk_size)
+                .get_events(filter.clone(), continuation_token, self.events_chunk_size)
                 .await
                 .map_err(StarknetClientError::Provider)?;
 
@@ -301,13 +350,12 @@ impl StarknetClient for StarknetClientHttp {
             keys,
         };
 
-        let chunk_size = 1000;
         let mut continuation_token: Option<String> = None;
 
         loop {
             let event_page = self
                 .provider
-                .get_events(filter.clone(), continuation_token, chunk_size)
+                .get_events(filter.clone(), continuation_token, self.events_chunk_size)
                 .await
                 .map_err(StarknetClientError::Provider)?;
 
@@ -362,7 +410,7 @@ impl StarknetClient for StarknetClientHttp {
                         Err(StarknetClientError::Contract(s))
                     }
                 } else {
-                    Err(StarknetClientError::Contract(e.to_string()))
+                    Err(StarknetClientError::Provider(e))
                 }
             }
         }
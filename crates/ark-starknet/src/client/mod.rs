@@ -1,7 +1,7 @@
 pub mod http;
 use crate::EventResult;
 use async_trait::async_trait;
-pub use http::StarknetClientHttp;
+pub use http::{StarknetClientHttp, DEFAULT_EVENTS_CHUNK_SIZE};
 #[cfg(any(test, feature = "mock"))]
 use mockall::automock;
 use starknet::core::{types::FieldElement, types::*};
@@ -28,6 +28,28 @@ pub enum StarknetClientError {
     Other(String),
 }
 
+impl StarknetClientError {
+    /// True if the called entry point doesn't exist in the contract.
+    pub fn is_entrypoint_not_found(&self) -> bool {
+        match self {
+            StarknetClientError::EntrypointNotFound(_) => true,
+            StarknetClientError::Contract(s) => s.contains("not found in contract"),
+            _ => false,
+        }
+    }
+
+    /// True if the call was executed and reverted by the contract, like
+    /// `owner_of` on a burnt token. The failures of the node or of the
+    /// transport are not reverts.
+    pub fn is_revert(&self) -> bool {
+        match self {
+            StarknetClientError::Contract(_) => !self.is_entrypoint_not_found(),
+            StarknetClientError::InputTooLong | StarknetClientError::InputTooShort => true,
+            _ => false,
+        }
+    }
+}
+
 /// Starknet client interface with required methods
 /// for arkproject capabilities only.
 #[cfg_attr(any(test, feature = "mock"), automock)]
@@ -62,6 +84,24 @@ pub trait StarknetClient {
 
     async fn block_number(&self) -> Result<u64, StarknetClientError>;
 
+    /// Returns the state diff of the block, containing the contracts
+    /// deployed and the classes declared in this block.
+    async fn block_state_diff(&self, block: BlockId) -> Result<StateDiff, StarknetClientError>;
+
+    /// Returns the class hash of the contract deployed at the address.
+    async fn class_hash_at(
+        &self,
+        contract_address: FieldElement,
+        block: BlockId,
+    ) -> Result<FieldElement, StarknetClientError>;
+
+    /// Returns the class declared with the given hash, to inspect its ABI.
+    async fn class_by_hash(
+        &self,
+        class_hash: FieldElement,
+        block: BlockId,
+    ) -> Result<ContractClass, StarknetClientError>;
+
     /// On Starknet, a chunk size limits the maximum number of events
     /// that can be retrieved with one call.
     /// To ensure all events are fetched, we must ensure all events pages
@@ -102,3 +142,21 @@ pub trait StarknetClient {
         block: BlockId,
     ) -> Result<Vec<FieldElement>, StarknetClientError>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_is_revert() {
+        assert!(StarknetClientError::Contract("Invalid token id".to_string()).is_revert());
+        assert!(StarknetClientError::InputTooShort.is_revert());
+        assert!(!StarknetClientError::Provider(ProviderError::RateLimited).is_revert());
+        assert!(!StarknetClientError::Other("timeout".to_string()).is_revert());
+
+        let not_found =
+            StarknetClientError::Contract("Entry point 0x1 not found in contract 0x2".to_string());
+        assert!(not_found.is_entrypoint_not_found());
+        assert!(!not_found.is_revert());
+    }
+}
@@ -0,0 +1,157 @@
+//! ERC20 currencies used to pay the sales, shared by the indexers.
+use crate::cairo_string_parser::parse_cairo_string;
+use crate::client::StarknetClient;
+use crate::format::to_hex_str;
+use crate::CairoU256;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use starknet::core::types::{BlockId, BlockTag, FieldElement};
+use starknet::macros::selector;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{info, trace};
+
+/// ERC20 currency used to pay a sale.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct CurrencyInfo {
+    pub currency_address: String,
+    pub symbol: Option<String>,
+    pub decimals: u8,
+}
+
+/// Registry of the ERC20 currencies used to pay sales.
+///
+/// Decimals and symbol are resolved on chain the first time
+/// a currency is seen, and cached for the lifetime of the manager.
+pub struct CurrencyManager<C: StarknetClient> {
+    client: Arc<C>,
+    /// A cache with currency address mapped to its info.
+    cache: HashMap<FieldElement, CurrencyInfo>,
+}
+
+impl<C: StarknetClient> CurrencyManager<C> {
+    /// Initializes a new instance.
+    pub fn new(client: Arc<C>) -> Self {
+        Self {
+            client,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Gets the currency info from local cache, or resolves it on chain.
+    pub async fn get_currency(&mut self, address: FieldElement) -> Result<CurrencyInfo> {
+        if let Some(info) = self.cache.get(&address) {
+            return Ok(info.clone());
+        }
+
+        trace!("Cache miss for currency {:#064x}", address);
+
+        let block = BlockId::Tag(BlockTag::Pending);
+
+        let decimals = self
+            .client
+            .call_contract(address, selector!("decimals"), vec![], block)
+            .await?
+            .first()
+            .copied()
+            .ok_or_else(|| anyhow!("Empty decimals response"))?;
+
+        let decimals: u8 = decimals
+            .try_into()
+            .map_err(|_| anyhow!("Invalid decimals for currency {:#064x}", address))?;
+
+        let symbol = match self
+            .client
+            .call_contract(address, selector!("symbol"), vec![], block)
+            .await
+        {
+            Ok(response) => parse_cairo_string(response).ok(),
+            Err(_) => None,
+        };
+
+        info!(
+            "Currency [0x{:064x}] details - Symbol: {:?}, Decimals: {}",
+            address, symbol, decimals
+        );
+
+        let info = CurrencyInfo {
+            currency_address: to_hex_str(&address),
+            symbol,
+            decimals,
+        };
+
+        self.cache.insert(address, info.clone());
+
+        Ok(info)
+    }
+
+    /// Resolves the currency of a price, and returns it with the price
+    /// normalized with its decimals. The price is the hexadecimal u256
+    /// amount in the smallest unit of the currency.
+    pub async fn normalize_price(
+        &mut self,
+        currency_address: &str,
+        price: &str,
+    ) -> Result<(CurrencyInfo, String)> {
+        let currency = self
+            .get_currency(FieldElement::from_hex_be(currency_address)?)
+            .await?;
+        let normalized_price =
+            CairoU256::from_hex_be(price)?.to_normalized_decimal(currency.decimals);
+
+        Ok((currency, normalized_price))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::MockStarknetClient;
+    use starknet::core::utils::cairo_short_string_to_felt;
+    use starknet::macros::selector;
+
+    fn client(decimals: u8, symbol: &'static str) -> MockStarknetClient {
+        let mut client = MockStarknetClient::default();
+        client
+            .expect_call_contract()
+            .times(2)
+            .returning(move |_, selector, _, _| {
+                if selector == selector!("decimals") {
+                    Ok(vec![FieldElement::from(decimals)])
+                } else {
+                    Ok(vec![cairo_short_string_to_felt(symbol).unwrap()])
+                }
+            });
+        client
+    }
+
+    #[tokio::test]
+    async fn test_get_currency_is_cached() {
+        let mut manager = CurrencyManager::new(Arc::new(client(18, "STRK")));
+        let address = FieldElement::from_hex_be("0x1234").unwrap();
+
+        let info = manager.get_currency(address).await.unwrap();
+        assert_eq!(info.decimals, 18);
+        assert_eq!(info.symbol, Some("STRK".to_string()));
+
+        // Second call must be served by the cache.
+        let cached = manager.get_currency(address).await.unwrap();
+        assert_eq!(cached, info);
+    }
+
+    #[tokio::test]
+    async fn test_normalize_price() {
+        let mut manager = CurrencyManager::new(Arc::new(client(6, "USDC")));
+        let price = CairoU256 {
+            low: 12_340_000,
+            high: 0,
+        }
+        .to_hex();
+
+        let (currency, normalized_price) = manager.normalize_price("0x1234", &price).await.unwrap();
+
+        assert_eq!(currency.symbol, Some("USDC".to_string()));
+        assert_eq!(currency.decimals, 6);
+        assert_eq!(normalized_price, "12.34");
+    }
+}
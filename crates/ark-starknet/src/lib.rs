@@ -1,15 +1,17 @@
 pub mod byte_array;
 pub mod cairo_string_parser;
 pub mod client;
+pub mod currency;
 pub mod format;
+pub mod price_oracle;
 use anyhow::Result;
 use format::to_hex_str;
 use num_bigint::BigUint;
 use num_traits::Num;
-use starknet::core::types::EmittedEvent;
+use starknet::core::types::{EmittedEvent, FieldElement};
 use std::collections::HashMap;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct CairoU256 {
     pub low: u128,
     pub high: u128,
@@ -22,6 +24,55 @@ pub struct EventResult {
 }
 
 impl CairoU256 {
+    /// Builds a `CairoU256` from a single felt. The bits above
+    /// the 128 first ones are kept into the `high` part.
+    pub fn from_felt(value: &FieldElement) -> Self {
+        let bytes = value.to_bytes_be();
+        let (high, low) = bytes.split_at(16);
+
+        // Safe to unwrap, a felt is always 32 bytes long.
+        Self {
+            low: u128::from_be_bytes(low.try_into().unwrap()),
+            high: u128::from_be_bytes(high.try_into().unwrap()),
+        }
+    }
+
+    /// Builds a `CairoU256` from its two felts representation,
+    /// as serialized by Cairo.
+    pub fn from_felts(low: &FieldElement, high: &FieldElement) -> Result<Self> {
+        Ok(Self {
+            low: (*low)
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Low part of u256 overflows u128"))?,
+            high: (*high)
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("High part of u256 overflows u128"))?,
+        })
+    }
+
+    /// Returns the exact decimal representation of the value divided
+    /// by `10^decimals`, which is the amount expressed in the unit of an
+    /// ERC20 with the given decimals. Unlike a float, it keeps all the
+    /// digits of the amount, and can be summed without loss.
+    pub fn to_normalized_decimal(&self, decimals: u8) -> String {
+        let digits = self.to_biguint().to_str_radix(10);
+        let decimals = decimals as usize;
+
+        if decimals == 0 {
+            return digits;
+        }
+
+        let padded = format!("{:0>width$}", digits, width = decimals + 1);
+        let (integer, fraction) = padded.split_at(padded.len() - decimals);
+        let fraction = fraction.trim_end_matches('0');
+
+        if fraction.is_empty() {
+            integer.to_string()
+        } else {
+            format!("{}.{}", integer, fraction)
+        }
+    }
+
     pub fn to_biguint(&self) -> BigUint {
         let low_bytes = self.low.to_be_bytes();
         let high_bytes = self.high.to_be_bytes();
@@ -111,6 +162,72 @@ mod tests {
         assert_eq!(result_padded, expected_padded_decimal);
     }
 
+    #[test]
+    fn test_from_felt() {
+        let felt = FieldElement::from_hex_be(
+            "0x05f7cd1fd465baff2ba9d2d1501ad0a2eb5337d9a885be319366b5205a414fdd",
+        )
+        .unwrap();
+
+        let u256 = CairoU256::from_felt(&felt);
+
+        assert_eq!(
+            u256.low,
+            u128::from_str_radix("eb5337d9a885be319366b5205a414fdd", 16).unwrap()
+        );
+        assert_eq!(
+            u256.high,
+            u128::from_str_radix("05f7cd1fd465baff2ba9d2d1501ad0a2", 16).unwrap()
+        );
+        assert_eq!(u256.to_hex(), to_hex_str(&felt));
+    }
+
+    #[test]
+    fn test_from_felts() {
+        let u256 = CairoU256::from_felts(&FieldElement::from(15_u64), &FieldElement::ONE).unwrap();
+
+        assert_eq!(u256.low, 15);
+        assert_eq!(u256.high, 1);
+
+        // Low part doesn't fit into a u128.
+        let too_big = FieldElement::from_hex_be("0x100000000000000000000000000000000").unwrap();
+        assert!(CairoU256::from_felts(&too_big, &FieldElement::ZERO).is_err());
+    }
+
+    #[test]
+    fn test_to_normalized_decimal() {
+        // 1.5 ETH.
+        let u256 = CairoU256 {
+            low: 1_500_000_000_000_000_000,
+            high: 0,
+        };
+        assert_eq!(u256.to_normalized_decimal(18), "1.5");
+
+        // 12.34 USDC.
+        let u256 = CairoU256 {
+            low: 12_340_000,
+            high: 0,
+        };
+        assert_eq!(u256.to_normalized_decimal(6), "12.34");
+
+        let u256 = CairoU256 { low: 42, high: 0 };
+        assert_eq!(u256.to_normalized_decimal(0), "42");
+
+        // 1 wei.
+        let u256 = CairoU256 { low: 1, high: 0 };
+        assert_eq!(u256.to_normalized_decimal(18), "0.000000000000000001");
+
+        // More digits than a f64 can hold.
+        let u256 = CairoU256 {
+            low: 123_456_789_012_345_678_901_234_567_891,
+            high: 0,
+        };
+        assert_eq!(
+            u256.to_normalized_decimal(18),
+            "123456789012.345678901234567891"
+        );
+    }
+
     #[test]
     fn test_from_hex_be() {
         let hex_string = "0x000000000000000000000000000000000000000000000000000000000000000f";
@@ -0,0 +1,175 @@
+//! Trait related to the fiat valuation of the sales indexed by Pontos and Sana.
+use crate::format::to_hex_str;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use starknet::core::types::FieldElement;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// A trait to be implemented in order to give the indexers
+/// the USD value of the currencies used to pay sales.
+///
+/// The oracle is consulted once per registered sale,
+/// so any remote call should be cached by the implementation.
+#[async_trait]
+pub trait PriceOracle {
+    /// Returns the USD price of one unit of the currency (already
+    /// normalized with its decimals) at the given timestamp.
+    /// `None` is returned if the rate is unknown.
+    async fn get_usd_rate(&self, currency_address: &str, timestamp: u64) -> Result<Option<f64>>;
+}
+
+/// A rate of a currency, starting at the given timestamp.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PriceRecord {
+    pub currency_address: String,
+    pub timestamp: u64,
+    pub usd_rate: f64,
+}
+
+/// Price oracle backed by a list of rates loaded in memory,
+/// for offline usage and tests.
+///
+/// The rate used for a timestamp is the last one known at this timestamp.
+#[derive(Debug, Default)]
+pub struct FilePriceOracle {
+    rates: HashMap<String, BTreeMap<u64, f64>>,
+}
+
+impl FilePriceOracle {
+    pub fn new(records: Vec<PriceRecord>) -> Result<Self> {
+        let mut rates: HashMap<String, BTreeMap<u64, f64>> = HashMap::new();
+
+        for record in records {
+            rates
+                .entry(normalize_address(&record.currency_address)?)
+                .or_default()
+                .insert(record.timestamp, record.usd_rate);
+        }
+
+        Ok(Self { rates })
+    }
+
+    /// Loads the rates from a `.csv` or `.json` file, based on the file extension.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => Self::from_csv_str(&content),
+            Some("json") => Self::from_json_str(&content),
+            _ => Err(anyhow!("Unsupported price file format: {}", path.display())),
+        }
+    }
+
+    /// Parses a JSON array of `PriceRecord`.
+    pub fn from_json_str(content: &str) -> Result<Self> {
+        Self::new(serde_json::from_str(content)?)
+    }
+
+    /// Parses a CSV with a `currency_address,timestamp,usd_rate` header.
+    pub fn from_csv_str(content: &str) -> Result<Self> {
+        let mut lines = content.lines().filter(|l| !l.trim().is_empty());
+
+        let header: Vec<&str> = lines
+            .next()
+            .ok_or_else(|| anyhow!("Empty price file"))?
+            .split(',')
+            .map(str::trim)
+            .collect();
+
+        let column = |name: &str| {
+            header
+                .iter()
+                .position(|h| *h == name)
+                .ok_or_else(|| anyhow!("Missing column {} in price file", name))
+        };
+
+        let address_idx = column("currency_address")?;
+        let timestamp_idx = column("timestamp")?;
+        let rate_idx = column("usd_rate")?;
+
+        let mut records = vec![];
+        for (i, line) in lines.enumerate() {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let field = |idx: usize| {
+                fields
+                    .get(idx)
+                    .copied()
+                    .ok_or_else(|| anyhow!("Missing field on line {} of price file", i + 2))
+            };
+
+            records.push(PriceRecord {
+                currency_address: field(address_idx)?.to_string(),
+                timestamp: field(timestamp_idx)?.parse()?,
+                usd_rate: field(rate_idx)?.parse()?,
+            });
+        }
+
+        Self::new(records)
+    }
+}
+
+#[async_trait]
+impl PriceOracle for FilePriceOracle {
+    async fn get_usd_rate(&self, currency_address: &str, timestamp: u64) -> Result<Option<f64>> {
+        let rate = self
+            .rates
+            .get(&normalize_address(currency_address)?)
+            .and_then(|rates| rates.range(..=timestamp).next_back())
+            .map(|(_, rate)| *rate);
+
+        Ok(rate)
+    }
+}
+
+/// Addresses are compared on their padded representation,
+/// to be independent of the format used in the price file.
+fn normalize_address(address: &str) -> Result<String> {
+    Ok(to_hex_str(&FieldElement::from_hex_be(address)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV: &str = "currency_address,timestamp,usd_rate
+0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d,1000,0.5
+0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d,2000,0.75
+";
+
+    #[tokio::test]
+    async fn test_csv_rate_lookup() {
+        let oracle = FilePriceOracle::from_csv_str(CSV).unwrap();
+        let strk = "0x04718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d";
+
+        // No rate known before the first record.
+        assert_eq!(oracle.get_usd_rate(strk, 999).await.unwrap(), None);
+        assert_eq!(oracle.get_usd_rate(strk, 1000).await.unwrap(), Some(0.5));
+        assert_eq!(oracle.get_usd_rate(strk, 1999).await.unwrap(), Some(0.5));
+        assert_eq!(oracle.get_usd_rate(strk, 5000).await.unwrap(), Some(0.75));
+
+        // Unknown currency.
+        assert_eq!(oracle.get_usd_rate("0x1", 5000).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_json_rate_lookup() {
+        let json = r#"[
+            {"currency_address": "0x1234", "timestamp": 10, "usd_rate": 3000.0}
+        ]"#;
+
+        let oracle = FilePriceOracle::from_json_str(json).unwrap();
+
+        assert_eq!(
+            oracle.get_usd_rate("0x001234", 11).await.unwrap(),
+            Some(3000.0)
+        );
+    }
+
+    #[test]
+    fn test_csv_missing_column() {
+        assert!(FilePriceOracle::from_csv_str("currency_address,usd_rate\n0x1,1.0").is_err());
+    }
+}
@@ -0,0 +1,18 @@
+//! Tunables of Diri.
+use serde::{Deserialize, Serialize};
+
+/// Configuration of a Diri instance.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiriConfig {
+    /// Number of events requested by `starknet_getEvents` call.
+    pub events_chunk_size: u64,
+}
+
+impl Default for DiriConfig {
+    fn default() -> Self {
+        DiriConfig {
+            events_chunk_size: 1000,
+        }
+    }
+}
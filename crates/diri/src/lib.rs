@@ -4,6 +4,9 @@ use storage::*;
 pub mod event_handler;
 use event_handler::EventHandler;
 
+pub mod config;
+pub use config::DiriConfig;
+
 mod orderbook;
 
 use starknet::core::types::{
@@ -39,6 +42,7 @@ pub struct Diri<S: Storage, E: EventHandler> {
     provider: Arc<AnyProvider>,
     storage: Arc<S>,
     event_handler: Arc<E>,
+    config: DiriConfig,
 }
 
 impl<S: Storage, E: EventHandler> Diri<S, E> {
@@ -48,9 +52,16 @@ impl<S: Storage, E: EventHandler> Diri<S, E> {
             provider: Arc::clone(&provider),
             storage: Arc::clone(&storage),
             event_handler: Arc::clone(&event_handler),
+            config: DiriConfig::default(),
         }
     }
 
+    /// Replaces the default configuration.
+    pub fn with_config(mut self, config: DiriConfig) -> Self {
+        self.config = config;
+        self
+    }
+
     /// Indexes a range of blocks, including `from` and `to` block.
     ///
     /// # Arguments
@@ -187,13 +198,16 @@ impl<S: Storage, E: EventHandler> Diri<S, E> {
             keys,
         };
 
-        let chunk_size = 1000;
         let mut continuation_token: Option<String> = None;
 
         loop {
             let event_page = self
                 .provider
-                .get_events(filter.clone(), continuation_token, chunk_size)
+                .get_events(
+                    filter.clone(),
+                    continuation_token,
+                    self.config.events_chunk_size,
+                )
                 .await?;
 
             event_page.events.iter().for_each(|e| {
@@ -0,0 +1,210 @@
+//! Diri `Storage` kept in memory, used by the tests of the indexer.
+//!
+//! Keeps the current status of each order with the history of
+//! the orderbook events, and exposes them for inspection.
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tracing::trace;
+
+use super::types::{CancelledData, ExecutedData, FulfilledData, PlacedData, RollbackStatusData};
+use super::{Storage, StorageResult};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderStatus {
+    Open,
+    Cancelled(String),
+    Fulfilled,
+    Executed,
+}
+
+/// Orderbook event as registered, in the order of registration.
+#[derive(Debug, Clone)]
+pub enum OrderEvent {
+    Placed(PlacedData),
+    Cancelled(CancelledData),
+    Fulfilled(FulfilledData),
+    Executed(ExecutedData),
+    BackToOpen(RollbackStatusData),
+}
+
+#[derive(Debug, Clone)]
+pub struct StoredOrder {
+    /// Missing if the order was placed before the indexed range.
+    pub placed: Option<PlacedData>,
+    pub status: OrderStatus,
+    pub block_id: u64,
+    pub block_timestamp: u64,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    orders: HashMap<String, StoredOrder>,
+    events: Vec<(u64, u64, OrderEvent)>,
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    state: RwLock<MemoryState>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, MemoryState> {
+        self.state.read().expect("memory storage lock poisoned")
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, MemoryState> {
+        self.state.write().expect("memory storage lock poisoned")
+    }
+
+    pub fn order(&self, order_hash: &str) -> Option<StoredOrder> {
+        self.read().orders.get(order_hash).cloned()
+    }
+
+    pub fn orders(&self) -> Vec<StoredOrder> {
+        self.read().orders.values().cloned().collect()
+    }
+
+    /// Registered events with their block id and block timestamp.
+    pub fn events(&self) -> Vec<(u64, u64, OrderEvent)> {
+        self.read().events.clone()
+    }
+
+    pub fn clear(&self) {
+        *self.write() = MemoryState::default();
+    }
+
+    fn update_status(
+        &self,
+        block_id: u64,
+        block_timestamp: u64,
+        order_hash: &str,
+        status: OrderStatus,
+        event: OrderEvent,
+    ) {
+        let mut state = self.write();
+
+        let order = state
+            .orders
+            .entry(order_hash.to_string())
+            .or_insert_with(|| StoredOrder {
+                placed: None,
+                status: OrderStatus::Open,
+                block_id,
+                block_timestamp,
+            });
+        order.status = status;
+        order.block_id = block_id;
+        order.block_timestamp = block_timestamp;
+
+        state.events.push((block_id, block_timestamp, event));
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn register_placed(
+        &self,
+        block_id: u64,
+        block_timestamp: u64,
+        order: &PlacedData,
+    ) -> StorageResult<()> {
+        trace!("Registering placed order {}", order.order_hash);
+
+        let mut state = self.write();
+        state.orders.insert(
+            order.order_hash.clone(),
+            StoredOrder {
+                placed: Some(order.clone()),
+                status: OrderStatus::Open,
+                block_id,
+                block_timestamp,
+            },
+        );
+        state
+            .events
+            .push((block_id, block_timestamp, OrderEvent::Placed(order.clone())));
+
+        Ok(())
+    }
+
+    async fn register_cancelled(
+        &self,
+        block_id: u64,
+        block_timestamp: u64,
+        order: &CancelledData,
+    ) -> StorageResult<()> {
+        trace!("Registering cancelled order {}", order.order_hash);
+
+        self.update_status(
+            block_id,
+            block_timestamp,
+            &order.order_hash,
+            OrderStatus::Cancelled(order.reason.clone()),
+            OrderEvent::Cancelled(order.clone()),
+        );
+
+        Ok(())
+    }
+
+    async fn register_fulfilled(
+        &self,
+        block_id: u64,
+        block_timestamp: u64,
+        order: &FulfilledData,
+    ) -> StorageResult<()> {
+        trace!("Registering fulfilled order {}", order.order_hash);
+
+        self.update_status(
+            block_id,
+            block_timestamp,
+            &order.order_hash,
+            OrderStatus::Fulfilled,
+            OrderEvent::Fulfilled(order.clone()),
+        );
+
+        Ok(())
+    }
+
+    async fn register_executed(
+        &self,
+        block_id: u64,
+        block_timestamp: u64,
+        order: &ExecutedData,
+    ) -> StorageResult<()> {
+        trace!("Registering executed order {}", order.order_hash);
+
+        self.update_status(
+            block_id,
+            block_timestamp,
+            &order.order_hash,
+            OrderStatus::Executed,
+            OrderEvent::Executed(order.clone()),
+        );
+
+        Ok(())
+    }
+
+    async fn status_back_to_open(
+        &self,
+        block_id: u64,
+        block_timestamp: u64,
+        order: &RollbackStatusData,
+    ) -> StorageResult<()> {
+        trace!("Order {} back to open", order.order_hash);
+
+        self.update_status(
+            block_id,
+            block_timestamp,
+            &order.order_hash,
+            OrderStatus::Open,
+            OrderEvent::BackToOpen(order.clone()),
+        );
+
+        Ok(())
+    }
+}
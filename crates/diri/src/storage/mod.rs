@@ -1,6 +1,10 @@
 use async_trait::async_trait;
 
+#[cfg(any(test, feature = "memory"))]
+pub mod memory;
 pub mod types;
+#[cfg(any(test, feature = "memory"))]
+pub use memory::MemoryStorage;
 use types::{CancelledData, ExecutedData, FulfilledData, PlacedData, RollbackStatusData};
 
 pub type StorageResult<T> = Result<T, StorageError>;
@@ -0,0 +1,244 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(name = "pontos", author, version, about, long_about = None)]
+#[command(propagate_version = true)]
+pub struct Cli {
+    #[command(flatten)]
+    pub settings: SettingsArgs,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+/// Options overriding the values of the configuration file.
+#[derive(Args, Debug, Default)]
+pub struct SettingsArgs {
+    /// Configuration file (TOML or YAML), see the `ark-config` crate.
+    #[arg(long, global = true, env = "ARK_CONFIG", value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Starknet RPC endpoints, the first one reachable is used.
+    #[arg(
+        long = "rpc-url",
+        global = true,
+        env = "PONTOS_RPC_URLS",
+        value_delimiter = ','
+    )]
+    pub rpc_urls: Vec<String>,
+
+    #[arg(long, global = true, env = "DATABASE_URL")]
+    pub database_url: Option<String>,
+
+    #[arg(long, global = true, env = "PONTOS_CHAIN_ID")]
+    pub chain_id: Option<String>,
+
+    #[arg(long, global = true, env = "PONTOS_INDEXER_VERSION")]
+    pub indexer_version: Option<String>,
+
+    #[arg(long, global = true, env = "PONTOS_INDEXER_IDENTIFIER")]
+    pub indexer_identifier: Option<String>,
+
+    /// USD rates of the currencies (`.csv` or `.json`), to value the sales.
+    /// Without it, the volumes of the collection statistics stay at zero.
+    #[arg(long, global = true, env = "PONTOS_PRICE_FILE", value_name = "PATH")]
+    pub price_file: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Indexes a range of blocks.
+    Index {
+        /// Block number, hash, `latest` or `pending`.
+        #[arg(long)]
+        from: String,
+
+        #[arg(long, default_value = "latest")]
+        to: String,
+
+        /// Indexes again the blocks already indexed by this version.
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Indexes the new blocks as they are produced, from the last indexed block.
+    Follow {
+        /// First block to index when the database is empty.
+        #[arg(long, default_value_t = 0)]
+        from: u64,
+
+        /// Seconds between two checks of the latest block.
+        #[arg(long, default_value_t = 10)]
+        interval: u64,
+    },
+
+    /// Follows all the chains of the `chains` configuration section
+    /// concurrently, each in its own database schema.
+    FollowChains {
+        /// First block to index when the schema of a chain is empty.
+        #[arg(long, default_value_t = 0)]
+        from: u64,
+
+        /// Seconds between two checks of the latest block.
+        #[arg(long, default_value_t = 10)]
+        interval: u64,
+    },
+
+    /// Indexes the pending block.
+    Pending,
+
+    /// Indexes the events of a single contract.
+    ContractEvents {
+        #[arg(long)]
+        address: String,
+
+        #[arg(long)]
+        from: Option<String>,
+
+        #[arg(long)]
+        to: Option<String>,
+    },
+
+    /// Shows the last indexed block and the distance to the chain head.
+    Status,
+
+    /// Lists the block ranges missing or not terminated.
+    Gaps {
+        #[arg(long, default_value_t = 0)]
+        from: u64,
+
+        /// Defaults to the last indexed block.
+        #[arg(long)]
+        to: Option<u64>,
+    },
+
+    /// Indexes again the block ranges listed by `gaps`.
+    Reindex {
+        #[arg(long, default_value_t = 0)]
+        from: u64,
+
+        /// Defaults to the last indexed block.
+        #[arg(long)]
+        to: Option<u64>,
+    },
+
+    /// Indexes again only the events of some contracts or kinds, over
+    /// blocks already indexed. The other events of the blocks are kept.
+    ReindexEvents {
+        #[arg(long)]
+        from: u64,
+
+        /// Defaults to the last indexed block.
+        #[arg(long)]
+        to: Option<u64>,
+
+        /// NFT contracts of the transfers and approvals, comma separated.
+        #[arg(long = "contract", value_delimiter = ',')]
+        contracts: Vec<String>,
+
+        /// Marketplaces of the sales, comma separated. Without any
+        /// contract nor marketplace, all the contracts are reindexed.
+        #[arg(long = "marketplace", value_delimiter = ',')]
+        marketplaces: Vec<String>,
+
+        /// Kinds of events, comma separated. All the kinds by default.
+        #[arg(long = "kind", value_enum, value_delimiter = ',')]
+        kinds: Vec<EventKind>,
+    },
+
+    /// Writes the holders of collections at a block, from the indexed transfers
+    /// (ERC721) or the transfer events read on chain (ERC1155).
+    Snapshot {
+        /// ERC721 or ERC1155 collection addresses, comma separated.
+        #[arg(long = "contract", required = true, value_delimiter = ',')]
+        contracts: Vec<String>,
+
+        #[arg(long)]
+        block: u64,
+
+        #[arg(long, value_enum, default_value_t = SnapshotFormat::Csv)]
+        format: SnapshotFormat,
+
+        /// Defaults to the standard output.
+        #[arg(long)]
+        output: Option<PathBuf>,
+
+        /// Reads the owners (ERC721) or balances (ERC1155) on chain at the block.
+        #[arg(long)]
+        verify: bool,
+    },
+
+    /// Compares the stored owners of a collection with `owner_of` on chain.
+    CheckOwners {
+        #[arg(long)]
+        contract: String,
+
+        /// Defaults to the last indexed block.
+        #[arg(long)]
+        block: Option<u64>,
+
+        /// Number of tokens checked, evenly spread over the collection.
+        /// All the tokens are checked by default.
+        #[arg(long)]
+        sample: Option<usize>,
+
+        /// Sets the drifting tokens to their on-chain owner and marks the
+        /// blocks where they diverged as not indexed, for `reindex`.
+        #[arg(long)]
+        repair: bool,
+    },
+
+    /// Registers the tokens of collections minted before the indexed blocks,
+    /// with their owner at a block.
+    Backfill {
+        /// Collection addresses, comma separated.
+        #[arg(long = "contract", required = true, value_delimiter = ',')]
+        contracts: Vec<String>,
+
+        /// Defaults to the last indexed block.
+        #[arg(long)]
+        block: Option<u64>,
+
+        /// First token id probed for the collections without `token_by_index`.
+        #[arg(long, default_value_t = 0)]
+        first_token_id: u64,
+
+        /// Last token id probed, if known.
+        #[arg(long)]
+        last_token_id: Option<u64>,
+
+        /// Consecutive token ids without owner before probing stops.
+        #[arg(long, default_value_t = 100)]
+        max_missing: u64,
+    },
+}
+
+impl Command {
+    /// True if the command registers sales.
+    pub fn indexes_sales(&self) -> bool {
+        matches!(
+            self,
+            Command::Index { .. }
+                | Command::Follow { .. }
+                | Command::FollowChains { .. }
+                | Command::Pending
+                | Command::ContractEvents { .. }
+                | Command::Reindex { .. }
+                | Command::ReindexEvents { .. }
+        )
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum SnapshotFormat {
+    Csv,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum EventKind {
+    Transfer,
+    Approval,
+    Sale,
+}
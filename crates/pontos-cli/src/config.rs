@@ -0,0 +1,189 @@
+//! Settings of the CLI, read from the configuration file and
+//! the `ARK_` environment variables, and overridden by the
+//! command-line options.
+use anyhow::{anyhow, Result};
+use ark_config::ArkConfig;
+use pontos::PontosConfig;
+use std::path::PathBuf;
+
+use crate::args::SettingsArgs;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub rpc_urls: Vec<String>,
+    pub database_url: String,
+    pub chain_id: String,
+    pub events_chunk_size: u64,
+    pub pontos: PontosConfig,
+    pub chains: Vec<ChainSettings>,
+    /// Rates of the `FilePriceOracle` of the indexers.
+    pub price_file: Option<PathBuf>,
+}
+
+/// Chain of the `chains` configuration section, with the
+/// `starknet` and `pontos` sections as defaults.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainSettings {
+    pub chain_id: String,
+    pub rpc_urls: Vec<String>,
+    pub database_schema: String,
+    pub pontos: PontosConfig,
+}
+
+impl Settings {
+    pub fn from_args(args: &SettingsArgs) -> Result<Self> {
+        let config = ArkConfig::load(args.config.as_deref())?;
+        Self::merge(args, config)
+    }
+
+    /// Options given on the command line take precedence over the configuration.
+    fn merge(args: &SettingsArgs, config: ArkConfig) -> Result<Self> {
+        let rpc_urls = if args.rpc_urls.is_empty() {
+            config.starknet.rpc_urls
+        } else {
+            args.rpc_urls.clone()
+        };
+
+        if rpc_urls.is_empty() && config.chains.is_empty() {
+            return Err(anyhow!(
+                "At least one RPC url is required (--rpc-url or starknet.rpc_urls)"
+            ));
+        }
+
+        let database_url = args
+            .database_url
+            .clone()
+            .or(config.database_url)
+            .ok_or_else(|| {
+                anyhow!("The database url is required (--database-url or database_url)")
+            })?;
+
+        let chains = config
+            .chains
+            .iter()
+            .map(|chain| {
+                let rpc_urls = if chain.rpc_urls.is_empty() {
+                    rpc_urls.clone()
+                } else {
+                    chain.rpc_urls.clone()
+                };
+
+                if rpc_urls.is_empty() {
+                    return Err(anyhow!("No RPC url for chain {}", chain.chain_id));
+                }
+
+                let mut pontos = config.chain_pontos(chain);
+                override_pontos(args, &mut pontos);
+
+                Ok(ChainSettings {
+                    chain_id: chain.chain_id.clone(),
+                    rpc_urls,
+                    database_schema: chain.database_schema(),
+                    pontos,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut pontos = config.pontos;
+        override_pontos(args, &mut pontos);
+
+        Ok(Settings {
+            rpc_urls,
+            database_url,
+            chain_id: args.chain_id.clone().unwrap_or(config.starknet.chain_id),
+            events_chunk_size: config.starknet.events_chunk_size,
+            pontos,
+            chains,
+            price_file: args.price_file.clone(),
+        })
+    }
+}
+
+fn override_pontos(args: &SettingsArgs, pontos: &mut PontosConfig) {
+    if let Some(version) = &args.indexer_version {
+        pontos.indexer_version.clone_from(version);
+    }
+
+    if let Some(identifier) = &args.indexer_identifier {
+        pontos.indexer_identifier.clone_from(identifier);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_args_override_config() {
+        let config = ArkConfig::parse(
+            r#"
+            database_url = "postgres://file"
+
+            [starknet]
+            rpc_urls = ["http://node-1", "http://node-2"]
+            chain_id = "0x1"
+
+            [pontos]
+            indexer_identifier = "pontos-1"
+            "#,
+            "toml",
+        )
+        .unwrap();
+
+        let args = SettingsArgs {
+            database_url: Some("postgres://args".to_string()),
+            indexer_version: Some("1.0.0".to_string()),
+            ..Default::default()
+        };
+
+        let settings = Settings::merge(&args, config).unwrap();
+        assert_eq!(settings.rpc_urls, vec!["http://node-1", "http://node-2"]);
+        assert_eq!(settings.database_url, "postgres://args");
+        assert_eq!(settings.chain_id, "0x1");
+        assert_eq!(settings.pontos.indexer_version, "1.0.0");
+        assert_eq!(settings.pontos.indexer_identifier, "pontos-1");
+    }
+
+    #[test]
+    fn test_missing_settings() {
+        let args = SettingsArgs {
+            rpc_urls: vec!["http://node".to_string()],
+            ..Default::default()
+        };
+
+        assert!(Settings::merge(&args, ArkConfig::default()).is_err());
+    }
+
+    #[test]
+    fn test_chains() {
+        let config = ArkConfig::parse(
+            r#"
+            database_url = "postgres://file"
+
+            [starknet]
+            rpc_urls = ["http://mainnet"]
+
+            [[chains]]
+            chain_id = "SN_MAIN"
+
+            [[chains]]
+            chain_id = "SN_SEPOLIA"
+            rpc_urls = ["http://sepolia"]
+            "#,
+            "toml",
+        )
+        .unwrap();
+
+        let args = SettingsArgs {
+            indexer_identifier: Some("pontos-multi".to_string()),
+            ..Default::default()
+        };
+
+        let settings = Settings::merge(&args, config).unwrap();
+        let (main, sepolia) = (&settings.chains[0], &settings.chains[1]);
+        assert_eq!(main.rpc_urls, vec!["http://mainnet"]);
+        assert_eq!(main.database_schema, "sn_main");
+        assert_eq!(sepolia.rpc_urls, vec!["http://sepolia"]);
+        assert_eq!(sepolia.pontos.indexer_identifier, "pontos-multi");
+    }
+}
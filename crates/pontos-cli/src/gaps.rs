@@ -0,0 +1,55 @@
+use pontos::storage::types::{BlockIndexingStatus, BlockInfo};
+
+/// Ranges (included) of the blocks between `from` and `to` that are
+/// missing or not terminated, `blocks` being ordered by number.
+pub fn find_gaps(blocks: &[BlockInfo], from: u64, to: u64) -> Vec<(u64, u64)> {
+    let mut gaps = vec![];
+    let mut next = from;
+
+    let terminated = blocks
+        .iter()
+        .filter(|b| b.status == BlockIndexingStatus::Terminated)
+        .map(|b| b.block_number)
+        .filter(|n| (from..=to).contains(n));
+
+    for block_number in terminated {
+        if block_number > next {
+            gaps.push((next, block_number - 1));
+        }
+        next = block_number + 1;
+    }
+
+    if next <= to {
+        gaps.push((next, to));
+    }
+
+    gaps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(block_number: u64, status: BlockIndexingStatus) -> BlockInfo {
+        BlockInfo {
+            indexer_version: "0.1.0".to_string(),
+            indexer_identifier: "test".to_string(),
+            status,
+            block_number,
+        }
+    }
+
+    #[test]
+    fn test_find_gaps() {
+        let blocks = vec![
+            block(1, BlockIndexingStatus::Terminated),
+            block(2, BlockIndexingStatus::Terminated),
+            block(4, BlockIndexingStatus::Processing),
+            block(5, BlockIndexingStatus::Terminated),
+        ];
+
+        assert_eq!(find_gaps(&blocks, 0, 7), vec![(0, 0), (3, 4), (6, 7)]);
+        assert_eq!(find_gaps(&blocks, 1, 2), vec![]);
+        assert_eq!(find_gaps(&[], 3, 3), vec![(3, 3)]);
+    }
+}
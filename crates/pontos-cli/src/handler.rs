@@ -0,0 +1,63 @@
+use async_trait::async_trait;
+use pontos::event_handler::{BlockSkipReason, EventFailure, EventHandler};
+use pontos::storage::types::{ContractInfo, TokenEvent, TokenInfo};
+use tracing::{info, warn};
+
+/// Prints the progress of the indexation.
+pub struct ProgressHandler;
+
+#[async_trait]
+impl EventHandler for ProgressHandler {
+    async fn on_block_processing(&self, block_timestamp: u64, block_number: Option<u64>) {
+        info!(
+            "Processing block {:?} [ts: {}]",
+            block_number, block_timestamp
+        );
+    }
+
+    async fn on_block_processed(&self, block_number: u64, indexation_progress: f64) {
+        println!(
+            "Block {} indexed ({:.2}%)",
+            block_number, indexation_progress
+        );
+    }
+
+    async fn on_indexation_range_completed(&self) {
+        println!("Indexation range completed");
+    }
+
+    async fn on_token_registered(&self, token: TokenInfo) {
+        info!(
+            "Token registered {} #{}",
+            token.contract_address, token.token_id
+        );
+    }
+
+    async fn on_event_registered(&self, event: TokenEvent) {
+        info!("Event registered {:?}", event);
+    }
+
+    async fn on_contract_identified(&self, contract: ContractInfo) {
+        println!(
+            "New {} contract {} ({})",
+            contract.contract_type,
+            contract.contract_address,
+            contract.name.as_deref().unwrap_or("unnamed")
+        );
+    }
+
+    async fn on_block_skipped(&self, block_number: u64, reason: BlockSkipReason) {
+        info!("Block {} skipped: {:?}", block_number, reason);
+    }
+
+    async fn on_event_failed(&self, failure: EventFailure) {
+        warn!(
+            "{:?} event of {} failed in transaction {}: {}",
+            failure.stage, failure.contract_address, failure.transaction_hash, failure.error
+        );
+    }
+
+    async fn on_new_latest_block(&self, block_number: u64) {
+        println!("New latest block {}", block_number);
+    }
+}
@@ -0,0 +1,445 @@
+//! `pontos` command-line indexer.
+//!
+//! Indexes the NFT events of a Starknet chain into a Postgres database,
+//! with the settings read from a configuration file and the command-line options.
+use anyhow::{anyhow, Result};
+use ark_starknet::client::{StarknetClient, StarknetClientHttp};
+use ark_starknet::format::to_hex_str;
+use clap::Parser;
+use pontos::backfill::{CollectionBackfill, ProbeRange};
+use pontos::consistency::OwnershipVerifier;
+use pontos::multichain::{ChainIndexer, ChainTask, MultiChainIndexer};
+use pontos::price_oracle::{FilePriceOracle, PriceOracle};
+use pontos::snapshot::OwnershipSnapshotter;
+use pontos::storage::types::{ReindexEventKind, ReindexFilter};
+use pontos::storage::{PostgresStorage, QueryStorage};
+use pontos::Pontos;
+use starknet::core::types::{BlockId, FieldElement};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+use tracing_subscriber::EnvFilter;
+
+mod args;
+mod config;
+mod gaps;
+mod handler;
+
+use args::{Cli, Command, EventKind, SnapshotFormat};
+use config::Settings;
+use gaps::find_gaps;
+use handler::ProgressHandler;
+
+type Indexer = Pontos<PostgresStorage, StarknetClientHttp, ProgressHandler>;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv::dotenv().ok();
+
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .init();
+
+    let cli = Cli::parse();
+    let settings = Settings::from_args(&cli.settings)?;
+    let price_oracle = load_price_oracle(&settings)?;
+
+    if price_oracle.is_none() && cli.command.indexes_sales() {
+        warn!("No price file (--price-file): the sales have no USD price, and the collection volumes stay at zero");
+    }
+
+    if let Command::FollowChains { from, interval } = cli.command {
+        return follow_chains(&settings, price_oracle, from, interval).await;
+    }
+
+    let client = Arc::new(
+        connect_client(&settings.rpc_urls)
+            .await?
+            .with_events_chunk_size(settings.events_chunk_size),
+    );
+    let storage = Arc::new(PostgresStorage::new(&settings.database_url).await?);
+
+    let pontos = with_price_oracle(
+        Indexer::new(
+            Arc::clone(&client),
+            Arc::clone(&storage),
+            Arc::new(ProgressHandler),
+            settings.pontos.clone(),
+        ),
+        &price_oracle,
+    );
+
+    let chain_id = settings.chain_id.as_str();
+
+    match cli.command {
+        Command::Index { from, to, force } => {
+            let (from, to) = client.parse_block_range(&from, &to)?;
+            pontos.index_block_range(from, to, force, chain_id).await?;
+        }
+        Command::Follow { from, interval } => {
+            follow(&pontos, &client, &storage, from, interval, chain_id).await?;
+        }
+        Command::FollowChains { .. } => unreachable!("handled before connecting to the chain"),
+        Command::Pending => pontos.index_pending().await?,
+        Command::ContractEvents { address, from, to } => {
+            let address = FieldElement::from_hex_be(&address)
+                .map_err(|_| anyhow!("Invalid contract address {}", address))?;
+            let from = from.map(|b| client.parse_block_id(&b)).transpose()?;
+            let to = to.map(|b| client.parse_block_id(&b)).transpose()?;
+
+            pontos
+                .index_contract_events(from, to, address, chain_id)
+                .await?;
+        }
+        Command::Status => {
+            let latest = client.block_number().await?;
+
+            match storage.last_block_info().await? {
+                Some(block) => println!(
+                    "Last indexed block: {} ({}, version {}, {})\nLatest block: {} ({} behind)",
+                    block.block_number,
+                    block.status.to_string(),
+                    block.indexer_version,
+                    block.indexer_identifier,
+                    latest,
+                    latest.saturating_sub(block.block_number)
+                ),
+                None => println!("No block indexed\nLatest block: {}", latest),
+            }
+        }
+        Command::Gaps { from, to } => {
+            let gaps = gaps_between(&storage, from, to).await?;
+
+            if gaps.is_empty() {
+                println!("No gap");
+            }
+
+            for (start, end) in gaps {
+                println!("{} - {} ({} blocks)", start, end, end - start + 1);
+            }
+        }
+        Command::Reindex { from, to } => {
+            for (start, end) in gaps_between(&storage, from, to).await? {
+                println!("Reindexing blocks {} - {}", start, end);
+
+                let (start, end) = (BlockId::Number(start), BlockId::Number(end));
+                pontos.index_block_range(start, end, true, chain_id).await?;
+            }
+        }
+        Command::ReindexEvents {
+            from,
+            to,
+            contracts,
+            marketplaces,
+            kinds,
+        } => {
+            let to = match to {
+                Some(to) => to,
+                None => match storage.last_block_info().await? {
+                    Some(info) => info.block_number,
+                    None => return Err(anyhow!("No block indexed")),
+                },
+            };
+
+            let padded = |addresses: &[String]| {
+                addresses
+                    .iter()
+                    .map(|c| {
+                        FieldElement::from_hex_be(c)
+                            .map(|address| to_hex_str(&address))
+                            .map_err(|_| anyhow!("Invalid contract address {}", c))
+                    })
+                    .collect::<Result<Vec<_>>>()
+            };
+
+            let filter = ReindexFilter {
+                nft_contracts: padded(&contracts)?,
+                marketplaces: padded(&marketplaces)?,
+                event_kinds: kinds
+                    .into_iter()
+                    .map(|kind| match kind {
+                        EventKind::Transfer => ReindexEventKind::Transfer,
+                        EventKind::Approval => ReindexEventKind::Approval,
+                        EventKind::Sale => ReindexEventKind::Sale,
+                    })
+                    .collect(),
+            };
+
+            println!("Reindexing events of blocks {} - {}", from, to);
+            pontos.reindex_events(from, to, &filter, chain_id).await?;
+        }
+        Command::Snapshot {
+            contracts,
+            block,
+            format,
+            output,
+            verify,
+        } => {
+            let contracts = contracts
+                .iter()
+                .map(|c| {
+                    FieldElement::from_hex_be(c)
+                        .map_err(|_| anyhow!("Invalid contract address {}", c))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let snapshotter = OwnershipSnapshotter::new(storage, client, chain_id);
+            let mut snapshot = snapshotter.snapshot(&contracts, block).await?;
+
+            if verify {
+                let mismatches = snapshotter.verify_on_chain(&mut snapshot).await?;
+                info!("{} holdings differ from the chain", mismatches.len());
+            }
+
+            let content = match format {
+                SnapshotFormat::Csv => snapshot.to_csv(),
+                SnapshotFormat::Json => serde_json::to_string_pretty(&snapshot)?,
+            };
+
+            match output {
+                Some(path) => std::fs::write(&path, content)?,
+                None => print!("{}", content),
+            }
+        }
+        Command::CheckOwners {
+            contract,
+            block,
+            sample,
+            repair,
+        } => {
+            let contract = FieldElement::from_hex_be(&contract)
+                .map_err(|_| anyhow!("Invalid contract address {}", contract))?;
+            let block = match block {
+                Some(block) => block,
+                None => match storage.last_block_info().await? {
+                    Some(info) => info.block_number,
+                    None => return Err(anyhow!("No block indexed")),
+                },
+            };
+
+            let mut verifier =
+                OwnershipVerifier::new(storage, client, chain_id).with_repair(repair);
+            if let Some(sample) = sample {
+                verifier = verifier.with_sample_size(sample);
+            }
+
+            let report = verifier.verify(contract, block).await?;
+
+            for drift in &report.drifts {
+                println!(
+                    "{} {} -> {} (affected block {}){}",
+                    drift.token_id,
+                    drift.stored_owner,
+                    drift.on_chain_owner.as_deref().unwrap_or("none"),
+                    drift
+                        .affected_block
+                        .map_or("unknown".to_string(), |b| b.to_string()),
+                    if drift.repaired { ", repaired" } else { "" }
+                );
+            }
+
+            println!(
+                "{} of {} checked tokens ({} stored) drift from the chain at block {} ({:.2}%)",
+                report.drifts.len(),
+                report.checked_tokens,
+                report.total_tokens,
+                report.block_number,
+                report.drift_ratio() * 100.0
+            );
+
+            if !report.queued_blocks.is_empty() {
+                println!("Blocks queued for `reindex`: {:?}", report.queued_blocks);
+            }
+        }
+        Command::Backfill {
+            contracts,
+            block,
+            first_token_id,
+            last_token_id,
+            max_missing,
+        } => {
+            let block = match block {
+                Some(block) => block,
+                None => match storage.last_block_info().await? {
+                    Some(info) => info.block_number,
+                    None => return Err(anyhow!("No block indexed")),
+                },
+            };
+
+            let backfill =
+                CollectionBackfill::new(storage, client, chain_id).with_probe_range(ProbeRange {
+                    first_token_id,
+                    last_token_id,
+                    max_missing,
+                });
+
+            for contract in contracts {
+                let address = FieldElement::from_hex_be(&contract)
+                    .map_err(|_| anyhow!("Invalid contract address {}", contract))?;
+                let report = backfill.backfill(address, block).await?;
+
+                println!(
+                    "{}: {} tokens at block {} ({:?})",
+                    report.contract_address, report.tokens, report.block_number, report.method
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns a client on the first RPC url answering.
+async fn connect_client(rpc_urls: &[String]) -> Result<StarknetClientHttp> {
+    for url in rpc_urls {
+        let client = match StarknetClientHttp::new(url) {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("Invalid RPC url {}: {}", url, e);
+                continue;
+            }
+        };
+
+        match client.block_number().await {
+            Ok(block_number) => {
+                info!("Connected to {} (latest block {})", url, block_number);
+                return Ok(client);
+            }
+            Err(e) => warn!("RPC {} unavailable: {}", url, e),
+        }
+    }
+
+    Err(anyhow!("None of the RPC urls is reachable"))
+}
+
+async fn follow(
+    pontos: &Indexer,
+    client: &StarknetClientHttp,
+    storage: &PostgresStorage,
+    from: u64,
+    interval: u64,
+    chain_id: &str,
+) -> Result<()> {
+    loop {
+        let latest = client.block_number().await?;
+        let next = match storage.last_block_info().await? {
+            Some(block) => from.max(block.block_number + 1),
+            None => from,
+        };
+
+        if next <= latest {
+            pontos
+                .index_block_range(
+                    BlockId::Number(next),
+                    BlockId::Number(latest),
+                    false,
+                    chain_id,
+                )
+                .await?;
+        }
+
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+/// Oracle of the USD rates of the price file, if any.
+fn load_price_oracle(settings: &Settings) -> Result<Option<Arc<dyn PriceOracle + Send + Sync>>> {
+    match &settings.price_file {
+        Some(path) => {
+            let oracle: Arc<dyn PriceOracle + Send + Sync> = Arc::new(
+                FilePriceOracle::from_file(path)
+                    .map_err(|e| anyhow!("Invalid price file {}: {}", path.display(), e))?,
+            );
+            Ok(Some(oracle))
+        }
+        None => Ok(None),
+    }
+}
+
+fn with_price_oracle(
+    pontos: Indexer,
+    price_oracle: &Option<Arc<dyn PriceOracle + Send + Sync>>,
+) -> Indexer {
+    match price_oracle {
+        Some(oracle) => pontos.with_price_oracle(Arc::clone(oracle)),
+        None => pontos,
+    }
+}
+
+/// Follows every configured chain concurrently, each with its own
+/// client and Postgres schema. A chain failing doesn't stop the others.
+async fn follow_chains(
+    settings: &Settings,
+    price_oracle: Option<Arc<dyn PriceOracle + Send + Sync>>,
+    from: u64,
+    interval: u64,
+) -> Result<()> {
+    if settings.chains.is_empty() {
+        return Err(anyhow!("No chain configured in the `chains` section"));
+    }
+
+    let mut indexer = MultiChainIndexer::new();
+
+    for chain in &settings.chains {
+        let client = Arc::new(
+            connect_client(&chain.rpc_urls)
+                .await?
+                .with_events_chunk_size(settings.events_chunk_size),
+        );
+        let storage = Arc::new(
+            PostgresStorage::new_in_schema(&settings.database_url, &chain.database_schema).await?,
+        );
+
+        let start_block = match storage.last_block_info().await? {
+            Some(block) => from.max(block.block_number + 1),
+            None => from,
+        };
+
+        info!(
+            "Chain {} in schema {}, starting at block {}",
+            chain.chain_id, chain.database_schema, start_block
+        );
+
+        let pontos = with_price_oracle(
+            Indexer::new(
+                client,
+                storage,
+                Arc::new(ProgressHandler),
+                chain.pontos.clone(),
+            ),
+            &price_oracle,
+        );
+
+        indexer
+            .add_chain(ChainIndexer::new(&chain.chain_id, pontos).with_start_block(start_block))?;
+    }
+
+    let task = ChainTask::Follow {
+        interval: Duration::from_secs(interval),
+    };
+
+    for (chain_id, result) in indexer.run(&task).await {
+        if let Err(e) = result {
+            return Err(anyhow!("Chain {} stopped: {}", chain_id, e));
+        }
+    }
+
+    Ok(())
+}
+
+async fn gaps_between(
+    storage: &PostgresStorage,
+    from: u64,
+    to: Option<u64>,
+) -> Result<Vec<(u64, u64)>> {
+    let to = match to {
+        Some(to) => to,
+        None => match storage.last_block_info().await? {
+            Some(block) => block.block_number,
+            None => return Ok(vec![]),
+        },
+    };
+
+    let blocks = storage.blocks_info(from, to).await?;
+    Ok(find_gaps(&blocks, from, to))
+}
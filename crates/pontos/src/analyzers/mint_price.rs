@@ -0,0 +1,86 @@
+use super::{
+    group_by, sum_payments, MintPriceInfo, TransactionAnalyzer, TransactionEvents,
+    TransactionRecord,
+};
+use crate::storage::types::{EventType, TokenTransferEvent};
+use ark_starknet::format::to_hex_str;
+use num_bigint::BigUint;
+
+/// Computes the price paid for each token minted in the transaction,
+/// from the ERC20 transfers made by the minter.
+/// The paid amount is split evenly between all the tokens received
+/// by the minter in the transaction.
+pub struct MintPriceAnalyzer;
+
+impl TransactionAnalyzer for MintPriceAnalyzer {
+    fn analyze(&self, tx: &TransactionEvents) -> Vec<TransactionRecord> {
+        let mints: Vec<TokenTransferEvent> = tx
+            .transfers
+            .iter()
+            .filter(|t| t.event_type == EventType::Mint)
+            .cloned()
+            .collect();
+
+        let mut records = vec![];
+
+        for (minter, minted) in group_by(&mints, |t| t.to_address.clone()) {
+            let (currency_address, total) = match sum_payments(&tx.payments_from(&minter)) {
+                Some(sum) => sum,
+                None => continue,
+            };
+
+            let price = total / BigUint::from(minted.len());
+
+            for mint in minted {
+                records.push(TransactionRecord::MintPrice(MintPriceInfo {
+                    transaction_hash: tx.transaction_hash.clone(),
+                    contract_address: mint.contract_address.clone(),
+                    token_id: mint.token_id.clone(),
+                    token_id_hex: mint.token_id_hex.clone(),
+                    minter_address: minter.clone(),
+                    currency_address: currency_address.clone(),
+                    price: to_hex_str(&price),
+                    chain_id: tx.chain_id.clone(),
+                }));
+            }
+        }
+
+        records
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::*;
+    use super::*;
+
+    #[test]
+    fn test_free_mint() {
+        let mut tx = tx();
+        tx.transfers.push(transfer(0, 2, 7, 1));
+
+        assert!(MintPriceAnalyzer.analyze(&tx).is_empty());
+    }
+
+    #[test]
+    fn test_mint_price_is_split() {
+        let mut tx = tx();
+        tx.transfers.push(transfer(0, 2, 7, 1));
+        tx.transfers.push(transfer(0, 2, 7, 2));
+        tx.transfers.push(transfer(0, 2, 7, 3));
+        tx.add_payment(payment(2, 7, 9, 300));
+
+        let records = MintPriceAnalyzer.analyze(&tx);
+        assert_eq!(records.len(), 3);
+
+        match &records[1] {
+            TransactionRecord::MintPrice(info) => {
+                assert_eq!(info.minter_address, addr(2));
+                assert_eq!(info.token_id, "2");
+                assert_eq!(info.currency_address, addr(9));
+                assert_eq!(info.price, amount(100));
+            }
+            r => panic!("Expected a mint price, got {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,316 @@
+//! Transaction level analysis of the indexed events.
+//!
+//! Events are processed one by one by the managers, without any
+//! knowledge of the other events emitted by the same transaction.
+//! Once all the events of a transaction are processed, the analyzers
+//! receive them together to detect patterns that are only visible at
+//! transaction level (sweeps, sales on unknown marketplaces, mint prices...).
+pub mod mint_price;
+pub use mint_price::MintPriceAnalyzer;
+
+pub mod sweep;
+pub use sweep::SweepAnalyzer;
+
+pub mod transfer_payment;
+pub use transfer_payment::TransferPaymentSaleAnalyzer;
+
+use crate::storage::types::{TokenSaleEvent, TokenTransferEvent};
+use ark_starknet::CairoU256;
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Address receiving the transaction fees on Starknet. The fee transfer
+/// is emitted as a regular ERC20 `Transfer`, which must not be
+/// mistaken for a payment.
+pub const SEQUENCER_ADDRESS_HEX: &str =
+    "0x01176a1bd84444c89232ec27754698e5d2e7e1a7f1539f12027f28b23ec9f3d8";
+
+/// An ERC20 transfer, seen as a potential payment.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CurrencyTransferEvent {
+    pub currency_address: String,
+    pub from_address: String,
+    pub to_address: String,
+    /// Hexadecimal representation of the u256 amount.
+    pub amount: String,
+    pub transaction_hash: String,
+}
+
+impl CurrencyTransferEvent {
+    pub fn amount_biguint(&self) -> Option<BigUint> {
+        CairoU256::from_hex_be(&self.amount)
+            .ok()
+            .map(|a| a.to_biguint())
+    }
+}
+
+/// All the events registered by Pontos for a single transaction.
+#[derive(Debug, Clone, Default)]
+pub struct TransactionEvents {
+    pub transaction_hash: String,
+    pub block_timestamp: u64,
+    pub block_number: Option<u64>,
+    pub chain_id: String,
+    /// NFT transfers (including mints and burns).
+    pub transfers: Vec<TokenTransferEvent>,
+    /// Sales emitted by known marketplaces, or detected by an analyzer.
+    pub sales: Vec<TokenSaleEvent>,
+    /// ERC20 transfers, excluding the fee transfer to the sequencer.
+    pub payments: Vec<CurrencyTransferEvent>,
+}
+
+impl TransactionEvents {
+    pub fn new(
+        transaction_hash: String,
+        block_timestamp: u64,
+        block_number: Option<u64>,
+        chain_id: &str,
+    ) -> Self {
+        Self {
+            transaction_hash,
+            block_timestamp,
+            block_number,
+            chain_id: chain_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Adds a payment, ignoring the transaction fee transfer.
+    pub fn add_payment(&mut self, payment: CurrencyTransferEvent) {
+        if payment.to_address != SEQUENCER_ADDRESS_HEX {
+            self.payments.push(payment);
+        }
+    }
+
+    /// Returns the payments made by the given address.
+    pub fn payments_from(&self, address: &str) -> Vec<&CurrencyTransferEvent> {
+        self.payments
+            .iter()
+            .filter(|p| p.from_address == address)
+            .collect()
+    }
+}
+
+/// A sweep, several NFTs bought by the same buyer in one transaction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SweepInfo {
+    pub transaction_hash: String,
+    pub buyer_address: String,
+    pub collections: Vec<String>,
+    /// Ids of the sales events composing the sweep.
+    pub sale_event_ids: Vec<String>,
+    pub block_timestamp: u64,
+    pub block_number: Option<u64>,
+    pub chain_id: String,
+}
+
+/// The price paid by a minter for one token.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MintPriceInfo {
+    pub transaction_hash: String,
+    pub contract_address: String,
+    pub token_id: String,
+    pub token_id_hex: String,
+    pub minter_address: String,
+    pub currency_address: String,
+    /// Hexadecimal representation of the u256 price paid for this token.
+    pub price: String,
+    pub chain_id: String,
+}
+
+/// Enriched records produced by the analyzers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TransactionRecord {
+    /// A sale detected from the transfers and payments of the transaction,
+    /// without any marketplace event.
+    Sale(TokenSaleEvent),
+    Sweep(SweepInfo),
+    MintPrice(MintPriceInfo),
+}
+
+/// A trait to be implemented to analyze all the events of a transaction.
+pub trait TransactionAnalyzer {
+    fn analyze(&self, tx: &TransactionEvents) -> Vec<TransactionRecord>;
+}
+
+/// Runs the analyzers in order on each transaction.
+pub struct TransactionAnalyzers {
+    analyzers: Vec<Box<dyn TransactionAnalyzer + Send + Sync>>,
+}
+
+impl TransactionAnalyzers {
+    pub fn new(analyzers: Vec<Box<dyn TransactionAnalyzer + Send + Sync>>) -> Self {
+        Self { analyzers }
+    }
+
+    pub fn push(&mut self, analyzer: Box<dyn TransactionAnalyzer + Send + Sync>) {
+        self.analyzers.push(analyzer);
+    }
+
+    /// Runs all the analyzers. Sales detected by an analyzer are added
+    /// to the transaction sales, and are then visible to the next analyzers.
+    pub fn analyze(&self, tx: &mut TransactionEvents) -> Vec<TransactionRecord> {
+        let mut records = vec![];
+
+        for analyzer in &self.analyzers {
+            for record in analyzer.analyze(tx) {
+                if let TransactionRecord::Sale(sale) = &record {
+                    tx.sales.push(sale.clone());
+                }
+
+                records.push(record);
+            }
+        }
+
+        records
+    }
+}
+
+impl Default for TransactionAnalyzers {
+    fn default() -> Self {
+        Self::new(vec![
+            Box::new(TransferPaymentSaleAnalyzer),
+            Box::new(SweepAnalyzer),
+            Box::new(MintPriceAnalyzer),
+        ])
+    }
+}
+
+/// Sums the given payments made in the currency of the first payment.
+/// Returns the currency and the total amount.
+pub(crate) fn sum_payments(payments: &[&CurrencyTransferEvent]) -> Option<(String, BigUint)> {
+    let currency = payments.first()?.currency_address.clone();
+
+    let total = payments
+        .iter()
+        .filter(|p| p.currency_address == currency)
+        .filter_map(|p| p.amount_biguint())
+        .fold(BigUint::default(), |acc, a| acc + a);
+
+    Some((currency, total))
+}
+
+/// Groups the values by key, keeping the order of first appearance of the keys.
+pub(crate) fn group_by<'a, T, F>(values: &'a [T], key: F) -> Vec<(String, Vec<&'a T>)>
+where
+    F: Fn(&T) -> String,
+{
+    let mut groups: Vec<(String, Vec<&T>)> = vec![];
+    let mut indexes: HashMap<String, usize> = HashMap::new();
+
+    for value in values {
+        let k = key(value);
+        match indexes.get(&k) {
+            Some(i) => groups[*i].1.push(value),
+            None => {
+                indexes.insert(k.clone(), groups.len());
+                groups.push((k, vec![value]));
+            }
+        }
+    }
+
+    groups
+}
+
+#[cfg(test)]
+pub(crate) mod test_utils {
+    use super::*;
+    use crate::storage::types::EventType;
+    use ark_starknet::format::to_hex_str;
+    use starknet::core::types::FieldElement;
+
+    pub const TX_HASH: &str = "0x0abc";
+
+    pub fn addr(value: u64) -> String {
+        to_hex_str(&FieldElement::from(value))
+    }
+
+    pub fn amount(value: u128) -> String {
+        CairoU256 {
+            low: value,
+            high: 0,
+        }
+        .to_hex()
+    }
+
+    pub fn transfer(from: u64, to: u64, contract: u64, token_id: u128) -> TokenTransferEvent {
+        TokenTransferEvent {
+            from_address: addr(from),
+            to_address: addr(to),
+            contract_address: addr(contract),
+            contract_type: "ERC721".to_string(),
+            transaction_hash: TX_HASH.to_string(),
+            token_id: token_id.to_string(),
+            token_id_hex: CairoU256 {
+                low: token_id,
+                high: 0,
+            }
+            .to_hex(),
+            event_type: if from == 0 {
+                EventType::Mint
+            } else {
+                EventType::Transfer
+            },
+            event_id: to_hex_str(&FieldElement::from(token_id)),
+            ..Default::default()
+        }
+    }
+
+    pub fn payment(from: u64, to: u64, currency: u64, value: u128) -> CurrencyTransferEvent {
+        CurrencyTransferEvent {
+            currency_address: addr(currency),
+            from_address: addr(from),
+            to_address: addr(to),
+            amount: amount(value),
+            transaction_hash: TX_HASH.to_string(),
+        }
+    }
+
+    pub fn tx() -> TransactionEvents {
+        TransactionEvents::new(TX_HASH.to_string(), 1000, Some(10), "SN_MAIN")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_utils::*;
+    use super::*;
+
+    #[test]
+    fn test_fee_transfer_is_not_a_payment() {
+        let mut tx = tx();
+
+        let mut fee = payment(1, 0, 9, 10);
+        fee.to_address = SEQUENCER_ADDRESS_HEX.to_string();
+        tx.add_payment(fee);
+        tx.add_payment(payment(1, 2, 9, 100));
+
+        assert_eq!(tx.payments.len(), 1);
+        assert_eq!(tx.payments_from(&addr(1)).len(), 1);
+    }
+
+    #[test]
+    fn test_detected_sales_are_visible_to_next_analyzers() {
+        let mut tx = tx();
+        tx.transfers.push(transfer(1, 2, 7, 1));
+        tx.transfers.push(transfer(3, 2, 7, 2));
+        tx.add_payment(payment(2, 1, 9, 100));
+        tx.add_payment(payment(2, 3, 9, 200));
+
+        let records = TransactionAnalyzers::default().analyze(&mut tx);
+
+        let sales = records
+            .iter()
+            .filter(|r| matches!(r, TransactionRecord::Sale(_)))
+            .count();
+        let sweeps = records
+            .iter()
+            .filter(|r| matches!(r, TransactionRecord::Sweep(_)))
+            .count();
+
+        assert_eq!(sales, 2);
+        assert_eq!(sweeps, 1);
+        assert_eq!(tx.sales.len(), 2);
+    }
+}
@@ -0,0 +1,81 @@
+use super::{group_by, SweepInfo, TransactionAnalyzer, TransactionEvents, TransactionRecord};
+
+/// Detects the transactions where the same buyer bought
+/// at least two NFTs.
+pub struct SweepAnalyzer;
+
+impl TransactionAnalyzer for SweepAnalyzer {
+    fn analyze(&self, tx: &TransactionEvents) -> Vec<TransactionRecord> {
+        group_by(&tx.sales, |s| s.to_address.clone())
+            .into_iter()
+            .filter(|(_, sales)| sales.len() > 1)
+            .map(|(buyer_address, sales)| {
+                let mut collections: Vec<String> = vec![];
+                for sale in &sales {
+                    if !collections.contains(&sale.nft_contract_address) {
+                        collections.push(sale.nft_contract_address.clone());
+                    }
+                }
+
+                TransactionRecord::Sweep(SweepInfo {
+                    transaction_hash: tx.transaction_hash.clone(),
+                    buyer_address,
+                    collections,
+                    sale_event_ids: sales.iter().map(|s| s.event_id.clone()).collect(),
+                    block_timestamp: tx.block_timestamp,
+                    block_number: tx.block_number,
+                    chain_id: tx.chain_id.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::*;
+    use super::*;
+    use crate::analyzers::TransferPaymentSaleAnalyzer;
+
+    #[test]
+    fn test_single_sale_is_not_a_sweep() {
+        let mut tx = tx();
+        tx.transfers.push(transfer(1, 2, 7, 1));
+        tx.add_payment(payment(2, 1, 9, 100));
+
+        for record in TransferPaymentSaleAnalyzer.analyze(&tx) {
+            if let TransactionRecord::Sale(sale) = record {
+                tx.sales.push(sale);
+            }
+        }
+
+        assert_eq!(tx.sales.len(), 1);
+        assert!(SweepAnalyzer.analyze(&tx).is_empty());
+    }
+
+    #[test]
+    fn test_sweep_over_two_collections() {
+        let mut tx = tx();
+        tx.transfers.push(transfer(1, 2, 7, 1));
+        tx.transfers.push(transfer(1, 2, 8, 1));
+        tx.add_payment(payment(2, 1, 9, 100));
+
+        for record in TransferPaymentSaleAnalyzer.analyze(&tx) {
+            if let TransactionRecord::Sale(sale) = record {
+                tx.sales.push(sale);
+            }
+        }
+
+        let records = SweepAnalyzer.analyze(&tx);
+        assert_eq!(records.len(), 1);
+
+        match &records[0] {
+            TransactionRecord::Sweep(sweep) => {
+                assert_eq!(sweep.buyer_address, addr(2));
+                assert_eq!(sweep.collections, vec![addr(7), addr(8)]);
+                assert_eq!(sweep.sale_event_ids.len(), 2);
+            }
+            r => panic!("Expected a sweep, got {:?}", r),
+        }
+    }
+}
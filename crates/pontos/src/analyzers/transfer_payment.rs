@@ -0,0 +1,195 @@
+use super::{group_by, sum_payments, TransactionAnalyzer, TransactionEvents, TransactionRecord};
+use crate::storage::types::{EventType, TokenSaleEvent, TokenTransferEvent};
+use ark_starknet::format::to_hex_str;
+use num_bigint::BigUint;
+use starknet::core::types::FieldElement;
+use starknet::core::utils::starknet_keccak;
+
+const UNKNOWN_MARKETPLACE_NAME: &str = "Unknown";
+
+/// Detects sales made outside of the known marketplaces: a NFT transfer
+/// where the receiver pays the sender with an ERC20 in the same transaction.
+///
+/// If the buyer pays directly the seller, only those payments are considered
+/// as the price. Otherwise (escrow, fees paid to a third party...), all the
+/// payments of the buyer are considered, but only if there is a single seller.
+/// The price is split evenly between the tokens bought from the same seller.
+pub struct TransferPaymentSaleAnalyzer;
+
+impl TransactionAnalyzer for TransferPaymentSaleAnalyzer {
+    fn analyze(&self, tx: &TransactionEvents) -> Vec<TransactionRecord> {
+        // A marketplace already gave us the sales of this transaction.
+        if !tx.sales.is_empty() {
+            return vec![];
+        }
+
+        let transfers: Vec<TokenTransferEvent> = tx
+            .transfers
+            .iter()
+            .filter(|t| t.event_type == EventType::Transfer)
+            .cloned()
+            .collect();
+
+        let mut records = vec![];
+
+        for (buyer, bought) in group_by(&transfers, |t| t.to_address.clone()) {
+            let buyer_payments = tx.payments_from(&buyer);
+            if buyer_payments.is_empty() {
+                continue;
+            }
+
+            let sellers = group_by(&bought, |t| t.from_address.clone());
+            let is_single_seller = sellers.len() == 1;
+
+            for (seller, seller_transfers) in sellers {
+                let direct_payments: Vec<_> = buyer_payments
+                    .iter()
+                    .filter(|p| p.to_address == seller)
+                    .copied()
+                    .collect();
+
+                let payments = if !direct_payments.is_empty() {
+                    direct_payments
+                } else if is_single_seller {
+                    buyer_payments.clone()
+                } else {
+                    continue;
+                };
+
+                let (currency_address, total) = match sum_payments(&payments) {
+                    Some(sum) => sum,
+                    None => continue,
+                };
+
+                let price = total / BigUint::from(seller_transfers.len());
+
+                for transfer in seller_transfers {
+                    records.push(TransactionRecord::Sale(Self::to_sale(
+                        tx,
+                        transfer,
+                        &currency_address,
+                        &price,
+                    )));
+                }
+            }
+        }
+
+        records
+    }
+}
+
+impl TransferPaymentSaleAnalyzer {
+    fn to_sale(
+        tx: &TransactionEvents,
+        transfer: &TokenTransferEvent,
+        currency_address: &str,
+        price: &BigUint,
+    ) -> TokenSaleEvent {
+        TokenSaleEvent {
+            event_id: Self::get_sale_event_id(&transfer.event_id),
+            event_type: EventType::Sale,
+            block_number: transfer.block_number,
+            from_address: transfer.from_address.clone(),
+            to_address: transfer.to_address.clone(),
+            nft_contract_address: transfer.contract_address.clone(),
+            nft_type: Some(transfer.contract_type.clone()),
+            transaction_hash: tx.transaction_hash.clone(),
+            token_id_hex: transfer.token_id_hex.clone(),
+            token_id: transfer.token_id.clone(),
+            timestamp: tx.block_timestamp,
+            updated_at: transfer.updated_at,
+            quantity: 1,
+            currency_address: Some(currency_address.to_string()),
+            marketplace_contract_address: to_hex_str(&FieldElement::ZERO),
+            marketplace_name: UNKNOWN_MARKETPLACE_NAME.to_string(),
+            price: to_hex_str(price),
+            currency_symbol: None,
+            currency_decimals: None,
+            normalized_price: None,
+            price_usd: None,
+            chain_id: tx.chain_id.clone(),
+            wash_trade_flags: vec![],
+        }
+    }
+
+    /// The sale id is derived from the transfer id, to be different
+    /// but still recomputable from the transfer.
+    fn get_sale_event_id(transfer_event_id: &str) -> String {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(
+            &FieldElement::from_hex_be(transfer_event_id)
+                .unwrap_or(FieldElement::ZERO)
+                .to_bytes_be(),
+        );
+        bytes.extend_from_slice(b"sale");
+        to_hex_str(&starknet_keccak(&bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::*;
+    use super::*;
+
+    fn sales(records: Vec<TransactionRecord>) -> Vec<TokenSaleEvent> {
+        records
+            .into_iter()
+            .filter_map(|r| match r {
+                TransactionRecord::Sale(s) => Some(s),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_transfer_without_payment_is_not_a_sale() {
+        let mut tx = tx();
+        tx.transfers.push(transfer(1, 2, 7, 1));
+        tx.add_payment(payment(3, 1, 9, 100));
+
+        assert!(TransferPaymentSaleAnalyzer.analyze(&tx).is_empty());
+    }
+
+    #[test]
+    fn test_bundle_price_is_split() {
+        let mut tx = tx();
+        tx.transfers.push(transfer(1, 2, 7, 1));
+        tx.transfers.push(transfer(1, 2, 7, 2));
+        // Payment to the seller, and fees to a third party.
+        tx.add_payment(payment(2, 1, 9, 90));
+        tx.add_payment(payment(2, 5, 9, 10));
+
+        let sales = sales(TransferPaymentSaleAnalyzer.analyze(&tx));
+
+        assert_eq!(sales.len(), 2);
+        assert_eq!(sales[0].price, amount(45));
+        assert_eq!(sales[0].from_address, addr(1));
+        assert_eq!(sales[0].to_address, addr(2));
+        assert_eq!(sales[0].currency_address, Some(addr(9)));
+        assert_eq!(sales[0].marketplace_name, UNKNOWN_MARKETPLACE_NAME);
+        assert_ne!(sales[0].event_id, sales[1].event_id);
+    }
+
+    #[test]
+    fn test_escrow_payment_with_single_seller() {
+        let mut tx = tx();
+        tx.transfers.push(transfer(1, 2, 7, 1));
+        // Buyer pays an escrow contract, which pays the seller.
+        tx.add_payment(payment(2, 5, 9, 100));
+        tx.add_payment(payment(5, 1, 9, 95));
+
+        let sales = sales(TransferPaymentSaleAnalyzer.analyze(&tx));
+
+        assert_eq!(sales.len(), 1);
+        assert_eq!(sales[0].price, amount(100));
+    }
+
+    #[test]
+    fn test_mints_are_ignored() {
+        let mut tx = tx();
+        tx.transfers.push(transfer(0, 2, 7, 1));
+        tx.add_payment(payment(2, 7, 9, 100));
+
+        assert!(TransferPaymentSaleAnalyzer.analyze(&tx).is_empty());
+    }
+}
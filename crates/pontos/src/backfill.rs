@@ -0,0 +1,472 @@
+//! Registration of the tokens minted before the first indexed block.
+//!
+//! When Pontos starts indexing from a recent block, the tokens minted
+//! earlier are only registered once transferred. A backfill finds them
+//! on chain with their owner at a given block, using the ERC721
+//! Enumerable extension when the collection implements it, or by
+//! probing `owner_of` over the token ids otherwise.
+use crate::managers::TokenManager;
+use crate::snapshot::u256_from_felts;
+use crate::storage::Storage;
+use crate::{IndexerError, IndexerResult};
+use ark_starknet::client::StarknetClient;
+use ark_starknet::format::to_hex_str;
+use ark_starknet::CairoU256;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use starknet::core::types::{BlockId, FieldElement};
+use starknet::core::utils::get_selector_from_name;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Contract calls in flight while enumerating a collection.
+const CONCURRENT_CALLS: usize = 16;
+
+/// Retries of a contract call failed for another reason than a revert.
+const DEFAULT_CALL_RETRIES: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackfillMethod {
+    /// `total_supply` and `token_by_index`.
+    Enumerable,
+    /// `owner_of` over the token ids.
+    OwnerProbing,
+}
+
+/// Token ids probed when the collection is not enumerable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProbeRange {
+    pub first_token_id: u64,
+    /// Last token id probed, if known.
+    pub last_token_id: Option<u64>,
+    /// Probing stops after this number of consecutive ids without
+    /// owner (never minted or burnt).
+    pub max_missing: u64,
+}
+
+impl Default for ProbeRange {
+    fn default() -> Self {
+        Self {
+            first_token_id: 0,
+            last_token_id: None,
+            max_missing: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackfillReport {
+    pub contract_address: String,
+    pub block_number: u64,
+    pub method: BackfillMethod,
+    /// Tokens found on chain. Those already in the storage are unchanged.
+    pub tokens: usize,
+}
+
+/// Registers the existing tokens of collections, through the `TokenManager`.
+pub struct CollectionBackfill<S: Storage, C: StarknetClient> {
+    client: Arc<C>,
+    token_manager: TokenManager<S, C>,
+    chain_id: String,
+    probe_range: ProbeRange,
+    call_retries: u32,
+    retry_delay: Duration,
+}
+
+impl<S, C> CollectionBackfill<S, C>
+where
+    S: Storage + Send + Sync,
+    C: StarknetClient + Send + Sync,
+{
+    pub fn new(storage: Arc<S>, client: Arc<C>, chain_id: &str) -> Self {
+        Self {
+            token_manager: TokenManager::new(storage, Arc::clone(&client)),
+            client,
+            chain_id: chain_id.to_string(),
+            probe_range: ProbeRange::default(),
+            call_retries: DEFAULT_CALL_RETRIES,
+            retry_delay: Duration::from_secs(1),
+        }
+    }
+
+    pub fn with_probe_range(mut self, probe_range: ProbeRange) -> Self {
+        self.probe_range = probe_range;
+        self
+    }
+
+    /// Sets how many times a call failed on the node is retried, and the
+    /// delay between the attempts, before the backfill fails.
+    pub fn with_call_retries(mut self, call_retries: u32, retry_delay: Duration) -> Self {
+        self.call_retries = call_retries;
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    /// Registers every token of the collection existing at `block_number`
+    /// with its owner at this block.
+    pub async fn backfill(
+        &self,
+        contract_address: FieldElement,
+        block_number: u64,
+    ) -> IndexerResult<BackfillReport> {
+        let block = BlockId::Number(block_number);
+        let block_timestamp = self.client.block_time(block).await?;
+
+        let total_supply = self
+            .call_optional(
+                contract_address,
+                &["total_supply", "totalSupply"],
+                vec![],
+                block,
+            )
+            .await?
+            .map(|felts| u256_from_felts(&felts))
+            .transpose()?;
+
+        let enumerable = match &total_supply {
+            Some(_) => self
+                .call_optional(
+                    contract_address,
+                    &["token_by_index", "tokenByIndex"],
+                    vec![FieldElement::ZERO, FieldElement::ZERO],
+                    block,
+                )
+                .await?
+                .is_some(),
+            None => false,
+        };
+
+        let (method, tokens) = match total_supply {
+            Some(supply) if enumerable => (
+                BackfillMethod::Enumerable,
+                self.enumerate(contract_address, supply, block).await?,
+            ),
+            _ => (
+                BackfillMethod::OwnerProbing,
+                self.probe(contract_address, total_supply, block).await?,
+            ),
+        };
+
+        for (token_id, owner) in &tokens {
+            self.token_manager
+                .register_backfilled_token(
+                    contract_address,
+                    &self.chain_id,
+                    token_id,
+                    *owner,
+                    block_number,
+                    block_timestamp,
+                )
+                .await?;
+        }
+
+        info!(
+            "{} tokens of {} backfilled at block {} ({:?})",
+            tokens.len(),
+            to_hex_str(&contract_address),
+            block_number,
+            method
+        );
+
+        Ok(BackfillReport {
+            contract_address: to_hex_str(&contract_address),
+            block_number,
+            method,
+            tokens: tokens.len(),
+        })
+    }
+
+    /// Token ids from `token_by_index`, with their owner.
+    async fn enumerate(
+        &self,
+        contract_address: FieldElement,
+        total_supply: CairoU256,
+        block: BlockId,
+    ) -> IndexerResult<Vec<(CairoU256, FieldElement)>> {
+        if total_supply.high != 0 || total_supply.low > u64::MAX as u128 {
+            return Err(IndexerError::Anyhow(format!(
+                "Total supply of {} too large to enumerate",
+                to_hex_str(&contract_address)
+            )));
+        }
+
+        let tokens: Vec<Option<(CairoU256, FieldElement)>> = stream::iter(0..total_supply.low)
+            .map(|index| async move {
+                let token_id = self
+                    .call_optional(
+                        contract_address,
+                        &["token_by_index", "tokenByIndex"],
+                        vec![FieldElement::from(index), FieldElement::ZERO],
+                        block,
+                    )
+                    .await?
+                    .map(|felts| u256_from_felts(&felts))
+                    .transpose()?;
+
+                match token_id {
+                    Some(token_id) => Ok::<_, IndexerError>(
+                        self.owner_of(contract_address, &token_id, block)
+                            .await?
+                            .map(|owner| (token_id, owner)),
+                    ),
+                    None => Ok(None),
+                }
+            })
+            .buffered(CONCURRENT_CALLS)
+            .try_collect()
+            .await?;
+
+        Ok(tokens.into_iter().flatten().collect())
+    }
+
+    /// Token ids with an owner, probed from the first id of the range
+    /// until too many are missing, or all the supply is found.
+    async fn probe(
+        &self,
+        contract_address: FieldElement,
+        total_supply: Option<CairoU256>,
+        block: BlockId,
+    ) -> IndexerResult<Vec<(CairoU256, FieldElement)>> {
+        let range = self.probe_range;
+        let supply = total_supply.map(|s| s.low);
+        let mut tokens = vec![];
+        let mut missing = 0;
+        let mut id = range.first_token_id;
+
+        while missing < range.max_missing
+            && range.last_token_id.map_or(true, |last| id <= last)
+            && supply.map_or(true, |s| (tokens.len() as u128) < s)
+        {
+            let token_id = CairoU256 {
+                low: id as u128,
+                high: 0,
+            };
+
+            match self.owner_of(contract_address, &token_id, block).await? {
+                Some(owner) => {
+                    tokens.push((token_id, owner));
+                    missing = 0;
+                }
+                None => missing += 1,
+            }
+
+            id += 1;
+        }
+
+        debug!(
+            "Probed token ids {} to {} of {}",
+            range.first_token_id,
+            id.saturating_sub(1),
+            to_hex_str(&contract_address)
+        );
+
+        Ok(tokens)
+    }
+
+    async fn owner_of(
+        &self,
+        contract_address: FieldElement,
+        token_id: &CairoU256,
+        block: BlockId,
+    ) -> IndexerResult<Option<FieldElement>> {
+        let owner = self
+            .call_optional(
+                contract_address,
+                &["owner_of", "ownerOf"],
+                vec![token_id.low.into(), token_id.high.into()],
+                block,
+            )
+            .await?
+            .and_then(|felts| felts.first().copied())
+            .filter(|owner| *owner != FieldElement::ZERO);
+
+        Ok(owner)
+    }
+
+    /// Result of the first selector found in the contract, `None` if
+    /// none is found or if the call is reverted. The other failures are
+    /// retried, as counting them as missing tokens would end the probing.
+    async fn call_optional(
+        &self,
+        contract_address: FieldElement,
+        selectors: &[&str],
+        calldata: Vec<FieldElement>,
+        block: BlockId,
+    ) -> IndexerResult<Option<Vec<FieldElement>>> {
+        for name in selectors {
+            let selector = get_selector_from_name(name)
+                .map_err(|_| IndexerError::Anyhow(format!("Invalid selector: {}", name)))?;
+
+            let mut attempt = 0;
+
+            loop {
+                match self
+                    .client
+                    .call_contract(contract_address, selector, calldata.clone(), block)
+                    .await
+                {
+                    Ok(felts) => return Ok(Some(felts)),
+                    Err(e) if e.is_entrypoint_not_found() => break,
+                    Err(e) if e.is_revert() => return Ok(None),
+                    Err(e) if attempt < self.call_retries => {
+                        warn!(
+                            "Call to {} of {} failed, retrying: {:?}",
+                            name,
+                            to_hex_str(&contract_address),
+                            e
+                        );
+                        attempt += 1;
+                        tokio::time::sleep(self.retry_delay).await;
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use ark_starknet::client::{MockStarknetClient, StarknetClientError};
+    use starknet::macros::selector;
+    use starknet::providers::ProviderError;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn small(felt: FieldElement) -> usize {
+        let bytes = felt.to_bytes_be();
+        u64::from_be_bytes(bytes[24..].try_into().unwrap()) as usize
+    }
+
+    /// Tokens 1, 2 and 5 exist, owned by 0xa.
+    fn client(enumerable: bool) -> MockStarknetClient {
+        let mut client = MockStarknetClient::default();
+        client.expect_block_time().returning(|_| Ok(1000));
+        client
+            .expect_call_contract()
+            .returning(move |_, selector, calldata, _| {
+                if selector == selector!("total_supply") {
+                    Ok(vec![FieldElement::from(3_u64), FieldElement::ZERO])
+                } else if selector == selector!("token_by_index") && enumerable {
+                    let ids = [1_u64, 2, 5];
+                    Ok(vec![
+                        FieldElement::from(ids[small(calldata[0])]),
+                        FieldElement::ZERO,
+                    ])
+                } else if selector == selector!("owner_of") {
+                    if [1, 2, 5].contains(&small(calldata[0])) {
+                        Ok(vec![FieldElement::from(0xa_u64)])
+                    } else {
+                        Err(StarknetClientError::Contract(
+                            "Invalid token id".to_string(),
+                        ))
+                    }
+                } else {
+                    Err(StarknetClientError::EntrypointNotFound(String::new()))
+                }
+            });
+        client
+    }
+
+    async fn backfill(enumerable: bool, probe_range: ProbeRange) -> (BackfillReport, Vec<u64>) {
+        let storage = Arc::new(MemoryStorage::new());
+        let backfill = CollectionBackfill::new(
+            Arc::clone(&storage),
+            Arc::new(client(enumerable)),
+            "SN_MAIN",
+        )
+        .with_probe_range(probe_range);
+
+        let report = backfill
+            .backfill(FieldElement::from(0xc0_u64), 100)
+            .await
+            .unwrap();
+
+        let mut ids: Vec<u64> = storage
+            .tokens()
+            .iter()
+            .map(|t| {
+                assert_eq!(t.backfilled_block_number, Some(100));
+                assert_eq!(t.mint, None);
+                assert_eq!(t.token.owner, to_hex_str(&FieldElement::from(0xa_u64)));
+                t.token.token_id.parse().unwrap()
+            })
+            .collect();
+        ids.sort_unstable();
+
+        (report, ids)
+    }
+
+    #[tokio::test]
+    async fn test_backfill_enumerable() {
+        let (report, ids) = backfill(true, ProbeRange::default()).await;
+
+        assert_eq!(report.method, BackfillMethod::Enumerable);
+        assert_eq!(report.tokens, 3);
+        assert_eq!(ids, vec![1, 2, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_backfill_probing() {
+        let (report, ids) = backfill(false, ProbeRange::default()).await;
+        assert_eq!(report.method, BackfillMethod::OwnerProbing);
+        assert_eq!(ids, vec![1, 2, 5]);
+
+        // Stops at the first gap of two ids.
+        let probe_range = ProbeRange {
+            max_missing: 2,
+            ..Default::default()
+        };
+        let (_, ids) = backfill(false, probe_range).await;
+        assert_eq!(ids, vec![1, 2]);
+    }
+
+    /// Probing over a node failing `failures` times on each call of token 2.
+    async fn backfill_with_node_errors(failures: u32) -> IndexerResult<BackfillReport> {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let mut client = MockStarknetClient::default();
+        client.expect_block_time().returning(|_| Ok(1000));
+        client
+            .expect_call_contract()
+            .returning(move |_, selector, calldata, _| {
+                if selector != selector!("owner_of") {
+                    Err(StarknetClientError::EntrypointNotFound(String::new()))
+                } else if small(calldata[0]) == 2
+                    && attempts.fetch_add(1, Ordering::SeqCst) < failures
+                {
+                    Err(StarknetClientError::Provider(ProviderError::RateLimited))
+                } else if [1, 2].contains(&small(calldata[0])) {
+                    Ok(vec![FieldElement::from(0xa_u64)])
+                } else {
+                    Err(StarknetClientError::Contract(
+                        "Invalid token id".to_string(),
+                    ))
+                }
+            });
+
+        CollectionBackfill::new(Arc::new(MemoryStorage::new()), Arc::new(client), "SN_MAIN")
+            .with_probe_range(ProbeRange {
+                first_token_id: 1,
+                max_missing: 1,
+                ..Default::default()
+            })
+            .with_call_retries(2, Duration::ZERO)
+            .backfill(FieldElement::from(0xc0_u64), 100)
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_backfill_node_errors() {
+        // Retried, and not counted as a missing token.
+        let report = backfill_with_node_errors(2).await.unwrap();
+        assert_eq!(report.tokens, 2);
+
+        // The backfill fails once the retries are exhausted.
+        assert!(backfill_with_node_errors(3).await.is_err());
+    }
+}
@@ -0,0 +1,101 @@
+//! Tunables of Pontos.
+use serde::{Deserialize, Serialize};
+use starknet::core::types::FieldElement;
+use std::time::Duration;
+use tracing::error;
+
+use crate::{UDC_ADDRESSES_HEX, VENTORY_MARKETPLACE_CURRENCY_HEX};
+
+/// Element and Ventory marketplaces.
+const MARKETPLACE_ADDRESSES_HEX: [&str; 2] = [
+    "0x04d8bb956e6bd7a50fcb8b49d8e9fd8269cfadbeb73f457fd6d3fc1dff4b879e",
+    "0x008755a98ccf7d25e69aa90ef3b73b07c470ba4ec6391b0b0c7c598f992c3fee",
+];
+
+/// Configuration of a Pontos instance.
+///
+/// Every field has a default value, so a configuration file
+/// only has to contain the values to change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PontosConfig {
+    pub indexer_version: String,
+    pub indexer_identifier: String,
+    /// Seconds between two polls of the pending block.
+    pub pending_poll_interval_secs: u64,
+    /// Seconds to wait before retrying a failed RPC call.
+    pub retry_delay_secs: u64,
+    /// Retries to get the timestamp of a block before skipping it.
+    pub max_block_retries: u32,
+    /// Contracts whose events are processed as marketplace sales.
+    pub marketplace_addresses: Vec<String>,
+    /// Currency of the Ventory sales, which is not part of the event.
+    pub ventory_currency_address: String,
+    /// Universal Deployer Contracts emitting `ContractDeployed`.
+    pub udc_addresses: Vec<String>,
+    /// If not empty with `allowed_class_hashes`, only the events of
+    /// these contracts, or of the contracts of these classes, are indexed.
+    pub allowed_contracts: Vec<String>,
+    pub allowed_class_hashes: Vec<String>,
+    /// Contracts whose events are never indexed, even if allowed.
+    pub denied_contracts: Vec<String>,
+    pub denied_class_hashes: Vec<String>,
+    /// Distinct recipients of the mints of a collection in a single
+    /// transaction from which the collection is scored as mass airdropped.
+    pub spam_airdrop_min_recipients: usize,
+    /// Blocks during which a sale back to the seller of a previous sale
+    /// of the collection, or a sale funded by its seller, is a wash trade.
+    pub wash_trade_window_blocks: u64,
+    /// Ratio to the median price of the recent sales of the collection
+    /// beyond which a price is flagged, above or below.
+    pub wash_trade_price_ratio: f64,
+    /// Recent sales of the collection needed before flagging prices.
+    pub wash_trade_min_price_samples: usize,
+}
+
+impl Default for PontosConfig {
+    fn default() -> Self {
+        PontosConfig {
+            indexer_version: env!("CARGO_PKG_VERSION").to_string(),
+            indexer_identifier: "pontos".to_string(),
+            pending_poll_interval_secs: 2,
+            retry_delay_secs: 1,
+            max_block_retries: 5,
+            marketplace_addresses: MARKETPLACE_ADDRESSES_HEX.map(String::from).to_vec(),
+            ventory_currency_address: VENTORY_MARKETPLACE_CURRENCY_HEX.to_string(),
+            udc_addresses: UDC_ADDRESSES_HEX.map(String::from).to_vec(),
+            allowed_contracts: vec![],
+            allowed_class_hashes: vec![],
+            denied_contracts: vec![],
+            denied_class_hashes: vec![],
+            spam_airdrop_min_recipients: 20,
+            wash_trade_window_blocks: 1000,
+            wash_trade_price_ratio: 10.0,
+            wash_trade_min_price_samples: 10,
+        }
+    }
+}
+
+impl PontosConfig {
+    pub fn pending_poll_interval(&self) -> Duration {
+        Duration::from_secs(self.pending_poll_interval_secs)
+    }
+
+    pub fn retry_delay(&self) -> Duration {
+        Duration::from_secs(self.retry_delay_secs)
+    }
+}
+
+/// Parses the addresses of the configuration, the invalid ones are ignored.
+pub(crate) fn parse_addresses(addresses: &[String]) -> Vec<FieldElement> {
+    addresses
+        .iter()
+        .filter_map(|a| match FieldElement::from_hex_be(a) {
+            Ok(address) => Some(address),
+            Err(_) => {
+                error!("Invalid address in configuration: {}", a);
+                None
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,422 @@
+//! Checks of the stored token owners against the chain.
+//!
+//! Skipped blocks and owners registered from a pending block that
+//! has changed leave tokens with an owner the chain doesn't agree
+//! with. The verifier finds them, and can repair the tokens and
+//! queue the blocks where they diverged for reindexing.
+use crate::snapshot::{felt, owner_of};
+use crate::storage::types::{
+    BlockIndexingStatus, Pagination, StorageError, TokenInfo, TokenTransferEvent,
+};
+use crate::storage::{QueryStorage, Storage};
+use crate::IndexerResult;
+use ark_starknet::client::StarknetClient;
+use ark_starknet::format::to_hex_str;
+use serde::{Deserialize, Serialize};
+use starknet::core::types::{BlockId, FieldElement};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// A token whose stored owner differs from its owner on chain.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OwnershipDrift {
+    pub contract_address: String,
+    pub token_id: String,
+    pub token_id_hex: String,
+    pub stored_owner: String,
+    /// `None` if the token doesn't exist on chain at the checked block.
+    pub on_chain_owner: Option<String>,
+    /// First block at which the chain disagrees with the stored
+    /// owner, only searched when repairing.
+    pub affected_block: Option<u64>,
+    /// The stored owner has been set to the on-chain owner.
+    pub repaired: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConsistencyReport {
+    pub chain_id: String,
+    pub contract_address: String,
+    pub block_number: u64,
+    /// Tokens of the collection in the storage.
+    pub total_tokens: usize,
+    /// Tokens compared with the chain.
+    pub checked_tokens: usize,
+    pub drifts: Vec<OwnershipDrift>,
+    /// Blocks marked as not indexed, listed by `pontos gaps`
+    /// and indexed again by `pontos reindex`.
+    pub queued_blocks: Vec<u64>,
+}
+
+impl ConsistencyReport {
+    /// Share of the checked tokens with a drift.
+    pub fn drift_ratio(&self) -> f64 {
+        if self.checked_tokens == 0 {
+            0.0
+        } else {
+            self.drifts.len() as f64 / self.checked_tokens as f64
+        }
+    }
+}
+
+/// Compares the stored owners of a collection with `owner_of`.
+///
+/// The stored owners are the current ones: the checked block must be
+/// the last indexed block, or tokens transferred after it are reported.
+pub struct OwnershipVerifier<S, C> {
+    storage: Arc<S>,
+    client: Arc<C>,
+    chain_id: String,
+    sample_size: Option<usize>,
+    repair: bool,
+}
+
+impl<S, C> OwnershipVerifier<S, C>
+where
+    S: Storage + QueryStorage + Send + Sync,
+    C: StarknetClient + Send + Sync,
+{
+    pub fn new(storage: Arc<S>, client: Arc<C>, chain_id: &str) -> Self {
+        Self {
+            storage,
+            client,
+            chain_id: chain_id.to_string(),
+            sample_size: None,
+            repair: false,
+        }
+    }
+
+    /// Checks this number of tokens evenly spread over the
+    /// collection, instead of all of them.
+    pub fn with_sample_size(mut self, sample_size: usize) -> Self {
+        self.sample_size = Some(sample_size);
+        self
+    }
+
+    /// Sets the drifting tokens to their on-chain owner, and marks
+    /// the block where they diverged as not indexed.
+    pub fn with_repair(mut self, repair: bool) -> Self {
+        self.repair = repair;
+        self
+    }
+
+    pub async fn verify(
+        &self,
+        contract_address: FieldElement,
+        block_number: u64,
+    ) -> IndexerResult<ConsistencyReport> {
+        let contract_address = to_hex_str(&contract_address);
+        let block = BlockId::Number(block_number);
+
+        let tokens = self.collection_tokens(&contract_address).await?;
+        let sample = match self.sample_size {
+            Some(size) => sample(&tokens, size),
+            None => tokens.iter().collect(),
+        };
+
+        let mut report = ConsistencyReport {
+            chain_id: self.chain_id.clone(),
+            contract_address: contract_address.clone(),
+            block_number,
+            total_tokens: tokens.len(),
+            checked_tokens: sample.len(),
+            drifts: vec![],
+            queued_blocks: vec![],
+        };
+
+        let contract = felt(&contract_address)?;
+        for token in sample {
+            let on_chain_owner =
+                owner_of(self.client.as_ref(), contract, &token.token_id_hex, block).await?;
+
+            if on_chain_owner.as_deref() == Some(token.owner.as_str()) {
+                continue;
+            }
+
+            warn!(
+                "Token {} of {}: stored owner {}, on chain {:?} at block {}",
+                token.token_id_hex, contract_address, token.owner, on_chain_owner, block_number
+            );
+
+            let mut drift = OwnershipDrift {
+                contract_address: contract_address.clone(),
+                token_id: token.token_id.clone(),
+                token_id_hex: token.token_id_hex.clone(),
+                stored_owner: token.owner.clone(),
+                on_chain_owner,
+                affected_block: None,
+                repaired: false,
+            };
+
+            if self.repair {
+                self.repair_drift(&mut drift, token, block_number).await?;
+
+                if let Some(b) = drift.affected_block {
+                    if !report.queued_blocks.contains(&b) && self.queue_block(b).await? {
+                        report.queued_blocks.push(b);
+                    }
+                }
+            }
+
+            report.drifts.push(drift);
+        }
+
+        report.queued_blocks.sort_unstable();
+        info!(
+            "{} of {} checked tokens of {} drift from the chain",
+            report.drifts.len(),
+            report.checked_tokens,
+            contract_address
+        );
+
+        Ok(report)
+    }
+
+    async fn repair_drift(
+        &self,
+        drift: &mut OwnershipDrift,
+        token: &TokenInfo,
+        block_number: u64,
+    ) -> IndexerResult<()> {
+        let last_transfer_block = self
+            .last_transfer(token)
+            .await?
+            .and_then(|t| t.block_number)
+            .unwrap_or(0)
+            .min(block_number);
+
+        drift.affected_block = Some(
+            self.first_drift_block(token, last_transfer_block, block_number)
+                .await?,
+        );
+
+        // Burnt tokens can't be removed from the storage, they are
+        // only fixed by indexing the affected block again.
+        if let Some(owner) = &drift.on_chain_owner {
+            let block_timestamp = self
+                .client
+                .block_time(BlockId::Number(block_number))
+                .await?;
+            let repaired = TokenInfo {
+                owner: owner.clone(),
+                ..token.clone()
+            };
+
+            self.storage
+                .register_token(&repaired, block_timestamp)
+                .await?;
+            drift.repaired = true;
+        }
+
+        Ok(())
+    }
+
+    /// First block between the last stored transfer of the token and
+    /// `to_block` where the owner on chain is not the stored owner,
+    /// by bisection. It is the block of the last stored transfer if
+    /// the owner was already different there.
+    async fn first_drift_block(
+        &self,
+        token: &TokenInfo,
+        from_block: u64,
+        to_block: u64,
+    ) -> IndexerResult<u64> {
+        let contract = felt(&token.contract_address)?;
+        let matches = |block_number: u64| async move {
+            owner_of(
+                self.client.as_ref(),
+                contract,
+                &token.token_id_hex,
+                BlockId::Number(block_number),
+            )
+            .await
+            .map(|owner| owner.as_deref() == Some(token.owner.as_str()))
+        };
+
+        if !matches(from_block).await? {
+            return Ok(from_block);
+        }
+
+        // The stored owner holds the token at `low`, not at `high`.
+        let (mut low, mut high) = (from_block, to_block);
+        while high - low > 1 {
+            let middle = low + (high - low) / 2;
+            if matches(middle).await? {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+
+        Ok(high)
+    }
+
+    /// Marks an indexed block as not terminated. Returns false
+    /// if the block has never been indexed, being already a gap.
+    async fn queue_block(&self, block_number: u64) -> IndexerResult<bool> {
+        let mut info = match self.storage.get_block_info(block_number).await {
+            Ok(info) => info,
+            Err(StorageError::NotFound(_)) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+
+        let block_timestamp = self
+            .client
+            .block_time(BlockId::Number(block_number))
+            .await?;
+        info.status = BlockIndexingStatus::None;
+
+        self.storage
+            .set_block_info(block_number, block_timestamp, info)
+            .await?;
+        Ok(true)
+    }
+
+    async fn last_transfer(&self, token: &TokenInfo) -> IndexerResult<Option<TokenTransferEvent>> {
+        let page = self
+            .storage
+            .token_history(
+                &token.contract_address,
+                &self.chain_id,
+                &token.token_id_hex,
+                Pagination::new(0, 1),
+            )
+            .await?;
+
+        Ok(page.items.into_iter().next())
+    }
+
+    async fn collection_tokens(&self, contract_address: &str) -> IndexerResult<Vec<TokenInfo>> {
+        let mut tokens = vec![];
+        let mut pagination = Pagination::new(0, Pagination::MAX_LIMIT);
+
+        loop {
+            let page = self
+                .storage
+                .collection_tokens(contract_address, &self.chain_id, pagination)
+                .await?;
+
+            tokens.extend(page.items);
+
+            match page.next_offset {
+                Some(offset) => pagination.offset = offset,
+                None => return Ok(tokens),
+            }
+        }
+    }
+}
+
+/// `size` items evenly spread over `items`.
+fn sample<T>(items: &[T], size: usize) -> Vec<&T> {
+    if size >= items.len() {
+        return items.iter().collect();
+    }
+
+    (0..size).map(|i| &items[i * items.len() / size]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::types::{BlockInfo, EventType};
+    use crate::storage::MemoryStorage;
+    use ark_starknet::client::MockStarknetClient;
+
+    fn address(value: u64) -> String {
+        to_hex_str(&FieldElement::from(value))
+    }
+
+    fn token(token_id: u64, owner: u64) -> TokenInfo {
+        TokenInfo {
+            contract_address: address(0xc0),
+            chain_id: "SN_MAIN".to_string(),
+            token_id: token_id.to_string(),
+            token_id_hex: address(token_id),
+            owner: address(owner),
+        }
+    }
+
+    #[test]
+    fn test_sample() {
+        let items: Vec<u32> = (0..10).collect();
+        assert_eq!(sample(&items, 3), vec![&0, &3, &6]);
+        assert_eq!(sample(&items, 20).len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_repairs_drift() {
+        let storage = Arc::new(MemoryStorage::new());
+        storage.register_token(&token(1, 0xa), 10).await.unwrap();
+        storage.register_token(&token(2, 0xa), 10).await.unwrap();
+        storage
+            .register_transfer_event(
+                &TokenTransferEvent {
+                    contract_address: address(0xc0),
+                    chain_id: "SN_MAIN".to_string(),
+                    token_id: "2".to_string(),
+                    token_id_hex: address(2),
+                    from_address: address(0),
+                    to_address: address(0xa),
+                    event_type: EventType::Mint,
+                    event_id: "0x1".to_string(),
+                    block_number: Some(10),
+                    ..Default::default()
+                },
+                10,
+            )
+            .await
+            .unwrap();
+
+        for block_number in [10, 57] {
+            storage
+                .set_block_info(
+                    block_number,
+                    block_number * 10,
+                    BlockInfo {
+                        indexer_version: "0.1.0".to_string(),
+                        indexer_identifier: "test".to_string(),
+                        status: BlockIndexingStatus::Terminated,
+                        block_number,
+                    },
+                )
+                .await
+                .unwrap();
+        }
+
+        // Token 2 has been transferred to 0xb at block 57, missed by the storage.
+        let mut client = MockStarknetClient::default();
+        client
+            .expect_call_contract()
+            .returning(|_, _, calldata, block| {
+                let owner = match (calldata[0], block) {
+                    (id, BlockId::Number(n)) if id == FieldElement::from(2_u64) && n >= 57 => {
+                        0xb_u64
+                    }
+                    _ => 0xa_u64,
+                };
+                Ok(vec![FieldElement::from(owner)])
+            });
+        client.expect_block_time().returning(|_| Ok(1000));
+
+        let verifier = OwnershipVerifier::new(Arc::clone(&storage), Arc::new(client), "SN_MAIN")
+            .with_repair(true);
+        let report = verifier
+            .verify(FieldElement::from(0xc0_u64), 100)
+            .await
+            .unwrap();
+
+        assert_eq!(report.checked_tokens, 2);
+        assert_eq!(report.drifts.len(), 1);
+        assert_eq!(report.drifts[0].on_chain_owner, Some(address(0xb)));
+        assert_eq!(report.drifts[0].affected_block, Some(57));
+        assert!(report.drifts[0].repaired);
+        assert_eq!(report.queued_blocks, vec![57]);
+
+        let stored = storage.token(&address(0xc0), &address(2)).unwrap();
+        assert_eq!(stored.token.owner, address(0xb));
+        assert_eq!(
+            storage.get_block_info(57).await.unwrap().status,
+            BlockIndexingStatus::None
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! Allow and deny lists of the indexed contracts.
+//!
+//! Contracts can be listed by address or by class hash. The class hash
+//! is only required when some class hashes are listed.
+use crate::config::{parse_addresses, PontosConfig};
+use starknet::core::types::FieldElement;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ContractFilter {
+    allowed_contracts: HashSet<FieldElement>,
+    allowed_class_hashes: HashSet<FieldElement>,
+    denied_contracts: HashSet<FieldElement>,
+    denied_class_hashes: HashSet<FieldElement>,
+}
+
+impl ContractFilter {
+    pub fn from_config(config: &PontosConfig) -> Self {
+        let set = |addresses: &[String]| parse_addresses(addresses).into_iter().collect();
+
+        Self {
+            allowed_contracts: set(&config.allowed_contracts),
+            allowed_class_hashes: set(&config.allowed_class_hashes),
+            denied_contracts: set(&config.denied_contracts),
+            denied_class_hashes: set(&config.denied_class_hashes),
+        }
+    }
+
+    /// Returns true if every contract is accepted.
+    pub fn is_empty(&self) -> bool {
+        self.allowed_contracts.is_empty()
+            && self.allowed_class_hashes.is_empty()
+            && self.denied_contracts.is_empty()
+            && self.denied_class_hashes.is_empty()
+    }
+
+    /// Returns true if `accepts` requires the class hash of the contract.
+    pub fn needs_class_hash(&self) -> bool {
+        !self.allowed_class_hashes.is_empty() || !self.denied_class_hashes.is_empty()
+    }
+
+    /// Returns true if the events of the contract are indexed.
+    ///
+    /// The deny lists take precedence. When an allow list is set, the
+    /// contract must be in one of them. An unknown class hash is never
+    /// denied nor allowed by class.
+    pub fn accepts(
+        &self,
+        contract_address: FieldElement,
+        class_hash: Option<FieldElement>,
+    ) -> bool {
+        if self.denied_contracts.contains(&contract_address)
+            || class_hash.map_or(false, |c| self.denied_class_hashes.contains(&c))
+        {
+            return false;
+        }
+
+        if self.allowed_contracts.is_empty() && self.allowed_class_hashes.is_empty() {
+            return true;
+        }
+
+        self.allowed_contracts.contains(&contract_address)
+            || class_hash.map_or(false, |c| self.allowed_class_hashes.contains(&c))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn felt(n: u64) -> FieldElement {
+        FieldElement::from(n)
+    }
+
+    fn filter(
+        allowed: &[&str],
+        allowed_classes: &[&str],
+        denied: &[&str],
+        denied_classes: &[&str],
+    ) -> ContractFilter {
+        let list = |l: &[&str]| l.iter().map(|a| a.to_string()).collect();
+
+        ContractFilter::from_config(&PontosConfig {
+            allowed_contracts: list(allowed),
+            allowed_class_hashes: list(allowed_classes),
+            denied_contracts: list(denied),
+            denied_class_hashes: list(denied_classes),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_deny_lists() {
+        let filter = filter(&[], &[], &["0x1"], &["0xc1"]);
+        assert!(filter.needs_class_hash());
+
+        assert!(!filter.accepts(felt(1), None));
+        assert!(!filter.accepts(felt(2), Some(felt(0xc1))));
+        assert!(filter.accepts(felt(2), Some(felt(0xc2))));
+        assert!(filter.accepts(felt(2), None));
+    }
+
+    #[test]
+    fn test_allow_lists() {
+        let filter = filter(&["0x1", "0x2"], &["0xc1"], &["0x2"], &[]);
+
+        assert!(filter.accepts(felt(1), None));
+        assert!(filter.accepts(felt(3), Some(felt(0xc1))));
+        // Denied, even if allowed.
+        assert!(!filter.accepts(felt(2), None));
+        assert!(!filter.accepts(felt(3), Some(felt(0xc2))));
+        assert!(!filter.accepts(felt(3), None));
+
+        assert!(ContractFilter::default().is_empty());
+        assert!(ContractFilter::default().accepts(felt(3), None));
+    }
+}
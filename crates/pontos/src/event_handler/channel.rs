@@ -0,0 +1,497 @@
+//! Event handler running the callbacks on a separate task.
+use super::{BlockSkipReason, EventFailure, EventHandler, MintEvent};
+use crate::analyzers::TransactionRecord;
+use crate::storage::types::{ApprovalEvent, ContractInfo, TokenEvent, TokenInfo, TokenSaleEvent};
+use async_trait::async_trait;
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::{error, warn};
+
+/// A callback of the `EventHandler` trait, with its arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HandlerEvent {
+    BlockProcessed {
+        block_number: u64,
+        indexation_progress: f64,
+    },
+    BlockProcessing {
+        block_timestamp: u64,
+        block_number: Option<u64>,
+    },
+    IndexationRangeCompleted,
+    TokenRegistered(TokenInfo),
+    EventRegistered(TokenEvent),
+    MintRegistered(MintEvent),
+    SaleRegistered(TokenSaleEvent),
+    ContractIdentified(ContractInfo),
+    BlockSkipped {
+        block_number: u64,
+        reason: BlockSkipReason,
+    },
+    EventFailed(EventFailure),
+    ApprovalRegistered(ApprovalEvent),
+    NewLatestBlock(u64),
+    TransactionAnalyzed(Vec<TransactionRecord>),
+}
+
+impl HandlerEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HandlerEvent::BlockProcessed { .. } => "block_processed",
+            HandlerEvent::BlockProcessing { .. } => "block_processing",
+            HandlerEvent::IndexationRangeCompleted => "indexation_range_completed",
+            HandlerEvent::TokenRegistered(_) => "token_registered",
+            HandlerEvent::EventRegistered(_) => "event_registered",
+            HandlerEvent::MintRegistered(_) => "mint_registered",
+            HandlerEvent::SaleRegistered(_) => "sale_registered",
+            HandlerEvent::ContractIdentified(_) => "contract_identified",
+            HandlerEvent::BlockSkipped { .. } => "block_skipped",
+            HandlerEvent::EventFailed(_) => "event_failed",
+            HandlerEvent::ApprovalRegistered(_) => "approval_registered",
+            HandlerEvent::NewLatestBlock(_) => "new_latest_block",
+            HandlerEvent::TransactionAnalyzed(_) => "transaction_analyzed",
+        }
+    }
+
+    /// Invokes the callback of the handler matching the event.
+    pub async fn dispatch<E: EventHandler + Sync + ?Sized>(self, handler: &E) {
+        match self {
+            HandlerEvent::BlockProcessed {
+                block_number,
+                indexation_progress,
+            } => {
+                handler
+                    .on_block_processed(block_number, indexation_progress)
+                    .await
+            }
+            HandlerEvent::BlockProcessing {
+                block_timestamp,
+                block_number,
+            } => {
+                handler
+                    .on_block_processing(block_timestamp, block_number)
+                    .await
+            }
+            HandlerEvent::IndexationRangeCompleted => handler.on_indexation_range_completed().await,
+            HandlerEvent::TokenRegistered(token) => handler.on_token_registered(token).await,
+            HandlerEvent::EventRegistered(event) => handler.on_event_registered(event).await,
+            HandlerEvent::MintRegistered(mint) => handler.on_mint_registered(mint).await,
+            HandlerEvent::SaleRegistered(sale) => handler.on_sale_registered(sale).await,
+            HandlerEvent::ContractIdentified(contract) => {
+                handler.on_contract_identified(contract).await
+            }
+            HandlerEvent::BlockSkipped {
+                block_number,
+                reason,
+            } => handler.on_block_skipped(block_number, reason).await,
+            HandlerEvent::EventFailed(failure) => handler.on_event_failed(failure).await,
+            HandlerEvent::ApprovalRegistered(approval) => {
+                handler.on_approval_registered(approval).await
+            }
+            HandlerEvent::NewLatestBlock(block_number) => {
+                handler.on_new_latest_block(block_number).await
+            }
+            HandlerEvent::TransactionAnalyzed(records) => {
+                handler.on_transaction_analyzed(records).await
+            }
+        }
+    }
+}
+
+/// What to do with a new event when the queue is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Pontos waits for an event to be handled, slowing down the indexation.
+    #[default]
+    Block,
+    /// The oldest queued event is discarded.
+    DropOldest,
+    /// The new event is rejected.
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DispatchError {
+    /// The queue is full, and the overflow policy is `Error`.
+    Full(HandlerEvent),
+    /// The handler is closed.
+    Closed(HandlerEvent),
+}
+
+impl fmt::Display for DispatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DispatchError::Full(event) => {
+                write!(f, "Event handler queue is full, {} rejected", event.name())
+            }
+            DispatchError::Closed(event) => {
+                write!(f, "Event handler is closed, {} rejected", event.name())
+            }
+        }
+    }
+}
+
+impl std::error::Error for DispatchError {}
+
+#[derive(Debug, Default)]
+struct Queue {
+    events: VecDeque<HandlerEvent>,
+    closed: bool,
+}
+
+#[derive(Debug)]
+struct Shared {
+    queue: Mutex<Queue>,
+    capacity: usize,
+    /// Events queued or being handled.
+    pending: AtomicUsize,
+    overflowed: AtomicU64,
+    not_empty: Notify,
+    not_full: Notify,
+    idle: Notify,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().expect("event queue lock poisoned")
+    }
+}
+
+/// Event handler queuing the events on a bounded channel, and running
+/// the callbacks of the wrapped handler on a separate task, in order.
+///
+/// Pontos only waits for the event to be queued, or for a free slot
+/// with the `Block` overflow policy.
+pub struct ChannelEventHandler {
+    shared: Arc<Shared>,
+    overflow: OverflowPolicy,
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl ChannelEventHandler {
+    /// Spawns the task running the callbacks of `handler`, which requires
+    /// a Tokio runtime. `capacity` is the number of events queued before
+    /// the `overflow` policy is applied.
+    pub fn new<H>(handler: Arc<H>, capacity: usize, overflow: OverflowPolicy) -> Self
+    where
+        H: EventHandler + Send + Sync + ?Sized + 'static,
+    {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue::default()),
+            capacity: capacity.max(1),
+            pending: AtomicUsize::new(0),
+            overflowed: AtomicU64::new(0),
+            not_empty: Notify::new(),
+            not_full: Notify::new(),
+            idle: Notify::new(),
+        });
+
+        let worker = tokio::spawn(run_worker(Arc::clone(&shared), handler));
+
+        ChannelEventHandler {
+            shared,
+            overflow,
+            worker: Mutex::new(Some(worker)),
+        }
+    }
+
+    /// Queues the event, applying the overflow policy if the queue is full.
+    pub async fn try_dispatch(&self, event: HandlerEvent) -> Result<(), DispatchError> {
+        loop {
+            let not_full = self.shared.not_full.notified();
+
+            {
+                let mut queue = self.shared.lock();
+
+                if queue.closed {
+                    return Err(DispatchError::Closed(event));
+                }
+
+                if queue.events.len() < self.shared.capacity {
+                    queue.events.push_back(event);
+                    self.shared.pending.fetch_add(1, Ordering::SeqCst);
+                    drop(queue);
+
+                    self.shared.not_empty.notify_one();
+                    return Ok(());
+                }
+
+                match self.overflow {
+                    OverflowPolicy::Block => {}
+                    OverflowPolicy::DropOldest => {
+                        // The dropped event is replaced, the pending count is unchanged.
+                        let dropped = queue.events.pop_front();
+                        queue.events.push_back(event);
+                        drop(queue);
+
+                        self.shared.overflowed.fetch_add(1, Ordering::SeqCst);
+                        if let Some(dropped) = dropped {
+                            warn!("Event handler queue is full, {} dropped", dropped.name());
+                        }
+                        return Ok(());
+                    }
+                    OverflowPolicy::Error => {
+                        self.shared.overflowed.fetch_add(1, Ordering::SeqCst);
+                        return Err(DispatchError::Full(event));
+                    }
+                }
+            }
+
+            not_full.await;
+        }
+    }
+
+    /// Waits for all the queued events to be handled.
+    pub async fn flush(&self) {
+        loop {
+            let idle = self.shared.idle.notified();
+
+            if self.shared.pending.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+
+            idle.await;
+        }
+    }
+
+    /// Stops accepting events, and waits for the queued ones to be handled.
+    pub async fn close(&self) {
+        self.shared.lock().closed = true;
+        self.shared.not_empty.notify_one();
+        self.shared.not_full.notify_waiters();
+
+        let worker = self
+            .worker
+            .lock()
+            .expect("event worker lock poisoned")
+            .take();
+
+        if let Some(worker) = worker {
+            if let Err(e) = worker.await {
+                error!("Event handler task failed: {}", e);
+            }
+        }
+    }
+
+    /// Number of events waiting to be handled.
+    pub fn queued(&self) -> usize {
+        self.shared.lock().events.len()
+    }
+
+    /// Number of events dropped or rejected because the queue was full.
+    pub fn overflowed(&self) -> u64 {
+        self.shared.overflowed.load(Ordering::SeqCst)
+    }
+
+    async fn send(&self, event: HandlerEvent) {
+        if let Err(e) = self.try_dispatch(event).await {
+            error!("{}", e);
+        }
+    }
+}
+
+impl Drop for ChannelEventHandler {
+    /// The task terminates once the queued events are handled.
+    fn drop(&mut self) {
+        if let Ok(mut queue) = self.shared.queue.lock() {
+            queue.closed = true;
+        }
+        self.shared.not_empty.notify_one();
+    }
+}
+
+async fn run_worker<H>(shared: Arc<Shared>, handler: Arc<H>)
+where
+    H: EventHandler + Send + Sync + ?Sized,
+{
+    loop {
+        let not_empty = shared.not_empty.notified();
+
+        let event = {
+            let mut queue = shared.lock();
+            match queue.events.pop_front() {
+                Some(event) => Some(event),
+                None if queue.closed => return,
+                None => None,
+            }
+        };
+
+        let Some(event) = event else {
+            not_empty.await;
+            continue;
+        };
+
+        shared.not_full.notify_one();
+
+        let name = event.name();
+        if AssertUnwindSafe(event.dispatch(handler.as_ref()))
+            .catch_unwind()
+            .await
+            .is_err()
+        {
+            error!("Event handler panicked on {}", name);
+        }
+
+        if shared.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+            shared.idle.notify_waiters();
+        }
+    }
+}
+
+#[async_trait]
+impl EventHandler for ChannelEventHandler {
+    async fn on_block_processed(&self, block_number: u64, indexation_progress: f64) {
+        self.send(HandlerEvent::BlockProcessed {
+            block_number,
+            indexation_progress,
+        })
+        .await;
+    }
+
+    async fn on_block_processing(&self, block_timestamp: u64, block_number: Option<u64>) {
+        self.send(HandlerEvent::BlockProcessing {
+            block_timestamp,
+            block_number,
+        })
+        .await;
+    }
+
+    async fn on_indexation_range_completed(&self) {
+        self.send(HandlerEvent::IndexationRangeCompleted).await;
+    }
+
+    async fn on_token_registered(&self, token: TokenInfo) {
+        self.send(HandlerEvent::TokenRegistered(token)).await;
+    }
+
+    async fn on_event_registered(&self, event: TokenEvent) {
+        self.send(HandlerEvent::EventRegistered(event)).await;
+    }
+
+    async fn on_mint_registered(&self, mint: MintEvent) {
+        self.send(HandlerEvent::MintRegistered(mint)).await;
+    }
+
+    async fn on_sale_registered(&self, sale: TokenSaleEvent) {
+        self.send(HandlerEvent::SaleRegistered(sale)).await;
+    }
+
+    async fn on_contract_identified(&self, contract: ContractInfo) {
+        self.send(HandlerEvent::ContractIdentified(contract)).await;
+    }
+
+    async fn on_block_skipped(&self, block_number: u64, reason: BlockSkipReason) {
+        self.send(HandlerEvent::BlockSkipped {
+            block_number,
+            reason,
+        })
+        .await;
+    }
+
+    async fn on_event_failed(&self, failure: EventFailure) {
+        self.send(HandlerEvent::EventFailed(failure)).await;
+    }
+
+    async fn on_approval_registered(&self, approval: ApprovalEvent) {
+        self.send(HandlerEvent::ApprovalRegistered(approval)).await;
+    }
+
+    async fn on_new_latest_block(&self, block_number: u64) {
+        self.send(HandlerEvent::NewLatestBlock(block_number)).await;
+    }
+
+    async fn on_transaction_analyzed(&self, records: Vec<TransactionRecord>) {
+        self.send(HandlerEvent::TransactionAnalyzed(records)).await;
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    /// Records the blocks of `on_new_latest_block`.
+    #[derive(Default)]
+    pub struct BlockRecorder {
+        pub blocks: Mutex<Vec<u64>>,
+    }
+
+    impl BlockRecorder {
+        pub fn blocks(&self) -> Vec<u64> {
+            self.blocks.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl EventHandler for BlockRecorder {
+        async fn on_new_latest_block(&self, block_number: u64) {
+            self.blocks.lock().unwrap().push(block_number);
+        }
+    }
+
+    // The tests run on a single thread: the worker only
+    // handles the events once the test awaits.
+
+    #[tokio::test]
+    async fn test_block_keeps_every_event() {
+        let recorder = Arc::new(BlockRecorder::default());
+        let handler = ChannelEventHandler::new(Arc::clone(&recorder), 2, OverflowPolicy::Block);
+
+        for block in 1..=5 {
+            handler.on_new_latest_block(block).await;
+        }
+        handler.flush().await;
+
+        assert_eq!(recorder.blocks(), vec![1, 2, 3, 4, 5]);
+        assert_eq!(handler.overflowed(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest() {
+        let recorder = Arc::new(BlockRecorder::default());
+        let handler =
+            ChannelEventHandler::new(Arc::clone(&recorder), 2, OverflowPolicy::DropOldest);
+
+        for block in 1..=5 {
+            handler.on_new_latest_block(block).await;
+        }
+        assert_eq!(handler.queued(), 2);
+        handler.flush().await;
+
+        assert_eq!(recorder.blocks(), vec![4, 5]);
+        assert_eq!(handler.overflowed(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_error_rejects_new_events() {
+        let recorder = Arc::new(BlockRecorder::default());
+        let handler = ChannelEventHandler::new(Arc::clone(&recorder), 2, OverflowPolicy::Error);
+
+        assert!(handler
+            .try_dispatch(HandlerEvent::NewLatestBlock(1))
+            .await
+            .is_ok());
+        assert!(handler
+            .try_dispatch(HandlerEvent::NewLatestBlock(2))
+            .await
+            .is_ok());
+        assert_eq!(
+            handler.try_dispatch(HandlerEvent::NewLatestBlock(3)).await,
+            Err(DispatchError::Full(HandlerEvent::NewLatestBlock(3)))
+        );
+
+        handler.close().await;
+        assert_eq!(recorder.blocks(), vec![1, 2]);
+        assert!(matches!(
+            handler.try_dispatch(HandlerEvent::NewLatestBlock(4)).await,
+            Err(DispatchError::Closed(_))
+        ));
+    }
+}
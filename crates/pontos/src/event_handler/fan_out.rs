@@ -0,0 +1,136 @@
+//! Event handler forwarding the events to several handlers.
+use super::{BlockSkipReason, EventFailure, EventHandler, HandlerEvent, MintEvent};
+use crate::analyzers::TransactionRecord;
+use crate::storage::types::{ApprovalEvent, ContractInfo, TokenEvent, TokenInfo, TokenSaleEvent};
+use async_trait::async_trait;
+use futures::future::join_all;
+use std::sync::Arc;
+
+/// Forwards every event to all its handlers, invoked concurrently.
+///
+/// A slow handler still delays the others: it should be wrapped
+/// into its own `ChannelEventHandler`.
+#[derive(Clone, Default)]
+pub struct FanOutEventHandler {
+    handlers: Vec<Arc<dyn EventHandler + Send + Sync>>,
+}
+
+impl FanOutEventHandler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_handler(mut self, handler: Arc<dyn EventHandler + Send + Sync>) -> Self {
+        self.handlers.push(handler);
+        self
+    }
+
+    async fn broadcast(&self, event: HandlerEvent) {
+        join_all(
+            self.handlers
+                .iter()
+                .map(|handler| event.clone().dispatch(handler.as_ref())),
+        )
+        .await;
+    }
+}
+
+#[async_trait]
+impl EventHandler for FanOutEventHandler {
+    async fn on_block_processed(&self, block_number: u64, indexation_progress: f64) {
+        self.broadcast(HandlerEvent::BlockProcessed {
+            block_number,
+            indexation_progress,
+        })
+        .await;
+    }
+
+    async fn on_block_processing(&self, block_timestamp: u64, block_number: Option<u64>) {
+        self.broadcast(HandlerEvent::BlockProcessing {
+            block_timestamp,
+            block_number,
+        })
+        .await;
+    }
+
+    async fn on_indexation_range_completed(&self) {
+        self.broadcast(HandlerEvent::IndexationRangeCompleted).await;
+    }
+
+    async fn on_token_registered(&self, token: TokenInfo) {
+        self.broadcast(HandlerEvent::TokenRegistered(token)).await;
+    }
+
+    async fn on_event_registered(&self, event: TokenEvent) {
+        self.broadcast(HandlerEvent::EventRegistered(event)).await;
+    }
+
+    async fn on_mint_registered(&self, mint: MintEvent) {
+        self.broadcast(HandlerEvent::MintRegistered(mint)).await;
+    }
+
+    async fn on_sale_registered(&self, sale: TokenSaleEvent) {
+        self.broadcast(HandlerEvent::SaleRegistered(sale)).await;
+    }
+
+    async fn on_contract_identified(&self, contract: ContractInfo) {
+        self.broadcast(HandlerEvent::ContractIdentified(contract))
+            .await;
+    }
+
+    async fn on_block_skipped(&self, block_number: u64, reason: BlockSkipReason) {
+        self.broadcast(HandlerEvent::BlockSkipped {
+            block_number,
+            reason,
+        })
+        .await;
+    }
+
+    async fn on_event_failed(&self, failure: EventFailure) {
+        self.broadcast(HandlerEvent::EventFailed(failure)).await;
+    }
+
+    async fn on_approval_registered(&self, approval: ApprovalEvent) {
+        self.broadcast(HandlerEvent::ApprovalRegistered(approval))
+            .await;
+    }
+
+    async fn on_new_latest_block(&self, block_number: u64) {
+        self.broadcast(HandlerEvent::NewLatestBlock(block_number))
+            .await;
+    }
+
+    async fn on_transaction_analyzed(&self, records: Vec<TransactionRecord>) {
+        self.broadcast(HandlerEvent::TransactionAnalyzed(records))
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_handler::channel::tests::BlockRecorder;
+    use crate::event_handler::{ChannelEventHandler, OverflowPolicy};
+
+    #[tokio::test]
+    async fn test_fan_out_to_every_handler() {
+        let direct = Arc::new(BlockRecorder::default());
+        let queued = Arc::new(BlockRecorder::default());
+        let channel = Arc::new(ChannelEventHandler::new(
+            Arc::clone(&queued),
+            10,
+            OverflowPolicy::Block,
+        ));
+
+        let handler = FanOutEventHandler::new()
+            .with_handler(direct.clone())
+            .with_handler(channel.clone());
+
+        handler.on_new_latest_block(1).await;
+        handler.on_new_latest_block(2).await;
+        channel.flush().await;
+
+        assert_eq!(direct.blocks(), vec![1, 2]);
+        assert_eq!(queued.blocks(), vec![1, 2]);
+    }
+}
@@ -0,0 +1,119 @@
+//! Trait related to any events that Pontos can emit to be handled.
+use crate::analyzers::TransactionRecord;
+use crate::storage::types::{
+    ApprovalEvent, ContractInfo, TokenEvent, TokenInfo, TokenMintInfo, TokenSaleEvent,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+mod channel;
+mod fan_out;
+#[cfg(feature = "webhook")]
+pub mod webhook;
+
+pub use channel::{ChannelEventHandler, DispatchError, HandlerEvent, OverflowPolicy};
+pub use fan_out::FanOutEventHandler;
+#[cfg(feature = "webhook")]
+pub use webhook::{WebhookEndpoint, WebhookEventHandler, WebhookEventKind, WebhookRetry};
+
+/// A trait to be implemented in order to handle
+/// events emitted by Pontos, in an external code.
+///
+/// Any long computation in the code of those functions
+/// will directly impact Pontos performances.
+/// Please consider spawning tasks if some work may
+/// be too heavy and impact negatively Pontos performances,
+/// or wrapping the handler into a `ChannelEventHandler`.
+#[async_trait]
+#[allow(unused)]
+pub trait EventHandler {
+    /// Pontos has normally terminated the indexation of the given blocks.
+    async fn on_block_processed(&self, block_number: u64, indexation_progress: f64) {}
+
+    /// Block is processing by Pontos.
+    async fn on_block_processing(&self, block_timestamp: u64, block_number: Option<u64>) {}
+
+    /// Invoked when Pontos has successfully indexed a range of blocks up to the given block number.
+    async fn on_indexation_range_completed(&self) {}
+
+    /// A token has been registered, after a transfer.
+    async fn on_token_registered(&self, token: TokenInfo) {}
+
+    /// A token event (transfer or sale) has been registered.
+    async fn on_event_registered(&self, event: TokenEvent) {}
+
+    /// The mint info of a token has been registered, once per mint, with
+    /// the price paid when found by the analyzers of the transaction.
+    async fn on_mint_registered(&self, mint: MintEvent) {}
+
+    /// A sale has been registered, from a marketplace event or detected
+    /// by the analyzers. Also emitted by `on_event_registered`.
+    async fn on_sale_registered(&self, sale: TokenSaleEvent) {}
+
+    /// A contract seen for the first time has been identified and registered.
+    async fn on_contract_identified(&self, contract: ContractInfo) {}
+
+    /// A block of the range has not been indexed.
+    async fn on_block_skipped(&self, block_number: u64, reason: BlockSkipReason) {}
+
+    /// An event couldn't be indexed. The other events of
+    /// the block are still indexed.
+    async fn on_event_failed(&self, failure: EventFailure) {}
+
+    /// A new approval (token or operator) has been registered.
+    async fn on_approval_registered(&self, approval: ApprovalEvent) {}
+
+    // A new latest block has been detected.
+    async fn on_new_latest_block(&self, block_number: u64) {}
+
+    /// The analyzers have produced records (sweeps, detected sales, mint prices...)
+    /// for the events of a transaction.
+    async fn on_transaction_analyzed(&self, records: Vec<TransactionRecord>) {}
+}
+
+/// Mint info of a token.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MintEvent {
+    pub contract_address: String,
+    pub chain_id: String,
+    pub token_id: String,
+    pub token_id_hex: String,
+    pub mint: TokenMintInfo,
+}
+
+/// Why a block has not been indexed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockSkipReason {
+    /// The block is already indexed by this indexer version.
+    AlreadyIndexed,
+    /// The timestamp of the block couldn't be fetched after all the retries.
+    TimestampUnavailable { attempts: u32 },
+    /// The block is not indexed yet, and can't be partially reindexed.
+    NotIndexed,
+}
+
+/// Step of the indexation at which an event failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventStage {
+    MarketplaceSale,
+    ContractDeployment,
+    Approval,
+    Transfer,
+    DetectedSale,
+    MintPrice,
+}
+
+/// An event that couldn't be indexed, with its context.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventFailure {
+    pub stage: EventStage,
+    pub chain_id: String,
+    pub block_number: Option<u64>,
+    pub block_timestamp: u64,
+    pub transaction_hash: String,
+    /// Address of the contract emitting the event.
+    pub contract_address: String,
+    pub error: String,
+}
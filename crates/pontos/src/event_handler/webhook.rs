@@ -0,0 +1,495 @@
+//! Event handler notifying HTTP endpoints of the transfers and sales.
+//!
+//! Each event is POSTed as JSON to the endpoints interested in its
+//! collection and kind, signed with the secret of the endpoint:
+//!
+//! ```text
+//! X-Ark-Event: sale
+//! X-Ark-Timestamp: 1700000000
+//! X-Ark-Signature: sha256=<hex HMAC-SHA256 of "{timestamp}.{body}">
+//!
+//! {"id": "...", "type": "sale", "timestamp": 1700000000, "event": {...}}
+//! ```
+//!
+//! `event` is the serialization of the `TokenEvent`. Deliveries failing
+//! after all the retries are appended to the dead-letter file, one JSON
+//! line per delivery, and can be sent again with `replay_dead_letters`.
+use super::EventHandler;
+use crate::storage::types::TokenEvent;
+use anyhow::{anyhow, Result};
+use ark_starknet::format::to_hex_str;
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use starknet::core::types::FieldElement;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::{debug, error, warn};
+
+pub const SIGNATURE_HEADER: &str = "X-Ark-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Ark-Timestamp";
+pub const EVENT_HEADER: &str = "X-Ark-Event";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventKind {
+    Transfer,
+    Sale,
+}
+
+impl WebhookEventKind {
+    pub fn of(event: &TokenEvent) -> Self {
+        match event {
+            TokenEvent::Transfer(_) => WebhookEventKind::Transfer,
+            TokenEvent::Sale(_) => WebhookEventKind::Sale,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEventKind::Transfer => "transfer",
+            WebhookEventKind::Sale => "sale",
+        }
+    }
+}
+
+/// An endpoint, and the events it is notified of.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookEndpoint {
+    pub url: String,
+    /// Key of the HMAC signature of the payloads.
+    pub secret: String,
+    /// Addresses of the collections notified, all of them if empty.
+    #[serde(default)]
+    pub collections: Vec<String>,
+    /// Kinds of events notified, all of them if empty.
+    #[serde(default)]
+    pub event_kinds: Vec<WebhookEventKind>,
+}
+
+impl WebhookEndpoint {
+    pub fn new(url: &str, secret: &str) -> Self {
+        WebhookEndpoint {
+            url: url.to_string(),
+            secret: secret.to_string(),
+            collections: vec![],
+            event_kinds: vec![],
+        }
+    }
+
+    pub fn with_collection(mut self, contract_address: &str) -> Self {
+        self.collections.push(contract_address.to_string());
+        self
+    }
+
+    pub fn with_event_kind(mut self, kind: WebhookEventKind) -> Self {
+        self.event_kinds.push(kind);
+        self
+    }
+
+    /// Returns true if the endpoint is notified of the event.
+    pub fn accepts(&self, event: &TokenEvent) -> bool {
+        let contract_address = match event {
+            TokenEvent::Transfer(transfer) => &transfer.contract_address,
+            TokenEvent::Sale(sale) => &sale.nft_contract_address,
+        };
+
+        let kind_matches =
+            self.event_kinds.is_empty() || self.event_kinds.contains(&WebhookEventKind::of(event));
+        let collection_matches = self.collections.is_empty()
+            || self
+                .collections
+                .iter()
+                .any(|c| normalize_address(c) == normalize_address(contract_address));
+
+        kind_matches && collection_matches
+    }
+}
+
+/// Delays between the attempts of a delivery, doubled at each retry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WebhookRetry {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for WebhookRetry {
+    fn default() -> Self {
+        WebhookRetry {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl WebhookRetry {
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2_u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// A delivery that failed after all the retries.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub url: String,
+    pub payload: Value,
+    pub attempts: u32,
+    pub error: String,
+    pub failed_at: u64,
+}
+
+/// Event handler POSTing the registered transfers and sales to webhooks.
+///
+/// The deliveries are awaited with their retries: the handler should
+/// be wrapped into a `ChannelEventHandler` to not slow down Pontos.
+pub struct WebhookEventHandler {
+    client: Client,
+    endpoints: Vec<WebhookEndpoint>,
+    retry: WebhookRetry,
+    dead_letter_path: Option<PathBuf>,
+    /// Serializes the writes to the dead-letter file.
+    dead_letter_lock: Mutex<()>,
+}
+
+impl WebhookEventHandler {
+    pub fn new(endpoints: Vec<WebhookEndpoint>) -> Self {
+        WebhookEventHandler {
+            client: Client::new(),
+            endpoints,
+            retry: WebhookRetry::default(),
+            dead_letter_path: None,
+            dead_letter_lock: Mutex::new(()),
+        }
+    }
+
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: WebhookRetry) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// File where the failed deliveries are appended. Without it,
+    /// the failed deliveries are only logged.
+    pub fn with_dead_letter_file(mut self, path: &Path) -> Self {
+        self.dead_letter_path = Some(path.to_path_buf());
+        self
+    }
+
+    /// Sends the event to every interested endpoint.
+    pub async fn notify(&self, event: &TokenEvent) {
+        let endpoints: Vec<&WebhookEndpoint> =
+            self.endpoints.iter().filter(|e| e.accepts(event)).collect();
+
+        if endpoints.is_empty() {
+            return;
+        }
+
+        let payload = match payload(event) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!("Can't serialize webhook payload: {:?}", e);
+                return;
+            }
+        };
+
+        for endpoint in endpoints {
+            if let Err((attempts, e)) = self.deliver(endpoint, &payload).await {
+                self.dead_letter(DeadLetter {
+                    url: endpoint.url.clone(),
+                    payload: payload.clone(),
+                    attempts,
+                    error: e.to_string(),
+                    failed_at: unix_timestamp(),
+                })
+                .await;
+            }
+        }
+    }
+
+    /// Sends again the deliveries of the dead-letter file. The ones
+    /// failing again are kept in the file, and the number of
+    /// deliveries succeeding is returned.
+    pub async fn replay_dead_letters(&self) -> Result<usize> {
+        let path = self
+            .dead_letter_path
+            .as_ref()
+            .ok_or_else(|| anyhow!("No dead-letter file configured"))?;
+
+        let _guard = self.dead_letter_lock.lock().await;
+
+        let content = match tokio::fs::read_to_string(path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut delivered = 0;
+        let mut remaining = String::new();
+
+        for line in content.lines().filter(|l| !l.trim().is_empty()) {
+            let letter: DeadLetter = serde_json::from_str(line)?;
+
+            let endpoint = match self.endpoints.iter().find(|e| e.url == letter.url) {
+                Some(endpoint) => endpoint,
+                None => {
+                    warn!("No endpoint configured for dead letter to {}", letter.url);
+                    remaining.push_str(line);
+                    remaining.push('\n');
+                    continue;
+                }
+            };
+
+            match self.deliver(endpoint, &letter.payload).await {
+                Ok(()) => delivered += 1,
+                Err((attempts, e)) => {
+                    let letter = DeadLetter {
+                        attempts: letter.attempts + attempts,
+                        error: e.to_string(),
+                        failed_at: unix_timestamp(),
+                        ..letter
+                    };
+                    remaining.push_str(&serde_json::to_string(&letter)?);
+                    remaining.push('\n');
+                }
+            }
+        }
+
+        tokio::fs::write(path, remaining).await?;
+
+        Ok(delivered)
+    }
+
+    /// POSTs the payload until it is accepted, or the attempts are exhausted.
+    /// Returns the number of attempts with the last error on failure.
+    async fn deliver(
+        &self,
+        endpoint: &WebhookEndpoint,
+        payload: &Value,
+    ) -> std::result::Result<(), (u32, anyhow::Error)> {
+        let body = serde_json::to_string(payload).map_err(|e| (0, e.into()))?;
+        let kind = payload["type"].as_str().unwrap_or_default().to_string();
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+
+            let timestamp = unix_timestamp().to_string();
+            let result = self
+                .client
+                .post(&endpoint.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(EVENT_HEADER, &kind)
+                .header(TIMESTAMP_HEADER, &timestamp)
+                .header(
+                    SIGNATURE_HEADER,
+                    signature(&endpoint.secret, &timestamp, &body),
+                )
+                .body(body.clone())
+                .send()
+                .await;
+
+            let (error, retryable) = match result {
+                Ok(response) if response.status().is_success() => {
+                    debug!("Webhook {} notified of {}", endpoint.url, kind);
+                    return Ok(());
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    (
+                        anyhow!("{} answered {}", endpoint.url, status),
+                        is_retryable(status),
+                    )
+                }
+                Err(e) => (anyhow!("{} unreachable: {}", endpoint.url, e), true),
+            };
+
+            if !retryable || attempt >= self.retry.max_attempts {
+                error!(
+                    "Webhook delivery failed after {} attempt(s): {}",
+                    attempt, error
+                );
+                return Err((attempt, error));
+            }
+
+            warn!("Attempt #{} - {}", attempt, error);
+            tokio::time::sleep(self.retry.backoff(attempt)).await;
+        }
+    }
+
+    async fn dead_letter(&self, letter: DeadLetter) {
+        let path = match &self.dead_letter_path {
+            Some(path) => path,
+            None => return,
+        };
+
+        let _guard = self.dead_letter_lock.lock().await;
+
+        if let Err(e) = append_line(path, &letter).await {
+            error!(
+                "Can't write dead letter to {}: {:?}\n{:?}",
+                path.display(),
+                e,
+                letter
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl EventHandler for WebhookEventHandler {
+    async fn on_event_registered(&self, event: TokenEvent) {
+        self.notify(&event).await;
+    }
+}
+
+/// Signature of the payload sent in the `X-Ark-Signature` header.
+pub fn signature(secret: &str, timestamp: &str, body: &str) -> String {
+    format!(
+        "sha256={}",
+        hmac_sha256_hex(secret, &format!("{}.{}", timestamp, body))
+    )
+}
+
+fn hmac_sha256_hex(secret: &str, message: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(message.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn payload(event: &TokenEvent) -> Result<Value> {
+    let id = match event {
+        TokenEvent::Transfer(transfer) => &transfer.event_id,
+        TokenEvent::Sale(sale) => &sale.event_id,
+    };
+
+    Ok(serde_json::json!({
+        "id": id,
+        "type": WebhookEventKind::of(event).as_str(),
+        "timestamp": unix_timestamp(),
+        "event": serde_json::to_value(event)?,
+    }))
+}
+
+/// Client errors are not retried, except timeouts and rate limits.
+fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+fn normalize_address(address: &str) -> String {
+    FieldElement::from_hex_be(address)
+        .map(|a| to_hex_str(&a))
+        .unwrap_or_else(|_| address.to_lowercase())
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+async fn append_line(path: &Path, letter: &DeadLetter) -> Result<()> {
+    let mut line = serde_json::to_string(letter)?;
+    line.push('\n');
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(line.as_bytes()).await?;
+    file.flush().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::types::{EventType, TokenSaleEvent, TokenTransferEvent};
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231, test case 2.
+        assert_eq!(
+            hmac_sha256_hex("Jefe", "what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_endpoint_filters() {
+        let transfer = TokenEvent::Transfer(TokenTransferEvent {
+            contract_address: to_hex_str(&FieldElement::from(0x123_u64)),
+            ..Default::default()
+        });
+        let sale = TokenEvent::Sale(TokenSaleEvent {
+            timestamp: 0,
+            from_address: String::new(),
+            to_address: String::new(),
+            nft_contract_address: to_hex_str(&FieldElement::from(0x456_u64)),
+            nft_type: None,
+            marketplace_contract_address: String::new(),
+            marketplace_name: String::new(),
+            transaction_hash: String::new(),
+            token_id: String::new(),
+            token_id_hex: String::new(),
+            event_type: EventType::Sale,
+            event_id: String::new(),
+            block_number: None,
+            updated_at: None,
+            quantity: 1,
+            currency_address: None,
+            price: String::new(),
+            currency_symbol: None,
+            currency_decimals: None,
+            normalized_price: None,
+            price_usd: None,
+            chain_id: String::new(),
+            wash_trade_flags: vec![],
+        });
+
+        let all = WebhookEndpoint::new("http://localhost", "secret");
+        assert!(all.accepts(&transfer) && all.accepts(&sale));
+
+        let collection = all.clone().with_collection("0x0123");
+        assert!(collection.accepts(&transfer));
+        assert!(!collection.accepts(&sale));
+
+        let sales = all.with_event_kind(WebhookEventKind::Sale);
+        assert!(!sales.accepts(&transfer));
+        assert!(sales.accepts(&sale));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let retry = WebhookRetry {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+        };
+
+        assert_eq!(retry.backoff(1), Duration::from_millis(100));
+        assert_eq!(retry.backoff(2), Duration::from_millis(200));
+        assert_eq!(retry.backoff(3), Duration::from_millis(400));
+        assert_eq!(retry.backoff(4), Duration::from_millis(500));
+    }
+}
@@ -1,20 +1,45 @@
+pub mod analyzers;
+pub mod backfill;
+pub mod config;
+pub mod consistency;
+pub mod contract_filter;
 pub mod event_handler;
 pub mod managers;
+pub mod multichain;
+pub mod snapshot;
+pub mod spam;
 pub mod storage;
+pub mod wash_trade;
 
 use crate::storage::types::BlockIndexingStatus;
+pub use ark_starknet::price_oracle;
+pub use config::PontosConfig;
+
+use analyzers::{TransactionAnalyzer, TransactionAnalyzers, TransactionEvents, TransactionRecord};
 use anyhow::Result;
 use ark_starknet::client::{StarknetClient, StarknetClientError};
 use ark_starknet::format::to_hex_str;
-use event_handler::EventHandler;
-use managers::{BlockManager, ContractManager, EventManager, PendingBlockData, TokenManager};
+use contract_filter::ContractFilter;
+use event_handler::{
+    BlockSkipReason, EventFailure, EventHandler, EventStage, HandlerEvent, MintEvent,
+};
+use managers::contract_manager::ContractDeployment;
+use managers::{
+    BlockManager, ContractManager, CurrencyManager, EventManager, PendingBlockData, TokenManager,
+};
+use price_oracle::PriceOracle;
 use starknet::core::types::*;
+use std::collections::HashMap;
 use std::fmt;
 use std::sync::Arc;
-use storage::types::{ContractType, StorageError};
+use storage::types::{
+    ContractType, EventType, ReindexEventKind, ReindexFilter, StorageError, TokenEvent,
+    TokenSaleEvent, TokenTransferEvent,
+};
 use storage::Storage;
-use tokio::sync::RwLock as AsyncRwLock;
+use tokio::sync::{Mutex as AsyncMutex, RwLock as AsyncRwLock};
 use tracing::{debug, error, info, trace, warn};
+use wash_trade::WashTradeDetector;
 
 pub type IndexerResult<T> = Result<T, IndexerError>;
 
@@ -24,9 +49,20 @@ const ELEMENT_MARKETPLACE_EVENT_HEX: &str =
 const VENTORY_MARKETPLACE_EVENT_HEX: &str =
     "0x1b43f40d55364e989b3a8674460f61ba8f327542298ee6240a54ee2bf7b55bb"; // EventListingBought
 
+/// Ventory sales are always paid in STRK, the currency is not part of the event.
+const VENTORY_MARKETPLACE_CURRENCY_HEX: &str =
+    "0x04718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d";
+
 const VENTORY_MARKETPLACE_OFFER_ACCEPTED_EVENT_HEX: &str =
     "0xe214ba50bf9d17a50de9ab9f433295bd671144999d5258dbc261cbf1e1c2cc"; // EventOfferAccepted
 
+/// Universal Deployer Contracts (legacy and Cairo 1 versions),
+/// emitting `ContractDeployed` for each deployment made through them.
+const UDC_ADDRESSES_HEX: [&str; 2] = [
+    "0x041a78e741e5af2fec34b695679bc6891742439f7afb8484ecd7766661ad02bf",
+    "0x02ceed65a4bd731034c01113685c831b01c15d7d432f71afb1cf1634b53a2125",
+];
+
 /// Generic errors for Pontos.
 #[derive(Debug)]
 pub enum IndexerError {
@@ -65,11 +101,6 @@ impl fmt::Display for IndexerError {
 
 impl std::error::Error for IndexerError {}
 
-pub struct PontosConfig {
-    pub indexer_version: String,
-    pub indexer_identifier: String,
-}
-
 pub struct Pontos<S: Storage, C: StarknetClient, E: EventHandler> {
     client: Arc<C>,
     event_handler: Arc<E>,
@@ -78,7 +109,23 @@ pub struct Pontos<S: Storage, C: StarknetClient, E: EventHandler> {
     event_manager: Arc<EventManager<S>>,
     token_manager: Arc<TokenManager<S, C>>,
     contract_manager: Arc<AsyncRwLock<ContractManager<S, C>>>,
+    currency_manager: Arc<AsyncRwLock<CurrencyManager<C>>>,
+    price_oracle: Option<Arc<dyn PriceOracle + Send + Sync>>,
+    transaction_analyzers: TransactionAnalyzers,
+    state_diff_discovery: bool,
     pending_cache: Arc<AsyncRwLock<PendingBlockData>>,
+    marketplace_contracts: Vec<FieldElement>,
+    udc_contracts: Vec<FieldElement>,
+    contract_filter: ContractFilter,
+    /// History of the recent sales and payments, shared like the contract cache.
+    wash_trade_detector: Arc<AsyncRwLock<WashTradeDetector>>,
+    /// Callbacks of the block being applied, emitted once its unit of
+    /// work is committed. `None` outside of a unit of work.
+    block_events: Arc<AsyncMutex<Option<Vec<HandlerEvent>>>>,
+    /// Held while events are applied. The unit of work of the storage and
+    /// `block_events` are per instance, so concurrent calls of this instance
+    /// are applied one block at a time instead of sharing a transaction.
+    unit_of_work: Arc<AsyncMutex<()>>,
 }
 
 impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C, E> {
@@ -89,23 +136,61 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
         config: PontosConfig,
     ) -> Self {
         Pontos {
-            config,
             client: Arc::clone(&client),
             event_handler: Arc::clone(&event_handler),
             block_manager: Arc::new(BlockManager::new(Arc::clone(&storage))),
-            event_manager: Arc::new(EventManager::new(Arc::clone(&storage))),
+            event_manager: Arc::new(
+                EventManager::new(Arc::clone(&storage))
+                    .with_ventory_currency_address(&config.ventory_currency_address),
+            ),
             token_manager: Arc::new(TokenManager::new(Arc::clone(&storage), Arc::clone(&client))),
             // Contract manager has internal cache, so some functions are using `&mut self`.
             // For this reason, we must protect the write operations in order to share
-            // the cache with any possible thread using `index_block_range` of this instance.
+            // the cache between the tasks of this instance.
             contract_manager: Arc::new(AsyncRwLock::new(ContractManager::new(
                 Arc::clone(&storage),
                 Arc::clone(&client),
             ))),
+            currency_manager: Arc::new(AsyncRwLock::new(CurrencyManager::new(Arc::clone(&client)))),
+            price_oracle: None,
+            transaction_analyzers: TransactionAnalyzers::default(),
+            state_diff_discovery: false,
             pending_cache: Arc::new(AsyncRwLock::new(PendingBlockData::new())),
+            marketplace_contracts: crate::config::parse_addresses(&config.marketplace_addresses),
+            udc_contracts: crate::config::parse_addresses(&config.udc_addresses),
+            contract_filter: ContractFilter::from_config(&config),
+            wash_trade_detector: Arc::new(AsyncRwLock::new(WashTradeDetector::from_config(
+                &config,
+            ))),
+            block_events: Arc::new(AsyncMutex::new(None)),
+            unit_of_work: Arc::new(AsyncMutex::new(())),
+            config,
         }
     }
 
+    /// Sets the oracle used to compute the USD value of the sales.
+    pub fn with_price_oracle(mut self, price_oracle: Arc<dyn PriceOracle + Send + Sync>) -> Self {
+        self.price_oracle = Some(price_oracle);
+        self
+    }
+
+    /// Adds an analyzer, run after the default ones on each transaction.
+    pub fn with_transaction_analyzer(
+        mut self,
+        analyzer: Box<dyn TransactionAnalyzer + Send + Sync>,
+    ) -> Self {
+        self.transaction_analyzers.push(analyzer);
+        self
+    }
+
+    /// Enables the discovery of the contracts deployed without the UDC,
+    /// from the state diff of each indexed block.
+    /// This adds one RPC call per block.
+    pub fn with_state_diff_discovery(mut self) -> Self {
+        self.state_diff_discovery = true;
+        self
+    }
+
     /// Starts a loop to only index the pending block.
     pub async fn index_pending(&self) -> IndexerResult<()> {
         loop {
@@ -119,7 +204,7 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
                 Ok((ts, txs)) => (ts, txs),
                 Err(e) => {
                     error!("Error while fetching pending block txs: {:?}", e);
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                    tokio::time::sleep(self.config.retry_delay()).await;
                     continue;
                 }
             };
@@ -143,7 +228,7 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
                     Ok(n) => n,
                     Err(e) => {
                         error!("Error while fetching latest block number: {:?}", e);
-                        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                        tokio::time::sleep(self.config.retry_delay()).await;
                         continue;
                     }
                 };
@@ -161,8 +246,7 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
                 cache.clear_tx_hashes();
             }
 
-            // TODO: make this configurable?
-            tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+            tokio::time::sleep(self.config.pending_poll_interval()).await;
         }
     }
 
@@ -191,6 +275,8 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
             let mut current_block_timestamp: u64 = 0;
 
             for (block_number, events) in result.events {
+                let _unit_of_work = self.unit_of_work.lock().await;
+
                 if current_block_number != block_number {
                     current_block_number = block_number;
 
@@ -241,12 +327,11 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
         // Some contracts are causing too much recursion for the Cairo VM.
         // This is restarting the full node (Juno) as it is OOM and is shutdown by the OS.
         // To mitigate this problem before scaling the full node up,
-        // we setup a `max_attempt` to reach the full node before skipping
+        // we setup a `max_block_retries` to reach the full node before skipping
         // the entire block.
         // Currently, we observed that the node almost always reponds after the
         // second attempt.
-        let max_attempt = 5;
-        let mut attempt = 0;
+        let mut attempt: u32 = 0;
 
         loop {
             trace!("Indexing block range: {} {}", current_u64, to_u64);
@@ -257,7 +342,10 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
             }
 
             let block_ts = match self.client.block_time(BlockId::Number(current_u64)).await {
-                Ok(ts) => ts,
+                Ok(ts) => {
+                    attempt = 0;
+                    ts
+                }
                 Err(e) => {
                     error!(
                         "Attempt #{} - Couldn't get timestamp for block {}: {:?}",
@@ -265,14 +353,21 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
                         current_u64,
                         e
                     );
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                    tokio::time::sleep(self.config.retry_delay()).await;
                     attempt += 1;
 
-                    if attempt > max_attempt {
+                    if attempt > self.config.max_block_retries {
                         warn!(
                             "Skipping block {} as timestamp is not available",
                             current_u64
                         );
+                        self.event_handler
+                            .on_block_skipped(
+                                current_u64,
+                                BlockSkipReason::TimestampUnavailable { attempts: attempt },
+                            )
+                            .await;
+                        attempt = 0;
                         current_u64 += 1;
                     }
 
@@ -291,6 +386,9 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
                 .await?
             {
                 info!("Skipping block {}", current_u64);
+                self.event_handler
+                    .on_block_skipped(current_u64, BlockSkipReason::AlreadyIndexed)
+                    .await;
                 current_u64 += 1;
                 continue;
             }
@@ -321,7 +419,7 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
                 Ok(events) => events,
                 Err(e) => {
                     error!("Error while fetching events: {:?}", e);
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                    tokio::time::sleep(self.config.retry_delay()).await;
                     continue;
                 }
             };
@@ -332,19 +430,21 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
                 current_u64, total_events_count
             );
 
-            for (_, events) in blocks_events {
-                self.process_events(events, block_ts, chain_id).await?;
+            // The block is applied in a single unit of work, to never
+            // leave a partially indexed block in the storage.
+            let _unit_of_work = self.unit_of_work.lock().await;
+            let wash_trades = self.begin_block().await?;
+
+            if let Err(e) = self
+                .apply_block_events(blocks_events, current_u64, block_ts, chain_id)
+                .await
+            {
+                error!("Error while indexing block {}: {:?}", current_u64, e);
+                self.rollback_block(wash_trades).await?;
+                return Err(e);
             }
 
-            self.block_manager
-                .set_block_info(
-                    current_u64,
-                    block_ts,
-                    self.config.indexer_version.clone(),
-                    self.config.indexer_identifier.clone(),
-                    BlockIndexingStatus::Terminated,
-                )
-                .await?;
+            self.commit_block().await?;
 
             let progress = if to_u64 == from_u64 {
                 if current_u64 == to_u64 {
@@ -368,12 +468,338 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
         Ok(())
     }
 
+    /// Indexes again only the events matching the filter, over a range of
+    /// blocks already indexed. The matching transfers and sales of each block
+    /// are cleaned and registered again in a single unit of work, the other
+    /// events and the block info are left unchanged.
+    ///
+    /// Blocks never indexed are skipped, as a full indexing is required for them.
+    /// The reindexed events are emitted again to the event handler.
+    pub async fn reindex_events(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        filter: &ReindexFilter,
+        chain_id: &str,
+    ) -> IndexerResult<()> {
+        let keys = self.event_manager.reindex_keys_selector(filter);
+
+        for block_number in from_block..=to_block {
+            if !self.block_manager.is_block_registered(block_number).await? {
+                self.event_handler
+                    .on_block_skipped(block_number, BlockSkipReason::NotIndexed)
+                    .await;
+                continue;
+            }
+
+            let block_ts = self
+                .client
+                .block_time(BlockId::Number(block_number))
+                .await?;
+
+            let blocks_events = self
+                .client
+                .fetch_all_block_events(BlockId::Number(block_number), keys.clone())
+                .await?;
+
+            let blocks_events: Vec<Vec<EmittedEvent>> = blocks_events
+                .into_values()
+                .map(|events| {
+                    events
+                        .into_iter()
+                        .filter(|e| self.matches_reindex_filter(e, filter))
+                        .collect()
+                })
+                .collect();
+
+            let events_count: usize = blocks_events.iter().map(|events| events.len()).sum();
+            info!(
+                "✨ Reindexing block {}. Matching Events Count: {}.",
+                block_number, events_count
+            );
+
+            let _unit_of_work = self.unit_of_work.lock().await;
+            let wash_trades = self.begin_block().await?;
+
+            let result = async {
+                self.block_manager
+                    .clean_block_events(block_ts, block_number, filter)
+                    .await?;
+
+                for events in blocks_events {
+                    self.process_events(events, block_ts, chain_id).await?;
+                }
+
+                Ok::<_, IndexerError>(())
+            }
+            .await;
+
+            if let Err(e) = result {
+                error!("Error while reindexing block {}: {:?}", block_number, e);
+                self.rollback_block(wash_trades).await?;
+                return Err(e);
+            }
+
+            self.commit_block().await?;
+
+            let progress = if to_block == from_block {
+                100.0
+            } else {
+                ((block_number - from_block) as f64 / (to_block - from_block) as f64) * 100.0
+            };
+
+            self.event_handler
+                .on_block_processed(block_number, progress)
+                .await;
+        }
+
+        self.event_handler.on_indexation_range_completed().await;
+
+        Ok(())
+    }
+
+    /// Returns true if the event is reindexed with the filter.
+    /// The kind of the event is the one used to process it.
+    fn matches_reindex_filter(&self, event: &EmittedEvent, filter: &ReindexFilter) -> bool {
+        let kind = if self.is_udc_event(event) {
+            return false;
+        } else if self.marketplace_contracts.contains(&event.from_address) {
+            ReindexEventKind::Sale
+        } else if is_approval_event(event) {
+            ReindexEventKind::Approval
+        } else {
+            ReindexEventKind::Transfer
+        };
+
+        let contract_address = to_hex_str(&event.from_address);
+        let matches_contract = match kind {
+            ReindexEventKind::Sale => filter.matches_marketplace(&contract_address),
+            _ => filter.matches_nft_contract(&contract_address),
+        };
+
+        filter.matches_kind(kind) && matches_contract
+    }
+
+    /// Opens the unit of work of a block. Returns the state of the wash
+    /// trade detector before the block, to restore on rollback.
+    async fn begin_block(&self) -> IndexerResult<WashTradeDetector> {
+        let wash_trades = self.wash_trade_detector.read().await.clone();
+        self.block_manager.begin_unit_of_work().await?;
+        *self.block_events.lock().await = Some(vec![]);
+        Ok(wash_trades)
+    }
+
+    /// Commits the unit of work of a block, then emits its callbacks.
+    async fn commit_block(&self) -> IndexerResult<()> {
+        let events = self.block_events.lock().await.take().unwrap_or_default();
+        self.block_manager.commit_unit_of_work().await?;
+
+        for event in events {
+            event.dispatch(self.event_handler.as_ref()).await;
+        }
+
+        Ok(())
+    }
+
+    /// Rolls back the unit of work of a block, with the state this instance
+    /// built while applying it: the callbacks are dropped, the cached
+    /// contracts cleared and the wash trade detector restored from `wash_trades`.
+    async fn rollback_block(&self, wash_trades: WashTradeDetector) -> IndexerResult<()> {
+        self.block_events.lock().await.take();
+        self.block_manager.rollback_unit_of_work().await?;
+        self.contract_manager.write().await.reset_caches();
+        *self.wash_trade_detector.write().await = wash_trades;
+        Ok(())
+    }
+
+    /// Emits a callback of the event handler, or holds it until the commit
+    /// when the unit of work of a block is open.
+    async fn emit(&self, event: HandlerEvent) {
+        if let Some(events) = self.block_events.lock().await.as_mut() {
+            events.push(event);
+            return;
+        }
+
+        event.dispatch(self.event_handler.as_ref()).await;
+    }
+
+    /// Processes all the events of a block, and marks the block as terminated.
+    async fn apply_block_events(
+        &self,
+        blocks_events: HashMap<u64, Vec<EmittedEvent>>,
+        block_number: u64,
+        block_timestamp: u64,
+        chain_id: &str,
+    ) -> IndexerResult<()> {
+        self.discover_deployments(&blocks_events, block_number, block_timestamp, chain_id)
+            .await?;
+
+        for (_, events) in blocks_events {
+            self.process_events(events, block_timestamp, chain_id)
+                .await?;
+        }
+
+        self.block_manager
+            .set_block_info(
+                block_number,
+                block_timestamp,
+                self.config.indexer_version.clone(),
+                self.config.indexer_identifier.clone(),
+                BlockIndexingStatus::Terminated,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Registers all the contracts deployed in the block, before
+    /// the events of the block are processed: the constructor of a
+    /// collection emits its first mints before the UDC `ContractDeployed`
+    /// event, which would otherwise find the collection already identified
+    /// without its deployment details.
+    ///
+    /// With the state diff discovery, the classes declared in the block are
+    /// typed from their ABI, and the contracts deployed without the UDC are
+    /// registered too. The deployer is only known for deployments made
+    /// through the UDC.
+    async fn discover_deployments(
+        &self,
+        blocks_events: &HashMap<u64, Vec<EmittedEvent>>,
+        block_number: u64,
+        block_timestamp: u64,
+        chain_id: &str,
+    ) -> IndexerResult<()> {
+        let udc_deployments: Vec<(ContractDeployment, Option<&EmittedEvent>)> = blocks_events
+            .values()
+            .flatten()
+            .filter(|e| self.is_udc_event(e))
+            .filter_map(|e| {
+                EventManager::<S>::format_contract_deployed_event(e).map(|d| (d, Some(e)))
+            })
+            .collect();
+
+        let mut deployments = udc_deployments.clone();
+
+        if self.state_diff_discovery {
+            match self
+                .client
+                .block_state_diff(BlockId::Number(block_number))
+                .await
+            {
+                Ok(state_diff) => {
+                    let declared = state_diff
+                        .declared_classes
+                        .iter()
+                        .map(|c| c.class_hash)
+                        .chain(state_diff.deprecated_declared_classes.iter().copied());
+
+                    for class_hash in declared {
+                        if let Err(e) = self
+                            .contract_manager
+                            .write()
+                            .await
+                            .register_declared_class(class_hash, block_number)
+                            .await
+                        {
+                            warn!(
+                                "Couldn't type class 0x{:064x} declared in block {}: {:?}",
+                                class_hash, block_number, e
+                            );
+                        }
+                    }
+
+                    deployments = state_diff
+                        .deployed_contracts
+                        .into_iter()
+                        .map(|deployed| {
+                            udc_deployments
+                                .iter()
+                                .find(|(d, _)| d.contract_address == deployed.address)
+                                .cloned()
+                                .unwrap_or_else(|| {
+                                    let deployment = ContractDeployment {
+                                        contract_address: deployed.address,
+                                        class_hash: deployed.class_hash,
+                                        deployer_address: None,
+                                        block_number: Some(block_number),
+                                    };
+                                    (deployment, None)
+                                })
+                        })
+                        .collect();
+                }
+                Err(e) => error!(
+                    "Error while fetching state diff of block {}: {:?}",
+                    block_number, e
+                ),
+            }
+        }
+
+        for (deployment, event) in deployments {
+            let result = self
+                .contract_manager
+                .write()
+                .await
+                .register_deployment(&deployment, block_timestamp, chain_id)
+                .await;
+
+            match result {
+                Ok(contract_type) => debug!(
+                    "Deployment of 0x{:064x} identified as {}",
+                    deployment.contract_address,
+                    contract_type.to_string()
+                ),
+                Err(e) => {
+                    error!(
+                        "Error while registering deployment {:?}: {:?}",
+                        deployment, e
+                    );
+                    let failure = EventFailure {
+                        stage: EventStage::ContractDeployment,
+                        chain_id: chain_id.to_string(),
+                        block_number: Some(block_number),
+                        block_timestamp,
+                        transaction_hash: event
+                            .map(|e| to_hex_str(&e.transaction_hash))
+                            .unwrap_or_default(),
+                        contract_address: to_hex_str(
+                            &event.map_or(deployment.contract_address, |e| e.from_address),
+                        ),
+                        error: String::new(),
+                    };
+                    self.report_failure(failure, e).await?;
+                }
+            }
+        }
+
+        self.emit_identified_contracts().await;
+
+        Ok(())
+    }
+
+    /// Emits an event that couldn't be indexed. A database error fails the
+    /// block instead: the event can't be skipped from its unit of work, and
+    /// the transaction may already be aborted.
+    async fn report_failure(
+        &self,
+        mut failure: EventFailure,
+        error: anyhow::Error,
+    ) -> IndexerResult<()> {
+        if let Some(e @ StorageError::DatabaseError(_)) = error.downcast_ref::<StorageError>() {
+            return Err(IndexerError::StorageError(e.clone()));
+        }
+
+        failure.error = format!("{:#}", error);
+        self.emit(HandlerEvent::EventFailed(failure)).await;
+        Ok(())
+    }
+
     async fn process_element_sale(
         &self,
         event: EmittedEvent,
         block_timestamp: u64,
         chain_id: &str,
-    ) -> Result<()> {
+    ) -> Result<Option<TokenSaleEvent>> {
         let mut token_sale_event = self
             .event_manager
             .format_element_sale_event(&event, block_timestamp, chain_id)
@@ -387,6 +813,14 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
             e
         })?;
 
+        if !self.is_contract_accepted(contract_addr).await {
+            debug!(
+                "Sale of filtered contract: {}",
+                token_sale_event.nft_contract_address
+            );
+            return Ok(None);
+        }
+
         let contract_type = match self
             .contract_manager
             .write()
@@ -400,7 +834,7 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
                     "Error while identifying contract {}: {:?}",
                     token_sale_event.nft_contract_address, e
                 );
-                return Ok(());
+                return Ok(None);
             }
         };
 
@@ -409,15 +843,14 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
                 "Contract identified as OTHER: {}",
                 token_sale_event.nft_contract_address
             );
-            return Ok(());
+            return Ok(None);
         }
 
         token_sale_event.nft_type = Some(contract_type.to_string());
-        self.event_manager
-            .register_sale_event(&token_sale_event, block_timestamp)
-            .await?;
+        self.normalize_sale_price(&mut token_sale_event).await;
+        self.compute_sale_usd_price(&mut token_sale_event).await;
 
-        Ok(())
+        Ok(Some(token_sale_event))
     }
 
     async fn process_ventory_sale_or_accepted_offer_event(
@@ -425,7 +858,7 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
         event: EmittedEvent,
         block_timestamp: u64,
         chain_id: &str,
-    ) -> Result<()> {
+    ) -> Result<Option<TokenSaleEvent>> {
         info!("Processing Ventory Sale or Accepted Offer event...");
 
         let mut token_sale_event = self
@@ -441,6 +874,14 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
             e
         })?;
 
+        if !self.is_contract_accepted(contract_addr).await {
+            debug!(
+                "Sale of filtered contract: {}",
+                token_sale_event.nft_contract_address
+            );
+            return Ok(None);
+        }
+
         let contract_type = match self
             .contract_manager
             .write()
@@ -454,7 +895,7 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
                     "Error while identifying contract {}: {:?}",
                     token_sale_event.nft_contract_address, e
                 );
-                return Ok(());
+                return Ok(None);
             }
         };
 
@@ -463,23 +904,117 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
                 "Contract identified as OTHER: {}",
                 token_sale_event.nft_contract_address
             );
-            return Ok(());
+            return Ok(None);
         }
 
         token_sale_event.nft_type = Some(contract_type.to_string());
+        self.normalize_sale_price(&mut token_sale_event).await;
+        self.compute_sale_usd_price(&mut token_sale_event).await;
+
+        Ok(Some(token_sale_event))
+    }
+
+    /// Flags the wash trades, registers the sale, and emits it to the event handler.
+    async fn register_sale(&self, sale: &mut TokenSaleEvent, block_timestamp: u64) -> Result<()> {
+        sale.wash_trade_flags = self.wash_trade_detector.write().await.analyze(sale);
+        if sale.is_wash_trade() {
+            debug!(
+                "Sale {} flagged as wash trade: {:?}",
+                sale.event_id, sale.wash_trade_flags
+            );
+        }
+
         self.event_manager
-            .register_sale_event(&token_sale_event, block_timestamp)
+            .register_sale_event(sale, block_timestamp)
             .await?;
 
+        self.emit(HandlerEvent::SaleRegistered(sale.clone())).await;
+        self.emit(HandlerEvent::EventRegistered(TokenEvent::Sale(
+            sale.clone(),
+        )))
+        .await;
+
         Ok(())
     }
 
+    /// Emits the NFT contracts identified since the last call.
+    async fn emit_identified_contracts(&self) {
+        let contracts = self.contract_manager.write().await.take_identified();
+
+        for contract in contracts {
+            self.emit(HandlerEvent::ContractIdentified(contract)).await;
+        }
+    }
+
+    /// Resolves the sale currency and normalizes the price with its decimals.
+    /// A sale is still registered if the currency can't be resolved.
+    async fn normalize_sale_price(&self, token_sale_event: &mut TokenSaleEvent) {
+        let Some(currency_address) = token_sale_event.currency_address.clone() else {
+            warn!(
+                "Couldn't normalize price of sale {}: sale has no currency",
+                token_sale_event.transaction_hash
+            );
+            return;
+        };
+
+        match self
+            .currency_manager
+            .write()
+            .await
+            .normalize_price(&currency_address, &token_sale_event.price)
+            .await
+        {
+            Ok((currency, normalized_price)) => {
+                token_sale_event.currency_symbol = currency.symbol;
+                token_sale_event.currency_decimals = Some(currency.decimals);
+                token_sale_event.normalized_price = Some(normalized_price);
+            }
+            Err(e) => warn!(
+                "Couldn't normalize price of sale {} (currency: {:?}): {:?}",
+                token_sale_event.transaction_hash, currency_address, e
+            ),
+        }
+    }
+
+    /// Computes the USD value of the sale at the time of the sale,
+    /// if a price oracle is configured.
+    async fn compute_sale_usd_price(&self, token_sale_event: &mut TokenSaleEvent) {
+        let oracle = match &self.price_oracle {
+            Some(oracle) => oracle,
+            None => return,
+        };
+
+        // The USD value is an estimate from the rate, a float is enough.
+        if let (Some(currency_address), Some(normalized_price)) = (
+            &token_sale_event.currency_address,
+            token_sale_event
+                .normalized_price
+                .as_deref()
+                .and_then(|price| price.parse::<f64>().ok()),
+        ) {
+            match oracle
+                .get_usd_rate(currency_address, token_sale_event.timestamp)
+                .await
+            {
+                Ok(Some(rate)) => token_sale_event.price_usd = Some(normalized_price * rate),
+                Ok(None) => debug!(
+                    "No USD rate for currency {} at {}",
+                    currency_address, token_sale_event.timestamp
+                ),
+                Err(e) => warn!(
+                    "Price oracle failed for currency {}: {:?}",
+                    currency_address, e
+                ),
+            }
+        }
+    }
+
     async fn process_marketplace_event(
         &self,
         event: EmittedEvent,
         block_timestamp: u64,
         chain_id: &str,
-    ) -> Result<()> {
+    ) -> Result<Option<TokenSaleEvent>> {
         let element_sale_event_name = FieldElement::from_hex_be(ELEMENT_MARKETPLACE_EVENT_HEX)?;
         let ventory_sale_event_name = FieldElement::from_hex_be(VENTORY_MARKETPLACE_EVENT_HEX)?;
         let ventory_offer_accepted_event_name =
@@ -488,10 +1023,10 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
         if let Some(event_name) = event.keys.first() {
             info!("Processing marketplace event: {:?}", event_name);
 
-            match event_name {
+            return match event_name {
                 name if name == &element_sale_event_name => {
                     self.process_element_sale(event, block_timestamp, chain_id)
-                        .await?
+                        .await
                 }
                 name if name == &ventory_sale_event_name
                     || name == &ventory_offer_accepted_event_name =>
@@ -501,22 +1036,25 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
                         block_timestamp,
                         chain_id,
                     )
-                    .await?
+                    .await
                 }
-                _ => (),
-            }
+                _ => Ok(None),
+            };
         }
 
-        Ok(())
+        Ok(None)
     }
 
+    /// Processes a `Transfer` event. The transfer is returned if the
+    /// contract is an NFT contract, with the mint info of the token if
+    /// it was minted, and `None` for any other contract.
     async fn process_nft_transfers(
         &self,
-        event: EmittedEvent,
+        event: &EmittedEvent,
         block_timestamp: u64,
         contract_address: FieldElement,
         chain_id: &str,
-    ) -> Result<()> {
+    ) -> Result<Option<(TokenTransferEvent, Option<MintEvent>)>> {
         let contract_address_hex = to_hex_str(&contract_address);
         let contract_type = self
             .contract_manager
@@ -534,7 +1072,7 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
 
         if contract_type == ContractType::Other {
             debug!("Contract identified as OTHER: {}", contract_address_hex);
-            return Ok(());
+            return Ok(None);
         }
 
         info!(
@@ -544,14 +1082,15 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
 
         let (token_id, token_event) = self
             .event_manager
-            .format_and_register_event(&event, contract_type, block_timestamp)
+            .format_and_register_event(event, contract_type.clone(), block_timestamp)
             .await
             .map_err(|err| {
                 error!("Error while registering event {:?}\n{:?}", err, event);
                 err
             })?;
 
-        self.token_manager
+        let (token, mint) = self
+            .token_manager
             .format_and_register_token(&token_id, &token_event, block_timestamp, event.block_number)
             .await
             .map_err(|err| {
@@ -559,46 +1098,373 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Pontos<S, C,
                 err
             })?;
 
+        // ERC721 transfers revoke the approval of the token.
+        if contract_type == ContractType::ERC721 && token_event.event_type != EventType::Mint {
+            self.event_manager
+                .clear_token_approval(&token_event)
+                .await
+                .map_err(|err| {
+                    error!("Can't clear token approval {:?}", err);
+                    err
+                })?;
+        }
+
+        let mint = mint.map(|mint| MintEvent {
+            contract_address: token.contract_address.clone(),
+            chain_id: token.chain_id.clone(),
+            token_id: token.token_id.clone(),
+            token_id_hex: token.token_id_hex.clone(),
+            mint,
+        });
+
+        self.emit(HandlerEvent::TokenRegistered(token)).await;
+        self.emit(HandlerEvent::EventRegistered(TokenEvent::Transfer(
+            token_event.clone(),
+        )))
+        .await;
+
+        Ok(Some((token_event, mint)))
+    }
+
+    async fn process_nft_approvals(
+        &self,
+        event: &EmittedEvent,
+        block_timestamp: u64,
+        contract_address: FieldElement,
+        chain_id: &str,
+    ) -> Result<()> {
+        // ERC20 approvals share the `Approval` selector. The Cairo 1 ones are
+        // skipped from their layout, the Cairo 0 ones once their contract is
+        // known, without taking the manager for writing.
+        if EventManager::<S>::is_erc20_approval(event) {
+            return Ok(());
+        }
+
+        let cached = self
+            .contract_manager
+            .read()
+            .await
+            .cached_contract_type(contract_address);
+
+        let contract_type = match cached {
+            Some(contract_type) => contract_type,
+            None => {
+                self.contract_manager
+                    .write()
+                    .await
+                    .identify_contract(contract_address, block_timestamp, chain_id)
+                    .await?
+            }
+        };
+
+        if contract_type == ContractType::Other {
+            return Ok(());
+        }
+
+        let approval = self
+            .event_manager
+            .format_and_register_approval_event(event, contract_type, block_timestamp, chain_id)
+            .await
+            .map_err(|err| {
+                error!("Error while registering approval {:?}\n{:?}", err, event);
+                err
+            })?;
+
+        self.emit(HandlerEvent::ApprovalRegistered(approval)).await;
+
         Ok(())
     }
 
     /// Inner function to process events.
+    ///
+    /// Events are grouped by transaction, to let the analyzers
+    /// inspect all the events of a transaction at once.
     async fn process_events(
         &self,
         events: Vec<EmittedEvent>,
         block_timestamp: u64,
         chain_id: &str,
     ) -> IndexerResult<()> {
-        let marketplace_contracts = [
-            FieldElement::from_hex_be(
-                "0x04d8bb956e6bd7a50fcb8b49d8e9fd8269cfadbeb73f457fd6d3fc1dff4b879e", // Element Marketplace
-            )
-            .unwrap(),
-            FieldElement::from_hex_be(
-                "0x008755a98ccf7d25e69aa90ef3b73b07c470ba4ec6391b0b0c7c598f992c3fee", // Ventory Marketplace
-            )
-            .unwrap(),
-        ];
+        let mut transactions: Vec<(FieldElement, Vec<EmittedEvent>)> = vec![];
+
+        for e in events {
+            match transactions.last_mut() {
+                Some((tx_hash, tx_events)) if *tx_hash == e.transaction_hash => tx_events.push(e),
+                _ => transactions.push((e.transaction_hash, vec![e])),
+            }
+        }
+
+        for (tx_hash, tx_events) in transactions {
+            self.process_transaction_events(tx_hash, tx_events, block_timestamp, chain_id)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Processes all the events of a single transaction, and runs
+    /// the transaction analyzers on the registered events.
+    ///
+    /// The mints are emitted once the analyzers are done, so that a mint
+    /// priced by the analyzers is emitted only once, with its price.
+    async fn process_transaction_events(
+        &self,
+        transaction_hash: FieldElement,
+        events: Vec<EmittedEvent>,
+        block_timestamp: u64,
+        chain_id: &str,
+    ) -> IndexerResult<()> {
+        let block_number = events.first().and_then(|e| e.block_number);
+        let mut tx = TransactionEvents::new(
+            to_hex_str(&transaction_hash),
+            block_timestamp,
+            block_number,
+            chain_id,
+        );
+
+        let failure = |stage: EventStage, contract_address: String| EventFailure {
+            stage,
+            chain_id: chain_id.to_string(),
+            block_number,
+            block_timestamp,
+            transaction_hash: to_hex_str(&transaction_hash),
+            contract_address,
+            error: String::new(),
+        };
+
+        let mut mints: Vec<MintEvent> = vec![];
 
         for e in events {
             let contract_address = e.from_address;
-            let is_marketplace_event = marketplace_contracts.contains(&contract_address);
+            let is_marketplace_event = self.marketplace_contracts.contains(&contract_address);
 
             if is_marketplace_event {
-                if let Err(e) = self
+                match self
                     .process_marketplace_event(e, block_timestamp, chain_id)
                     .await
                 {
-                    error!("Error while processing marketplace event: {:?}", e);
+                    Ok(Some(sale)) => tx.sales.push(sale),
+                    Ok(None) => (),
+                    Err(e) => {
+                        error!("Error while processing marketplace event: {:?}", e);
+                        self.report_failure(
+                            failure(EventStage::MarketplaceSale, to_hex_str(&contract_address)),
+                            e,
+                        )
+                        .await?;
+                    }
+                }
+            } else if self.is_udc_event(&e) {
+                // Registered with the deployments of the block, before its events.
+                trace!("Contract deployment {:?}", e.data.first());
+            } else if !self.is_contract_accepted(contract_address).await {
+                trace!(
+                    "Event of filtered contract: {}",
+                    to_hex_str(&contract_address)
+                );
+
+                // Filtered contracts may still be ERC20 currencies.
+                if let Some(payment) = EventManager::<S>::format_currency_transfer_event(&e) {
+                    tx.add_payment(payment);
+                }
+            } else if is_approval_event(&e) {
+                if let Err(e) = self
+                    .process_nft_approvals(&e, block_timestamp, contract_address, chain_id)
+                    .await
+                {
+                    error!("Error while processing NFT approvals: {:?}", e);
+                    self.report_failure(
+                        failure(EventStage::Approval, to_hex_str(&contract_address)),
+                        e,
+                    )
+                    .await?;
+                }
+            } else {
+                match self
+                    .process_nft_transfers(&e, block_timestamp, contract_address, chain_id)
+                    .await
+                {
+                    Ok(Some((transfer, mint))) => {
+                        tx.transfers.push(transfer);
+                        mints.extend(mint);
+                    }
+                    Ok(None) => {
+                        // Transfers of other contracts may be ERC20 payments.
+                        if let Some(payment) = EventManager::<S>::format_currency_transfer_event(&e)
+                        {
+                            tx.add_payment(payment);
+                        }
+                    }
+                    Err(e) => {
+                        error!("Error while processing NFT transfers: {:?}", e);
+                        self.report_failure(
+                            failure(EventStage::Transfer, to_hex_str(&contract_address)),
+                            e,
+                        )
+                        .await?;
+                    }
+                }
+            }
+        }
+
+        self.emit_identified_contracts().await;
+        self.flag_mass_airdrops(&tx.transfers, chain_id).await?;
+
+        // The marketplace sales are registered once all the payments
+        // of the transaction are known, to detect the funding of the buyer.
+        self.wash_trade_detector
+            .write()
+            .await
+            .record_payments(&tx.payments, block_number);
+
+        for mut sale in std::mem::take(&mut tx.sales) {
+            match self.register_sale(&mut sale, block_timestamp).await {
+                Ok(()) => tx.sales.push(sale),
+                Err(e) => {
+                    error!("Error while registering marketplace sale: {:?}", e);
+                    self.report_failure(
+                        failure(
+                            EventStage::MarketplaceSale,
+                            sale.marketplace_contract_address.clone(),
+                        ),
+                        e,
+                    )
+                    .await?;
+                }
+            }
+        }
+
+        if tx.transfers.is_empty() && tx.sales.is_empty() {
+            return Ok(());
+        }
+
+        let records = self.transaction_analyzers.analyze(&mut tx);
+
+        for record in &records {
+            match record {
+                TransactionRecord::Sale(sale) => {
+                    let mut sale = sale.clone();
+                    self.normalize_sale_price(&mut sale).await;
+                    self.compute_sale_usd_price(&mut sale).await;
+
+                    if let Err(e) = self.register_sale(&mut sale, block_timestamp).await {
+                        error!("Error while registering detected sale: {:?}", e);
+                        self.report_failure(
+                            failure(EventStage::DetectedSale, sale.nft_contract_address.clone()),
+                            e,
+                        )
+                        .await?;
+                    }
                 }
-            } else if let Err(e) = self
-                .process_nft_transfers(e, block_timestamp, contract_address, chain_id)
+                TransactionRecord::MintPrice(mint_price) => match self
+                    .token_manager
+                    .register_mint_price(mint_price, block_timestamp, block_number)
+                    .await
+                {
+                    Ok(mint) => {
+                        let priced = MintEvent {
+                            contract_address: mint_price.contract_address.clone(),
+                            chain_id: mint_price.chain_id.clone(),
+                            token_id: mint_price.token_id.clone(),
+                            token_id_hex: mint_price.token_id_hex.clone(),
+                            mint,
+                        };
+
+                        match mints.iter_mut().find(|m| {
+                            m.contract_address == priced.contract_address
+                                && m.token_id_hex == priced.token_id_hex
+                        }) {
+                            Some(m) => *m = priced,
+                            None => mints.push(priced),
+                        }
+                    }
+                    Err(e) => {
+                        error!("Error while registering mint price: {:?}", e);
+                        self.report_failure(
+                            failure(EventStage::MintPrice, mint_price.contract_address.clone()),
+                            e,
+                        )
+                        .await?;
+                    }
+                },
+                TransactionRecord::Sweep(_) => (),
+            }
+        }
+
+        for mint in mints {
+            self.emit(HandlerEvent::MintRegistered(mint)).await;
+        }
+
+        if !records.is_empty() {
+            self.emit(HandlerEvent::TransactionAnalyzed(records)).await;
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if the events of the contract are indexed, according
+    /// to the allow and deny lists of the configuration.
+    async fn is_contract_accepted(&self, contract_address: FieldElement) -> bool {
+        if self.contract_filter.is_empty() {
+            return true;
+        }
+
+        let class_hash = if self.contract_filter.needs_class_hash() {
+            self.contract_manager
+                .write()
+                .await
+                .class_hash_of(contract_address)
+                .await
+        } else {
+            None
+        };
+
+        self.contract_filter.accepts(contract_address, class_hash)
+    }
+
+    /// Raises the spam score of the collections minted to many
+    /// addresses by the transaction.
+    async fn flag_mass_airdrops(
+        &self,
+        transfers: &[TokenTransferEvent],
+        chain_id: &str,
+    ) -> IndexerResult<()> {
+        for contract in spam::mass_airdrops(transfers, self.config.spam_airdrop_min_recipients) {
+            let address = match FieldElement::from_hex_be(&contract) {
+                Ok(address) => address,
+                Err(_) => continue,
+            };
+
+            if let Err(e) = self
+                .contract_manager
+                .write()
+                .await
+                .flag_mass_airdrop(address, chain_id)
                 .await
             {
-                error!("Error while processing NFT transfers: {:?}", e);
+                error!("Error while flagging mass airdrop of {}: {:?}", contract, e);
+
+                if let StorageError::DatabaseError(_) = e {
+                    return Err(e.into());
+                }
             }
         }
 
         Ok(())
     }
+
+    /// Returns true if the event is a `ContractDeployed` event emitted by the UDC.
+    fn is_udc_event(&self, event: &EmittedEvent) -> bool {
+        event.keys.first() == Some(&managers::event_manager::CONTRACT_DEPLOYED_SELECTOR)
+            && self.udc_contracts.contains(&event.from_address)
+    }
+}
+
+/// Returns true if the event is an `Approval` or `ApprovalForAll` event.
+fn is_approval_event(event: &EmittedEvent) -> bool {
+    matches!(
+        event.keys.first(),
+        Some(k) if *k == managers::event_manager::APPROVAL_SELECTOR
+            || *k == managers::event_manager::APPROVAL_FOR_ALL_SELECTOR
+    )
 }
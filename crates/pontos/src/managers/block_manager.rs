@@ -1,4 +1,4 @@
-use crate::storage::types::{BlockIndexingStatus, BlockInfo, StorageError};
+use crate::storage::types::{BlockIndexingStatus, BlockInfo, ReindexFilter, StorageError};
 use crate::storage::Storage;
 use starknet::core::types::FieldElement;
 use std::sync::Arc;
@@ -27,6 +27,40 @@ impl<S: Storage> BlockManager<S> {
             .await
     }
 
+    /// Removes the events of the block matching the filter of a selective reindex.
+    pub async fn clean_block_events(
+        &self,
+        block_timestamp: u64,
+        block_number: u64,
+        filter: &ReindexFilter,
+    ) -> Result<(), StorageError> {
+        self.storage
+            .clean_block_events(block_timestamp, block_number, filter)
+            .await
+    }
+
+    /// Returns true if the block has a block info, whatever its status.
+    pub async fn is_block_registered(&self, block_number: u64) -> Result<bool, StorageError> {
+        match self.storage.get_block_info(block_number).await {
+            Ok(_) => Ok(true),
+            Err(StorageError::NotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Starts the unit of work in which a block is indexed.
+    pub async fn begin_unit_of_work(&self) -> Result<(), StorageError> {
+        self.storage.begin_unit_of_work().await
+    }
+
+    pub async fn commit_unit_of_work(&self) -> Result<(), StorageError> {
+        self.storage.commit_unit_of_work().await
+    }
+
+    pub async fn rollback_unit_of_work(&self) -> Result<(), StorageError> {
+        self.storage.rollback_unit_of_work().await
+    }
+
     /// Returns false if the given block number must be indexed.
     /// True otherwise.
     pub async fn should_skip_indexing(
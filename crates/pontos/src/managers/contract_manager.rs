@@ -1,3 +1,4 @@
+use crate::spam::SpamSignals;
 use crate::storage::{
     types::{ContractInfo, ContractType, StorageError},
     Storage,
@@ -9,18 +10,39 @@ use ark_starknet::{
     format::to_hex_str,
 };
 use starknet::core::{
-    types::{BlockId, BlockTag, FieldElement},
+    types::{BlockId, BlockTag, ContractClass, FieldElement, LegacyContractAbiEntry},
     utils::get_selector_from_name,
 };
 use std::collections::HashMap;
 use std::sync::Arc;
-use tracing::{error, info, trace};
+use tracing::{error, info, trace, warn};
+
+/// A contract deployment, discovered from the UDC `ContractDeployed`
+/// event or from the state diff of the block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractDeployment {
+    pub contract_address: FieldElement,
+    pub class_hash: FieldElement,
+    /// Only known for deployments made through the UDC.
+    pub deployer_address: Option<FieldElement>,
+    pub block_number: Option<u64>,
+}
 
 pub struct ContractManager<S: Storage, C: StarknetClient> {
     storage: Arc<S>,
     client: Arc<C>,
     /// A cache with contract address mapped to its type.
     cache: HashMap<FieldElement, ContractType>,
+    /// A cache with class hash mapped to the type of the contracts
+    /// deployed from this class.
+    class_cache: HashMap<FieldElement, ContractType>,
+    /// A cache with contract address mapped to its class hash,
+    /// `None` if it couldn't be fetched.
+    class_hashes: HashMap<FieldElement, Option<FieldElement>>,
+    /// Spam signals of the NFT contracts found so far.
+    spam_signals: HashMap<FieldElement, SpamSignals>,
+    /// NFT contracts registered since the last `take_identified`.
+    identified: Vec<ContractInfo>,
 }
 
 impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
@@ -30,6 +52,10 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
             storage,
             client,
             cache: HashMap::new(),
+            class_cache: HashMap::new(),
+            class_hashes: HashMap::new(),
+            spam_signals: HashMap::new(),
+            identified: vec![],
         }
     }
 
@@ -55,6 +81,12 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
         Ok(contract_type)
     }
 
+    /// Returns the type of the contract if already known by this instance,
+    /// without any storage nor RPC call.
+    pub fn cached_contract_type(&self, address: FieldElement) -> Option<ContractType> {
+        self.cache.get(&address).cloned()
+    }
+
     /// Identifies a contract from its address and caches its info.
     ///
     /// This function attempts to identify a contract by its address,
@@ -74,66 +106,256 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
     ) -> Result<ContractType> {
         match self.get_cached_or_fetch_info(address, chain_id).await {
             Ok(contract_type) => Ok(contract_type),
-            Err(_) => {
-                if let Ok(contract_type) = self.get_cached_or_fetch_info(address, chain_id).await {
-                    return Ok(contract_type);
-                }
-
+            Err(StorageError::NotFound(_)) => {
                 // If the contract info is not cached, identify and cache it.
                 let contract_type = self.get_contract_type(address).await?;
 
                 self.cache.insert(address, contract_type.clone());
 
-                let name = self
-                    .get_contract_property_string(
-                        address,
-                        "name",
-                        vec![],
-                        BlockId::Tag(BlockTag::Pending),
-                    )
-                    .await
-                    .ok();
-
-                let symbol = self
-                    .get_contract_property_string(
-                        address,
-                        "symbol",
-                        vec![],
-                        BlockId::Tag(BlockTag::Pending),
-                    )
-                    .await
-                    .ok();
-
-                info!(
-                    "Contract [0x{:064x}] details - Type: {}, Name: {:?}, Symbol: {:?}",
-                    address,
-                    contract_type.to_string(),
-                    name,
-                    symbol
-                );
-
-                let info = ContractInfo {
-                    contract_address: to_hex_str(&address),
-                    contract_type: contract_type.to_string(),
-                    name,
-                    symbol,
-                    image: None,
-                    chain_id: chain_id.to_string(),
-                };
-
-                if let Err(e) = self
-                    .storage
+                let mut info = self
+                    .get_contract_info(address, &contract_type, chain_id)
+                    .await;
+
+                if contract_type != ContractType::Other {
+                    self.score_spam(address, &mut info);
+                }
+
+                self.storage
                     .register_contract_info(&info, block_timestamp, chain_id)
                     .await
-                {
-                    error!(
-                        "Failed to store contract info for [0x{:064x}]: {:?}",
-                        address, e
-                    );
+                    .map_err(|e| {
+                        error!(
+                            "Failed to store contract info for [0x{:064x}]: {:?}",
+                            address, e
+                        );
+                        e
+                    })?;
+
+                if contract_type != ContractType::Other {
+                    self.identified.push(info);
                 }
 
                 Ok(contract_type)
             }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Registers a newly deployed contract with its deployment details,
+    /// before any of its events is indexed.
+    ///
+    /// Contracts already known are skipped. The type of the contracts
+    /// is cached by class hash, to avoid identifying every deployment
+    /// of the same class (like accounts) with calls.
+    pub async fn register_deployment(
+        &mut self,
+        deployment: &ContractDeployment,
+        block_timestamp: u64,
+        chain_id: &str,
+    ) -> Result<ContractType> {
+        let address = deployment.contract_address;
+
+        match self.get_cached_or_fetch_info(address, chain_id).await {
+            Ok(contract_type) => return Ok(contract_type),
+            Err(StorageError::NotFound(_)) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let contract_type = match self.class_cache.get(&deployment.class_hash) {
+            Some(contract_type) => contract_type.clone(),
+            None => {
+                let contract_type = self.get_contract_type(address).await?;
+                self.class_cache
+                    .insert(deployment.class_hash, contract_type.clone());
+                contract_type
+            }
+        };
+
+        self.cache.insert(address, contract_type.clone());
+
+        let mut info = if contract_type == ContractType::Other {
+            ContractInfo {
+                contract_address: to_hex_str(&address),
+                contract_type: contract_type.to_string(),
+                chain_id: chain_id.to_string(),
+                ..Default::default()
+            }
+        } else {
+            self.get_contract_info(address, &contract_type, chain_id)
+                .await
+        };
+
+        info.deployed_block_number = deployment.block_number;
+        info.deployer_address = deployment.deployer_address.map(|a| to_hex_str(&a));
+        info.class_hash = Some(to_hex_str(&deployment.class_hash));
+        self.class_hashes
+            .insert(address, Some(deployment.class_hash));
+
+        if contract_type != ContractType::Other {
+            self.score_spam(address, &mut info);
+        }
+
+        self.storage
+            .register_contract_info(&info, block_timestamp, chain_id)
+            .await?;
+
+        if contract_type != ContractType::Other {
+            self.identified.push(info);
+        }
+
+        Ok(contract_type)
+    }
+
+    /// Types a class declared in the block from its ABI, so that the
+    /// contracts later deployed from it are identified without any call.
+    pub async fn register_declared_class(
+        &mut self,
+        class_hash: FieldElement,
+        block_number: u64,
+    ) -> Result<ContractType> {
+        if let Some(contract_type) = self.class_cache.get(&class_hash) {
+            return Ok(contract_type.clone());
+        }
+
+        let class = self
+            .client
+            .class_by_hash(class_hash, BlockId::Number(block_number))
+            .await?;
+
+        let contract_type = contract_type_from_abi(&abi_functions(&class));
+        trace!(
+            "Class [0x{:064x}] declared as {}",
+            class_hash,
+            contract_type.to_string()
+        );
+
+        self.class_cache.insert(class_hash, contract_type.clone());
+
+        Ok(contract_type)
+    }
+
+    /// Returns the class hash of a contract, `None` if it can't be fetched.
+    pub async fn class_hash_of(&mut self, address: FieldElement) -> Option<FieldElement> {
+        if let Some(class_hash) = self.class_hashes.get(&address) {
+            return *class_hash;
+        }
+
+        let class_hash = match self
+            .client
+            .class_hash_at(address, BlockId::Tag(BlockTag::Pending))
+            .await
+        {
+            Ok(class_hash) => Some(class_hash),
+            Err(e) => {
+                warn!("Couldn't get class hash of [0x{:064x}]: {:?}", address, e);
+                None
+            }
+        };
+
+        self.class_hashes.insert(address, class_hash);
+        class_hash
+    }
+
+    /// Raises the spam score of a collection minted to many addresses
+    /// in a single transaction. Only the first airdrop is registered.
+    ///
+    /// The signals are kept in memory: for a collection identified before
+    /// a restart, those of its identification are rebuilt from its name
+    /// and symbol, so that the raised score includes them.
+    pub async fn flag_mass_airdrop(
+        &mut self,
+        address: FieldElement,
+        chain_id: &str,
+    ) -> Result<(), StorageError> {
+        if !self.spam_signals.contains_key(&address) {
+            let contract_type = self.get_cached_or_fetch_info(address, chain_id).await?;
+            let info = self
+                .get_contract_info(address, &contract_type, chain_id)
+                .await;
+            self.spam_signals
+                .insert(address, SpamSignals::from_contract(&info));
+        }
+
+        let signals = self.spam_signals.entry(address).or_default();
+
+        if signals.mass_airdrop {
+            return Ok(());
+        }
+
+        signals.mass_airdrop = true;
+        let score = signals.score();
+
+        info!(
+            "Contract [0x{:064x}] mass airdropped, spam score {}",
+            address, score
+        );
+
+        self.storage
+            .raise_contract_spam_score(&to_hex_str(&address), chain_id, score)
+            .await
+    }
+
+    /// Scores the contract from its info, before its registration.
+    fn score_spam(&mut self, address: FieldElement, info: &mut ContractInfo) {
+        let signals = SpamSignals::from_contract(info);
+        info.spam_score = Some(signals.score());
+        self.spam_signals.insert(address, signals);
+    }
+
+    /// Returns the NFT contracts identified and registered since the last call.
+    pub fn take_identified(&mut self) -> Vec<ContractInfo> {
+        std::mem::take(&mut self.identified)
+    }
+
+    /// Drops everything cached by this instance, when the unit of work of a
+    /// block is rolled back: the contracts cached during the block are not
+    /// stored anymore, and are identified again from the storage or the chain.
+    pub fn reset_caches(&mut self) {
+        self.cache.clear();
+        self.class_cache.clear();
+        self.class_hashes.clear();
+        self.spam_signals.clear();
+        self.identified.clear();
+    }
+
+    /// Fetches the name and symbol of the contract.
+    async fn get_contract_info(
+        &self,
+        address: FieldElement,
+        contract_type: &ContractType,
+        chain_id: &str,
+    ) -> ContractInfo {
+        let name = self
+            .get_contract_property_string(address, "name", vec![], BlockId::Tag(BlockTag::Pending))
+            .await
+            .ok();
+
+        let symbol = self
+            .get_contract_property_string(
+                address,
+                "symbol",
+                vec![],
+                BlockId::Tag(BlockTag::Pending),
+            )
+            .await
+            .ok();
+
+        info!(
+            "Contract [0x{:064x}] details - Type: {}, Name: {:?}, Symbol: {:?}",
+            address,
+            contract_type.to_string(),
+            name,
+            symbol
+        );
+
+        ContractInfo {
+            contract_address: to_hex_str(&address),
+            contract_type: contract_type.to_string(),
+            name,
+            symbol,
+            image: None,
+            chain_id: chain_id.to_string(),
+            ..Default::default()
         }
     }
 
@@ -172,6 +394,7 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
                     }
                 }
                 StarknetClientError::EntrypointNotFound(_) => (),
+                e @ StarknetClientError::Provider(_) => return Err(e.into()),
                 _ => return Ok(false),
             },
         };
@@ -191,6 +414,7 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
                     }
                 }
                 StarknetClientError::EntrypointNotFound(_) => Ok(false),
+                e @ StarknetClientError::Provider(_) => Err(e.into()),
                 _ => Ok(false),
             },
         }
@@ -215,6 +439,7 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
             Err(e) => match e {
                 StarknetClientError::EntrypointNotFound(_) => (),
                 StarknetClientError::InputTooLong => return Ok(false), // ERC20.
+                e @ StarknetClientError::Provider(_) => return Err(e.into()),
                 _ => return Ok(false),
             },
         };
@@ -227,6 +452,7 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
             Err(e) => match e {
                 StarknetClientError::EntrypointNotFound(_) => Ok(false),
                 StarknetClientError::InputTooLong => Ok(false), // ERC20.
+                e @ StarknetClientError::Provider(_) => Err(e.into()),
                 _ => Ok(false),
             },
         }
@@ -275,3 +501,213 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
         })
     }
 }
+
+/// Returns the name and the number of inputs of the functions of a class,
+/// including the functions of the interfaces of a Sierra class.
+fn abi_functions(class: &ContractClass) -> Vec<(String, usize)> {
+    match class {
+        ContractClass::Sierra(class) => {
+            let abi: serde_json::Value = serde_json::from_str(&class.abi).unwrap_or_default();
+            let mut functions = vec![];
+            collect_sierra_functions(&abi, &mut functions);
+            functions
+        }
+        ContractClass::Legacy(class) => class
+            .abi
+            .iter()
+            .flatten()
+            .filter_map(|entry| match entry {
+                LegacyContractAbiEntry::Function(f) => Some((f.name.clone(), f.inputs.len())),
+                _ => None,
+            })
+            .collect(),
+    }
+}
+
+fn collect_sierra_functions(abi: &serde_json::Value, functions: &mut Vec<(String, usize)>) {
+    for entry in abi.as_array().into_iter().flatten() {
+        match entry["type"].as_str() {
+            Some("function") => {
+                if let Some(name) = entry["name"].as_str() {
+                    let inputs = entry["inputs"].as_array().map_or(0, |i| i.len());
+                    functions.push((name.to_string(), inputs));
+                }
+            }
+            Some("interface") => collect_sierra_functions(&entry["items"], functions),
+            _ => (),
+        }
+    }
+}
+
+/// Types a class from its functions, with the same rules as the calls
+/// of `get_contract_type`: `owner_of` is specific to ERC721, and only
+/// the ERC1155 `balance_of` takes an account and a token id.
+fn contract_type_from_abi(functions: &[(String, usize)]) -> ContractType {
+    let has = |names: [&str; 2], inputs: usize| {
+        functions
+            .iter()
+            .any(|(name, n)| names.contains(&name.as_str()) && *n == inputs)
+    };
+
+    if has(["ownerOf", "owner_of"], 1) {
+        ContractType::ERC721
+    } else if has(["balanceOf", "balance_of"], 2) {
+        ContractType::ERC1155
+    } else {
+        ContractType::Other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MockStorage;
+    use ark_starknet::client::MockStarknetClient;
+
+    #[tokio::test]
+    async fn test_register_deployment_uses_class_cache() {
+        let mut mock_storage = MockStorage::default();
+        let mut mock_client = MockStarknetClient::default();
+
+        mock_storage.expect_get_contract_type().returning(|_, _| {
+            Box::pin(futures::future::ready(Err(StorageError::NotFound(
+                String::new(),
+            ))))
+        });
+
+        mock_storage
+            .expect_register_contract_info()
+            .withf(|info, _, _| {
+                info.contract_type == "ERC721"
+                    && info.deployed_block_number == Some(10)
+                    && info.class_hash == Some(to_hex_str(&FieldElement::from(0xc1a55_u64)))
+            })
+            .times(2)
+            .returning(|_, _, _| Box::pin(futures::future::ready(Ok(()))));
+
+        // `ownerOf` for the first deployment only, then `name` and `symbol`
+        // for each deployment.
+        mock_client
+            .expect_call_contract()
+            .times(5)
+            .returning(|_, _, _, _| Ok(vec![]));
+
+        let mut manager = ContractManager::new(Arc::new(mock_storage), Arc::new(mock_client));
+
+        for address in [1_u64, 2_u64] {
+            let deployment = ContractDeployment {
+                contract_address: FieldElement::from(address),
+                class_hash: FieldElement::from(0xc1a55_u64),
+                deployer_address: None,
+                block_number: Some(10),
+            };
+
+            let contract_type = manager
+                .register_deployment(&deployment, 1000, "SN_MAIN")
+                .await
+                .unwrap();
+
+            assert_eq!(contract_type, ContractType::ERC721);
+        }
+
+        let identified = manager.take_identified();
+        assert_eq!(identified.len(), 2);
+        assert_eq!(identified[0].deployed_block_number, Some(10));
+        assert!(manager.take_identified().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reset_caches_after_rollback() {
+        let mut mock_storage = MockStorage::default();
+        let mut mock_client = MockStarknetClient::default();
+
+        mock_storage.expect_get_contract_type().returning(|_, _| {
+            Box::pin(futures::future::ready(Err(StorageError::NotFound(
+                String::new(),
+            ))))
+        });
+        mock_storage
+            .expect_register_contract_info()
+            .returning(|_, _, _| Box::pin(futures::future::ready(Ok(()))));
+        mock_client
+            .expect_call_contract()
+            .returning(|_, _, _, _| Ok(vec![]));
+
+        let mut manager = ContractManager::new(Arc::new(mock_storage), Arc::new(mock_client));
+        let deployment = ContractDeployment {
+            contract_address: FieldElement::from(1_u64),
+            class_hash: FieldElement::from(0xc1a55_u64),
+            deployer_address: None,
+            block_number: Some(10),
+        };
+
+        manager
+            .register_deployment(&deployment, 1000, "SN_MAIN")
+            .await
+            .unwrap();
+        assert_eq!(
+            manager.cached_contract_type(deployment.contract_address),
+            Some(ContractType::ERC721)
+        );
+
+        manager.reset_caches();
+
+        assert_eq!(
+            manager.cached_contract_type(deployment.contract_address),
+            None
+        );
+        assert!(manager.take_identified().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_flag_mass_airdrop_rebuilds_signals() {
+        let mut mock_storage = MockStorage::default();
+        let mut mock_client = MockStarknetClient::default();
+
+        mock_storage
+            .expect_get_contract_type()
+            .returning(|_, _| Box::pin(futures::future::ready(Ok(ContractType::ERC721))));
+
+        // No metadata (20) and the airdrop (50), raised only once.
+        mock_storage
+            .expect_raise_contract_spam_score()
+            .withf(|_, _, score| *score == 70)
+            .times(1)
+            .returning(|_, _, _| Box::pin(futures::future::ready(Ok(()))));
+
+        mock_client
+            .expect_call_contract()
+            .times(2)
+            .returning(|_, _, _, _| Err(StarknetClientError::Other("reverted".to_string())));
+
+        let mut manager = ContractManager::new(Arc::new(mock_storage), Arc::new(mock_client));
+        let address = FieldElement::from(1_u64);
+
+        manager.flag_mass_airdrop(address, "SN_MAIN").await.unwrap();
+        manager.flag_mass_airdrop(address, "SN_MAIN").await.unwrap();
+    }
+
+    #[test]
+    fn test_contract_type_from_abi() {
+        let abi = r#"[
+            {"type": "impl", "name": "ERC721Impl", "interface_name": "IERC721"},
+            {"type": "interface", "name": "IERC721", "items": [
+                {"type": "function", "name": "balance_of", "inputs": [{"name": "account"}]},
+                {"type": "function", "name": "owner_of", "inputs": [{"name": "token_id"}]}
+            ]},
+            {"type": "event", "name": "Transfer"}
+        ]"#;
+        let abi: serde_json::Value = serde_json::from_str(abi).unwrap();
+        let mut functions = vec![];
+        collect_sierra_functions(&abi, &mut functions);
+
+        assert_eq!(functions.len(), 2);
+        assert_eq!(contract_type_from_abi(&functions), ContractType::ERC721);
+
+        let erc1155 = vec![("balanceOf".to_string(), 2)];
+        assert_eq!(contract_type_from_abi(&erc1155), ContractType::ERC1155);
+
+        let erc20 = vec![("balanceOf".to_string(), 1), ("transfer".to_string(), 2)];
+        assert_eq!(contract_type_from_abi(&erc20), ContractType::Other);
+    }
+}
@@ -1,7 +1,13 @@
-use crate::storage::types::{EventType, TokenSaleEvent, TokenTransferEvent};
+use crate::analyzers::CurrencyTransferEvent;
+use crate::managers::contract_manager::ContractDeployment;
+use crate::storage::types::{
+    ApprovalEvent, EventType, OperatorApproval, ReindexEventKind, ReindexFilter, TokenApproval,
+    TokenSaleEvent, TokenTransferEvent,
+};
 use crate::storage::Storage;
 use crate::{
-    ContractType, VENTORY_MARKETPLACE_EVENT_HEX, VENTORY_MARKETPLACE_OFFER_ACCEPTED_EVENT_HEX,
+    ContractType, VENTORY_MARKETPLACE_CURRENCY_HEX, VENTORY_MARKETPLACE_EVENT_HEX,
+    VENTORY_MARKETPLACE_OFFER_ACCEPTED_EVENT_HEX,
 };
 use anyhow::{anyhow, Result};
 use ark_starknet::{format::to_hex_str, CairoU256};
@@ -13,12 +19,16 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::trace;
 
 const TRANSFER_SELECTOR: FieldElement = selector!("Transfer");
+pub(crate) const APPROVAL_SELECTOR: FieldElement = selector!("Approval");
+pub(crate) const APPROVAL_FOR_ALL_SELECTOR: FieldElement = selector!("ApprovalForAll");
+pub(crate) const CONTRACT_DEPLOYED_SELECTOR: FieldElement = selector!("ContractDeployed");
 const ELEMENT_NFT_MARKETPLACE_HEX: &str =
     "0x351e5a57ea6ca22e3e3cd212680ef7f3b57404609bda942a5e75ba4724b55e0";
 
 #[derive(Debug)]
 pub struct EventManager<S: Storage> {
     storage: Arc<S>,
+    ventory_currency_address: String,
 }
 
 impl<S: Storage> EventManager<S> {
@@ -26,9 +36,16 @@ impl<S: Storage> EventManager<S> {
     pub fn new(storage: Arc<S>) -> Self {
         EventManager {
             storage: Arc::clone(&storage),
+            ventory_currency_address: VENTORY_MARKETPLACE_CURRENCY_HEX.to_string(),
         }
     }
 
+    /// Sets the currency of the Ventory sales.
+    pub fn with_ventory_currency_address(mut self, address: &str) -> Self {
+        self.ventory_currency_address = address.to_string();
+        self
+    }
+
     /// Returns the selectors used to filter events.
     pub fn keys_selector(&self) -> Option<Vec<Vec<FieldElement>>> {
         let element_nft_marketplace = FieldElement::from_hex_be(ELEMENT_NFT_MARKETPLACE_HEX)
@@ -43,12 +60,43 @@ impl<S: Storage> EventManager<S> {
 
         Some(vec![vec![
             TRANSFER_SELECTOR,
+            APPROVAL_SELECTOR,
+            APPROVAL_FOR_ALL_SELECTOR,
+            CONTRACT_DEPLOYED_SELECTOR,
             element_nft_marketplace,
             ventory_nft_marketplace,
             ventory_accepted_offer_event,
         ]])
     }
 
+    /// Returns the selectors of the event kinds of a selective reindex.
+    /// Contract deployments are never reindexed.
+    pub fn reindex_keys_selector(&self, filter: &ReindexFilter) -> Option<Vec<Vec<FieldElement>>> {
+        let mut keys = vec![];
+
+        if filter.matches_kind(ReindexEventKind::Transfer) {
+            keys.push(TRANSFER_SELECTOR);
+        }
+
+        if filter.matches_kind(ReindexEventKind::Approval) {
+            keys.extend([APPROVAL_SELECTOR, APPROVAL_FOR_ALL_SELECTOR]);
+        }
+
+        if filter.matches_kind(ReindexEventKind::Sale) {
+            for hex in [
+                ELEMENT_NFT_MARKETPLACE_HEX,
+                VENTORY_MARKETPLACE_EVENT_HEX,
+                VENTORY_MARKETPLACE_OFFER_ACCEPTED_EVENT_HEX,
+            ] {
+                keys.push(
+                    FieldElement::from_hex_be(hex).expect("Failed to parse marketplace selector"),
+                );
+            }
+        }
+
+        Some(vec![keys])
+    }
+
     pub async fn register_sale_event(
         &self,
         event: &TokenSaleEvent,
@@ -95,12 +143,9 @@ impl<S: Storage> EventManager<S> {
             .get(6)
             .ok_or_else(|| anyhow!("Status not found"))?;
 
-        let token_id = CairoU256 {
-            low: (*token_id)
-                .try_into()
-                .map_err(|_| anyhow!("Failed to parse token id"))?,
-            high: 0,
-        };
+        // Ventory emits the token id as a single felt, which may
+        // be greater than a u128.
+        let token_id = CairoU256::from_felt(token_id);
 
         let event_id = Self::get_event_id(&token_id, seller, buyer, block_timestamp, event);
 
@@ -118,11 +163,16 @@ impl<S: Storage> EventManager<S> {
             timestamp: block_timestamp,
             updated_at: Some(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()),
             quantity: 1,
-            currency_address: None,
+            currency_address: Some(self.ventory_currency_address.clone()),
             marketplace_contract_address: to_hex_str(&event.from_address),
             marketplace_name: "Ventory".to_string(),
-            price: to_hex_str(price),
+            price: CairoU256::from_felt(price).to_hex(),
+            currency_symbol: None,
+            currency_decimals: None,
+            normalized_price: None,
+            price_usd: None,
             chain_id: chain_id.to_string(),
+            wash_trade_flags: vec![],
         })
     }
 
@@ -234,8 +284,13 @@ impl<S: Storage> EventManager<S> {
             currency_address: Some(to_hex_str(currency_address)),
             marketplace_contract_address: to_hex_str(&event.from_address),
             marketplace_name: "Element".to_string(),
-            price: to_hex_str(price),
+            price: CairoU256::from_felt(price).to_hex(),
+            currency_symbol: None,
+            currency_decimals: None,
+            normalized_price: None,
+            price_usd: None,
             chain_id: chain_id.to_string(),
+            wash_trade_flags: vec![],
         })
     }
 
@@ -299,6 +354,159 @@ impl<S: Storage> EventManager<S> {
         Ok((token_id, token_event.clone()))
     }
 
+    /// Formats & registers an `Approval` or `ApprovalForAll` event
+    /// as the current approval state.
+    pub async fn format_and_register_approval_event(
+        &self,
+        event: &EmittedEvent,
+        contract_type: ContractType,
+        block_timestamp: u64,
+        chain_id: &str,
+    ) -> Result<ApprovalEvent> {
+        let approval = Self::format_approval_event(event, contract_type, block_timestamp, chain_id)
+            .ok_or_else(|| anyhow!("Can't find approval data into this event"))?;
+
+        trace!("Registering approval: {:?}", approval);
+
+        match &approval {
+            ApprovalEvent::Token(a) => self.storage.register_token_approval(a).await?,
+            ApprovalEvent::Operator(a) => self.storage.register_operator_approval(a).await?,
+        };
+
+        Ok(approval)
+    }
+
+    /// Revokes the approval of a transferred ERC721 token,
+    /// as the approval is cleared by the transfer without
+    /// any `Approval` event being emitted.
+    pub async fn clear_token_approval(&self, event: &TokenTransferEvent) -> Result<()> {
+        let approval = TokenApproval {
+            contract_address: event.contract_address.clone(),
+            contract_type: event.contract_type.clone(),
+            token_id: event.token_id.clone(),
+            token_id_hex: event.token_id_hex.clone(),
+            owner: event.to_address.clone(),
+            approved_address: to_hex_str(&FieldElement::ZERO),
+            transaction_hash: event.transaction_hash.clone(),
+            timestamp: event.timestamp,
+            block_number: event.block_number,
+            chain_id: event.chain_id.clone(),
+        };
+
+        self.storage.register_token_approval(&approval).await?;
+
+        Ok(())
+    }
+
+    /// Formats an `Approval` (ERC721) or `ApprovalForAll` (ERC721 and ERC1155) event.
+    /// Returns `None` if the event doesn't have the expected layout (Cairo 0 or Cairo 1).
+    pub fn format_approval_event(
+        event: &EmittedEvent,
+        contract_type: ContractType,
+        block_timestamp: u64,
+        chain_id: &str,
+    ) -> Option<ApprovalEvent> {
+        let selector = *event.keys.first()?;
+
+        // Cairo 1 events have the indexed fields as keys, Cairo 0 events
+        // have everything in the data.
+        let felts: Vec<FieldElement> = event.keys[1..]
+            .iter()
+            .chain(event.data.iter())
+            .copied()
+            .collect();
+
+        if selector == APPROVAL_SELECTOR && contract_type == ContractType::ERC721 {
+            if felts.len() != 4 {
+                return None;
+            }
+
+            let token_id = CairoU256::from_felts(&felts[2], &felts[3]).ok()?;
+
+            Some(ApprovalEvent::Token(TokenApproval {
+                contract_address: to_hex_str(&event.from_address),
+                contract_type: contract_type.to_string(),
+                token_id: token_id.to_decimal(false),
+                token_id_hex: token_id.to_hex(),
+                owner: to_hex_str(&felts[0]),
+                approved_address: to_hex_str(&felts[1]),
+                transaction_hash: to_hex_str(&event.transaction_hash),
+                timestamp: block_timestamp,
+                block_number: event.block_number,
+                chain_id: chain_id.to_string(),
+            }))
+        } else if selector == APPROVAL_FOR_ALL_SELECTOR && contract_type != ContractType::Other {
+            if felts.len() != 3 {
+                return None;
+            }
+
+            Some(ApprovalEvent::Operator(OperatorApproval {
+                contract_address: to_hex_str(&event.from_address),
+                contract_type: contract_type.to_string(),
+                owner: to_hex_str(&felts[0]),
+                operator: to_hex_str(&felts[1]),
+                approved: felts[2] != FieldElement::ZERO,
+                transaction_hash: to_hex_str(&event.transaction_hash),
+                timestamp: block_timestamp,
+                block_number: event.block_number,
+                chain_id: chain_id.to_string(),
+            }))
+        } else {
+            None
+        }
+    }
+
+    /// Returns true for a Cairo 1 ERC20 `Approval`, recognized from its
+    /// layout: owner and spender as keys, the u256 amount as data.
+    /// The Cairo 0 approvals have the same layout for ERC20 and ERC721.
+    pub fn is_erc20_approval(event: &EmittedEvent) -> bool {
+        event.keys.first() == Some(&APPROVAL_SELECTOR)
+            && event.keys.len() == 3
+            && event.data.len() == 2
+    }
+
+    /// Formats a `ContractDeployed` event of the Universal Deployer Contract.
+    /// The event data is `[address, deployer, unique, class_hash, calldata_len, calldata.., salt]`.
+    pub fn format_contract_deployed_event(event: &EmittedEvent) -> Option<ContractDeployment> {
+        if event.keys.first() != Some(&CONTRACT_DEPLOYED_SELECTOR) || event.data.len() < 5 {
+            return None;
+        }
+
+        Some(ContractDeployment {
+            contract_address: event.data[0],
+            class_hash: event.data[3],
+            deployer_address: Some(event.data[1]),
+            block_number: event.block_number,
+        })
+    }
+
+    /// Formats an ERC20 transfer, used to detect payments.
+    /// Returns `None` if the event doesn't have the layout
+    /// of an ERC20 transfer (Cairo 0 or Cairo 1).
+    pub fn format_currency_transfer_event(event: &EmittedEvent) -> Option<CurrencyTransferEvent> {
+        if event.keys.first() != Some(&TRANSFER_SELECTOR) {
+            return None;
+        }
+
+        let (from, to, amount_low, amount_high) = match (event.keys.len(), event.data.len()) {
+            // Cairo 1: from and to are keys.
+            (3, 2) => (event.keys[1], event.keys[2], event.data[0], event.data[1]),
+            // Cairo 0: everything is in the data.
+            (1, 4) => (event.data[0], event.data[1], event.data[2], event.data[3]),
+            _ => return None,
+        };
+
+        let amount = CairoU256::from_felts(&amount_low, &amount_high).ok()?;
+
+        Some(CurrencyTransferEvent {
+            currency_address: to_hex_str(&event.from_address),
+            from_address: to_hex_str(&from),
+            to_address: to_hex_str(&to),
+            amount: amount.to_hex(),
+            transaction_hash: to_hex_str(&event.transaction_hash),
+        })
+    }
+
     pub fn get_event_type(from: FieldElement, to: FieldElement) -> EventType {
         if from == FieldElement::ZERO {
             EventType::Mint
@@ -484,6 +692,9 @@ mod tests {
         // Define expected result
         let expected = vec![vec![
             selector!("Transfer"),
+            selector!("Approval"),
+            selector!("ApprovalForAll"),
+            selector!("ContractDeployed"),
             FieldElement::from_hex_be(ELEMENT_NFT_MARKETPLACE_HEX).unwrap(),
             FieldElement::from_hex_be(VENTORY_MARKETPLACE_EVENT_HEX).unwrap(),
             FieldElement::from_hex_be(VENTORY_MARKETPLACE_OFFER_ACCEPTED_EVENT_HEX).unwrap(),
@@ -493,6 +704,192 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_reindex_keys_selector() {
+        let storage = Arc::new(MockStorage::default());
+        let manager = EventManager::new(storage);
+
+        let filter = ReindexFilter {
+            event_kinds: vec![ReindexEventKind::Approval, ReindexEventKind::Sale],
+            ..Default::default()
+        };
+        let expected = vec![vec![
+            selector!("Approval"),
+            selector!("ApprovalForAll"),
+            FieldElement::from_hex_be(ELEMENT_NFT_MARKETPLACE_HEX).unwrap(),
+            FieldElement::from_hex_be(VENTORY_MARKETPLACE_EVENT_HEX).unwrap(),
+            FieldElement::from_hex_be(VENTORY_MARKETPLACE_OFFER_ACCEPTED_EVENT_HEX).unwrap(),
+        ]];
+        assert_eq!(manager.reindex_keys_selector(&filter).unwrap(), expected);
+
+        // All the kinds, without the deployments.
+        let result = manager
+            .reindex_keys_selector(&ReindexFilter::default())
+            .unwrap();
+        assert_eq!(result[0].len(), 6);
+        assert!(!result[0].contains(&selector!("ContractDeployed")));
+    }
+
+    #[test]
+    fn test_format_currency_transfer_event() {
+        let mut event = setup_sample_event();
+        event.keys = vec![
+            TRANSFER_SELECTOR,
+            FieldElement::from_hex_be("0x1234").unwrap(),
+            FieldElement::from_hex_be("0x5678").unwrap(),
+        ];
+        event.data = vec![FieldElement::from(1000_u64), FieldElement::ZERO];
+
+        let payment = EventManager::<MockStorage>::format_currency_transfer_event(&event).unwrap();
+
+        assert_eq!(payment.currency_address, to_hex_str(&event.from_address));
+        assert_eq!(
+            payment.from_address,
+            to_hex_str(&FieldElement::from_hex_be("0x1234").unwrap())
+        );
+        assert_eq!(
+            payment.to_address,
+            to_hex_str(&FieldElement::from_hex_be("0x5678").unwrap())
+        );
+        assert_eq!(payment.amount, CairoU256 { low: 1000, high: 0 }.to_hex());
+
+        // A Cairo 1 NFT transfer has the token id in the keys.
+        event.keys.push(FieldElement::ONE);
+        event.keys.push(FieldElement::ZERO);
+        event.data = vec![];
+        assert!(EventManager::<MockStorage>::format_currency_transfer_event(&event).is_none());
+    }
+
+    #[test]
+    fn test_format_approval_event() {
+        let mut event = setup_sample_event();
+
+        // Cairo 1 ERC721 Approval.
+        event.keys = vec![
+            APPROVAL_SELECTOR,
+            FieldElement::from_hex_be("0x1234").unwrap(),
+            FieldElement::from_hex_be("0x5678").unwrap(),
+            FieldElement::from(7_u64),
+            FieldElement::ZERO,
+        ];
+        event.data = vec![];
+
+        match EventManager::<MockStorage>::format_approval_event(
+            &event,
+            ContractType::ERC721,
+            1000,
+            "SN_MAIN",
+        ) {
+            Some(ApprovalEvent::Token(approval)) => {
+                assert_eq!(approval.token_id, "7");
+                assert_eq!(
+                    approval.owner,
+                    to_hex_str(&FieldElement::from_hex_be("0x1234").unwrap())
+                );
+                assert_eq!(
+                    approval.approved_address,
+                    to_hex_str(&FieldElement::from_hex_be("0x5678").unwrap())
+                );
+            }
+            a => panic!("Expected a token approval, got {:?}", a),
+        }
+
+        // Cairo 0 ApprovalForAll.
+        event.keys = vec![APPROVAL_FOR_ALL_SELECTOR];
+        event.data = vec![
+            FieldElement::from_hex_be("0x1234").unwrap(),
+            FieldElement::from_hex_be("0x5678").unwrap(),
+            FieldElement::ZERO,
+        ];
+
+        match EventManager::<MockStorage>::format_approval_event(
+            &event,
+            ContractType::ERC1155,
+            1000,
+            "SN_MAIN",
+        ) {
+            Some(ApprovalEvent::Operator(approval)) => {
+                assert!(!approval.approved);
+                assert_eq!(approval.contract_type, "ERC1155");
+            }
+            a => panic!("Expected an operator approval, got {:?}", a),
+        }
+
+        // ERC20 approvals are ignored.
+        assert!(EventManager::<MockStorage>::format_approval_event(
+            &event,
+            ContractType::Other,
+            1000,
+            "SN_MAIN",
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_is_erc20_approval() {
+        let mut event = setup_sample_event();
+        let owner = FieldElement::from_hex_be("0x1234").unwrap();
+        let spender = FieldElement::from_hex_be("0x5678").unwrap();
+
+        // Cairo 1 ERC20 Approval.
+        event.keys = vec![APPROVAL_SELECTOR, owner, spender];
+        event.data = vec![FieldElement::from(100_u64), FieldElement::ZERO];
+        assert!(EventManager::<MockStorage>::is_erc20_approval(&event));
+
+        // Cairo 1 ERC721 Approval.
+        event.keys = vec![
+            APPROVAL_SELECTOR,
+            owner,
+            spender,
+            FieldElement::from(7_u64),
+            FieldElement::ZERO,
+        ];
+        event.data = vec![];
+        assert!(!EventManager::<MockStorage>::is_erc20_approval(&event));
+
+        // Cairo 0 approvals can't be told apart.
+        event.keys = vec![APPROVAL_SELECTOR];
+        event.data = vec![
+            owner,
+            spender,
+            FieldElement::from(7_u64),
+            FieldElement::ZERO,
+        ];
+        assert!(!EventManager::<MockStorage>::is_erc20_approval(&event));
+    }
+
+    #[test]
+    fn test_format_contract_deployed_event() {
+        let mut event = setup_sample_event();
+        event.keys = vec![CONTRACT_DEPLOYED_SELECTOR];
+        event.data = vec![
+            FieldElement::from_hex_be("0x1234").unwrap(),
+            FieldElement::from_hex_be("0x5678").unwrap(),
+            FieldElement::ONE,
+            FieldElement::from_hex_be("0xc1a55").unwrap(),
+            FieldElement::ONE,
+            FieldElement::from(42_u64),
+            FieldElement::from(7_u64),
+        ];
+
+        let deployment =
+            EventManager::<MockStorage>::format_contract_deployed_event(&event).unwrap();
+
+        assert_eq!(
+            deployment.contract_address,
+            FieldElement::from_hex_be("0x1234").unwrap()
+        );
+        assert_eq!(
+            deployment.deployer_address,
+            Some(FieldElement::from_hex_be("0x5678").unwrap())
+        );
+        assert_eq!(
+            deployment.class_hash,
+            FieldElement::from_hex_be("0xc1a55").unwrap()
+        );
+        assert_eq!(deployment.block_number, Some(111));
+    }
+
     /// Tests the `get_event_info_from_felts` method with correct input format and length.
     /// Ensures that the method correctly extracts and returns the event info.
     #[test]
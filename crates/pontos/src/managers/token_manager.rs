@@ -1,3 +1,4 @@
+use crate::analyzers::MintPriceInfo;
 use crate::storage::types::{EventType, TokenInfo, TokenMintInfo, TokenTransferEvent};
 use crate::storage::Storage;
 use anyhow::{anyhow, Result};
@@ -24,13 +25,14 @@ impl<S: Storage, C: StarknetClient> TokenManager<S, C> {
     }
 
     /// Formats a token registry from the token event data.
+    /// The mint info is returned with the token for mint events.
     pub async fn format_and_register_token(
         &self,
         token_id: &CairoU256,
         event: &TokenTransferEvent,
         block_timestamp: u64,
         block_number: Option<u64>,
-    ) -> Result<()> {
+    ) -> Result<(TokenInfo, Option<TokenMintInfo>)> {
         let mut token = TokenInfo {
             contract_address: event.contract_address.clone(),
             token_id: event.token_id.clone(),
@@ -55,25 +57,82 @@ impl<S: Storage, C: StarknetClient> TokenManager<S, C> {
 
         self.storage.register_token(&token, block_timestamp).await?;
 
-        if event.event_type == EventType::Mint {
-            let info = TokenMintInfo {
-                address: event.to_address.clone(),
-                timestamp: event.timestamp,
-                transaction_hash: event.transaction_hash.clone(),
-                block_number,
-            };
-
-            self.storage
-                .register_mint(
-                    &token.contract_address,
-                    &token.token_id_hex,
-                    &token.token_id,
-                    &info,
-                )
-                .await?;
+        if event.event_type != EventType::Mint {
+            return Ok((token, None));
         }
 
-        Ok(())
+        let info = TokenMintInfo {
+            address: event.to_address.clone(),
+            timestamp: event.timestamp,
+            transaction_hash: event.transaction_hash.clone(),
+            block_number,
+            ..Default::default()
+        };
+
+        self.storage
+            .register_mint(
+                &token.contract_address,
+                &token.token_id_hex,
+                &token.token_id,
+                &info,
+            )
+            .await?;
+
+        Ok((token, Some(info)))
+    }
+
+    /// Completes the mint info of a token with the price paid by the minter.
+    pub async fn register_mint_price(
+        &self,
+        mint_price: &MintPriceInfo,
+        block_timestamp: u64,
+        block_number: Option<u64>,
+    ) -> Result<TokenMintInfo> {
+        let info = TokenMintInfo {
+            address: mint_price.minter_address.clone(),
+            timestamp: block_timestamp,
+            transaction_hash: mint_price.transaction_hash.clone(),
+            block_number,
+            price: Some(mint_price.price.clone()),
+            currency_address: Some(mint_price.currency_address.clone()),
+        };
+
+        self.storage
+            .register_mint(
+                &mint_price.contract_address,
+                &mint_price.token_id_hex,
+                &mint_price.token_id,
+                &info,
+            )
+            .await?;
+
+        Ok(info)
+    }
+
+    /// Registers a token minted before the indexed blocks, with
+    /// its owner at the given block. No mint info is registered.
+    pub async fn register_backfilled_token(
+        &self,
+        contract_address: FieldElement,
+        chain_id: &str,
+        token_id: &CairoU256,
+        owner: FieldElement,
+        block_number: u64,
+        block_timestamp: u64,
+    ) -> Result<TokenInfo> {
+        let token = TokenInfo {
+            contract_address: to_hex_str(&contract_address),
+            token_id: token_id.to_decimal(false),
+            chain_id: chain_id.to_string(),
+            token_id_hex: token_id.to_hex(),
+            owner: to_hex_str(&owner),
+        };
+
+        self.storage
+            .register_backfilled_token(&token, block_number, block_timestamp)
+            .await?;
+
+        Ok(token)
     }
 
     /// Retrieves the token owner for the last block.
@@ -137,4 +196,41 @@ mod tests {
         assert_eq!(owners.len(), 1);
         assert_eq!(owners[0], FieldElement::from_dec_str("1").unwrap());
     }
+
+    #[tokio::test]
+    async fn test_register_mint_price() {
+        let mut mock_storage = MockStorage::default();
+        let mock_client = MockStarknetClient::default();
+
+        mock_storage
+            .expect_register_mint()
+            .withf(|contract_address, _, token_id, info| {
+                contract_address == "0x1234"
+                    && token_id == "1"
+                    && info.address == "0x0002"
+                    && info.price == Some("0x64".to_string())
+                    && info.currency_address == Some("0x0009".to_string())
+            })
+            .times(1)
+            .returning(|_, _, _, _| Box::pin(futures::future::ready(Ok(()))));
+
+        let token_manager = TokenManager::new(Arc::new(mock_storage), Arc::new(mock_client));
+
+        let mint_price = MintPriceInfo {
+            transaction_hash: "0xabc".to_string(),
+            contract_address: "0x1234".to_string(),
+            token_id: "1".to_string(),
+            token_id_hex: "0x1".to_string(),
+            minter_address: "0x0002".to_string(),
+            currency_address: "0x0009".to_string(),
+            price: "0x64".to_string(),
+            chain_id: "SN_MAIN".to_string(),
+        };
+
+        let result = token_manager
+            .register_mint_price(&mint_price, 1000, Some(10))
+            .await;
+
+        assert!(result.is_ok());
+    }
 }
@@ -0,0 +1,225 @@
+//! Indexation of several chains in the same process.
+//!
+//! Each chain is indexed by its own `Pontos` instance, with its own client,
+//! storage and configuration. The `ChainIndexer` owning this instance passes
+//! the chain id to every indexing call, and the `MultiChainIndexer` runs
+//! the chains concurrently.
+//!
+//! Pontos storages don't scope the blocks by chain: each chain must
+//! be given a dedicated storage, like a `PostgresStorage::new_in_schema`.
+use crate::event_handler::EventHandler;
+use crate::storage::Storage;
+use crate::{IndexerError, IndexerResult, Pontos};
+use ark_starknet::client::StarknetClient;
+use futures::future::join_all;
+use starknet::core::types::{BlockId, FieldElement};
+use std::time::Duration;
+use tracing::{error, info};
+
+/// Pontos instance bound to a chain.
+pub struct ChainIndexer<S: Storage, C: StarknetClient, E: EventHandler> {
+    chain_id: String,
+    pontos: Pontos<S, C, E>,
+    start_block: u64,
+}
+
+impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> ChainIndexer<S, C, E> {
+    pub fn new(chain_id: &str, pontos: Pontos<S, C, E>) -> Self {
+        ChainIndexer {
+            chain_id: chain_id.to_string(),
+            pontos,
+            start_block: 0,
+        }
+    }
+
+    /// First block indexed by `ChainTask::Follow`, usually
+    /// the one after the last block indexed in the storage.
+    pub fn with_start_block(mut self, start_block: u64) -> Self {
+        self.start_block = start_block;
+        self
+    }
+
+    pub fn chain_id(&self) -> &str {
+        &self.chain_id
+    }
+
+    pub fn pontos(&self) -> &Pontos<S, C, E> {
+        &self.pontos
+    }
+
+    pub async fn index_block_range(
+        &self,
+        from_block: BlockId,
+        to_block: BlockId,
+        do_force: bool,
+    ) -> IndexerResult<()> {
+        self.pontos
+            .index_block_range(from_block, to_block, do_force, &self.chain_id)
+            .await
+    }
+
+    pub async fn index_pending(&self) -> IndexerResult<()> {
+        self.pontos.index_pending().await
+    }
+
+    pub async fn index_contract_events(
+        &self,
+        from_block: Option<BlockId>,
+        to_block: Option<BlockId>,
+        contract_address: FieldElement,
+    ) -> IndexerResult<()> {
+        self.pontos
+            .index_contract_events(from_block, to_block, contract_address, &self.chain_id)
+            .await
+    }
+
+    /// Indexes the blocks as they are produced, starting at `from_block`.
+    /// The latest block number is polled every `interval`, and an
+    /// unreachable node is retried at the next poll.
+    pub async fn follow(&self, from_block: u64, interval: Duration) -> IndexerResult<()> {
+        let mut next = from_block;
+
+        loop {
+            match self.pontos.client.block_number().await {
+                Ok(latest) if next <= latest => {
+                    self.index_block_range(BlockId::Number(next), BlockId::Number(latest), false)
+                        .await?;
+                    next = latest + 1;
+                }
+                Ok(_) => {}
+                Err(e) => error!(
+                    "[{}] Error while fetching the latest block: {:?}",
+                    self.chain_id, e
+                ),
+            }
+
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    async fn run(&self, task: &ChainTask) -> IndexerResult<()> {
+        match task {
+            ChainTask::BlockRange {
+                from_block,
+                to_block,
+                do_force,
+            } => {
+                self.index_block_range(*from_block, *to_block, *do_force)
+                    .await
+            }
+            ChainTask::Pending => self.index_pending().await,
+            ChainTask::Follow { interval } => self.follow(self.start_block, *interval).await,
+        }
+    }
+}
+
+/// Indexing work executed on every chain of a `MultiChainIndexer`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainTask {
+    BlockRange {
+        from_block: BlockId,
+        to_block: BlockId,
+        do_force: bool,
+    },
+    Pending,
+    /// Follows each chain from its start block.
+    Follow {
+        interval: Duration,
+    },
+}
+
+pub struct MultiChainIndexer<S: Storage, C: StarknetClient, E: EventHandler> {
+    chains: Vec<ChainIndexer<S, C, E>>,
+}
+
+impl<S: Storage, C: StarknetClient, E: EventHandler> Default for MultiChainIndexer<S, C, E> {
+    fn default() -> Self {
+        MultiChainIndexer { chains: vec![] }
+    }
+}
+
+impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> MultiChainIndexer<S, C, E> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a chain, which id must not be already registered.
+    pub fn add_chain(&mut self, chain: ChainIndexer<S, C, E>) -> IndexerResult<()> {
+        if self.chain(chain.chain_id()).is_some() {
+            return Err(IndexerError::Anyhow(format!(
+                "Chain {} is already registered",
+                chain.chain_id()
+            )));
+        }
+
+        self.chains.push(chain);
+        Ok(())
+    }
+
+    pub fn with_chain(mut self, chain: ChainIndexer<S, C, E>) -> IndexerResult<Self> {
+        self.add_chain(chain)?;
+        Ok(self)
+    }
+
+    pub fn chain(&self, chain_id: &str) -> Option<&ChainIndexer<S, C, E>> {
+        self.chains.iter().find(|c| c.chain_id() == chain_id)
+    }
+
+    pub fn chain_ids(&self) -> Vec<&str> {
+        self.chains.iter().map(|c| c.chain_id()).collect()
+    }
+
+    /// Runs the task on all the chains concurrently, and returns
+    /// the result of each chain once they are all terminated.
+    /// A chain failing doesn't interrupt the others.
+    pub async fn run(&self, task: &ChainTask) -> Vec<(String, IndexerResult<()>)> {
+        join_all(self.chains.iter().map(|chain| async move {
+            info!("[{}] Starting {:?}", chain.chain_id(), task);
+
+            let result = chain.run(task).await;
+            if let Err(e) = &result {
+                error!("[{}] Indexing failed: {}", chain.chain_id(), e);
+            }
+
+            (chain.chain_id().to_string(), result)
+        }))
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MockStorage;
+    use crate::PontosConfig;
+    use ark_starknet::client::MockStarknetClient;
+    use async_trait::async_trait;
+    use std::sync::Arc;
+
+    struct NoopHandler;
+
+    #[async_trait]
+    impl EventHandler for NoopHandler {}
+
+    fn chain(chain_id: &str) -> ChainIndexer<MockStorage, MockStarknetClient, NoopHandler> {
+        let pontos = Pontos::new(
+            Arc::new(MockStarknetClient::default()),
+            Arc::new(MockStorage::default()),
+            Arc::new(NoopHandler),
+            PontosConfig::default(),
+        );
+
+        ChainIndexer::new(chain_id, pontos)
+    }
+
+    #[test]
+    fn test_add_chain_rejects_duplicates() {
+        let mut indexer = MultiChainIndexer::new();
+
+        assert!(indexer.add_chain(chain("SN_MAIN")).is_ok());
+        assert!(indexer.add_chain(chain("SN_SEPOLIA")).is_ok());
+        assert!(indexer.add_chain(chain("SN_MAIN")).is_err());
+        assert_eq!(indexer.chain_ids(), vec!["SN_MAIN", "SN_SEPOLIA"]);
+        assert!(indexer.chain("SN_SEPOLIA").is_some());
+    }
+}
@@ -0,0 +1,1054 @@
+//! Ownership of collections at a given block, for airdrops and allowlists.
+//!
+//! The holders of an ERC721 collection are rebuilt from the transfers
+//! already stored by Pontos, without indexing the chain again. Pontos
+//! doesn't index the `TransferSingle` and `TransferBatch` events, so the
+//! balances of an ERC1155 collection are summed from these events, read
+//! on chain for this collection only. Both can then be checked on chain
+//! at the same block.
+use crate::storage::types::{
+    ContractType, Pagination, RegisteredToken, StorageError, TokenInfo, TokenTransferEvent,
+};
+use crate::storage::QueryStorage;
+use crate::{IndexerError, IndexerResult};
+use ark_starknet::client::StarknetClient;
+use ark_starknet::format::to_hex_str;
+use ark_starknet::CairoU256;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use starknet::core::types::{BlockId, EmittedEvent, FieldElement};
+use starknet::core::utils::get_selector_from_name;
+use starknet::macros::selector;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// Contract calls in flight during an on-chain check.
+const CONCURRENT_CALLS: usize = 16;
+
+const TRANSFER_SINGLE_SELECTOR: FieldElement = selector!("TransferSingle");
+const TRANSFER_BATCH_SELECTOR: FieldElement = selector!("TransferBatch");
+
+/// A token held by an owner at the snapshot block.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Holding {
+    pub contract_address: String,
+    pub contract_type: String,
+    pub token_id: String,
+    pub token_id_hex: String,
+    pub owner: String,
+    /// Decimal balance of the owner: `1` for an ERC721 token, the amount
+    /// held for an ERC1155 token, or `0` once found empty on chain.
+    pub balance: Option<String>,
+    /// The holding has been read on chain at the snapshot block.
+    pub verified: bool,
+}
+
+/// Holding whose stored owner or balance differs from the chain.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OwnershipMismatch {
+    pub contract_address: String,
+    pub token_id_hex: String,
+    pub stored_owner: String,
+    /// `None` if the token doesn't exist on chain at the block, or
+    /// isn't held anymore by the stored owner (ERC1155).
+    pub on_chain_owner: Option<String>,
+    pub stored_balance: Option<String>,
+    pub on_chain_balance: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OwnershipSnapshot {
+    pub chain_id: String,
+    pub block_number: u64,
+    /// Ordered by contract address, token id and owner.
+    pub holdings: Vec<Holding>,
+}
+
+impl OwnershipSnapshot {
+    pub const CSV_HEADER: &'static str =
+        "contract_address,contract_type,token_id,token_id_hex,owner,balance,verified";
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("{}\n", Self::CSV_HEADER);
+
+        for h in &self.holdings {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{}\n",
+                h.contract_address,
+                h.contract_type,
+                h.token_id,
+                h.token_id_hex,
+                h.owner,
+                h.balance.as_deref().unwrap_or_default(),
+                h.verified
+            ));
+        }
+
+        csv
+    }
+}
+
+/// Builds the ownership snapshots of the collections of a chain.
+pub struct OwnershipSnapshotter<S: QueryStorage, C: StarknetClient> {
+    storage: Arc<S>,
+    client: Arc<C>,
+    chain_id: String,
+}
+
+impl<S, C> OwnershipSnapshotter<S, C>
+where
+    S: QueryStorage + Send + Sync,
+    C: StarknetClient + Send + Sync,
+{
+    pub fn new(storage: Arc<S>, client: Arc<C>, chain_id: &str) -> Self {
+        Self {
+            storage,
+            client,
+            chain_id: chain_id.to_string(),
+        }
+    }
+
+    /// Holders of the given collections at the end of `block_number`.
+    /// For the ERC721 collections, the blocks up to `block_number` are
+    /// expected to be indexed.
+    pub async fn snapshot(
+        &self,
+        contract_addresses: &[FieldElement],
+        block_number: u64,
+    ) -> IndexerResult<OwnershipSnapshot> {
+        let mut holdings = vec![];
+
+        for address in contract_addresses {
+            let contract_address = to_hex_str(address);
+
+            if self.is_erc1155(&contract_address).await? {
+                holdings.extend(self.erc1155_holdings(*address, block_number).await?);
+                continue;
+            }
+
+            let tokens = self
+                .collection_tokens(&contract_address, block_number)
+                .await?;
+            let transfers = self
+                .collection_transfers(&contract_address, block_number)
+                .await?;
+            debug!(
+                "{} tokens and {} transfers of {} up to block {}",
+                tokens.len(),
+                transfers.len(),
+                contract_address,
+                block_number
+            );
+
+            holdings.extend(holdings_from_transfers(&tokens, &transfers));
+        }
+
+        holdings.sort_by(|a, b| {
+            (&a.contract_address, &a.token_id_hex, &a.owner).cmp(&(
+                &b.contract_address,
+                &b.token_id_hex,
+                &b.owner,
+            ))
+        });
+
+        Ok(OwnershipSnapshot {
+            chain_id: self.chain_id.clone(),
+            block_number,
+            holdings,
+        })
+    }
+
+    /// Reads the owner (ERC721) or the balance (ERC1155) of every holding
+    /// on chain at the snapshot block. The mismatching holdings are set to
+    /// the on-chain owner or balance, and the empty ones are removed.
+    pub async fn verify_on_chain(
+        &self,
+        snapshot: &mut OwnershipSnapshot,
+    ) -> IndexerResult<Vec<OwnershipMismatch>> {
+        let block = BlockId::Number(snapshot.block_number);
+        // The snapshot is left unchanged if a call fails.
+        let checked: Vec<(Holding, Option<OwnershipMismatch>)> =
+            stream::iter(snapshot.holdings.clone())
+                .map(|holding| self.verify_holding(holding, block))
+                .buffered(CONCURRENT_CALLS)
+                .try_collect()
+                .await?;
+
+        snapshot.holdings.clear();
+        let mut mismatches = vec![];
+        for (holding, mismatch) in checked {
+            if let Some(mismatch) = mismatch {
+                warn!(
+                    "Token {} of {}: stored owner {}, on chain {:?}",
+                    mismatch.token_id_hex,
+                    mismatch.contract_address,
+                    mismatch.stored_owner,
+                    mismatch.on_chain_owner
+                );
+                mismatches.push(mismatch);
+            }
+
+            let empty = holding.balance.as_deref() == Some("0");
+            if !empty {
+                snapshot.holdings.push(holding);
+            }
+        }
+
+        Ok(mismatches)
+    }
+
+    async fn verify_holding(
+        &self,
+        mut holding: Holding,
+        block: BlockId,
+    ) -> IndexerResult<(Holding, Option<OwnershipMismatch>)> {
+        let contract_address = felt(&holding.contract_address)?;
+
+        if holding.contract_type == ContractType::ERC1155.to_string() {
+            let balance = balance_of(
+                self.client.as_ref(),
+                contract_address,
+                &holding.owner,
+                &holding.token_id_hex,
+                block,
+            )
+            .await?
+            .unwrap_or_else(|| "0".to_string());
+
+            let mut mismatch = None;
+            if holding.balance.as_deref() != Some(balance.as_str()) {
+                mismatch = Some(OwnershipMismatch {
+                    contract_address: holding.contract_address.clone(),
+                    token_id_hex: holding.token_id_hex.clone(),
+                    stored_owner: holding.owner.clone(),
+                    on_chain_owner: (balance != "0").then(|| holding.owner.clone()),
+                    stored_balance: holding.balance.clone(),
+                    on_chain_balance: balance.clone(),
+                });
+                holding.balance = Some(balance);
+            }
+
+            holding.verified = true;
+            return Ok((holding, mismatch));
+        }
+
+        let mut mismatch = None;
+
+        let owner = owner_of(
+            self.client.as_ref(),
+            contract_address,
+            &holding.token_id_hex,
+            block,
+        )
+        .await?;
+
+        if owner.as_deref() != Some(holding.owner.as_str()) {
+            mismatch = Some(OwnershipMismatch {
+                contract_address: holding.contract_address.clone(),
+                token_id_hex: holding.token_id_hex.clone(),
+                stored_owner: holding.owner.clone(),
+                on_chain_owner: owner.clone(),
+                stored_balance: holding.balance.clone(),
+                on_chain_balance: if owner.is_some() { "1" } else { "0" }.to_string(),
+            });
+
+            match owner {
+                Some(owner) => holding.owner = owner,
+                None => holding.balance = Some("0".to_string()),
+            }
+        }
+
+        holding.verified = true;
+        Ok((holding, mismatch))
+    }
+
+    /// A collection unknown to the storage has no stored transfers,
+    /// and is snapshotted as an ERC721 collection.
+    async fn is_erc1155(&self, contract_address: &str) -> IndexerResult<bool> {
+        match self
+            .storage
+            .get_contract_info(contract_address, &self.chain_id)
+            .await
+        {
+            Ok(info) => Ok(info.contract_type == ContractType::ERC1155.to_string()),
+            Err(StorageError::NotFound(_)) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Balances of an ERC1155 collection at the end of `block_number`, summed
+    /// from the `TransferSingle` and `TransferBatch` events read on chain.
+    async fn erc1155_holdings(
+        &self,
+        contract_address: FieldElement,
+        block_number: u64,
+    ) -> IndexerResult<Vec<Holding>> {
+        let keys = Some(vec![vec![
+            TRANSFER_SINGLE_SELECTOR,
+            TRANSFER_BATCH_SELECTOR,
+        ]]);
+        let mut balances = Erc1155Balances::default();
+        let mut continuation_token = None;
+        let mut events_count = 0;
+
+        loop {
+            let result = self
+                .client
+                .fetch_events(
+                    Some(BlockId::Number(0)),
+                    Some(BlockId::Number(block_number)),
+                    keys.clone(),
+                    Some(contract_address),
+                    continuation_token,
+                )
+                .await?;
+
+            for event in result.events.into_values().flatten() {
+                balances.apply(&event)?;
+                events_count += 1;
+            }
+
+            continuation_token = result.continuation_token;
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+
+        debug!(
+            "{} ERC1155 transfers of {} up to block {}",
+            events_count,
+            to_hex_str(&contract_address),
+            block_number
+        );
+
+        Ok(balances.into_holdings(&to_hex_str(&contract_address)))
+    }
+
+    async fn collection_tokens(
+        &self,
+        contract_address: &str,
+        block_number: u64,
+    ) -> IndexerResult<Vec<RegisteredToken>> {
+        let mut tokens = vec![];
+        let mut pagination = Pagination::new(0, Pagination::MAX_LIMIT);
+
+        loop {
+            let page = self
+                .storage
+                .collection_tokens_until(contract_address, &self.chain_id, block_number, pagination)
+                .await?;
+
+            tokens.extend(page.items);
+
+            match page.next_offset {
+                Some(offset) => pagination.offset = offset,
+                None => return Ok(tokens),
+            }
+        }
+    }
+
+    async fn collection_transfers(
+        &self,
+        contract_address: &str,
+        block_number: u64,
+    ) -> IndexerResult<Vec<TokenTransferEvent>> {
+        let mut transfers = vec![];
+        let mut pagination = Pagination::new(0, Pagination::MAX_LIMIT);
+
+        loop {
+            let page = self
+                .storage
+                .collection_transfers_until(
+                    contract_address,
+                    &self.chain_id,
+                    block_number,
+                    pagination,
+                )
+                .await?;
+
+            transfers.extend(page.items);
+
+            match page.next_offset {
+                Some(offset) => pagination.offset = offset,
+                None => return Ok(transfers),
+            }
+        }
+    }
+}
+
+/// Replays the transfers of an ERC721 collection, ordered by block, over
+/// the tokens of the token table.
+///
+/// A backfilled token starts from its owner at the backfill block, and
+/// only its later transfers are replayed. A minted token without stored
+/// transfer up to the block, like the events of a pending block which
+/// have no number, is held by its current owner.
+///
+/// The events of a same block are not stored in the order of their
+/// emission: for each token, they are chained from its current owner
+/// (`from` of a transfer equal to the `to` of the previous one).
+fn holdings_from_transfers(
+    tokens: &[RegisteredToken],
+    transfers: &[TokenTransferEvent],
+) -> Vec<Holding> {
+    let zero = to_hex_str(&FieldElement::ZERO);
+
+    // Holding of each token, keyed by token id.
+    let mut holdings: BTreeMap<String, Holding> = BTreeMap::new();
+    // Block of the backfill of each backfilled token.
+    let mut backfilled: BTreeMap<&str, u64> = BTreeMap::new();
+
+    for t in tokens {
+        if let Some(owner) = &t.backfilled_owner {
+            backfilled.insert(&t.token.token_id_hex, t.block_number);
+            holdings.insert(
+                t.token.token_id_hex.clone(),
+                erc721_holding(&t.token, owner),
+            );
+        }
+    }
+
+    let mut replayed: HashSet<String> = HashSet::new();
+    let mut by_block: BTreeMap<(Option<u64>, String), Vec<&TokenTransferEvent>> = BTreeMap::new();
+
+    for transfer in transfers {
+        let before_backfill = backfilled
+            .get(transfer.token_id_hex.as_str())
+            .map_or(false, |b| transfer.block_number.map_or(true, |n| n <= *b));
+        if before_backfill {
+            continue;
+        }
+
+        replayed.insert(transfer.token_id_hex.clone());
+        by_block
+            .entry((transfer.block_number, transfer.token_id_hex.clone()))
+            .or_default()
+            .push(transfer);
+    }
+
+    for ((_, token_id_hex), mut events) in by_block {
+        let mut current = holdings
+            .get(&token_id_hex)
+            .map_or(zero.clone(), |h| h.owner.clone());
+
+        while !events.is_empty() {
+            let next = events
+                .iter()
+                .position(|e| e.from_address == current)
+                .unwrap_or(0);
+            let event = events.remove(next);
+            current = event.to_address.clone();
+
+            if current == zero {
+                holdings.remove(&token_id_hex);
+            } else {
+                holdings
+                    .entry(token_id_hex.clone())
+                    .or_insert_with(|| Holding {
+                        contract_address: event.contract_address.clone(),
+                        contract_type: event.contract_type.clone(),
+                        token_id: event.token_id.clone(),
+                        token_id_hex: event.token_id_hex.clone(),
+                        owner: current.clone(),
+                        balance: Some("1".to_string()),
+                        verified: false,
+                    })
+                    .owner = current.clone();
+            }
+        }
+    }
+
+    for t in tokens {
+        let minted = t.backfilled_owner.is_none();
+        if minted && !replayed.contains(&t.token.token_id_hex) && t.token.owner != zero {
+            holdings.insert(
+                t.token.token_id_hex.clone(),
+                erc721_holding(&t.token, &t.token.owner),
+            );
+        }
+    }
+
+    holdings.into_values().collect()
+}
+
+fn erc721_holding(token: &TokenInfo, owner: &str) -> Holding {
+    Holding {
+        contract_address: token.contract_address.clone(),
+        contract_type: ContractType::ERC721.to_string(),
+        token_id: token.token_id.clone(),
+        token_id_hex: token.token_id_hex.clone(),
+        owner: owner.to_string(),
+        balance: Some("1".to_string()),
+        verified: false,
+    }
+}
+
+/// Amounts received and sent by each owner of an ERC1155 collection, by
+/// token id. The events can be applied in any order.
+#[derive(Default)]
+struct Erc1155Balances {
+    moves: BTreeMap<(String, String), (CairoU256, BigUint, BigUint)>,
+}
+
+impl Erc1155Balances {
+    fn apply(&mut self, event: &EmittedEvent) -> IndexerResult<()> {
+        let (from, to, amounts) = erc1155_transfers(event)?;
+
+        for (token_id, amount) in amounts {
+            let amount = amount.to_biguint();
+
+            if from != FieldElement::ZERO {
+                self.entry(&token_id, &from).2 += &amount;
+            }
+            if to != FieldElement::ZERO {
+                self.entry(&token_id, &to).1 += &amount;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn entry(
+        &mut self,
+        token_id: &CairoU256,
+        owner: &FieldElement,
+    ) -> &mut (CairoU256, BigUint, BigUint) {
+        self.moves
+            .entry((token_id.to_hex(), to_hex_str(owner)))
+            .or_insert_with(|| (token_id.clone(), BigUint::default(), BigUint::default()))
+    }
+
+    fn into_holdings(self, contract_address: &str) -> Vec<Holding> {
+        self.moves
+            .into_iter()
+            .filter_map(|((token_id_hex, owner), (token_id, received, sent))| {
+                if sent > received {
+                    warn!(
+                        "Token {} of {}: {} sent more than received",
+                        token_id_hex, contract_address, owner
+                    );
+                }
+
+                (received > sent).then(|| Holding {
+                    contract_address: contract_address.to_string(),
+                    contract_type: ContractType::ERC1155.to_string(),
+                    token_id: token_id.to_decimal(false),
+                    token_id_hex,
+                    owner,
+                    balance: Some((received - sent).to_str_radix(10)),
+                    verified: false,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Sender, recipient, and token ids with their amount of a `TransferSingle`
+/// or `TransferBatch` event. The members are read from the keys then the
+/// data, which covers the events with and without indexed members.
+fn erc1155_transfers(
+    event: &EmittedEvent,
+) -> IndexerResult<(FieldElement, FieldElement, Vec<(CairoU256, CairoU256)>)> {
+    let invalid = || {
+        IndexerError::Anyhow(format!(
+            "Invalid ERC1155 transfer in transaction {}",
+            to_hex_str(&event.transaction_hash)
+        ))
+    };
+
+    let felts: Vec<FieldElement> = event
+        .keys
+        .iter()
+        .skip(1)
+        .chain(event.data.iter())
+        .copied()
+        .collect();
+
+    let (from, to, members) = match felts.as_slice() {
+        [_operator, from, to, members @ ..] => (*from, *to, members),
+        _ => return Err(invalid()),
+    };
+
+    let amounts = if event.keys.first() == Some(&TRANSFER_SINGLE_SELECTOR) {
+        match members {
+            [id_low, id_high, value_low, value_high] => vec![(
+                CairoU256::from_felts(id_low, id_high).map_err(|_| invalid())?,
+                CairoU256::from_felts(value_low, value_high).map_err(|_| invalid())?,
+            )],
+            _ => return Err(invalid()),
+        }
+    } else {
+        let (ids, members) = u256_array(members).ok_or_else(invalid)?;
+        let (values, members) = u256_array(members).ok_or_else(invalid)?;
+        if !members.is_empty() || ids.len() != values.len() {
+            return Err(invalid());
+        }
+        ids.into_iter().zip(values).collect()
+    };
+
+    Ok((from, to, amounts))
+}
+
+/// Array of u256 serialized with its length, and the felts after it.
+fn u256_array(felts: &[FieldElement]) -> Option<(Vec<CairoU256>, &[FieldElement])> {
+    let (len, felts) = felts.split_first()?;
+    let len = usize::try_from(u64::try_from(*len).ok()?).ok()?;
+    let size = len.checked_mul(2)?;
+
+    if felts.len() < size {
+        return None;
+    }
+
+    let (items, felts) = felts.split_at(size);
+    let values = items
+        .chunks(2)
+        .map(|u256| CairoU256::from_felts(&u256[0], &u256[1]).ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    Some((values, felts))
+}
+
+/// Balance of an ERC1155 token owned by `owner` on chain at the given
+/// block, `None` if the call is reverted.
+async fn balance_of<C: StarknetClient + Send + Sync>(
+    client: &C,
+    contract_address: FieldElement,
+    owner: &str,
+    token_id_hex: &str,
+    block: BlockId,
+) -> IndexerResult<Option<String>> {
+    let mut calldata = vec![felt(owner)?];
+    calldata.extend(u256_calldata(token_id_hex)?);
+
+    call(
+        client,
+        contract_address,
+        &["balance_of", "balanceOf"],
+        calldata,
+        block,
+    )
+    .await?
+    .map(|felts| u256_from_felts(&felts).map(|balance| balance.to_decimal(false)))
+    .transpose()
+}
+
+/// Owner of a token on chain at the given block, `None` if
+/// the token doesn't exist at this block.
+pub(crate) async fn owner_of<C: StarknetClient + Send + Sync>(
+    client: &C,
+    contract_address: FieldElement,
+    token_id_hex: &str,
+    block: BlockId,
+) -> IndexerResult<Option<String>> {
+    let owner = call(
+        client,
+        contract_address,
+        &["owner_of", "ownerOf"],
+        u256_calldata(token_id_hex)?,
+        block,
+    )
+    .await?
+    .and_then(|felts| felts.first().map(to_hex_str));
+
+    Ok(owner)
+}
+
+/// Calls the first selector found in the contract. Returns `None`
+/// if the call is reverted, like `owner_of` on a burnt token.
+async fn call<C: StarknetClient + Send + Sync>(
+    client: &C,
+    contract_address: FieldElement,
+    selectors: &[&str],
+    calldata: Vec<FieldElement>,
+    block: BlockId,
+) -> IndexerResult<Option<Vec<FieldElement>>> {
+    for name in selectors {
+        let selector = get_selector_from_name(name)
+            .map_err(|_| IndexerError::Anyhow(format!("Invalid selector: {}", name)))?;
+
+        match client
+            .call_contract(contract_address, selector, calldata.clone(), block)
+            .await
+        {
+            Ok(felts) => return Ok(Some(felts)),
+            Err(e) if e.is_entrypoint_not_found() => continue,
+            Err(e) if e.is_revert() => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Err(IndexerError::Anyhow(format!(
+        "None of {:?} found in contract {}",
+        selectors,
+        to_hex_str(&contract_address)
+    )))
+}
+
+fn u256_calldata(token_id_hex: &str) -> IndexerResult<Vec<FieldElement>> {
+    let token_id =
+        CairoU256::from_hex_be(token_id_hex).map_err(|e| IndexerError::Anyhow(e.to_string()))?;
+
+    Ok(vec![
+        FieldElement::from(token_id.low),
+        FieldElement::from(token_id.high),
+    ])
+}
+
+pub(crate) fn felt(value: &str) -> IndexerResult<FieldElement> {
+    FieldElement::from_hex_be(value)
+        .map_err(|_| IndexerError::Anyhow(format!("Invalid felt {}", value)))
+}
+
+pub(crate) fn u256_from_felts(felts: &[FieldElement]) -> IndexerResult<CairoU256> {
+    match felts {
+        [low, high, ..] => {
+            CairoU256::from_felts(low, high).map_err(|e| IndexerError::Anyhow(e.to_string()))
+        }
+        [low] => CairoU256::from_felts(low, &FieldElement::ZERO)
+            .map_err(|e| IndexerError::Anyhow(e.to_string())),
+        [] => Err(IndexerError::Anyhow("Empty u256".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::types::{ContractInfo, EventType, TokenMintInfo};
+    use crate::storage::{MemoryStorage, Storage};
+    use ark_starknet::client::{MockStarknetClient, StarknetClientError};
+    use ark_starknet::EventResult;
+    use starknet::providers::ProviderError;
+    use std::collections::HashMap;
+
+    fn address(value: u64) -> String {
+        to_hex_str(&FieldElement::from(value))
+    }
+
+    fn transfer(
+        contract_type: ContractType,
+        block_number: u64,
+        token_id: u64,
+        from: u64,
+        to: u64,
+    ) -> TokenTransferEvent {
+        TokenTransferEvent {
+            contract_address: address(0xc0),
+            chain_id: "SN_MAIN".to_string(),
+            contract_type: contract_type.to_string(),
+            token_id: token_id.to_string(),
+            token_id_hex: address(token_id),
+            from_address: address(from),
+            to_address: address(to),
+            event_type: EventType::Transfer,
+            // Ids in the reverse order of the senders.
+            event_id: format!("0x{:02x}{:02x}{:02x}", 0xff - from, token_id, block_number),
+            block_number: Some(block_number),
+            timestamp: block_number * 10,
+            ..Default::default()
+        }
+    }
+
+    async fn snapshotter(
+        transfers: Vec<TokenTransferEvent>,
+        client: MockStarknetClient,
+    ) -> OwnershipSnapshotter<MemoryStorage, MockStarknetClient> {
+        let storage = MemoryStorage::new();
+        for t in &transfers {
+            storage
+                .register_transfer_event(t, t.timestamp)
+                .await
+                .unwrap();
+        }
+
+        OwnershipSnapshotter::new(Arc::new(storage), Arc::new(client), "SN_MAIN")
+    }
+
+    #[tokio::test]
+    async fn test_erc721_snapshot_at_block() {
+        let erc721 = ContractType::ERC721;
+        let snapshotter = snapshotter(
+            vec![
+                transfer(erc721.clone(), 1, 1, 0, 0xa),
+                transfer(erc721.clone(), 1, 2, 0, 0xa),
+                // Stored before the mint, chained after it.
+                transfer(erc721.clone(), 2, 3, 0xb, 0xc),
+                transfer(erc721.clone(), 2, 3, 0, 0xb),
+                transfer(erc721.clone(), 3, 2, 0xa, 0),
+                transfer(erc721.clone(), 4, 1, 0xa, 0xd),
+            ],
+            MockStarknetClient::default(),
+        )
+        .await;
+
+        let contract = FieldElement::from(0xc0_u64);
+        let snapshot = snapshotter.snapshot(&[contract], 3).await.unwrap();
+
+        let owners: Vec<(String, String)> = snapshot
+            .holdings
+            .iter()
+            .map(|h| (h.token_id.clone(), h.owner.clone()))
+            .collect();
+        assert_eq!(
+            owners,
+            vec![
+                ("1".to_string(), address(0xa)),
+                ("3".to_string(), address(0xc)),
+            ]
+        );
+        assert!(snapshot.to_csv().starts_with(OwnershipSnapshot::CSV_HEADER));
+        assert_eq!(snapshot.to_csv().lines().count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_erc721_snapshot_from_token_table() {
+        let erc721 = ContractType::ERC721;
+        let snapshotter = snapshotter(
+            vec![
+                // Before the backfill of token 7, and after the snapshot.
+                transfer(erc721.clone(), 3, 7, 0, 0xe),
+                transfer(erc721.clone(), 8, 7, 0xa, 0xb),
+                // Pending block event, without number.
+                TokenTransferEvent {
+                    block_number: None,
+                    ..transfer(erc721.clone(), 0, 9, 0, 0xf)
+                },
+            ],
+            MockStarknetClient::default(),
+        )
+        .await;
+
+        let token = |token_id: u64, owner: u64| TokenInfo {
+            contract_address: address(0xc0),
+            chain_id: "SN_MAIN".to_string(),
+            token_id: token_id.to_string(),
+            token_id_hex: address(token_id),
+            owner: address(owner),
+        };
+        let storage = &snapshotter.storage;
+        storage
+            .register_backfilled_token(&token(7, 0xa), 5, 50)
+            .await
+            .unwrap();
+        storage.register_token(&token(9, 0xf), 20).await.unwrap();
+        let mint = TokenMintInfo {
+            block_number: Some(2),
+            ..Default::default()
+        };
+        storage
+            .register_mint(&address(0xc0), &address(9), "9", &mint)
+            .await
+            .unwrap();
+
+        let contract = FieldElement::from(0xc0_u64);
+        let snapshot = snapshotter.snapshot(&[contract], 6).await.unwrap();
+
+        let owners: Vec<(String, String)> = snapshot
+            .holdings
+            .iter()
+            .map(|h| (h.token_id.clone(), h.owner.clone()))
+            .collect();
+        assert_eq!(
+            owners,
+            vec![
+                ("7".to_string(), address(0xa)),
+                ("9".to_string(), address(0xf)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_on_chain() {
+        let mut client = MockStarknetClient::default();
+        // Token 1 burnt on chain, token 2 owned by 0xd instead of 0xc.
+        client
+            .expect_call_contract()
+            .returning(|_, selector, calldata, _| {
+                assert_eq!(selector, get_selector_from_name("owner_of").unwrap());
+                if calldata[0] == FieldElement::from(1_u64) {
+                    Err(StarknetClientError::Contract("Invalid token".to_string()))
+                } else {
+                    Ok(vec![FieldElement::from(0xd_u64)])
+                }
+            });
+
+        let snapshotter = snapshotter(
+            vec![
+                transfer(ContractType::ERC721, 1, 1, 0, 0xa),
+                transfer(ContractType::ERC721, 1, 2, 0, 0xb),
+                transfer(ContractType::ERC721, 2, 2, 0xb, 0xc),
+            ],
+            client,
+        )
+        .await;
+
+        let contract = FieldElement::from(0xc0_u64);
+        let mut snapshot = snapshotter.snapshot(&[contract], 2).await.unwrap();
+        assert_eq!(snapshot.holdings.len(), 2);
+
+        let mismatches = snapshotter.verify_on_chain(&mut snapshot).await.unwrap();
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(mismatches[0].on_chain_owner, None);
+        assert_eq!(mismatches[1].on_chain_owner, Some(address(0xd)));
+
+        assert_eq!(snapshot.holdings.len(), 1);
+        assert_eq!(snapshot.holdings[0].owner, address(0xd));
+        assert_eq!(snapshot.holdings[0].balance, Some("1".to_string()));
+        assert!(snapshot.holdings[0].verified);
+    }
+
+    #[tokio::test]
+    async fn test_verify_on_chain_node_error() {
+        let mut client = MockStarknetClient::default();
+        client
+            .expect_call_contract()
+            .returning(|_, _, _, _| Err(StarknetClientError::Provider(ProviderError::RateLimited)));
+
+        let snapshotter =
+            snapshotter(vec![transfer(ContractType::ERC721, 1, 1, 0, 0xa)], client).await;
+
+        let contract = FieldElement::from(0xc0_u64);
+        let mut snapshot = snapshotter.snapshot(&[contract], 2).await.unwrap();
+
+        // Not a burnt token: the error is returned and the holding kept.
+        assert!(snapshotter.verify_on_chain(&mut snapshot).await.is_err());
+        assert_eq!(snapshot.holdings.len(), 1);
+    }
+
+    fn felts(values: &[u64]) -> Vec<FieldElement> {
+        values.iter().map(|v| FieldElement::from(*v)).collect()
+    }
+
+    fn erc1155_event(keys: Vec<FieldElement>, data: Vec<FieldElement>) -> EmittedEvent {
+        EmittedEvent {
+            from_address: FieldElement::from(0xc0_u64),
+            keys,
+            data,
+            block_hash: None,
+            block_number: Some(1),
+            transaction_hash: FieldElement::ONE,
+        }
+    }
+
+    async fn erc1155_snapshotter(
+        client: MockStarknetClient,
+    ) -> OwnershipSnapshotter<MemoryStorage, MockStarknetClient> {
+        let storage = MemoryStorage::new();
+        let contract_info = ContractInfo {
+            contract_address: address(0xc0),
+            contract_type: ContractType::ERC1155.to_string(),
+            chain_id: "SN_MAIN".to_string(),
+            ..Default::default()
+        };
+        storage
+            .register_contract_info(&contract_info, 10, "SN_MAIN")
+            .await
+            .unwrap();
+
+        OwnershipSnapshotter::new(Arc::new(storage), Arc::new(client), "SN_MAIN")
+    }
+
+    /// 0xa mints 5 of token 1 (indexed members), 0xb mints 2 of token 1
+    /// and 3 of token 2 (members in the data), then 0xa sends 2 of token
+    /// 1 to 0xb, on a second page.
+    fn erc1155_client() -> MockStarknetClient {
+        let mut client = MockStarknetClient::default();
+        client
+            .expect_fetch_events()
+            .returning(|_, to_block, _, _, continuation_token| {
+                assert_eq!(to_block, Some(BlockId::Number(2)));
+
+                let (events, continuation_token) = match continuation_token {
+                    None => (
+                        vec![
+                            erc1155_event(
+                                [vec![TRANSFER_SINGLE_SELECTOR], felts(&[0xe, 0, 0xa])].concat(),
+                                felts(&[1, 0, 5, 0]),
+                            ),
+                            erc1155_event(
+                                vec![TRANSFER_BATCH_SELECTOR],
+                                felts(&[0xe, 0, 0xb, 2, 1, 0, 2, 0, 2, 2, 0, 3, 0]),
+                            ),
+                        ],
+                        Some("1".to_string()),
+                    ),
+                    Some(_) => (
+                        vec![erc1155_event(
+                            [vec![TRANSFER_SINGLE_SELECTOR], felts(&[0xa, 0xa, 0xb])].concat(),
+                            felts(&[1, 0, 2, 0]),
+                        )],
+                        None,
+                    ),
+                };
+
+                Ok(EventResult {
+                    events: HashMap::from([(1, events)]),
+                    continuation_token,
+                })
+            });
+        client
+    }
+
+    #[tokio::test]
+    async fn test_erc1155_snapshot_at_block() {
+        let snapshotter = erc1155_snapshotter(erc1155_client()).await;
+
+        let contract = FieldElement::from(0xc0_u64);
+        let snapshot = snapshotter.snapshot(&[contract], 2).await.unwrap();
+
+        let balances: Vec<(String, String, String)> = snapshot
+            .holdings
+            .iter()
+            .map(|h| {
+                assert_eq!(h.contract_type, "ERC1155");
+                (
+                    h.token_id.clone(),
+                    h.owner.clone(),
+                    h.balance.clone().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            balances,
+            vec![
+                ("1".to_string(), address(0xa), "3".to_string()),
+                ("1".to_string(), address(0xb), "4".to_string()),
+                ("2".to_string(), address(0xb), "3".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_erc1155_verify_on_chain() {
+        let mut client = erc1155_client();
+        // 0xb doesn't hold token 2 anymore.
+        client
+            .expect_call_contract()
+            .returning(|_, selector, calldata, _| {
+                assert_eq!(selector, get_selector_from_name("balance_of").unwrap());
+                match (small(calldata[0]), small(calldata[1])) {
+                    (0xa, 1) => Ok(felts(&[3, 0])),
+                    (0xb, 1) => Ok(felts(&[4, 0])),
+                    _ => Ok(felts(&[0, 0])),
+                }
+            });
+        let snapshotter = erc1155_snapshotter(client).await;
+
+        let contract = FieldElement::from(0xc0_u64);
+        let mut snapshot = snapshotter.snapshot(&[contract], 2).await.unwrap();
+
+        let mismatches = snapshotter.verify_on_chain(&mut snapshot).await.unwrap();
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].token_id_hex, address(2));
+        assert_eq!(mismatches[0].on_chain_owner, None);
+        assert_eq!(mismatches[0].on_chain_balance, "0");
+
+        assert_eq!(snapshot.holdings.len(), 2);
+        assert!(snapshot.holdings.iter().all(|h| h.verified));
+    }
+
+    fn small(felt: FieldElement) -> u64 {
+        u64::try_from(felt).unwrap()
+    }
+}
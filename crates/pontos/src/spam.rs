@@ -0,0 +1,153 @@
+//! Heuristic scoring of the spam collections.
+//!
+//! Most spam collections on Starknet are airdropped to many wallets at
+//! once, to advertise a website through their name. Each signal adds
+//! to the score stored on the `ContractInfo`, from 0 to 100, and the
+//! collections above `ContractInfo::SPAM_SCORE_THRESHOLD` can be hidden.
+use crate::storage::types::{ContractInfo, EventType, TokenTransferEvent};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+const MASS_AIRDROP_SCORE: u8 = 50;
+const URL_NAME_SCORE: u8 = 40;
+const NO_METADATA_SCORE: u8 = 20;
+
+/// Top level domains commonly found in the names of the spam collections.
+const URL_TLDS: [&str; 13] = [
+    "com", "io", "xyz", "net", "org", "app", "site", "top", "fun", "me", "gg", "co", "link",
+];
+
+/// Signals of a spam collection found so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct SpamSignals {
+    /// Minted to many distinct addresses in a single transaction.
+    pub mass_airdrop: bool,
+    /// Name or symbol looking like a URL.
+    pub url_name: bool,
+    /// Neither name nor symbol.
+    pub no_metadata: bool,
+}
+
+impl SpamSignals {
+    /// Signals known from the contract info, at identification.
+    pub fn from_contract(info: &ContractInfo) -> Self {
+        let url_name = [&info.name, &info.symbol]
+            .into_iter()
+            .flatten()
+            .any(|s| looks_like_url(s));
+
+        Self {
+            mass_airdrop: false,
+            url_name,
+            no_metadata: info.name.is_none() && info.symbol.is_none(),
+        }
+    }
+
+    pub fn score(&self) -> u8 {
+        let score = [
+            (self.mass_airdrop, MASS_AIRDROP_SCORE),
+            (self.url_name, URL_NAME_SCORE),
+            (self.no_metadata, NO_METADATA_SCORE),
+        ]
+        .into_iter()
+        .filter(|(signal, _)| *signal)
+        .map(|(_, score)| score as u16)
+        .sum::<u16>();
+
+        score.min(100) as u8
+    }
+}
+
+/// Returns true if the text contains a URL or a domain name.
+pub fn looks_like_url(text: &str) -> bool {
+    let text = text.to_lowercase();
+
+    if text.contains("://") || text.contains("www.") {
+        return true;
+    }
+
+    text.split(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '-'))
+        .filter_map(|word| word.trim_matches('.').rsplit_once('.'))
+        .any(|(domain, tld)| !domain.is_empty() && URL_TLDS.contains(&tld))
+}
+
+/// Contracts of the transfers minting tokens to at least `min_recipients`
+/// distinct addresses, in the given transfers of a single transaction.
+pub fn mass_airdrops(transfers: &[TokenTransferEvent], min_recipients: usize) -> Vec<String> {
+    let mut recipients: HashMap<&str, HashSet<&str>> = HashMap::new();
+
+    for transfer in transfers.iter().filter(|t| t.event_type == EventType::Mint) {
+        recipients
+            .entry(transfer.contract_address.as_str())
+            .or_default()
+            .insert(transfer.to_address.as_str());
+    }
+
+    let mut contracts: Vec<String> = recipients
+        .into_iter()
+        .filter(|(_, to)| to.len() >= min_recipients)
+        .map(|(contract, _)| contract.to_string())
+        .collect();
+    contracts.sort();
+
+    contracts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_looks_like_url() {
+        assert!(looks_like_url("Claim at https://free-nft"));
+        assert!(looks_like_url("www.starknet-rewards"));
+        assert!(looks_like_url("Visit STRKDROP.XYZ"));
+        assert!(looks_like_url("airdrop-pass.io!"));
+
+        assert!(!looks_like_url("Starknet Identity"));
+        assert!(!looks_like_url("Briq Sets v1.2"));
+        assert!(!looks_like_url("..."));
+    }
+
+    #[test]
+    fn test_score() {
+        let info = ContractInfo {
+            name: Some("Voucher: stark-gift.com".to_string()),
+            ..Default::default()
+        };
+        let mut signals = SpamSignals::from_contract(&info);
+        assert!(signals.url_name && !signals.no_metadata);
+        assert_eq!(signals.score(), 40);
+
+        signals.mass_airdrop = true;
+        assert_eq!(signals.score(), 90);
+
+        signals.no_metadata = true;
+        assert_eq!(signals.score(), 100);
+
+        let signals = SpamSignals::from_contract(&ContractInfo::default());
+        assert_eq!(signals.score(), 20);
+    }
+
+    #[test]
+    fn test_mass_airdrops() {
+        let mint = |contract: &str, to: &str| TokenTransferEvent {
+            contract_address: contract.to_string(),
+            to_address: to.to_string(),
+            event_type: EventType::Mint,
+            ..Default::default()
+        };
+
+        let mut transfers: Vec<TokenTransferEvent> =
+            (0..3).map(|i| mint("0x1", &format!("0xa{}", i))).collect();
+        transfers.push(mint("0x2", "0xb"));
+        transfers.push(mint("0x2", "0xb"));
+        transfers.push(TokenTransferEvent {
+            event_type: EventType::Transfer,
+            ..mint("0x2", "0xc")
+        });
+
+        assert_eq!(mass_airdrops(&transfers, 3), vec!["0x1".to_string()]);
+        assert!(mass_airdrops(&transfers, 4).is_empty());
+    }
+}
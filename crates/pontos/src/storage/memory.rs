@@ -0,0 +1,1209 @@
+//! Pontos `Storage` kept in process memory, lost when the indexer stops.
+//!
+//! Behaves like the SQL storages (same upserts and cleaning rules),
+//! and exposes some helpers to inspect what was indexed.
+use crate::storage::types::{
+    BlockInfo, CollectionActivity, CollectionStats, ContractInfo, ContractType, EventType,
+    OperatorApproval, Page, Pagination, RegisteredToken, ReindexFilter, SaleFilter, StorageError,
+    TokenApproval, TokenInfo, TokenMintInfo, TokenSaleEvent, TokenTransferEvent,
+};
+use crate::storage::utils::is_zero_address;
+use crate::storage::{QueryStorage, Storage};
+use ark_starknet::format::to_hex_str;
+use async_trait::async_trait;
+use starknet::core::types::FieldElement;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tracing::trace;
+
+/// Token as stored, with its mint info once known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredToken {
+    pub token: TokenInfo,
+    pub block_timestamp: u64,
+    pub mint: Option<TokenMintInfo>,
+    /// Block at which the token has been found on chain, for the
+    /// tokens registered by a backfill instead of their mint.
+    pub backfilled_block_number: Option<u64>,
+    /// Owner found on chain by the backfill.
+    pub backfilled_owner: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct MemoryState {
+    /// Keyed by (contract_address, chain_id, token_id_hex).
+    tokens: HashMap<(String, String, String), StoredToken>,
+    /// Unique by event id, in the order of their registration.
+    transfers: Vec<TokenTransferEvent>,
+    sales: Vec<TokenSaleEvent>,
+    /// Keyed by (contract_address, chain_id).
+    contracts: HashMap<(String, String), ContractInfo>,
+    /// Keyed by (contract_address, chain_id, token_id_hex).
+    token_approvals: HashMap<(String, String, String), TokenApproval>,
+    /// Keyed by (contract_address, chain_id, owner, operator).
+    operator_approvals: HashMap<(String, String, String, String), OperatorApproval>,
+    /// Block info with the block timestamp, keyed by block number.
+    blocks: BTreeMap<u64, (u64, BlockInfo)>,
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    state: RwLock<MemoryState>,
+    /// Copy of the state when the unit of work started,
+    /// restored on rollback.
+    snapshot: RwLock<Option<MemoryState>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, MemoryState> {
+        self.state.read().expect("memory storage lock poisoned")
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, MemoryState> {
+        self.state.write().expect("memory storage lock poisoned")
+    }
+
+    pub fn tokens(&self) -> Vec<StoredToken> {
+        self.read().tokens.values().cloned().collect()
+    }
+
+    pub fn token(&self, contract_address: &str, token_id_hex: &str) -> Option<StoredToken> {
+        self.read()
+            .tokens
+            .values()
+            .find(|t| {
+                t.token.contract_address == contract_address && t.token.token_id_hex == token_id_hex
+            })
+            .cloned()
+    }
+
+    pub fn transfer_events(&self) -> Vec<TokenTransferEvent> {
+        self.read().transfers.clone()
+    }
+
+    pub fn sale_events(&self) -> Vec<TokenSaleEvent> {
+        self.read().sales.clone()
+    }
+
+    pub fn contracts(&self) -> Vec<ContractInfo> {
+        self.read().contracts.values().cloned().collect()
+    }
+
+    pub fn token_approvals(&self) -> Vec<TokenApproval> {
+        self.read().token_approvals.values().cloned().collect()
+    }
+
+    pub fn operator_approvals(&self) -> Vec<OperatorApproval> {
+        self.read().operator_approvals.values().cloned().collect()
+    }
+
+    /// Block numbers with a block info, in ascending order.
+    pub fn block_numbers(&self) -> Vec<u64> {
+        self.read().blocks.keys().copied().collect()
+    }
+
+    /// Removes everything, including a unit of work in progress.
+    pub fn clear(&self) {
+        *self.write() = MemoryState::default();
+        *self.snapshot.write().expect("memory storage lock poisoned") = None;
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn begin_unit_of_work(&self) -> Result<(), StorageError> {
+        let mut snapshot = self.snapshot.write().expect("memory storage lock poisoned");
+
+        if snapshot.is_some() {
+            return Err(StorageError::DatabaseError(
+                "A unit of work is already in progress".to_string(),
+            ));
+        }
+
+        *snapshot = Some(self.read().clone());
+
+        Ok(())
+    }
+
+    async fn commit_unit_of_work(&self) -> Result<(), StorageError> {
+        match self
+            .snapshot
+            .write()
+            .expect("memory storage lock poisoned")
+            .take()
+        {
+            Some(_) => Ok(()),
+            None => Err(StorageError::DatabaseError(
+                "No unit of work in progress".to_string(),
+            )),
+        }
+    }
+
+    async fn rollback_unit_of_work(&self) -> Result<(), StorageError> {
+        let snapshot = self
+            .snapshot
+            .write()
+            .expect("memory storage lock poisoned")
+            .take();
+
+        if let Some(state) = snapshot {
+            *self.write() = state;
+        }
+
+        Ok(())
+    }
+
+    async fn register_mint(
+        &self,
+        contract_address: &str,
+        token_id_hex: &str,
+        token_id: &str,
+        info: &TokenMintInfo,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Registering mint {} {} {:?}",
+            contract_address,
+            token_id,
+            info
+        );
+
+        // Same as the SQL storages: only existing tokens are updated,
+        // and a known price is never erased.
+        let mut state = self.write();
+        for stored in state.tokens.values_mut().filter(|t| {
+            t.token.contract_address == contract_address && t.token.token_id_hex == token_id_hex
+        }) {
+            let previous = stored.mint.take().unwrap_or_default();
+            stored.mint = Some(TokenMintInfo {
+                price: info.price.clone().or(previous.price),
+                currency_address: info.currency_address.clone().or(previous.currency_address),
+                ..info.clone()
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn register_token(
+        &self,
+        token: &TokenInfo,
+        block_timestamp: u64,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Registering token {:?} - block_timestamp: {}",
+            token,
+            block_timestamp
+        );
+
+        let key = (
+            token.contract_address.clone(),
+            token.chain_id.clone(),
+            token.token_id_hex.clone(),
+        );
+
+        let mut state = self.write();
+        let stored = state.tokens.entry(key).or_insert_with(|| StoredToken {
+            token: token.clone(),
+            block_timestamp,
+            mint: None,
+            backfilled_block_number: None,
+            backfilled_owner: None,
+        });
+        stored.token.owner = token.owner.clone();
+        stored.block_timestamp = block_timestamp;
+
+        Ok(())
+    }
+
+    async fn register_backfilled_token(
+        &self,
+        token: &TokenInfo,
+        block_number: u64,
+        block_timestamp: u64,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Registering backfilled token {:?} - block_number: {}",
+            token,
+            block_number
+        );
+
+        let key = (
+            token.contract_address.clone(),
+            token.chain_id.clone(),
+            token.token_id_hex.clone(),
+        );
+
+        self.write()
+            .tokens
+            .entry(key)
+            .or_insert_with(|| StoredToken {
+                token: token.clone(),
+                block_timestamp,
+                mint: None,
+                backfilled_block_number: Some(block_number),
+                backfilled_owner: Some(token.owner.clone()),
+            });
+
+        Ok(())
+    }
+
+    async fn register_sale_event(
+        &self,
+        event: &TokenSaleEvent,
+        block_timestamp: u64,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Registering sale event {:?} - block_timestamp: {}",
+            event,
+            block_timestamp
+        );
+
+        let mut state = self.write();
+        if !state.sales.iter().any(|s| s.event_id == event.event_id) {
+            state.sales.push(TokenSaleEvent {
+                timestamp: block_timestamp,
+                ..event.clone()
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn register_transfer_event(
+        &self,
+        event: &TokenTransferEvent,
+        block_timestamp: u64,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Registering transfer event {:?} - block_timestamp: {}",
+            event,
+            block_timestamp
+        );
+
+        let mut state = self.write();
+        if !state.transfers.iter().any(|e| e.event_id == event.event_id) {
+            state.transfers.push(TokenTransferEvent {
+                timestamp: block_timestamp,
+                ..event.clone()
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn register_token_approval(&self, approval: &TokenApproval) -> Result<(), StorageError> {
+        trace!("Registering token approval {:?}", approval);
+
+        let key = (
+            approval.contract_address.clone(),
+            approval.chain_id.clone(),
+            approval.token_id_hex.clone(),
+        );
+
+        let mut state = self.write();
+        if approval.approved_address == to_hex_str(&FieldElement::ZERO) {
+            state.token_approvals.remove(&key);
+        } else {
+            state.token_approvals.insert(key, approval.clone());
+        }
+
+        Ok(())
+    }
+
+    async fn register_operator_approval(
+        &self,
+        approval: &OperatorApproval,
+    ) -> Result<(), StorageError> {
+        trace!("Registering operator approval {:?}", approval);
+
+        let key = (
+            approval.contract_address.clone(),
+            approval.chain_id.clone(),
+            approval.owner.clone(),
+            approval.operator.clone(),
+        );
+
+        let mut state = self.write();
+        if approval.approved {
+            state.operator_approvals.insert(key, approval.clone());
+        } else {
+            state.operator_approvals.remove(&key);
+        }
+
+        Ok(())
+    }
+
+    async fn get_contract_type(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+    ) -> Result<ContractType, StorageError> {
+        trace!("Getting contract info for contract {}", contract_address);
+
+        let key = (contract_address.to_string(), chain_id.to_string());
+        match self.read().contracts.get(&key) {
+            Some(info) => Ok(ContractType::from_str(&info.contract_type).unwrap()),
+            None => Err(StorageError::NotFound(format!(
+                "Contract {} not found",
+                contract_address
+            ))),
+        }
+    }
+
+    async fn register_contract_info(
+        &self,
+        info: &ContractInfo,
+        block_timestamp: u64,
+        chain_id: &str,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Registering contract info {:?} for contract {} - block_timestamp: {}",
+            info.contract_type,
+            info.contract_address,
+            block_timestamp
+        );
+
+        let key = (info.contract_address.clone(), chain_id.to_string());
+
+        let mut state = self.write();
+        let merged = match state.contracts.remove(&key) {
+            Some(previous) => ContractInfo {
+                name: info.name.clone().or(previous.name),
+                symbol: info.symbol.clone().or(previous.symbol),
+                image: info.image.clone().or(previous.image),
+                deployed_block_number: info
+                    .deployed_block_number
+                    .or(previous.deployed_block_number),
+                deployer_address: info.deployer_address.clone().or(previous.deployer_address),
+                class_hash: info.class_hash.clone().or(previous.class_hash),
+                spam_score: info.spam_score.max(previous.spam_score),
+                chain_id: chain_id.to_string(),
+                ..info.clone()
+            },
+            None => ContractInfo {
+                chain_id: chain_id.to_string(),
+                ..info.clone()
+            },
+        };
+        state.contracts.insert(key, merged);
+
+        Ok(())
+    }
+
+    async fn raise_contract_spam_score(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        spam_score: u8,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Raising spam score of contract {} to {}",
+            contract_address,
+            spam_score
+        );
+
+        let key = (contract_address.to_string(), chain_id.to_string());
+        if let Some(info) = self.write().contracts.get_mut(&key) {
+            info.spam_score = info.spam_score.max(Some(spam_score));
+        }
+
+        Ok(())
+    }
+
+    async fn set_block_info(
+        &self,
+        block_number: u64,
+        block_timestamp: u64,
+        info: BlockInfo,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Setting block info {:?} for block #{}",
+            info.status,
+            block_number
+        );
+
+        self.write()
+            .blocks
+            .insert(block_number, (block_timestamp, info));
+
+        Ok(())
+    }
+
+    async fn get_block_info(&self, block_number: u64) -> Result<BlockInfo, StorageError> {
+        trace!("Getting block info for block #{}", block_number);
+
+        match self.read().blocks.get(&block_number) {
+            Some((_, info)) => Ok(info.clone()),
+            None => Err(StorageError::NotFound(format!(
+                "Block {} not found",
+                block_number
+            ))),
+        }
+    }
+
+    async fn clean_block(
+        &self,
+        block_timestamp: u64,
+        block_number: Option<u64>,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Cleaning block #{:?} [ts: {}]",
+            block_number,
+            block_timestamp
+        );
+
+        let mut state = self.write();
+        state.blocks.retain(|_, (ts, _)| *ts != block_timestamp);
+        state.transfers.retain(|e| e.timestamp != block_timestamp);
+        state.sales.retain(|s| s.timestamp != block_timestamp);
+
+        Ok(())
+    }
+
+    async fn clean_block_events(
+        &self,
+        block_timestamp: u64,
+        block_number: u64,
+        filter: &ReindexFilter,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Cleaning events of block #{} [ts: {}] matching {:?}",
+            block_number,
+            block_timestamp,
+            filter
+        );
+
+        let mut state = self.write();
+        state
+            .transfers
+            .retain(|e| e.timestamp != block_timestamp || !filter.matches_transfer(e));
+        state
+            .sales
+            .retain(|s| s.timestamp != block_timestamp || !filter.matches_sale(s));
+
+        Ok(())
+    }
+}
+
+/// Page of the already sorted items.
+fn paginate<T>(items: impl Iterator<Item = T>, pagination: &Pagination) -> Page<T> {
+    let items = items
+        .skip(pagination.offset as usize)
+        .take(pagination.limit as usize + 1)
+        .collect();
+
+    Page::from_items(items, pagination)
+}
+
+/// Most recent events first.
+fn sorted_desc<T, F>(mut items: Vec<T>, key: F) -> Vec<T>
+where
+    F: Fn(&T) -> (u64, String),
+{
+    items.sort_by(|a, b| {
+        let (ts_a, id_a) = key(a);
+        let (ts_b, id_b) = key(b);
+        ts_b.cmp(&ts_a).then(id_a.cmp(&id_b))
+    });
+    items
+}
+
+impl MemoryStorage {
+    /// Tokens matching the predicate, sorted by contract address and token id.
+    fn sorted_tokens<F>(&self, predicate: F) -> Vec<TokenInfo>
+    where
+        F: Fn(&TokenInfo) -> bool,
+    {
+        let mut tokens: Vec<TokenInfo> = self
+            .read()
+            .tokens
+            .values()
+            .map(|t| t.token.clone())
+            .filter(predicate)
+            .collect();
+
+        tokens.sort_by(|a, b| {
+            (&a.contract_address, &a.token_id_hex).cmp(&(&b.contract_address, &b.token_id_hex))
+        });
+        tokens
+    }
+}
+
+#[async_trait]
+impl QueryStorage for MemoryStorage {
+    async fn get_token(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        token_id_hex: &str,
+    ) -> Result<TokenInfo, StorageError> {
+        let key = (
+            contract_address.to_string(),
+            chain_id.to_string(),
+            token_id_hex.to_string(),
+        );
+
+        match self.read().tokens.get(&key) {
+            Some(stored) => Ok(stored.token.clone()),
+            None => Err(StorageError::NotFound(format!(
+                "token {contract_address} {token_id_hex}"
+            ))),
+        }
+    }
+
+    async fn tokens_by_owner(
+        &self,
+        owner: &str,
+        chain_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<TokenInfo>, StorageError> {
+        let tokens = self.sorted_tokens(|t| t.owner == owner && t.chain_id == chain_id);
+        Ok(paginate(tokens.into_iter(), &pagination))
+    }
+
+    async fn collection_tokens(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<TokenInfo>, StorageError> {
+        let tokens = self
+            .sorted_tokens(|t| t.contract_address == contract_address && t.chain_id == chain_id);
+        Ok(paginate(tokens.into_iter(), &pagination))
+    }
+
+    async fn collection_tokens_until(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        block_number: u64,
+        pagination: Pagination,
+    ) -> Result<Page<RegisteredToken>, StorageError> {
+        let mut tokens: Vec<RegisteredToken> = self
+            .read()
+            .tokens
+            .values()
+            .filter(|t| {
+                t.token.contract_address == contract_address && t.token.chain_id == chain_id
+            })
+            .filter_map(|t| {
+                let registered_block = t
+                    .backfilled_block_number
+                    .or_else(|| t.mint.as_ref().and_then(|m| m.block_number))?;
+
+                (registered_block <= block_number).then(|| RegisteredToken {
+                    token: t.token.clone(),
+                    block_number: registered_block,
+                    backfilled_owner: t.backfilled_owner.clone(),
+                })
+            })
+            .collect();
+
+        tokens.sort_by(|a, b| a.token.token_id_hex.cmp(&b.token.token_id_hex));
+        Ok(paginate(tokens.into_iter(), &pagination))
+    }
+
+    async fn token_history(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        token_id_hex: &str,
+        pagination: Pagination,
+    ) -> Result<Page<TokenTransferEvent>, StorageError> {
+        let events = self
+            .read()
+            .transfers
+            .iter()
+            .filter(|e| {
+                e.contract_address == contract_address
+                    && e.chain_id == chain_id
+                    && e.token_id_hex == token_id_hex
+            })
+            .cloned()
+            .collect();
+
+        let events = sorted_desc(events, |e| (e.timestamp, e.event_id.clone()));
+        Ok(paginate(events.into_iter(), &pagination))
+    }
+
+    async fn collection_transfers_until(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        block_number: u64,
+        pagination: Pagination,
+    ) -> Result<Page<TokenTransferEvent>, StorageError> {
+        let mut events: Vec<(u64, TokenTransferEvent)> = self
+            .read()
+            .transfers
+            .iter()
+            .filter(|e| e.contract_address == contract_address && e.chain_id == chain_id)
+            .filter_map(|e| match e.block_number {
+                Some(n) if n <= block_number => Some((n, e.clone())),
+                _ => None,
+            })
+            .collect();
+
+        events.sort_by(|(a_block, a), (b_block, b)| {
+            a_block.cmp(b_block).then(a.event_id.cmp(&b.event_id))
+        });
+        Ok(paginate(events.into_iter().map(|(_, e)| e), &pagination))
+    }
+
+    async fn sales(
+        &self,
+        chain_id: &str,
+        filter: &SaleFilter,
+        pagination: Pagination,
+    ) -> Result<Page<TokenSaleEvent>, StorageError> {
+        let sales = self
+            .read()
+            .sales
+            .iter()
+            .filter(|s| s.chain_id == chain_id && filter.matches(s))
+            .cloned()
+            .collect();
+
+        let sales = sorted_desc(sales, |s| (s.timestamp, s.event_id.clone()));
+        Ok(paginate(sales.into_iter(), &pagination))
+    }
+
+    async fn collection_stats(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        timestamp: u64,
+    ) -> Result<CollectionStats, StorageError> {
+        let state = self.read();
+        let mut stats = CollectionStats {
+            contract_address: contract_address.to_string(),
+            chain_id: chain_id.to_string(),
+            ..Default::default()
+        };
+
+        // Derived from the state instead of being maintained,
+        // which gives the same figures as the SQL storages.
+        let holders: Vec<&str> = state
+            .tokens
+            .values()
+            .map(|t| &t.token)
+            .filter(|t| t.contract_address == contract_address && t.chain_id == chain_id)
+            .filter(|t| !is_zero_address(&t.owner))
+            .map(|t| t.owner.as_str())
+            .collect();
+        stats.supply = holders.len() as u64;
+        stats.owners_count = holders.into_iter().collect::<HashSet<_>>().len() as u64;
+
+        let sales = state
+            .sales
+            .iter()
+            .filter(|s| s.nft_contract_address == contract_address && s.chain_id == chain_id)
+            .filter(|s| !s.is_wash_trade())
+            .map(|s| {
+                let activity = CollectionActivity {
+                    sales_count: 1,
+                    volume_usd: s.price_usd.unwrap_or_default(),
+                    mint_count: 0,
+                };
+                (s.timestamp, activity)
+            });
+
+        let mints = state
+            .transfers
+            .iter()
+            .filter(|e| e.contract_address == contract_address && e.chain_id == chain_id)
+            .filter(|e| e.event_type == EventType::Mint)
+            .map(|e| {
+                let activity = CollectionActivity {
+                    mint_count: 1,
+                    ..Default::default()
+                };
+                (e.timestamp, activity)
+            });
+
+        for (event_timestamp, activity) in sales.chain(mints) {
+            stats.all_time.add(&activity);
+            stats.add_bucket(
+                timestamp,
+                CollectionStats::bucket(event_timestamp),
+                &activity,
+            );
+        }
+
+        Ok(stats)
+    }
+
+    async fn contracts(
+        &self,
+        chain_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<ContractInfo>, StorageError> {
+        let mut contracts: Vec<ContractInfo> = self
+            .read()
+            .contracts
+            .values()
+            .filter(|c| c.chain_id == chain_id)
+            .cloned()
+            .collect();
+        contracts.sort_by(|a, b| a.contract_address.cmp(&b.contract_address));
+
+        Ok(paginate(contracts.into_iter(), &pagination))
+    }
+
+    async fn get_contract_info(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+    ) -> Result<ContractInfo, StorageError> {
+        let key = (contract_address.to_string(), chain_id.to_string());
+
+        self.read()
+            .contracts
+            .get(&key)
+            .cloned()
+            .ok_or_else(|| StorageError::NotFound(format!("contract {contract_address}")))
+    }
+
+    async fn last_block_info(&self) -> Result<Option<BlockInfo>, StorageError> {
+        Ok(self
+            .read()
+            .blocks
+            .values()
+            .next_back()
+            .map(|(_, info)| info.clone()))
+    }
+
+    async fn blocks_info(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<BlockInfo>, StorageError> {
+        if from_block > to_block {
+            return Ok(vec![]);
+        }
+
+        Ok(self
+            .read()
+            .blocks
+            .range(from_block..=to_block)
+            .map(|(_, (_, info))| info.clone())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::types::{
+        BlockIndexingStatus, WashTradeFlag, WashTradeKind, STATS_BUCKET_SECS,
+    };
+
+    fn block_info(block_number: u64) -> BlockInfo {
+        BlockInfo {
+            indexer_version: "0.0.1".to_string(),
+            indexer_identifier: "test".to_string(),
+            status: BlockIndexingStatus::Terminated,
+            block_number,
+        }
+    }
+
+    fn token() -> TokenInfo {
+        TokenInfo {
+            contract_address: "0x1".to_string(),
+            token_id: "1".to_string(),
+            chain_id: "SN_TEST".to_string(),
+            token_id_hex: "0x1".to_string(),
+            owner: "0xa".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register_token_and_mint() {
+        let storage = MemoryStorage::new();
+
+        // Mint of an unknown token is ignored.
+        let mint = TokenMintInfo {
+            address: "0xa".to_string(),
+            timestamp: 10,
+            transaction_hash: "0x123".to_string(),
+            block_number: Some(1),
+            price: Some("0x64".to_string()),
+            currency_address: Some("0xe".to_string()),
+        };
+        storage
+            .register_mint("0x1", "0x1", "1", &mint)
+            .await
+            .unwrap();
+        assert!(storage.tokens().is_empty());
+
+        storage.register_token(&token(), 10).await.unwrap();
+        storage
+            .register_mint("0x1", "0x1", "1", &mint)
+            .await
+            .unwrap();
+
+        // A registration without price keeps the known one.
+        let mint_without_price = TokenMintInfo {
+            price: None,
+            currency_address: None,
+            ..mint.clone()
+        };
+        storage
+            .register_mint("0x1", "0x1", "1", &mint_without_price)
+            .await
+            .unwrap();
+
+        let transfer_owner = TokenInfo {
+            owner: "0xb".to_string(),
+            ..token()
+        };
+        storage.register_token(&transfer_owner, 20).await.unwrap();
+
+        let stored = storage.token("0x1", "0x1").unwrap();
+        assert_eq!(stored.token.owner, "0xb");
+        assert_eq!(stored.block_timestamp, 20);
+        assert_eq!(stored.mint, Some(mint));
+    }
+
+    #[tokio::test]
+    async fn test_clean_block() {
+        let storage = MemoryStorage::new();
+
+        let event = TokenTransferEvent {
+            event_id: "0xe1".to_string(),
+            ..Default::default()
+        };
+        storage.register_transfer_event(&event, 10).await.unwrap();
+        storage.register_transfer_event(&event, 10).await.unwrap();
+        storage.set_block_info(1, 10, block_info(1)).await.unwrap();
+        storage.set_block_info(2, 20, block_info(2)).await.unwrap();
+        assert_eq!(storage.transfer_events().len(), 1);
+
+        storage.clean_block(10, Some(1)).await.unwrap();
+
+        assert!(storage.transfer_events().is_empty());
+        assert_eq!(storage.block_numbers(), vec![2]);
+        assert!(matches!(
+            storage.get_block_info(1).await,
+            Err(StorageError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_clean_block_events() {
+        let storage = MemoryStorage::new();
+
+        for (event_id, contract_address) in [("0xe1", "0x1"), ("0xe2", "0x2")] {
+            let event = TokenTransferEvent {
+                event_id: event_id.to_string(),
+                contract_address: contract_address.to_string(),
+                ..Default::default()
+            };
+            storage.register_transfer_event(&event, 10).await.unwrap();
+        }
+
+        for (event_id, marketplace) in [("0xs1", "0x9"), ("0xs2", "0x0000")] {
+            let sale = TokenSaleEvent {
+                timestamp: 10,
+                from_address: "0xa".to_string(),
+                to_address: "0xb".to_string(),
+                nft_contract_address: "0x1".to_string(),
+                nft_type: None,
+                marketplace_contract_address: marketplace.to_string(),
+                marketplace_name: "test".to_string(),
+                transaction_hash: "0x123".to_string(),
+                token_id: "1".to_string(),
+                token_id_hex: "0x1".to_string(),
+                event_type: crate::storage::types::EventType::Sale,
+                event_id: event_id.to_string(),
+                block_number: Some(1),
+                updated_at: None,
+                quantity: 1,
+                currency_address: None,
+                price: "0x1".to_string(),
+                currency_symbol: None,
+                currency_decimals: None,
+                normalized_price: None,
+                price_usd: None,
+                chain_id: "SN_TEST".to_string(),
+                wash_trade_flags: vec![],
+            };
+            storage.register_sale_event(&sale, 10).await.unwrap();
+        }
+        storage.set_block_info(1, 10, block_info(1)).await.unwrap();
+
+        let filter = ReindexFilter {
+            nft_contracts: vec!["0x1".to_string()],
+            ..Default::default()
+        };
+        storage.clean_block_events(10, 1, &filter).await.unwrap();
+
+        let transfers = storage.transfer_events();
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].contract_address, "0x2");
+        assert_eq!(storage.sale_events().len(), 2);
+        assert!(storage.get_block_info(1).await.is_ok());
+
+        // The sales of the NFT contract 0x1 are matched by marketplace only.
+        let filter = ReindexFilter {
+            marketplaces: vec!["0x1".to_string()],
+            ..Default::default()
+        };
+        storage.clean_block_events(10, 1, &filter).await.unwrap();
+        assert_eq!(storage.transfer_events().len(), 1);
+        assert_eq!(storage.sale_events().len(), 2);
+
+        // Detected sales, without marketplace, are kept.
+        storage
+            .clean_block_events(10, 1, &ReindexFilter::default())
+            .await
+            .unwrap();
+
+        assert!(storage.transfer_events().is_empty());
+        let sales = storage.sale_events();
+        assert_eq!(sales.len(), 1);
+        assert_eq!(sales[0].event_id, "0xs2");
+    }
+
+    #[tokio::test]
+    async fn test_raise_contract_spam_score() {
+        let storage = MemoryStorage::new();
+        let info = ContractInfo {
+            contract_address: "0x1".to_string(),
+            contract_type: "ERC721".to_string(),
+            spam_score: Some(40),
+            ..Default::default()
+        };
+        storage
+            .register_contract_info(&info, 10, "SN_TEST")
+            .await
+            .unwrap();
+
+        storage
+            .raise_contract_spam_score("0x1", "SN_TEST", 20)
+            .await
+            .unwrap();
+        assert_eq!(storage.contracts()[0].spam_score, Some(40));
+
+        storage
+            .raise_contract_spam_score("0x1", "SN_TEST", 90)
+            .await
+            .unwrap();
+        assert!(storage.contracts()[0].is_spam());
+
+        // A registration without score keeps the known one.
+        let unscored = ContractInfo {
+            spam_score: None,
+            ..info
+        };
+        storage
+            .register_contract_info(&unscored, 20, "SN_TEST")
+            .await
+            .unwrap();
+        assert_eq!(storage.contracts()[0].spam_score, Some(90));
+    }
+
+    #[tokio::test]
+    async fn test_unit_of_work_rollback() {
+        let storage = MemoryStorage::new();
+        storage.set_block_info(1, 10, block_info(1)).await.unwrap();
+
+        storage.begin_unit_of_work().await.unwrap();
+        assert!(storage.begin_unit_of_work().await.is_err());
+
+        storage.set_block_info(2, 20, block_info(2)).await.unwrap();
+        storage.register_token(&token(), 20).await.unwrap();
+        assert!(storage.get_block_info(2).await.is_ok());
+
+        storage.rollback_unit_of_work().await.unwrap();
+
+        assert_eq!(storage.block_numbers(), vec![1]);
+        assert!(storage.tokens().is_empty());
+        assert!(storage.commit_unit_of_work().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query_tokens_and_sales() {
+        let storage = MemoryStorage::new();
+
+        for (id, owner) in [("0x3", "0xa"), ("0x1", "0xa"), ("0x2", "0xb")] {
+            let token = TokenInfo {
+                token_id_hex: id.to_string(),
+                owner: owner.to_string(),
+                ..token()
+            };
+            storage.register_token(&token, 10).await.unwrap();
+        }
+
+        let page = storage
+            .tokens_by_owner("0xa", "SN_TEST", Pagination::new(0, 1))
+            .await
+            .unwrap();
+        assert_eq!(page.items[0].token_id_hex, "0x1");
+        assert_eq!(page.next_offset, Some(1));
+
+        let page = storage
+            .tokens_by_owner("0xa", "SN_TEST", Pagination::new(1, 1))
+            .await
+            .unwrap();
+        assert_eq!(page.items[0].token_id_hex, "0x3");
+        assert_eq!(page.next_offset, None);
+
+        let page = storage
+            .collection_tokens("0x1", "SN_TEST", Pagination::default())
+            .await
+            .unwrap();
+        assert_eq!(page.items.len(), 3);
+
+        for (id, ts) in [("0xs1", 10), ("0xs2", 30), ("0xs3", 20)] {
+            let sale = TokenSaleEvent {
+                timestamp: ts,
+                from_address: "0xa".to_string(),
+                to_address: "0xb".to_string(),
+                nft_contract_address: "0x1".to_string(),
+                nft_type: None,
+                marketplace_contract_address: "0xm".to_string(),
+                marketplace_name: "test".to_string(),
+                transaction_hash: "0x123".to_string(),
+                token_id: "1".to_string(),
+                token_id_hex: "0x1".to_string(),
+                event_type: crate::storage::types::EventType::Sale,
+                event_id: id.to_string(),
+                block_number: None,
+                updated_at: None,
+                quantity: 1,
+                currency_address: None,
+                price: "0x1".to_string(),
+                currency_symbol: None,
+                currency_decimals: None,
+                normalized_price: None,
+                price_usd: None,
+                chain_id: "SN_TEST".to_string(),
+                wash_trade_flags: vec![],
+            };
+            storage.register_sale_event(&sale, ts).await.unwrap();
+        }
+
+        let filter = SaleFilter {
+            contract_address: Some("0x1".to_string()),
+            from_timestamp: Some(15),
+            ..Default::default()
+        };
+        let page = storage
+            .sales("SN_TEST", &filter, Pagination::default())
+            .await
+            .unwrap();
+        let ids: Vec<&str> = page.items.iter().map(|s| s.event_id.as_str()).collect();
+        assert_eq!(ids, vec!["0xs2", "0xs3"]);
+    }
+
+    #[tokio::test]
+    async fn test_collection_stats() {
+        let storage = MemoryStorage::new();
+        let hour = STATS_BUCKET_SECS;
+        let now = 1000 * hour + 10;
+
+        for (token_id_hex, owner) in [
+            ("0x1", "0xa"),
+            ("0x2", "0xa"),
+            ("0x3", "0xb"),
+            ("0x4", "0x0"),
+        ] {
+            let token = TokenInfo {
+                token_id_hex: token_id_hex.to_string(),
+                owner: owner.to_string(),
+                ..token()
+            };
+            storage.register_token(&token, 10).await.unwrap();
+        }
+
+        let mint = TokenTransferEvent {
+            timestamp: now - 2 * hour,
+            contract_address: "0x1".to_string(),
+            chain_id: "SN_TEST".to_string(),
+            event_type: EventType::Mint,
+            event_id: "0xm1".to_string(),
+            ..Default::default()
+        };
+        storage
+            .register_transfer_event(&mint, mint.timestamp)
+            .await
+            .unwrap();
+
+        // Current hour, same day, same week and older than a week.
+        let ages = [0, 5 * hour, 3 * 24 * hour, 8 * 24 * hour, 0];
+        for (i, age) in ages.into_iter().enumerate() {
+            let sale = TokenSaleEvent {
+                timestamp: now - age,
+                from_address: "0xa".to_string(),
+                to_address: "0xb".to_string(),
+                nft_contract_address: "0x1".to_string(),
+                nft_type: None,
+                marketplace_contract_address: "0xm".to_string(),
+                marketplace_name: "test".to_string(),
+                transaction_hash: "0x123".to_string(),
+                token_id: "1".to_string(),
+                token_id_hex: "0x1".to_string(),
+                event_type: EventType::Sale,
+                event_id: format!("0xs{i}"),
+                block_number: None,
+                updated_at: None,
+                quantity: 1,
+                currency_address: None,
+                price: "0x1".to_string(),
+                currency_symbol: None,
+                currency_decimals: None,
+                normalized_price: None,
+                price_usd: (i != 1).then_some(10.0),
+                chain_id: "SN_TEST".to_string(),
+                // The last sale is a wash trade, and is not counted.
+                wash_trade_flags: (i == 4)
+                    .then(|| WashTradeFlag {
+                        kind: WashTradeKind::SelfTrade,
+                        reason: "seller is buyer".to_string(),
+                    })
+                    .into_iter()
+                    .collect(),
+            };
+            storage
+                .register_sale_event(&sale, sale.timestamp)
+                .await
+                .unwrap();
+        }
+
+        let stats = storage
+            .collection_stats("0x1", "SN_TEST", now)
+            .await
+            .unwrap();
+        assert_eq!(stats.owners_count, 2);
+        assert_eq!(stats.supply, 3);
+        assert_eq!(stats.last_hour.sales_count, 1);
+        assert_eq!(stats.last_day.sales_count, 2);
+        assert_eq!(stats.last_day.volume_usd, 10.0);
+        assert_eq!(stats.last_day.mint_count, 1);
+        assert_eq!(stats.last_week.sales_count, 3);
+        assert_eq!(stats.all_time.sales_count, 4);
+        assert_eq!(stats.all_time.volume_usd, 30.0);
+
+        // A cleaned block is no longer counted.
+        storage.clean_block(now, None).await.unwrap();
+        let stats = storage
+            .collection_stats("0x1", "SN_TEST", now)
+            .await
+            .unwrap();
+        assert_eq!(stats.last_hour, CollectionActivity::default());
+        assert_eq!(stats.all_time.sales_count, 3);
+
+        let stats = storage
+            .collection_stats("0x2", "SN_TEST", now)
+            .await
+            .unwrap();
+        assert_eq!(stats.supply, 0);
+        assert_eq!(stats.all_time, CollectionActivity::default());
+    }
+}
@@ -1,21 +1,46 @@
+#[cfg(any(test, feature = "memory"))]
+pub mod memory;
 #[cfg(feature = "sqlxdb")]
 pub mod sqlx;
 pub mod types;
 pub mod utils;
 use self::types::TokenSaleEvent;
 use crate::storage::types::{
-    BlockInfo, ContractInfo, ContractType, StorageError, TokenInfo, TokenMintInfo,
-    TokenTransferEvent,
+    BlockInfo, CollectionStats, ContractInfo, ContractType, OperatorApproval, Page, Pagination,
+    RegisteredToken, ReindexFilter, SaleFilter, StorageError, TokenApproval, TokenInfo,
+    TokenMintInfo, TokenTransferEvent,
 };
 use async_trait::async_trait;
+#[cfg(any(test, feature = "memory"))]
+pub use memory::MemoryStorage;
 #[cfg(test)]
 use mockall::automock;
 #[cfg(feature = "sqlxdb")]
 pub use sqlx::DefaultSqlxStorage;
+#[cfg(feature = "postgres")]
+pub use sqlx::PostgresStorage;
 
 #[async_trait]
 #[cfg_attr(test, automock)]
 pub trait Storage {
+    /// Starts a unit of work. All the following operations are applied
+    /// at once on `commit_unit_of_work`, or discarded on `rollback_unit_of_work`.
+    /// Only one unit of work can be in progress at a time.
+    ///
+    /// Storages without transaction support can keep the default
+    /// implementation, where every operation is applied immediately.
+    async fn begin_unit_of_work(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    async fn commit_unit_of_work(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    async fn rollback_unit_of_work(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
     async fn register_mint(
         &self,
         contract_address: &str,
@@ -30,6 +55,16 @@ pub trait Storage {
         block_timestamp: u64,
     ) -> Result<(), StorageError>;
 
+    /// Registers a token minted before the indexed blocks, found on chain
+    /// with its owner at `block_number`. The token is marked as backfilled
+    /// and has no mint info. A token already registered is left unchanged.
+    async fn register_backfilled_token(
+        &self,
+        token: &TokenInfo,
+        block_number: u64,
+        block_timestamp: u64,
+    ) -> Result<(), StorageError>;
+
     async fn register_sale_event(
         &self,
         event: &TokenSaleEvent,
@@ -42,6 +77,17 @@ pub trait Storage {
         block_timestamp: u64,
     ) -> Result<(), StorageError>;
 
+    /// Sets the current approval of a token. An approved address
+    /// equal to zero revokes the approval.
+    async fn register_token_approval(&self, approval: &TokenApproval) -> Result<(), StorageError>;
+
+    /// Sets the current approval of an operator for all the tokens
+    /// of an owner. The approval is removed if `approved` is false.
+    async fn register_operator_approval(
+        &self,
+        approval: &OperatorApproval,
+    ) -> Result<(), StorageError>;
+
     async fn get_contract_type(
         &self,
         contract_address: &str,
@@ -55,6 +101,21 @@ pub trait Storage {
         chain_id: &str,
     ) -> Result<(), StorageError>;
 
+    /// Raises the spam score of a contract. A lower score than
+    /// the stored one is ignored, as the signals are only found
+    /// one at a time (like a mass airdrop after the deployment).
+    ///
+    /// Storages without spam support can keep the default implementation.
+    async fn raise_contract_spam_score(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        spam_score: u8,
+    ) -> Result<(), StorageError> {
+        let _ = (contract_address, chain_id, spam_score);
+        Ok(())
+    }
+
     /// A block info is only set if the block has a number and a timestamp.
     async fn set_block_info(
         &self,
@@ -72,4 +133,116 @@ pub trait Storage {
         block_timestamp: u64,
         block_number: Option<u64>,
     ) -> Result<(), StorageError>;
+
+    /// Removes the transfers and sales of a block matching the filter,
+    /// before they are indexed again by a selective reindex.
+    /// The block info, the tokens and the approvals are left unchanged.
+    async fn clean_block_events(
+        &self,
+        block_timestamp: u64,
+        block_number: u64,
+        filter: &ReindexFilter,
+    ) -> Result<(), StorageError>;
+}
+
+/// Read side of the indexed data, shared by the applications
+/// instead of querying the storage tables directly.
+///
+/// Tokens are ordered by contract address and token id, events
+/// and sales from the most recent to the oldest.
+#[async_trait]
+#[cfg_attr(test, automock)]
+pub trait QueryStorage {
+    async fn get_token(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        token_id_hex: &str,
+    ) -> Result<TokenInfo, StorageError>;
+
+    async fn tokens_by_owner(
+        &self,
+        owner: &str,
+        chain_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<TokenInfo>, StorageError>;
+
+    async fn collection_tokens(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<TokenInfo>, StorageError>;
+
+    /// Tokens of a collection minted or backfilled up to the given block
+    /// (included), ordered by token id. The tokens whose mint block is
+    /// unknown are excluded.
+    async fn collection_tokens_until(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        block_number: u64,
+        pagination: Pagination,
+    ) -> Result<Page<RegisteredToken>, StorageError>;
+
+    /// Transfers, mints and burns of a token.
+    async fn token_history(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        token_id_hex: &str,
+        pagination: Pagination,
+    ) -> Result<Page<TokenTransferEvent>, StorageError>;
+
+    /// Transfers, mints and burns of a collection up to the given block
+    /// (included), from the oldest block. Events of the pending block,
+    /// without number, are excluded. The order of the events of a same
+    /// block is not the order of their emission.
+    async fn collection_transfers_until(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        block_number: u64,
+        pagination: Pagination,
+    ) -> Result<Page<TokenTransferEvent>, StorageError>;
+
+    async fn sales(
+        &self,
+        chain_id: &str,
+        filter: &SaleFilter,
+        pagination: Pagination,
+    ) -> Result<Page<TokenSaleEvent>, StorageError>;
+
+    /// Aggregates of a collection, with the periods ending at the hour
+    /// containing `timestamp`. A collection without activity has zeros.
+    async fn collection_stats(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        timestamp: u64,
+    ) -> Result<CollectionStats, StorageError>;
+
+    /// Contracts ordered by address.
+    async fn contracts(
+        &self,
+        chain_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<ContractInfo>, StorageError>;
+
+    async fn get_contract_info(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+    ) -> Result<ContractInfo, StorageError>;
+
+    /// Info of the block with the highest number, if any.
+    async fn last_block_info(&self) -> Result<Option<BlockInfo>, StorageError>;
+
+    /// Info of the blocks registered between the two blocks (included),
+    /// ordered by number. Blocks never indexed are absent.
+    async fn blocks_info(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<BlockInfo>, StorageError>;
 }
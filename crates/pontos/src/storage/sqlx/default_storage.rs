@@ -3,11 +3,16 @@
 //! The implementation in this file is very naive, and mostly
 //! used for testing and as an example of implementation.
 //! No optimization was done for indexing or PK/FK managment.
+use ark_starknet::format::to_hex_str;
 use async_trait::async_trait;
 
 use log::trace;
-use sqlx::{any::AnyPoolOptions, AnyPool, Error as SqlxError, FromRow};
+use sqlx::any::{Any, AnyArguments, AnyPoolOptions, AnyQueryResult, AnyRow};
+use sqlx::query::Query;
+use sqlx::{AnyPool, Error as SqlxError, FromRow, Transaction};
+use starknet::core::types::FieldElement;
 use std::str::FromStr;
+use tokio::sync::Mutex;
 
 use super::types::*;
 use crate::storage::types::*;
@@ -19,8 +24,17 @@ impl From<SqlxError> for StorageError {
     }
 }
 
+/// Storage of the indexer on any database supported by sqlx, with the
+/// minimal schema of `migrations/0_default.sql`.
+///
+/// Only the `Storage` trait is implemented: the schema lacks the chain id,
+/// the block numbers of the events and the statistics, so `QueryStorage`
+/// is left to `PostgresStorage`.
 pub struct DefaultSqlxStorage {
     pool: AnyPool,
+    /// Transaction of the unit of work in progress, if any.
+    /// All the queries are executed in this transaction while it is set.
+    transaction: Mutex<Option<Transaction<'static, Any>>>,
 }
 
 impl DefaultSqlxStorage {
@@ -34,9 +48,53 @@ impl DefaultSqlxStorage {
                 .max_connections(1)
                 .connect(db_url)
                 .await?,
+            transaction: Mutex::new(None),
         })
     }
 
+    /// Executes the query in the unit of work in progress, or directly on the pool.
+    async fn execute<'q>(
+        &self,
+        query: Query<'q, Any, AnyArguments<'q>>,
+    ) -> Result<AnyQueryResult, StorageError> {
+        let mut transaction = self.transaction.lock().await;
+
+        let result = match transaction.as_mut() {
+            Some(tx) => query.execute(&mut **tx).await?,
+            None => query.execute(&self.pool).await?,
+        };
+
+        Ok(result)
+    }
+
+    async fn fetch_all<'q>(
+        &self,
+        query: Query<'q, Any, AnyArguments<'q>>,
+    ) -> Result<Vec<AnyRow>, StorageError> {
+        let mut transaction = self.transaction.lock().await;
+
+        let rows = match transaction.as_mut() {
+            Some(tx) => query.fetch_all(&mut **tx).await?,
+            None => query.fetch_all(&self.pool).await?,
+        };
+
+        Ok(rows)
+    }
+
+    async fn fetch_optional<'q>(
+        &self,
+        query: Query<'q, Any, AnyArguments<'q>>,
+    ) -> Result<Option<AnyRow>, StorageError> {
+        let mut transaction = self.transaction.lock().await;
+
+        let row = match transaction.as_mut() {
+            Some(tx) => query.fetch_optional(&mut **tx).await?,
+            None => query.fetch_optional(&self.pool).await?,
+        };
+
+        Ok(row)
+    }
+
     pub async fn dump_tables(&self) -> Result<(), StorageError> {
         let q = "SELECT * FROM token";
         let rows = sqlx::query(q).fetch_all(&self.pool).await?;
@@ -56,10 +114,8 @@ impl DefaultSqlxStorage {
     ) -> Result<Option<TokenData>, StorageError> {
         let q = "SELECT * FROM token WHERE contract_address = $1 AND token_id = $2";
 
-        match sqlx::query(q)
-            .bind(contract_address)
-            .bind(token_id)
-            .fetch_all(&self.pool)
+        match self
+            .fetch_all(sqlx::query(q).bind(contract_address).bind(token_id))
             .await
         {
             Ok(rows) => {
@@ -69,14 +125,14 @@ impl DefaultSqlxStorage {
                     Ok(Some(TokenData::from_row(&rows[0])?))
                 }
             }
-            Err(e) => Err(StorageError::DatabaseError(e.to_string())),
+            Err(e) => Err(e),
         }
     }
 
     async fn get_event_by_id(&self, event_id: &str) -> Result<Option<EventData>, StorageError> {
         let q = "SELECT * FROM event WHERE event_id = $1";
 
-        match sqlx::query(q).bind(event_id).fetch_all(&self.pool).await {
+        match self.fetch_all(sqlx::query(q).bind(event_id)).await {
             Ok(rows) => {
                 if rows.is_empty() {
                     Ok(None)
@@ -84,7 +140,7 @@ impl DefaultSqlxStorage {
                     Ok(Some(EventData::from_row(&rows[0])?))
                 }
             }
-            Err(e) => Err(StorageError::DatabaseError(e.to_string())),
+            Err(e) => Err(e),
         }
     }
 
@@ -95,10 +151,12 @@ impl DefaultSqlxStorage {
     ) -> Result<Option<ContractData>, StorageError> {
         let q = "SELECT * FROM contract WHERE contract_address = $1 AND chain_id = $2";
 
-        match sqlx::query(q)
-            .bind(contract_address.to_string())
-            .bind(chain_id.to_string())
-            .fetch_all(&self.pool)
+        match self
+            .fetch_all(
+                sqlx::query(q)
+                    .bind(contract_address.to_string())
+                    .bind(chain_id.to_string()),
+            )
             .await
         {
             Ok(rows) => {
@@ -108,18 +166,14 @@ impl DefaultSqlxStorage {
                     Ok(Some(ContractData::from_row(&rows[0])?))
                 }
             }
-            Err(e) => Err(StorageError::DatabaseError(e.to_string())),
+            Err(e) => Err(e),
         }
     }
 
     async fn get_block_by_timestamp(&self, ts: u64) -> Result<Option<Block>, StorageError> {
         let q = "SELECT block_number, block_status, block_timestamp, indexer_identifier FROM block WHERE block_timestamp = $1";
 
-        match sqlx::query(q)
-            .bind(ts.to_string())
-            .fetch_all(&self.pool)
-            .await
-        {
+        match self.fetch_all(sqlx::query(q).bind(ts.to_string())).await {
             Ok(rows) => {
                 if rows.is_empty() {
                     Ok(None)
@@ -127,13 +181,44 @@ impl DefaultSqlxStorage {
                     Ok(Some(Block::from_row(&rows[0])?))
                 }
             }
-            Err(e) => Err(StorageError::DatabaseError(e.to_string())),
+            Err(e) => Err(e),
         }
     }
 }
 
 #[async_trait]
 impl Storage for DefaultSqlxStorage {
+    async fn begin_unit_of_work(&self) -> Result<(), StorageError> {
+        let mut transaction = self.transaction.lock().await;
+
+        if transaction.is_some() {
+            return Err(StorageError::DatabaseError(
+                "A unit of work is already in progress".to_string(),
+            ));
+        }
+
+        *transaction = Some(self.pool.begin().await?);
+
+        Ok(())
+    }
+
+    async fn commit_unit_of_work(&self) -> Result<(), StorageError> {
+        match self.transaction.lock().await.take() {
+            Some(tx) => Ok(tx.commit().await?),
+            None => Err(StorageError::DatabaseError(
+                "No unit of work in progress".to_string(),
+            )),
+        }
+    }
+
+    async fn rollback_unit_of_work(&self) -> Result<(), StorageError> {
+        if let Some(tx) = self.transaction.lock().await.take() {
+            tx.rollback().await?;
+        }
+
+        Ok(())
+    }
+
     async fn register_mint(
         &self,
         contract_address: &str,
@@ -148,14 +233,18 @@ impl Storage for DefaultSqlxStorage {
             info
         );
 
-        let q = "UPDATE token SET mint_address = $1, mint_timestamp = $2, mint_transaction_hash = $3 WHERE token_id = $4";
-
-        let _r = sqlx::query(q)
-            .bind(info.address.clone())
-            .bind(info.timestamp.to_string())
-            .bind(info.transaction_hash.clone())
-            .bind(token_id)
-            .execute(&self.pool)
+        let q = "UPDATE token SET mint_address = $1, mint_timestamp = $2, mint_transaction_hash = $3, mint_price = $4, mint_currency_address = $5 WHERE token_id = $6";
+
+        let _r = self
+            .execute(
+                sqlx::query(q)
+                    .bind(info.address.clone())
+                    .bind(info.timestamp.to_string())
+                    .bind(info.transaction_hash.clone())
+                    .bind(info.price.clone().unwrap_or_default())
+                    .bind(info.currency_address.clone().unwrap_or_default())
+                    .bind(token_id),
+            )
             .await?;
 
         Ok(())
@@ -185,23 +274,74 @@ impl Storage for DefaultSqlxStorage {
 
         let q = "INSERT INTO token (contract_address, token_id, chain_id, owner, block_timestamp) VALUES ($1, $2, $3, $4, $5)";
 
-        let _r = sqlx::query(q)
-            .bind(token.contract_address.clone())
-            .bind(token.token_id.clone())
-            .bind(token.chain_id.clone())
-            .bind(token.owner.clone())
-            .bind(block_timestamp.to_string())
-            .execute(&self.pool)
+        let _r = self
+            .execute(
+                sqlx::query(q)
+                    .bind(token.contract_address.clone())
+                    .bind(token.token_id.clone())
+                    .bind(token.chain_id.clone())
+                    .bind(token.owner.clone())
+                    .bind(block_timestamp.to_string()),
+            )
             .await?;
 
         Ok(())
     }
 
+    async fn register_backfilled_token(
+        &self,
+        token: &TokenInfo,
+        block_number: u64,
+        block_timestamp: u64,
+    ) -> Result<(), StorageError> {
+        trace!("Registering backfilled token {:?}", token);
+
+        let q = "INSERT INTO token (contract_address, token_id, token_id_hex, owner, block_timestamp, backfilled_block_number) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (contract_address, token_id_hex) DO NOTHING";
+
+        self.execute(
+            sqlx::query(q)
+                .bind(token.contract_address.clone())
+                .bind(token.token_id.clone())
+                .bind(token.token_id_hex.clone())
+                .bind(token.owner.clone())
+                .bind(block_timestamp as i64)
+                .bind(block_number as i64),
+        )
+        .await?;
+
+        Ok(())
+    }
+
     async fn register_sale_event(
         &self,
-        _event: &TokenSaleEvent,
-        _block_timestamp: u64,
+        event: &TokenSaleEvent,
+        block_timestamp: u64,
     ) -> Result<(), StorageError> {
+        trace!("Registering sale {:?}", event);
+
+        let q = "INSERT INTO token_sale (event_id, block_timestamp, nft_contract_address, chain_id, token_id, token_id_hex, from_address, to_address, marketplace_contract_address, marketplace_name, transaction_hash, quantity, currency_address, price, normalized_price, price_usd) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16) ON CONFLICT (event_id) DO NOTHING";
+
+        self.execute(
+            sqlx::query(q)
+                .bind(event.event_id.clone())
+                .bind(block_timestamp as i64)
+                .bind(event.nft_contract_address.clone())
+                .bind(event.chain_id.clone())
+                .bind(event.token_id.clone())
+                .bind(event.token_id_hex.clone())
+                .bind(event.from_address.clone())
+                .bind(event.to_address.clone())
+                .bind(event.marketplace_contract_address.clone())
+                .bind(event.marketplace_name.clone())
+                .bind(event.transaction_hash.clone())
+                .bind(event.quantity as i64)
+                .bind(event.currency_address.clone())
+                .bind(event.price.clone())
+                .bind(event.normalized_price.clone())
+                .bind(event.price_usd),
+        )
+        .await?;
+
         Ok(())
     }
 
@@ -221,23 +361,93 @@ impl Storage for DefaultSqlxStorage {
 
         let q = "INSERT INTO token_event (block_timestamp, contract_address, chain_id, from_address, to_address, transaction_hash, token_id, contract_type, event_type, event_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)";
 
-        let _r = sqlx::query(q)
-            .bind(event.timestamp.to_string())
-            .bind(event.contract_address.clone())
-            .bind(event.chain_id.clone())
-            .bind(event.from_address.clone())
-            .bind(event.to_address.clone())
-            .bind(event.transaction_hash.clone())
-            .bind(event.token_id.clone())
-            .bind(event.contract_type.clone())
-            .bind(event.event_type.to_string())
-            .bind(event.event_id.clone())
-            .execute(&self.pool)
+        let _r = self
+            .execute(
+                sqlx::query(q)
+                    .bind(event.timestamp.to_string())
+                    .bind(event.contract_address.clone())
+                    .bind(event.chain_id.clone())
+                    .bind(event.from_address.clone())
+                    .bind(event.to_address.clone())
+                    .bind(event.transaction_hash.clone())
+                    .bind(event.token_id.clone())
+                    .bind(event.contract_type.clone())
+                    .bind(event.event_type.to_string())
+                    .bind(event.event_id.clone()),
+            )
             .await?;
 
         Ok(())
     }
 
+    async fn register_token_approval(&self, approval: &TokenApproval) -> Result<(), StorageError> {
+        trace!("Registering token approval {:?}", approval);
+
+        if approval.approved_address == to_hex_str(&FieldElement::ZERO) {
+            let q = "DELETE FROM token_approval WHERE contract_address = $1 AND token_id_hex = $2";
+
+            self.execute(
+                sqlx::query(q)
+                    .bind(approval.contract_address.clone())
+                    .bind(approval.token_id_hex.clone()),
+            )
+            .await?;
+
+            return Ok(());
+        }
+
+        let q = "INSERT INTO token_approval (contract_address, token_id, token_id_hex, owner, approved_address, transaction_hash, block_timestamp) VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT (contract_address, token_id_hex) DO UPDATE SET owner = excluded.owner, approved_address = excluded.approved_address, transaction_hash = excluded.transaction_hash, block_timestamp = excluded.block_timestamp";
+
+        self.execute(
+            sqlx::query(q)
+                .bind(approval.contract_address.clone())
+                .bind(approval.token_id.clone())
+                .bind(approval.token_id_hex.clone())
+                .bind(approval.owner.clone())
+                .bind(approval.approved_address.clone())
+                .bind(approval.transaction_hash.clone())
+                .bind(approval.timestamp.to_string()),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn register_operator_approval(
+        &self,
+        approval: &OperatorApproval,
+    ) -> Result<(), StorageError> {
+        trace!("Registering operator approval {:?}", approval);
+
+        if !approval.approved {
+            let q = "DELETE FROM operator_approval WHERE contract_address = $1 AND owner = $2 AND operator = $3";
+
+            self.execute(
+                sqlx::query(q)
+                    .bind(approval.contract_address.clone())
+                    .bind(approval.owner.clone())
+                    .bind(approval.operator.clone()),
+            )
+            .await?;
+
+            return Ok(());
+        }
+
+        let q = "INSERT INTO operator_approval (contract_address, owner, operator, transaction_hash, block_timestamp) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (contract_address, owner, operator) DO UPDATE SET transaction_hash = excluded.transaction_hash, block_timestamp = excluded.block_timestamp";
+
+        self.execute(
+            sqlx::query(q)
+                .bind(approval.contract_address.clone())
+                .bind(approval.owner.clone())
+                .bind(approval.operator.clone())
+                .bind(approval.transaction_hash.clone())
+                .bind(approval.timestamp.to_string()),
+        )
+        .await?;
+
+        Ok(())
+    }
+
     async fn get_contract_type(
         &self,
         contract_address: &str,
@@ -280,13 +490,18 @@ impl Storage for DefaultSqlxStorage {
             )));
         }
 
-        let q = "INSERT INTO contract (contract_address, contract_type, deployed_timestamp) VALUES ($1, $2, $3)";
-
-        let _r = sqlx::query(q)
-            .bind(info.contract_address.clone())
-            .bind(info.contract_type.to_string())
-            .bind(block_timestamp.to_string())
-            .execute(&self.pool)
+        let q = "INSERT INTO contract (contract_address, contract_type, deployed_timestamp, deployed_block_number, deployer_address, class_hash) VALUES ($1, $2, $3, $4, $5, $6)";
+
+        let _r = self
+            .execute(
+                sqlx::query(q)
+                    .bind(info.contract_address.clone())
+                    .bind(info.contract_type.to_string())
+                    .bind(block_timestamp.to_string())
+                    .bind(info.deployed_block_number.map(|n| n as i64))
+                    .bind(info.deployer_address.clone())
+                    .bind(info.class_hash.clone()),
+            )
             .await?;
 
         Ok(())
@@ -300,40 +515,45 @@ impl Storage for DefaultSqlxStorage {
     ) -> Result<(), StorageError> {
         trace!("Setting block info {:?} for block #{}", info, block_number);
 
-        let exists = sqlx::query("SELECT 1 FROM indexer WHERE indexer_identifier = $1")
-            .bind(info.indexer_identifier.clone())
-            .fetch_optional(&self.pool)
+        let exists = self
+            .fetch_optional(
+                sqlx::query("SELECT 1 FROM indexer WHERE indexer_identifier = $1")
+                    .bind(info.indexer_identifier.clone()),
+            )
             .await?
             .is_some();
 
         if !exists {
             let q = "INSERT INTO indexer (indexer_identifier, indexer_version) VALUES ($1, $2)";
-            sqlx::query(q)
-                .bind(info.indexer_identifier.clone())
-                .bind(info.indexer_version.clone())
-                .execute(&self.pool)
-                .await?;
+            self.execute(
+                sqlx::query(q)
+                    .bind(info.indexer_identifier.clone())
+                    .bind(info.indexer_version.clone()),
+            )
+            .await?;
         }
 
         let _r = if (self.get_block_by_timestamp(block_timestamp).await?).is_some() {
             let q = "UPDATE block SET block_number = $1, block_status = $2, indexer_identifier = $3 WHERE block_timestamp = $4";
-            sqlx::query(q)
-                .bind(block_number.to_string())
-                .bind(info.status.to_string())
-                .bind(info.indexer_identifier.clone())
-                .bind(block_timestamp.to_string())
-                .execute(&self.pool)
-                .await?
+            self.execute(
+                sqlx::query(q)
+                    .bind(block_number.to_string())
+                    .bind(info.status.to_string())
+                    .bind(info.indexer_identifier.clone())
+                    .bind(block_timestamp.to_string()),
+            )
+            .await?
         } else {
             let q = "INSERT INTO block (block_timestamp, block_number, block_status, indexer_identifier) VALUES ($1, $2, $3, $4) ON CONFLICT (block_number) DO NOTHING";
 
-            sqlx::query(q)
-                .bind(block_timestamp.to_string())
-                .bind(block_number.to_string())
-                .bind(info.status.to_string())
-                .bind(info.indexer_identifier.clone())
-                .execute(&self.pool)
-                .await?
+            self.execute(
+                sqlx::query(q)
+                    .bind(block_timestamp.to_string())
+                    .bind(block_number.to_string())
+                    .bind(info.status.to_string())
+                    .bind(info.indexer_identifier.clone()),
+            )
+            .await?
         };
 
         Ok(())
@@ -344,9 +564,8 @@ impl Storage for DefaultSqlxStorage {
 
         let q = "SELECT * FROM block WHERE block_number = $1";
 
-        match sqlx::query(q)
-            .bind(block_number.to_string())
-            .fetch_all(&self.pool)
+        match self
+            .fetch_all(sqlx::query(q).bind(block_number.to_string()))
             .await
         {
             Ok(rows) => {
@@ -364,7 +583,7 @@ impl Storage for DefaultSqlxStorage {
                     })
                 }
             }
-            Err(e) => Err(StorageError::DatabaseError(e.to_string())),
+            Err(e) => Err(e),
         }
     }
 
@@ -379,17 +598,77 @@ impl Storage for DefaultSqlxStorage {
             block_timestamp.to_string()
         );
         let q = "DELETE FROM block WHERE block_timestamp = $1::bigint";
-        sqlx::query(q)
-            .bind(block_timestamp.to_string())
-            .fetch_all(&self.pool)
+        self.fetch_all(sqlx::query(q).bind(block_timestamp.to_string()))
             .await?;
 
         let q = "DELETE FROM token_event WHERE block_timestamp = $1::bigint";
-        sqlx::query(q)
-            .bind(block_timestamp.to_string())
-            .fetch_all(&self.pool)
+        self.fetch_all(sqlx::query(q).bind(block_timestamp.to_string()))
+            .await?;
+
+        let q = "DELETE FROM token_sale WHERE block_timestamp = $1::bigint";
+        self.fetch_all(sqlx::query(q).bind(block_timestamp.to_string()))
             .await?;
 
         Ok(())
     }
+
+    async fn clean_block_events(
+        &self,
+        block_timestamp: u64,
+        block_number: u64,
+        filter: &ReindexFilter,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Cleaning events of block #{} [ts: {}] matching {:?}",
+            block_number,
+            block_timestamp,
+            filter
+        );
+
+        if filter.matches_kind(ReindexEventKind::Transfer) {
+            if filter.matches_all_contracts() {
+                let q = "DELETE FROM token_event WHERE block_timestamp = $1::bigint";
+                self.fetch_all(sqlx::query(q).bind(block_timestamp.to_string()))
+                    .await?;
+            }
+
+            for contract_address in &filter.nft_contracts {
+                let q = "DELETE FROM token_event WHERE block_timestamp = $1::bigint AND contract_address = $2";
+                self.fetch_all(
+                    sqlx::query(q)
+                        .bind(block_timestamp.to_string())
+                        .bind(contract_address.clone()),
+                )
+                .await?;
+            }
+        }
+
+        // The detected sales have no marketplace, and are kept.
+        if filter.matches_kind(ReindexEventKind::Sale) {
+            let detected = to_hex_str(&FieldElement::ZERO);
+
+            if filter.matches_all_contracts() {
+                let q = "DELETE FROM token_sale WHERE block_timestamp = $1::bigint AND marketplace_contract_address <> $2";
+                self.fetch_all(
+                    sqlx::query(q)
+                        .bind(block_timestamp.to_string())
+                        .bind(detected.clone()),
+                )
+                .await?;
+            }
+
+            for contract_address in &filter.marketplaces {
+                let q = "DELETE FROM token_sale WHERE block_timestamp = $1::bigint AND marketplace_contract_address <> $2 AND marketplace_contract_address = $3";
+                self.fetch_all(
+                    sqlx::query(q)
+                        .bind(block_timestamp.to_string())
+                        .bind(detected.clone())
+                        .bind(contract_address.clone()),
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
 }
@@ -12,7 +12,10 @@ CREATE TABLE token (
        mint_address TEXT DEFAULT '',
        mint_timestamp BIGINT DEFAULT 0,
        mint_transaction_hash TEXT DEFAULT '',
+       mint_price TEXT DEFAULT '',
+       mint_currency_address TEXT DEFAULT '',
        block_timestamp BIGINT NOT NULL,
+       backfilled_block_number BIGINT,
 
        PRIMARY KEY (contract_address, token_id_hex)
 );
@@ -32,6 +35,27 @@ CREATE TABLE event (
        PRIMARY KEY (event_id)
 );
 
+CREATE TABLE token_sale (
+       event_id TEXT NOT NULL,
+       block_timestamp BIGINT NOT NULL,
+       nft_contract_address TEXT NOT NULL,
+       chain_id TEXT NOT NULL,
+       token_id TEXT NOT NULL,
+       token_id_hex TEXT NOT NULL,
+       from_address TEXT NOT NULL,
+       to_address TEXT NOT NULL,
+       marketplace_contract_address TEXT NOT NULL,
+       marketplace_name TEXT NOT NULL,
+       transaction_hash TEXT NOT NULL,
+       quantity BIGINT NOT NULL,
+       currency_address TEXT,
+       price TEXT NOT NULL,
+       normalized_price TEXT,
+       price_usd DOUBLE PRECISION,
+
+       PRIMARY KEY (event_id)
+);
+
 CREATE TABLE block (
        block_timestamp BIGINT NOT NULL,
        block_number BIGINT NOT NULL,
@@ -46,6 +70,31 @@ CREATE TABLE contract (
        contract_address TEXT NOT NULL,
        contract_type TEXT NOT NULL,
        block_timestamp BIGINT NOT NULL,
+       deployed_block_number BIGINT,
+       deployer_address TEXT,
+       class_hash TEXT,
 
        PRIMARY KEY (contract_address)
 );
+
+CREATE TABLE token_approval (
+       contract_address TEXT NOT NULL,
+       token_id TEXT NOT NULL,
+       token_id_hex TEXT NOT NULL,
+       owner TEXT NOT NULL,
+       approved_address TEXT NOT NULL,
+       transaction_hash TEXT NOT NULL,
+       block_timestamp BIGINT NOT NULL,
+
+       PRIMARY KEY (contract_address, token_id_hex)
+);
+
+CREATE TABLE operator_approval (
+       contract_address TEXT NOT NULL,
+       owner TEXT NOT NULL,
+       operator TEXT NOT NULL,
+       transaction_hash TEXT NOT NULL,
+       block_timestamp BIGINT NOT NULL,
+
+       PRIMARY KEY (contract_address, owner, operator)
+);
@@ -0,0 +1,128 @@
+-- Pontos schema for the Postgres storage.
+--
+-- This migration is embedded in the binary and applied
+-- by `PostgresStorage` at startup.
+
+CREATE TABLE IF NOT EXISTS token (
+       contract_address TEXT NOT NULL,
+       chain_id TEXT NOT NULL,
+       token_id TEXT NOT NULL,
+       token_id_hex TEXT NOT NULL,
+       owner TEXT NOT NULL,
+       mint_address TEXT,
+       mint_timestamp BIGINT,
+       mint_transaction_hash TEXT,
+       mint_block_number BIGINT,
+       mint_price TEXT,
+       mint_currency_address TEXT,
+       block_timestamp BIGINT NOT NULL,
+
+       PRIMARY KEY (contract_address, chain_id, token_id_hex)
+);
+
+CREATE INDEX IF NOT EXISTS token_owner_idx ON token (owner);
+
+CREATE TABLE IF NOT EXISTS token_event (
+       event_id TEXT NOT NULL,
+       contract_address TEXT NOT NULL,
+       chain_id TEXT NOT NULL,
+       contract_type TEXT NOT NULL,
+       token_id TEXT NOT NULL,
+       token_id_hex TEXT NOT NULL,
+       from_address TEXT NOT NULL,
+       to_address TEXT NOT NULL,
+       transaction_hash TEXT NOT NULL,
+       event_type TEXT NOT NULL,
+       block_timestamp BIGINT NOT NULL,
+       block_number BIGINT,
+
+       PRIMARY KEY (event_id)
+);
+
+CREATE INDEX IF NOT EXISTS token_event_token_idx ON token_event (contract_address, token_id_hex);
+CREATE INDEX IF NOT EXISTS token_event_block_timestamp_idx ON token_event (block_timestamp);
+CREATE INDEX IF NOT EXISTS token_event_transaction_hash_idx ON token_event (transaction_hash);
+
+CREATE TABLE IF NOT EXISTS token_sale (
+       event_id TEXT NOT NULL,
+       nft_contract_address TEXT NOT NULL,
+       nft_type TEXT,
+       chain_id TEXT NOT NULL,
+       token_id TEXT NOT NULL,
+       token_id_hex TEXT NOT NULL,
+       from_address TEXT NOT NULL,
+       to_address TEXT NOT NULL,
+       marketplace_contract_address TEXT NOT NULL,
+       marketplace_name TEXT NOT NULL,
+       transaction_hash TEXT NOT NULL,
+       event_type TEXT NOT NULL,
+       quantity BIGINT NOT NULL,
+       currency_address TEXT,
+       currency_symbol TEXT,
+       currency_decimals SMALLINT,
+       price TEXT NOT NULL,
+       normalized_price DOUBLE PRECISION,
+       price_usd DOUBLE PRECISION,
+       block_timestamp BIGINT NOT NULL,
+       block_number BIGINT,
+
+       PRIMARY KEY (event_id)
+);
+
+CREATE INDEX IF NOT EXISTS token_sale_contract_idx ON token_sale (nft_contract_address, block_timestamp);
+CREATE INDEX IF NOT EXISTS token_sale_block_timestamp_idx ON token_sale (block_timestamp);
+
+CREATE TABLE IF NOT EXISTS contract (
+       contract_address TEXT NOT NULL,
+       chain_id TEXT NOT NULL,
+       contract_type TEXT NOT NULL,
+       name TEXT,
+       symbol TEXT,
+       image TEXT,
+       block_timestamp BIGINT NOT NULL,
+       deployed_block_number BIGINT,
+       deployer_address TEXT,
+       class_hash TEXT,
+
+       PRIMARY KEY (contract_address, chain_id)
+);
+
+CREATE INDEX IF NOT EXISTS contract_deployed_block_number_idx ON contract (deployed_block_number);
+
+CREATE TABLE IF NOT EXISTS token_approval (
+       contract_address TEXT NOT NULL,
+       chain_id TEXT NOT NULL,
+       token_id TEXT NOT NULL,
+       token_id_hex TEXT NOT NULL,
+       owner TEXT NOT NULL,
+       approved_address TEXT NOT NULL,
+       transaction_hash TEXT NOT NULL,
+       block_timestamp BIGINT NOT NULL,
+
+       PRIMARY KEY (contract_address, chain_id, token_id_hex)
+);
+
+CREATE TABLE IF NOT EXISTS operator_approval (
+       contract_address TEXT NOT NULL,
+       chain_id TEXT NOT NULL,
+       owner TEXT NOT NULL,
+       operator TEXT NOT NULL,
+       transaction_hash TEXT NOT NULL,
+       block_timestamp BIGINT NOT NULL,
+
+       PRIMARY KEY (contract_address, chain_id, owner, operator)
+);
+
+CREATE INDEX IF NOT EXISTS operator_approval_operator_idx ON operator_approval (operator);
+
+CREATE TABLE IF NOT EXISTS block (
+       block_number BIGINT NOT NULL,
+       block_timestamp BIGINT NOT NULL,
+       status TEXT NOT NULL,
+       indexer_version TEXT NOT NULL,
+       indexer_identifier TEXT NOT NULL,
+
+       PRIMARY KEY (block_number)
+);
+
+CREATE INDEX IF NOT EXISTS block_timestamp_idx ON block (block_timestamp);
@@ -0,0 +1,2 @@
+-- Transfers of a collection up to a block, for the ownership snapshots.
+CREATE INDEX IF NOT EXISTS token_event_contract_block_number_idx ON token_event (contract_address, block_number);
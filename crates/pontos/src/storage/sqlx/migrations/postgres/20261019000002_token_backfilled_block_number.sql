@@ -0,0 +1,3 @@
+-- Tokens registered by a backfill, with the block at which they
+-- have been found on chain, instead of being indexed from their mint.
+ALTER TABLE token ADD COLUMN IF NOT EXISTS backfilled_block_number BIGINT;
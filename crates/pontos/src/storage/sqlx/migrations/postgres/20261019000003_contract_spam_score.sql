@@ -0,0 +1,3 @@
+-- Heuristic spam score of the collections, from 0 to 100,
+-- to let the applications hide the spam airdrops.
+ALTER TABLE contract ADD COLUMN IF NOT EXISTS spam_score SMALLINT;
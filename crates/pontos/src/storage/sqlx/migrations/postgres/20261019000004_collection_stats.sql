@@ -0,0 +1,73 @@
+-- Aggregates of the collections, maintained by the storage while the
+-- events are registered and cleaned, instead of being computed on read.
+
+-- Owners and all time activity.
+CREATE TABLE IF NOT EXISTS collection_stats (
+    contract_address TEXT NOT NULL,
+    chain_id TEXT NOT NULL,
+    owners_count BIGINT NOT NULL DEFAULT 0,
+    supply BIGINT NOT NULL DEFAULT 0,
+    sales_count BIGINT NOT NULL DEFAULT 0,
+    volume_usd DOUBLE PRECISION NOT NULL DEFAULT 0,
+    mint_count BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (contract_address, chain_id)
+);
+
+-- Activity by hour, summed on read for the rolling periods.
+CREATE TABLE IF NOT EXISTS collection_stats_hourly (
+    contract_address TEXT NOT NULL,
+    chain_id TEXT NOT NULL,
+    hour_timestamp BIGINT NOT NULL,
+    sales_count BIGINT NOT NULL DEFAULT 0,
+    volume_usd DOUBLE PRECISION NOT NULL DEFAULT 0,
+    mint_count BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (contract_address, chain_id, hour_timestamp)
+);
+
+-- Tokens held by each owner, to count the distinct owners.
+-- The owners without token are deleted.
+CREATE TABLE IF NOT EXISTS collection_owner (
+    contract_address TEXT NOT NULL,
+    chain_id TEXT NOT NULL,
+    owner TEXT NOT NULL,
+    token_count BIGINT NOT NULL,
+    PRIMARY KEY (contract_address, chain_id, owner)
+);
+
+-- Aggregates of the data indexed before this migration.
+-- The zero address, once its "0x" and leading zeros trimmed, is empty.
+INSERT INTO collection_owner (contract_address, chain_id, owner, token_count)
+SELECT contract_address, chain_id, owner, COUNT(*)
+FROM token
+WHERE ltrim(owner, '0x') <> ''
+GROUP BY contract_address, chain_id, owner
+ON CONFLICT DO NOTHING;
+
+INSERT INTO collection_stats (contract_address, chain_id, owners_count, supply, sales_count, volume_usd, mint_count)
+SELECT contract_address, chain_id, SUM(owners_count), SUM(supply), SUM(sales_count), SUM(volume_usd), SUM(mint_count)
+FROM (
+    SELECT contract_address, chain_id, COUNT(*) AS owners_count, SUM(token_count) AS supply,
+    0 AS sales_count, 0 AS volume_usd, 0 AS mint_count
+    FROM collection_owner GROUP BY contract_address, chain_id
+    UNION ALL
+    SELECT nft_contract_address, chain_id, 0, 0, COUNT(*), COALESCE(SUM(price_usd), 0), 0
+    FROM token_sale GROUP BY nft_contract_address, chain_id
+    UNION ALL
+    SELECT contract_address, chain_id, 0, 0, 0, 0, COUNT(*)
+    FROM token_event WHERE event_type = 'MINT' GROUP BY contract_address, chain_id
+) stats
+GROUP BY contract_address, chain_id
+ON CONFLICT DO NOTHING;
+
+INSERT INTO collection_stats_hourly (contract_address, chain_id, hour_timestamp, sales_count, volume_usd, mint_count)
+SELECT contract_address, chain_id, hour_timestamp, SUM(sales_count), SUM(volume_usd), SUM(mint_count)
+FROM (
+    SELECT nft_contract_address AS contract_address, chain_id, block_timestamp - block_timestamp % 3600 AS hour_timestamp,
+    COUNT(*) AS sales_count, COALESCE(SUM(price_usd), 0) AS volume_usd, 0 AS mint_count
+    FROM token_sale GROUP BY 1, 2, 3
+    UNION ALL
+    SELECT contract_address, chain_id, block_timestamp - block_timestamp % 3600, 0, 0, COUNT(*)
+    FROM token_event WHERE event_type = 'MINT' GROUP BY 1, 2, 3
+) activity
+GROUP BY contract_address, chain_id, hour_timestamp
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,4 @@
+-- Wash trade suspicions of the sales, as parallel arrays of
+-- kinds and reasons. The flagged sales are not in the collection stats.
+ALTER TABLE token_sale ADD COLUMN IF NOT EXISTS wash_trade_flags TEXT[] NOT NULL DEFAULT '{}';
+ALTER TABLE token_sale ADD COLUMN IF NOT EXISTS wash_trade_reasons TEXT[] NOT NULL DEFAULT '{}';
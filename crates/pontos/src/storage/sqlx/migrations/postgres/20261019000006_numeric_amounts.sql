@@ -0,0 +1,20 @@
+-- Exact amounts: the normalized prices keep all the digits of the u256,
+-- and the volumes are added and removed by event without drifting.
+ALTER TABLE token_sale ALTER COLUMN normalized_price TYPE NUMERIC;
+ALTER TABLE token_sale ALTER COLUMN price_usd TYPE NUMERIC;
+ALTER TABLE collection_stats ALTER COLUMN volume_usd TYPE NUMERIC;
+ALTER TABLE collection_stats_hourly ALTER COLUMN volume_usd TYPE NUMERIC;
+
+-- The volumes accumulated as floats are computed again from the sales.
+UPDATE collection_stats s SET volume_usd = COALESCE((
+    SELECT SUM(t.price_usd) FROM token_sale t
+    WHERE t.nft_contract_address = s.contract_address AND t.chain_id = s.chain_id
+    AND cardinality(t.wash_trade_flags) = 0
+), 0);
+
+UPDATE collection_stats_hourly h SET volume_usd = COALESCE((
+    SELECT SUM(t.price_usd) FROM token_sale t
+    WHERE t.nft_contract_address = h.contract_address AND t.chain_id = h.chain_id
+    AND t.block_timestamp - t.block_timestamp % 3600 = h.hour_timestamp
+    AND cardinality(t.wash_trade_flags) = 0
+), 0);
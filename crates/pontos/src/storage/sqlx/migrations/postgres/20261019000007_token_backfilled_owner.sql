@@ -0,0 +1,3 @@
+-- Owner found on chain by the backfill, at `backfilled_block_number`. The
+-- `owner` column follows the later transfers, the snapshots need this one.
+ALTER TABLE token ADD COLUMN IF NOT EXISTS backfilled_owner TEXT;
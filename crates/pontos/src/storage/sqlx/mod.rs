@@ -3,7 +3,14 @@
 //! The main objective of this module is to add a default
 //! implementation for examples and testing.
 //! No optimization was made at database level.
+//!
+//! For production usage, the `postgres` feature enables `PostgresStorage`.
 pub mod default_storage;
 pub use default_storage::DefaultSqlxStorage;
 
+#[cfg(feature = "postgres")]
+pub mod postgres_storage;
+#[cfg(feature = "postgres")]
+pub use postgres_storage::PostgresStorage;
+
 pub mod types;
@@ -0,0 +1,1434 @@
+//! Postgres storage for production usage.
+//!
+//! Contrary to `DefaultSqlxStorage`, this storage uses a pool of
+//! connections, upserts instead of check-then-insert sequences, and
+//! the schema is embedded in the binary as sqlx migrations, which are
+//! applied when the storage is created.
+//!
+//! Queries are prepared once per connection and cached by sqlx.
+//!
+//! Several chains can share a database, each one in its own schema
+//! (see `new_in_schema`), as the blocks are not identified by chain.
+use ark_starknet::format::to_hex_str;
+use async_trait::async_trait;
+use sqlx::migrate::Migrator;
+use sqlx::postgres::{PgArguments, PgPoolOptions, PgQueryResult, PgRow, Postgres};
+use sqlx::query::Query;
+use sqlx::{Executor, PgPool, Row, Transaction};
+use starknet::core::types::FieldElement;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::{debug, trace};
+
+use crate::storage::types::*;
+use crate::storage::utils::is_zero_address;
+use crate::storage::QueryStorage;
+use crate::Storage;
+
+/// Migrations of the Postgres schema, embedded at compile time.
+pub static MIGRATOR: Migrator = sqlx::migrate!("./src/storage/sqlx/migrations/postgres");
+
+const DEFAULT_MAX_CONNECTIONS: u32 = 10;
+const DEFAULT_ACQUIRE_TIMEOUT_SECS: u64 = 30;
+
+pub struct PostgresStorage {
+    pool: PgPool,
+    /// Transaction of the unit of work in progress, if any.
+    /// All the queries are executed in this transaction while it is set.
+    transaction: Mutex<Option<Transaction<'static, Postgres>>>,
+}
+
+impl PostgresStorage {
+    pub fn get_pool_ref(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// Connects to the database with the default pool options,
+    /// and applies the pending migrations.
+    pub async fn new(db_url: &str) -> Result<Self, StorageError> {
+        let options = PgPoolOptions::new()
+            .max_connections(DEFAULT_MAX_CONNECTIONS)
+            .acquire_timeout(Duration::from_secs(DEFAULT_ACQUIRE_TIMEOUT_SECS));
+
+        Self::new_with_options(db_url, options).await
+    }
+
+    /// Connects to the database with every table in the given schema,
+    /// created if needed, and applies the pending migrations of this schema.
+    pub async fn new_in_schema(db_url: &str, schema: &str) -> Result<Self, StorageError> {
+        if !is_valid_schema_name(schema) {
+            return Err(StorageError::DatabaseError(format!(
+                "Invalid schema name {}",
+                schema
+            )));
+        }
+
+        let statement =
+            format!("CREATE SCHEMA IF NOT EXISTS {schema}; SET search_path TO {schema}");
+
+        let options = PgPoolOptions::new()
+            .max_connections(DEFAULT_MAX_CONNECTIONS)
+            .acquire_timeout(Duration::from_secs(DEFAULT_ACQUIRE_TIMEOUT_SECS))
+            .after_connect(move |conn, _meta| {
+                let statement = statement.clone();
+                Box::pin(async move {
+                    conn.execute(statement.as_str()).await?;
+                    Ok(())
+                })
+            });
+
+        Self::new_with_options(db_url, options).await
+    }
+
+    /// Connects to the database with the given pool options,
+    /// and applies the pending migrations.
+    pub async fn new_with_options(
+        db_url: &str,
+        options: PgPoolOptions,
+    ) -> Result<Self, StorageError> {
+        let pool = options.connect(db_url).await?;
+
+        MIGRATOR
+            .run(&pool)
+            .await
+            .map_err(|e| StorageError::DatabaseError(format!("Migration failed: {}", e)))?;
+
+        debug!("Postgres migrations applied");
+
+        Ok(Self {
+            pool,
+            transaction: Mutex::new(None),
+        })
+    }
+
+    /// Executes the query in the unit of work in progress, or directly on the pool.
+    async fn execute<'q>(
+        &self,
+        query: Query<'q, Postgres, PgArguments>,
+    ) -> Result<PgQueryResult, StorageError> {
+        let mut transaction = self.transaction.lock().await;
+
+        let result = match transaction.as_mut() {
+            Some(tx) => query.execute(&mut **tx).await?,
+            None => query.execute(&self.pool).await?,
+        };
+
+        Ok(result)
+    }
+
+    async fn fetch_all<'q>(
+        &self,
+        query: Query<'q, Postgres, PgArguments>,
+    ) -> Result<Vec<PgRow>, StorageError> {
+        let mut transaction = self.transaction.lock().await;
+
+        let rows = match transaction.as_mut() {
+            Some(tx) => query.fetch_all(&mut **tx).await?,
+            None => query.fetch_all(&self.pool).await?,
+        };
+
+        Ok(rows)
+    }
+
+    async fn fetch_optional<'q>(
+        &self,
+        query: Query<'q, Postgres, PgArguments>,
+    ) -> Result<Option<PgRow>, StorageError> {
+        let mut transaction = self.transaction.lock().await;
+
+        let row = match transaction.as_mut() {
+            Some(tx) => query.fetch_optional(&mut **tx).await?,
+            None => query.fetch_optional(&self.pool).await?,
+        };
+
+        Ok(row)
+    }
+
+    /// Adds the activity to the all time and hourly aggregates of a collection.
+    async fn add_collection_activity(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        block_timestamp: u64,
+        delta: &ActivityDelta,
+    ) -> Result<(), StorageError> {
+        let q = "INSERT INTO collection_stats (contract_address, chain_id, sales_count, volume_usd, mint_count)
+                 VALUES ($1, $2, $3, $4::NUMERIC, $5)
+                 ON CONFLICT (contract_address, chain_id) DO UPDATE SET
+                 sales_count = collection_stats.sales_count + EXCLUDED.sales_count,
+                 volume_usd = collection_stats.volume_usd + EXCLUDED.volume_usd,
+                 mint_count = collection_stats.mint_count + EXCLUDED.mint_count";
+
+        self.execute(
+            sqlx::query(q)
+                .bind(contract_address)
+                .bind(chain_id)
+                .bind(delta.sales_count)
+                .bind(delta.volume_usd.clone())
+                .bind(delta.mint_count),
+        )
+        .await?;
+
+        let q = "INSERT INTO collection_stats_hourly (contract_address, chain_id, hour_timestamp,
+                 sales_count, volume_usd, mint_count)
+                 VALUES ($1, $2, $3, $4, $5::NUMERIC, $6)
+                 ON CONFLICT (contract_address, chain_id, hour_timestamp) DO UPDATE SET
+                 sales_count = collection_stats_hourly.sales_count + EXCLUDED.sales_count,
+                 volume_usd = collection_stats_hourly.volume_usd + EXCLUDED.volume_usd,
+                 mint_count = collection_stats_hourly.mint_count + EXCLUDED.mint_count";
+
+        self.execute(
+            sqlx::query(q)
+                .bind(contract_address)
+                .bind(chain_id)
+                .bind(CollectionStats::bucket(block_timestamp) as i64)
+                .bind(delta.sales_count)
+                .bind(delta.volume_usd.clone())
+                .bind(delta.mint_count),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Removes the activity of the deleted rows, grouped by collection
+    /// with the columns `contract_address`, `chain_id`, and the negated
+    /// `sales_count`, `volume_usd` and `mint_count`.
+    async fn remove_collection_activity(
+        &self,
+        deleted: Vec<PgRow>,
+        block_timestamp: u64,
+    ) -> Result<(), StorageError> {
+        for row in deleted {
+            let contract_address: String = row.try_get("contract_address")?;
+            let chain_id: String = row.try_get("chain_id")?;
+            let delta = ActivityDelta {
+                sales_count: row.try_get("sales_count")?,
+                volume_usd: row.try_get("volume_usd")?,
+                mint_count: row.try_get("mint_count")?,
+            };
+
+            self.add_collection_activity(&contract_address, &chain_id, block_timestamp, &delta)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves a token from its previous owner, if any, to its new owner
+    /// in the owners of the collection. The zero address holds nothing.
+    async fn move_collection_token(
+        &self,
+        token: &TokenInfo,
+        previous_owner: Option<&str>,
+    ) -> Result<(), StorageError> {
+        let previous_owner = previous_owner.filter(|owner| !is_zero_address(owner));
+        let owner = Some(token.owner.as_str()).filter(|owner| !is_zero_address(owner));
+
+        if previous_owner == owner {
+            return Ok(());
+        }
+
+        let mut owners_delta: i64 = 0;
+        let mut supply_delta: i64 = 0;
+
+        if let Some(previous_owner) = previous_owner {
+            let q = "UPDATE collection_owner SET token_count = token_count - 1
+                     WHERE contract_address = $1 AND chain_id = $2 AND owner = $3
+                     RETURNING token_count";
+
+            let row = self
+                .fetch_optional(
+                    sqlx::query(q)
+                        .bind(token.contract_address.clone())
+                        .bind(token.chain_id.clone())
+                        .bind(previous_owner),
+                )
+                .await?;
+
+            if let Some(row) = row {
+                supply_delta -= 1;
+
+                if row.try_get::<i64, _>("token_count")? <= 0 {
+                    owners_delta -= 1;
+
+                    let q = "DELETE FROM collection_owner
+                             WHERE contract_address = $1 AND chain_id = $2 AND owner = $3";
+
+                    self.execute(
+                        sqlx::query(q)
+                            .bind(token.contract_address.clone())
+                            .bind(token.chain_id.clone())
+                            .bind(previous_owner),
+                    )
+                    .await?;
+                }
+            }
+        }
+
+        if let Some(owner) = owner {
+            let q = "INSERT INTO collection_owner (contract_address, chain_id, owner, token_count)
+                     VALUES ($1, $2, $3, 1)
+                     ON CONFLICT (contract_address, chain_id, owner)
+                     DO UPDATE SET token_count = collection_owner.token_count + 1
+                     RETURNING token_count";
+
+            let row = self
+                .fetch_optional(
+                    sqlx::query(q)
+                        .bind(token.contract_address.clone())
+                        .bind(token.chain_id.clone())
+                        .bind(owner),
+                )
+                .await?;
+
+            supply_delta += 1;
+
+            if let Some(row) = row {
+                if row.try_get::<i64, _>("token_count")? == 1 {
+                    owners_delta += 1;
+                }
+            }
+        }
+
+        let q = "INSERT INTO collection_stats (contract_address, chain_id, owners_count, supply)
+                 VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (contract_address, chain_id) DO UPDATE SET
+                 owners_count = collection_stats.owners_count + EXCLUDED.owners_count,
+                 supply = collection_stats.supply + EXCLUDED.supply";
+
+        self.execute(
+            sqlx::query(q)
+                .bind(token.contract_address.clone())
+                .bind(token.chain_id.clone())
+                .bind(owners_delta)
+                .bind(supply_delta),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Change of the activity of a collection, negative when events are cleaned.
+struct ActivityDelta {
+    sales_count: i64,
+    /// Decimal representation, added as a `NUMERIC` to keep the volumes exact.
+    volume_usd: String,
+    mint_count: i64,
+}
+
+#[async_trait]
+impl Storage for PostgresStorage {
+    async fn begin_unit_of_work(&self) -> Result<(), StorageError> {
+        let mut transaction = self.transaction.lock().await;
+
+        if transaction.is_some() {
+            return Err(StorageError::DatabaseError(
+                "A unit of work is already in progress".to_string(),
+            ));
+        }
+
+        *transaction = Some(self.pool.begin().await?);
+
+        Ok(())
+    }
+
+    async fn commit_unit_of_work(&self) -> Result<(), StorageError> {
+        match self.transaction.lock().await.take() {
+            Some(tx) => Ok(tx.commit().await?),
+            None => Err(StorageError::DatabaseError(
+                "No unit of work in progress".to_string(),
+            )),
+        }
+    }
+
+    async fn rollback_unit_of_work(&self) -> Result<(), StorageError> {
+        if let Some(tx) = self.transaction.lock().await.take() {
+            tx.rollback().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn register_mint(
+        &self,
+        contract_address: &str,
+        token_id_hex: &str,
+        token_id: &str,
+        info: &TokenMintInfo,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Registering mint {} {} {:?}",
+            contract_address,
+            token_id,
+            info
+        );
+
+        // The price is only known once the mint transaction is analyzed,
+        // and must not be erased by a later registration without price.
+        let q = "UPDATE token SET
+                 mint_address = $1, mint_timestamp = $2, mint_transaction_hash = $3, mint_block_number = $4,
+                 mint_price = COALESCE($5, mint_price),
+                 mint_currency_address = COALESCE($6, mint_currency_address)
+                 WHERE contract_address = $7 AND token_id_hex = $8";
+
+        self.execute(
+            sqlx::query(q)
+                .bind(info.address.clone())
+                .bind(info.timestamp as i64)
+                .bind(info.transaction_hash.clone())
+                .bind(info.block_number.map(|n| n as i64))
+                .bind(info.price.clone())
+                .bind(info.currency_address.clone())
+                .bind(contract_address)
+                .bind(token_id_hex),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn register_token(
+        &self,
+        token: &TokenInfo,
+        block_timestamp: u64,
+    ) -> Result<(), StorageError> {
+        trace!("Registering token {:?}", token);
+
+        // The previous owner is read in the same statement,
+        // to move the token in the owners of the collection.
+        let q = "WITH previous AS (
+                     SELECT owner FROM token
+                     WHERE contract_address = $1 AND chain_id = $2 AND token_id_hex = $4
+                 ), upserted AS (
+                     INSERT INTO token (contract_address, chain_id, token_id, token_id_hex, owner, block_timestamp)
+                     VALUES ($1, $2, $3, $4, $5, $6)
+                     ON CONFLICT (contract_address, chain_id, token_id_hex)
+                     DO UPDATE SET owner = EXCLUDED.owner, block_timestamp = EXCLUDED.block_timestamp
+                 )
+                 SELECT owner FROM previous";
+
+        let previous = self
+            .fetch_optional(
+                sqlx::query(q)
+                    .bind(token.contract_address.clone())
+                    .bind(token.chain_id.clone())
+                    .bind(token.token_id.clone())
+                    .bind(token.token_id_hex.clone())
+                    .bind(token.owner.clone())
+                    .bind(block_timestamp as i64),
+            )
+            .await?;
+
+        let previous_owner = match previous {
+            Some(row) => row.try_get::<Option<String>, _>("owner")?,
+            None => None,
+        };
+        self.move_collection_token(token, previous_owner.as_deref())
+            .await?;
+
+        Ok(())
+    }
+
+    async fn register_backfilled_token(
+        &self,
+        token: &TokenInfo,
+        block_number: u64,
+        block_timestamp: u64,
+    ) -> Result<(), StorageError> {
+        trace!("Registering backfilled token {:?}", token);
+
+        let q = "INSERT INTO token (contract_address, chain_id, token_id, token_id_hex, owner,
+                 block_timestamp, backfilled_block_number, backfilled_owner)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $5)
+                 ON CONFLICT (contract_address, chain_id, token_id_hex) DO NOTHING";
+
+        let result = self
+            .execute(
+                sqlx::query(q)
+                    .bind(token.contract_address.clone())
+                    .bind(token.chain_id.clone())
+                    .bind(token.token_id.clone())
+                    .bind(token.token_id_hex.clone())
+                    .bind(token.owner.clone())
+                    .bind(block_timestamp as i64)
+                    .bind(block_number as i64),
+            )
+            .await?;
+
+        if result.rows_affected() == 1 {
+            self.move_collection_token(token, None).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn register_sale_event(
+        &self,
+        event: &TokenSaleEvent,
+        block_timestamp: u64,
+    ) -> Result<(), StorageError> {
+        trace!("Registering sale {:?}", event);
+
+        let q = "INSERT INTO token_sale (event_id, nft_contract_address, nft_type, chain_id, token_id, token_id_hex,
+                 from_address, to_address, marketplace_contract_address, marketplace_name, transaction_hash,
+                 event_type, quantity, currency_address, currency_symbol, currency_decimals, price,
+                 normalized_price, price_usd, block_timestamp, block_number, wash_trade_flags, wash_trade_reasons)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18::NUMERIC,
+                 $19::NUMERIC, $20, $21, $22, $23)
+                 ON CONFLICT (event_id) DO NOTHING";
+
+        let (wash_trade_flags, wash_trade_reasons): (Vec<String>, Vec<String>) = event
+            .wash_trade_flags
+            .iter()
+            .map(|flag| (flag.kind.to_string(), flag.reason.clone()))
+            .unzip();
+
+        // The same decimal representation is stored and added to the
+        // volumes, so that removing the sale gives the volume back exactly.
+        let price_usd = event
+            .price_usd
+            .filter(|price| price.is_finite())
+            .map(|price| price.to_string());
+
+        let result = self
+            .execute(
+                sqlx::query(q)
+                    .bind(event.event_id.clone())
+                    .bind(event.nft_contract_address.clone())
+                    .bind(event.nft_type.clone())
+                    .bind(event.chain_id.clone())
+                    .bind(event.token_id.clone())
+                    .bind(event.token_id_hex.clone())
+                    .bind(event.from_address.clone())
+                    .bind(event.to_address.clone())
+                    .bind(event.marketplace_contract_address.clone())
+                    .bind(event.marketplace_name.clone())
+                    .bind(event.transaction_hash.clone())
+                    .bind(event.event_type.to_string())
+                    .bind(event.quantity as i64)
+                    .bind(event.currency_address.clone())
+                    .bind(event.currency_symbol.clone())
+                    .bind(event.currency_decimals.map(|d| d as i16))
+                    .bind(event.price.clone())
+                    .bind(event.normalized_price.clone())
+                    .bind(price_usd.clone())
+                    .bind(block_timestamp as i64)
+                    .bind(event.block_number.map(|n| n as i64))
+                    .bind(wash_trade_flags)
+                    .bind(wash_trade_reasons),
+            )
+            .await?;
+
+        // A sale registered again must not be counted twice,
+        // and the wash trades are not counted at all.
+        if result.rows_affected() == 1 && !event.is_wash_trade() {
+            let delta = ActivityDelta {
+                sales_count: 1,
+                volume_usd: price_usd.unwrap_or_else(|| "0".to_string()),
+                mint_count: 0,
+            };
+            self.add_collection_activity(
+                &event.nft_contract_address,
+                &event.chain_id,
+                block_timestamp,
+                &delta,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn register_transfer_event(
+        &self,
+        event: &TokenTransferEvent,
+        block_timestamp: u64,
+    ) -> Result<(), StorageError> {
+        trace!("Registering event {:?}", event);
+
+        let q = "INSERT INTO token_event (event_id, contract_address, chain_id, contract_type, token_id, token_id_hex,
+                 from_address, to_address, transaction_hash, event_type, block_timestamp, block_number)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+                 ON CONFLICT (event_id) DO NOTHING";
+
+        let result = self
+            .execute(
+                sqlx::query(q)
+                    .bind(event.event_id.clone())
+                    .bind(event.contract_address.clone())
+                    .bind(event.chain_id.clone())
+                    .bind(event.contract_type.clone())
+                    .bind(event.token_id.clone())
+                    .bind(event.token_id_hex.clone())
+                    .bind(event.from_address.clone())
+                    .bind(event.to_address.clone())
+                    .bind(event.transaction_hash.clone())
+                    .bind(event.event_type.to_string())
+                    .bind(block_timestamp as i64)
+                    .bind(event.block_number.map(|n| n as i64)),
+            )
+            .await?;
+
+        if event.event_type == EventType::Mint && result.rows_affected() == 1 {
+            let delta = ActivityDelta {
+                sales_count: 0,
+                volume_usd: "0".to_string(),
+                mint_count: 1,
+            };
+            self.add_collection_activity(
+                &event.contract_address,
+                &event.chain_id,
+                block_timestamp,
+                &delta,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn register_token_approval(&self, approval: &TokenApproval) -> Result<(), StorageError> {
+        trace!("Registering token approval {:?}", approval);
+
+        if approval.approved_address == to_hex_str(&FieldElement::ZERO) {
+            let q = "DELETE FROM token_approval
+                     WHERE contract_address = $1 AND chain_id = $2 AND token_id_hex = $3";
+
+            self.execute(
+                sqlx::query(q)
+                    .bind(approval.contract_address.clone())
+                    .bind(approval.chain_id.clone())
+                    .bind(approval.token_id_hex.clone()),
+            )
+            .await?;
+
+            return Ok(());
+        }
+
+        let q = "INSERT INTO token_approval (contract_address, chain_id, token_id, token_id_hex, owner,
+                 approved_address, transaction_hash, block_timestamp)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                 ON CONFLICT (contract_address, chain_id, token_id_hex)
+                 DO UPDATE SET owner = EXCLUDED.owner, approved_address = EXCLUDED.approved_address,
+                 transaction_hash = EXCLUDED.transaction_hash, block_timestamp = EXCLUDED.block_timestamp";
+
+        self.execute(
+            sqlx::query(q)
+                .bind(approval.contract_address.clone())
+                .bind(approval.chain_id.clone())
+                .bind(approval.token_id.clone())
+                .bind(approval.token_id_hex.clone())
+                .bind(approval.owner.clone())
+                .bind(approval.approved_address.clone())
+                .bind(approval.transaction_hash.clone())
+                .bind(approval.timestamp as i64),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn register_operator_approval(
+        &self,
+        approval: &OperatorApproval,
+    ) -> Result<(), StorageError> {
+        trace!("Registering operator approval {:?}", approval);
+
+        if !approval.approved {
+            let q = "DELETE FROM operator_approval
+                     WHERE contract_address = $1 AND chain_id = $2 AND owner = $3 AND operator = $4";
+
+            self.execute(
+                sqlx::query(q)
+                    .bind(approval.contract_address.clone())
+                    .bind(approval.chain_id.clone())
+                    .bind(approval.owner.clone())
+                    .bind(approval.operator.clone()),
+            )
+            .await?;
+
+            return Ok(());
+        }
+
+        let q = "INSERT INTO operator_approval (contract_address, chain_id, owner, operator, transaction_hash, block_timestamp)
+                 VALUES ($1, $2, $3, $4, $5, $6)
+                 ON CONFLICT (contract_address, chain_id, owner, operator)
+                 DO UPDATE SET transaction_hash = EXCLUDED.transaction_hash, block_timestamp = EXCLUDED.block_timestamp";
+
+        self.execute(
+            sqlx::query(q)
+                .bind(approval.contract_address.clone())
+                .bind(approval.chain_id.clone())
+                .bind(approval.owner.clone())
+                .bind(approval.operator.clone())
+                .bind(approval.transaction_hash.clone())
+                .bind(approval.timestamp as i64),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_contract_type(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+    ) -> Result<ContractType, StorageError> {
+        trace!("Getting contract info for contract {}", contract_address);
+
+        let q = "SELECT contract_type FROM contract WHERE contract_address = $1 AND chain_id = $2";
+
+        match self
+            .fetch_optional(sqlx::query(q).bind(contract_address).bind(chain_id))
+            .await?
+        {
+            Some(row) => {
+                let contract_type: String = row.try_get("contract_type")?;
+                ContractType::from_str(&contract_type).map_err(|_| {
+                    StorageError::DatabaseError(format!("Invalid contract type {}", contract_type))
+                })
+            }
+            None => Err(StorageError::NotFound(format!(
+                "contract_address: {contract_address}"
+            ))),
+        }
+    }
+
+    async fn register_contract_info(
+        &self,
+        info: &ContractInfo,
+        block_timestamp: u64,
+        chain_id: &str,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Registering contract info {:?} for contract {}",
+            info.contract_type,
+            info.contract_address
+        );
+
+        let q = "INSERT INTO contract (contract_address, chain_id, contract_type, name, symbol, image,
+                 block_timestamp, deployed_block_number, deployer_address, class_hash, spam_score)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                 ON CONFLICT (contract_address, chain_id) DO UPDATE SET
+                 contract_type = EXCLUDED.contract_type,
+                 name = COALESCE(EXCLUDED.name, contract.name),
+                 symbol = COALESCE(EXCLUDED.symbol, contract.symbol),
+                 image = COALESCE(EXCLUDED.image, contract.image),
+                 deployed_block_number = COALESCE(EXCLUDED.deployed_block_number, contract.deployed_block_number),
+                 deployer_address = COALESCE(EXCLUDED.deployer_address, contract.deployer_address),
+                 class_hash = COALESCE(EXCLUDED.class_hash, contract.class_hash),
+                 spam_score = GREATEST(EXCLUDED.spam_score, contract.spam_score)";
+
+        self.execute(
+            sqlx::query(q)
+                .bind(info.contract_address.clone())
+                .bind(chain_id)
+                .bind(info.contract_type.clone())
+                .bind(info.name.clone())
+                .bind(info.symbol.clone())
+                .bind(info.image.clone())
+                .bind(block_timestamp as i64)
+                .bind(info.deployed_block_number.map(|n| n as i64))
+                .bind(info.deployer_address.clone())
+                .bind(info.class_hash.clone())
+                .bind(info.spam_score.map(i16::from)),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn raise_contract_spam_score(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        spam_score: u8,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Raising spam score of contract {} to {}",
+            contract_address,
+            spam_score
+        );
+
+        let q = "UPDATE contract SET spam_score = GREATEST(spam_score, $3)
+                 WHERE contract_address = $1 AND chain_id = $2";
+
+        self.execute(
+            sqlx::query(q)
+                .bind(contract_address)
+                .bind(chain_id)
+                .bind(i16::from(spam_score)),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn set_block_info(
+        &self,
+        block_number: u64,
+        block_timestamp: u64,
+        info: BlockInfo,
+    ) -> Result<(), StorageError> {
+        trace!("Setting block info {:?} for block #{}", info, block_number);
+
+        let q = "INSERT INTO block (block_number, block_timestamp, status, indexer_version, indexer_identifier)
+                 VALUES ($1, $2, $3, $4, $5)
+                 ON CONFLICT (block_number) DO UPDATE SET
+                 block_timestamp = EXCLUDED.block_timestamp, status = EXCLUDED.status,
+                 indexer_version = EXCLUDED.indexer_version, indexer_identifier = EXCLUDED.indexer_identifier";
+
+        self.execute(
+            sqlx::query(q)
+                .bind(block_number as i64)
+                .bind(block_timestamp as i64)
+                .bind(info.status.to_string())
+                .bind(info.indexer_version.clone())
+                .bind(info.indexer_identifier.clone()),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_block_info(&self, block_number: u64) -> Result<BlockInfo, StorageError> {
+        trace!("Getting block info for block #{}", block_number);
+
+        let q =
+            "SELECT status, indexer_version, indexer_identifier FROM block WHERE block_number = $1";
+
+        match self
+            .fetch_optional(sqlx::query(q).bind(block_number as i64))
+            .await?
+        {
+            Some(row) => {
+                let status: String = row.try_get("status")?;
+
+                Ok(BlockInfo {
+                    indexer_version: row.try_get("indexer_version")?,
+                    indexer_identifier: row.try_get("indexer_identifier")?,
+                    status: BlockIndexingStatus::from_str(&status).map_err(|_| {
+                        StorageError::InvalidStatus(format!("Invalid block status {}", status))
+                    })?,
+                    block_number,
+                })
+            }
+            None => Err(StorageError::NotFound(format!(
+                "block number {block_number}"
+            ))),
+        }
+    }
+
+    async fn clean_block(
+        &self,
+        block_timestamp: u64,
+        block_number: Option<u64>,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Cleaning block #{:?} [ts: {}]",
+            block_number,
+            block_timestamp
+        );
+
+        let q = "DELETE FROM block WHERE block_timestamp = $1";
+        self.execute(sqlx::query(q).bind(block_timestamp as i64))
+            .await?;
+
+        // The activity of the deleted events is removed from the aggregates.
+        let q = format!(
+            "WITH deleted AS (
+                 DELETE FROM token_event WHERE block_timestamp = $1
+                 RETURNING contract_address, chain_id, event_type
+             ) {DELETED_MINTS_STATS}"
+        );
+        let deleted = self
+            .fetch_all(sqlx::query(&q).bind(block_timestamp as i64))
+            .await?;
+        self.remove_collection_activity(deleted, block_timestamp)
+            .await?;
+
+        let q = format!(
+            "WITH deleted AS (
+                 DELETE FROM token_sale WHERE block_timestamp = $1
+                 RETURNING nft_contract_address, chain_id, price_usd, wash_trade_flags
+             ) {DELETED_SALES_STATS}"
+        );
+        let deleted = self
+            .fetch_all(sqlx::query(&q).bind(block_timestamp as i64))
+            .await?;
+        self.remove_collection_activity(deleted, block_timestamp)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn clean_block_events(
+        &self,
+        block_timestamp: u64,
+        block_number: u64,
+        filter: &ReindexFilter,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Cleaning events of block #{} [ts: {}] matching {:?}",
+            block_number,
+            block_timestamp,
+            filter
+        );
+
+        // `$2` is set when the filter matches all the contracts.
+        if filter.matches_kind(ReindexEventKind::Transfer) {
+            let q = format!(
+                "WITH deleted AS (
+                     DELETE FROM token_event WHERE block_timestamp = $1
+                     AND ($2 OR contract_address = ANY($3))
+                     RETURNING contract_address, chain_id, event_type
+                 ) {DELETED_MINTS_STATS}"
+            );
+
+            let deleted = self
+                .fetch_all(
+                    sqlx::query(&q)
+                        .bind(block_timestamp as i64)
+                        .bind(filter.matches_all_contracts())
+                        .bind(&filter.nft_contracts),
+                )
+                .await?;
+            self.remove_collection_activity(deleted, block_timestamp)
+                .await?;
+        }
+
+        // The detected sales have no marketplace, and are kept.
+        if filter.matches_kind(ReindexEventKind::Sale) {
+            let q = format!(
+                "WITH deleted AS (
+                     DELETE FROM token_sale WHERE block_timestamp = $1
+                     AND marketplace_contract_address <> $4
+                     AND ($2 OR marketplace_contract_address = ANY($3))
+                     RETURNING nft_contract_address, chain_id, price_usd, wash_trade_flags
+                 ) {DELETED_SALES_STATS}"
+            );
+
+            let deleted = self
+                .fetch_all(
+                    sqlx::query(&q)
+                        .bind(block_timestamp as i64)
+                        .bind(filter.matches_all_contracts())
+                        .bind(&filter.marketplaces)
+                        .bind(to_hex_str(&FieldElement::ZERO)),
+                )
+                .await?;
+            self.remove_collection_activity(deleted, block_timestamp)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Negated activity of the mints deleted by a `deleted` CTE.
+const DELETED_MINTS_STATS: &str = "SELECT contract_address, chain_id,
+    0::BIGINT AS sales_count, '0'::TEXT AS volume_usd, -COUNT(*) AS mint_count
+    FROM deleted WHERE event_type = 'MINT' GROUP BY contract_address, chain_id";
+
+/// Negated activity of the sales deleted by a `deleted` CTE, without the wash trades.
+const DELETED_SALES_STATS: &str = "SELECT nft_contract_address AS contract_address, chain_id,
+    -COUNT(*) AS sales_count, (-COALESCE(SUM(price_usd), 0))::TEXT AS volume_usd,
+    0::BIGINT AS mint_count
+    FROM deleted WHERE cardinality(wash_trade_flags) = 0
+    GROUP BY nft_contract_address, chain_id";
+
+const TOKEN_COLUMNS: &str = "contract_address, chain_id, token_id, token_id_hex, owner";
+
+const TOKEN_EVENT_COLUMNS: &str = "event_id, contract_address, chain_id, contract_type, token_id,
+    token_id_hex, from_address, to_address, transaction_hash, event_type, block_timestamp, block_number";
+
+const TOKEN_SALE_COLUMNS: &str = "event_id, nft_contract_address, nft_type, chain_id, token_id,
+    token_id_hex, from_address, to_address, marketplace_contract_address, marketplace_name,
+    transaction_hash, event_type, quantity, currency_address, currency_symbol, currency_decimals,
+    price, normalized_price::TEXT AS normalized_price, price_usd::FLOAT8 AS price_usd,
+    block_timestamp, block_number, wash_trade_flags,
+    wash_trade_reasons";
+
+const CONTRACT_COLUMNS: &str = "contract_address, chain_id, contract_type, name, symbol, image,
+    deployed_block_number, deployer_address, class_hash, spam_score";
+
+fn token_from_row(row: &PgRow) -> Result<TokenInfo, StorageError> {
+    Ok(TokenInfo {
+        contract_address: row.try_get("contract_address")?,
+        chain_id: row.try_get("chain_id")?,
+        token_id: row.try_get("token_id")?,
+        token_id_hex: row.try_get("token_id_hex")?,
+        owner: row.try_get("owner")?,
+    })
+}
+
+fn registered_token_from_row(row: &PgRow) -> Result<RegisteredToken, StorageError> {
+    Ok(RegisteredToken {
+        token: token_from_row(row)?,
+        block_number: row.try_get::<i64, _>("registered_block_number")? as u64,
+        backfilled_owner: row.try_get("backfilled_owner")?,
+    })
+}
+
+fn event_type_from_row(row: &PgRow) -> Result<EventType, StorageError> {
+    let event_type: String = row.try_get("event_type")?;
+    EventType::from_str(&event_type)
+        .map_err(|_| StorageError::DatabaseError(format!("Invalid event type {}", event_type)))
+}
+
+fn transfer_from_row(row: &PgRow) -> Result<TokenTransferEvent, StorageError> {
+    Ok(TokenTransferEvent {
+        timestamp: row.try_get::<i64, _>("block_timestamp")? as u64,
+        from_address: row.try_get("from_address")?,
+        to_address: row.try_get("to_address")?,
+        contract_address: row.try_get("contract_address")?,
+        chain_id: row.try_get("chain_id")?,
+        contract_type: row.try_get("contract_type")?,
+        transaction_hash: row.try_get("transaction_hash")?,
+        token_id: row.try_get("token_id")?,
+        token_id_hex: row.try_get("token_id_hex")?,
+        event_type: event_type_from_row(row)?,
+        event_id: row.try_get("event_id")?,
+        block_number: row
+            .try_get::<Option<i64>, _>("block_number")?
+            .map(|n| n as u64),
+        updated_at: None,
+    })
+}
+
+fn sale_from_row(row: &PgRow) -> Result<TokenSaleEvent, StorageError> {
+    Ok(TokenSaleEvent {
+        timestamp: row.try_get::<i64, _>("block_timestamp")? as u64,
+        from_address: row.try_get("from_address")?,
+        to_address: row.try_get("to_address")?,
+        nft_contract_address: row.try_get("nft_contract_address")?,
+        nft_type: row.try_get("nft_type")?,
+        marketplace_contract_address: row.try_get("marketplace_contract_address")?,
+        marketplace_name: row.try_get("marketplace_name")?,
+        transaction_hash: row.try_get("transaction_hash")?,
+        token_id: row.try_get("token_id")?,
+        token_id_hex: row.try_get("token_id_hex")?,
+        event_type: event_type_from_row(row)?,
+        event_id: row.try_get("event_id")?,
+        block_number: row
+            .try_get::<Option<i64>, _>("block_number")?
+            .map(|n| n as u64),
+        updated_at: None,
+        quantity: row.try_get::<i64, _>("quantity")? as u64,
+        currency_address: row.try_get("currency_address")?,
+        price: row.try_get("price")?,
+        currency_symbol: row.try_get("currency_symbol")?,
+        currency_decimals: row
+            .try_get::<Option<i16>, _>("currency_decimals")?
+            .map(|d| d as u8),
+        normalized_price: row.try_get("normalized_price")?,
+        price_usd: row.try_get("price_usd")?,
+        chain_id: row.try_get("chain_id")?,
+        wash_trade_flags: wash_trade_flags_from_row(row)?,
+    })
+}
+
+/// Flags stored as parallel arrays of kinds and reasons.
+fn wash_trade_flags_from_row(row: &PgRow) -> Result<Vec<WashTradeFlag>, StorageError> {
+    let kinds: Vec<String> = row.try_get("wash_trade_flags")?;
+    let reasons: Vec<String> = row.try_get("wash_trade_reasons")?;
+
+    kinds
+        .into_iter()
+        .zip(reasons)
+        .map(|(kind, reason)| {
+            let kind = WashTradeKind::from_str(&kind).map_err(|_| {
+                StorageError::DatabaseError(format!("Invalid wash trade kind {}", kind))
+            })?;
+            Ok(WashTradeFlag { kind, reason })
+        })
+        .collect()
+}
+
+fn contract_from_row(row: &PgRow) -> Result<ContractInfo, StorageError> {
+    Ok(ContractInfo {
+        contract_address: row.try_get("contract_address")?,
+        chain_id: row.try_get("chain_id")?,
+        contract_type: row.try_get("contract_type")?,
+        name: row.try_get("name")?,
+        symbol: row.try_get("symbol")?,
+        image: row.try_get("image")?,
+        deployed_block_number: row
+            .try_get::<Option<i64>, _>("deployed_block_number")?
+            .map(|n| n as u64),
+        deployer_address: row.try_get("deployer_address")?,
+        class_hash: row.try_get("class_hash")?,
+        spam_score: row
+            .try_get::<Option<i16>, _>("spam_score")?
+            .map(|score| score.clamp(0, u8::MAX as i16) as u8),
+    })
+}
+
+fn activity_from_row(row: &PgRow) -> Result<CollectionActivity, StorageError> {
+    Ok(CollectionActivity {
+        sales_count: row.try_get::<i64, _>("sales_count")?.max(0) as u64,
+        volume_usd: row.try_get::<f64, _>("volume_usd")?.max(0.0),
+        mint_count: row.try_get::<i64, _>("mint_count")?.max(0) as u64,
+    })
+}
+
+/// Schema names are interpolated in the statements, only
+/// lowercase letters, digits and underscores are accepted.
+pub fn is_valid_schema_name(schema: &str) -> bool {
+    !schema.is_empty()
+        && !schema.starts_with(|c: char| c.is_ascii_digit())
+        && schema
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+fn block_info_from_row(row: &PgRow) -> Result<BlockInfo, StorageError> {
+    let status: String = row.try_get("status")?;
+
+    Ok(BlockInfo {
+        indexer_version: row.try_get("indexer_version")?,
+        indexer_identifier: row.try_get("indexer_identifier")?,
+        status: BlockIndexingStatus::from_str(&status)
+            .map_err(|_| StorageError::InvalidStatus(format!("Invalid block status {}", status)))?,
+        block_number: row.try_get::<i64, _>("block_number")? as u64,
+    })
+}
+
+/// Maps the rows fetched with a limit of `pagination.limit + 1`.
+fn page_from_rows<T>(
+    rows: Vec<PgRow>,
+    pagination: &Pagination,
+    from_row: fn(&PgRow) -> Result<T, StorageError>,
+) -> Result<Page<T>, StorageError> {
+    let items = rows
+        .iter()
+        .map(from_row)
+        .collect::<Result<Vec<T>, StorageError>>()?;
+
+    Ok(Page::from_items(items, pagination))
+}
+
+#[async_trait]
+impl QueryStorage for PostgresStorage {
+    async fn get_token(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        token_id_hex: &str,
+    ) -> Result<TokenInfo, StorageError> {
+        let q = format!(
+            "SELECT {TOKEN_COLUMNS} FROM token
+             WHERE contract_address = $1 AND chain_id = $2 AND token_id_hex = $3"
+        );
+
+        match self
+            .fetch_optional(
+                sqlx::query(&q)
+                    .bind(contract_address)
+                    .bind(chain_id)
+                    .bind(token_id_hex),
+            )
+            .await?
+        {
+            Some(row) => token_from_row(&row),
+            None => Err(StorageError::NotFound(format!(
+                "token {contract_address} {token_id_hex}"
+            ))),
+        }
+    }
+
+    async fn tokens_by_owner(
+        &self,
+        owner: &str,
+        chain_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<TokenInfo>, StorageError> {
+        let q = format!(
+            "SELECT {TOKEN_COLUMNS} FROM token
+             WHERE owner = $1 AND chain_id = $2
+             ORDER BY contract_address, token_id_hex
+             LIMIT $3 OFFSET $4"
+        );
+
+        let rows = self
+            .fetch_all(
+                sqlx::query(&q)
+                    .bind(owner)
+                    .bind(chain_id)
+                    .bind(pagination.limit as i64 + 1)
+                    .bind(pagination.offset as i64),
+            )
+            .await?;
+
+        page_from_rows(rows, &pagination, token_from_row)
+    }
+
+    async fn collection_tokens(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<TokenInfo>, StorageError> {
+        let q = format!(
+            "SELECT {TOKEN_COLUMNS} FROM token
+             WHERE contract_address = $1 AND chain_id = $2
+             ORDER BY token_id_hex
+             LIMIT $3 OFFSET $4"
+        );
+
+        let rows = self
+            .fetch_all(
+                sqlx::query(&q)
+                    .bind(contract_address)
+                    .bind(chain_id)
+                    .bind(pagination.limit as i64 + 1)
+                    .bind(pagination.offset as i64),
+            )
+            .await?;
+
+        page_from_rows(rows, &pagination, token_from_row)
+    }
+
+    async fn collection_tokens_until(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        block_number: u64,
+        pagination: Pagination,
+    ) -> Result<Page<RegisteredToken>, StorageError> {
+        let q = format!(
+            "SELECT {TOKEN_COLUMNS}, backfilled_owner,
+             COALESCE(backfilled_block_number, mint_block_number) AS registered_block_number
+             FROM token
+             WHERE contract_address = $1 AND chain_id = $2
+             AND COALESCE(backfilled_block_number, mint_block_number) <= $3
+             ORDER BY token_id_hex
+             LIMIT $4 OFFSET $5"
+        );
+
+        let rows = self
+            .fetch_all(
+                sqlx::query(&q)
+                    .bind(contract_address)
+                    .bind(chain_id)
+                    .bind(block_number as i64)
+                    .bind(pagination.limit as i64 + 1)
+                    .bind(pagination.offset as i64),
+            )
+            .await?;
+
+        page_from_rows(rows, &pagination, registered_token_from_row)
+    }
+
+    async fn token_history(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        token_id_hex: &str,
+        pagination: Pagination,
+    ) -> Result<Page<TokenTransferEvent>, StorageError> {
+        let q = format!(
+            "SELECT {TOKEN_EVENT_COLUMNS} FROM token_event
+             WHERE contract_address = $1 AND chain_id = $2 AND token_id_hex = $3
+             ORDER BY block_timestamp DESC, event_id
+             LIMIT $4 OFFSET $5"
+        );
+
+        let rows = self
+            .fetch_all(
+                sqlx::query(&q)
+                    .bind(contract_address)
+                    .bind(chain_id)
+                    .bind(token_id_hex)
+                    .bind(pagination.limit as i64 + 1)
+                    .bind(pagination.offset as i64),
+            )
+            .await?;
+
+        page_from_rows(rows, &pagination, transfer_from_row)
+    }
+
+    async fn collection_transfers_until(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        block_number: u64,
+        pagination: Pagination,
+    ) -> Result<Page<TokenTransferEvent>, StorageError> {
+        let q = format!(
+            "SELECT {TOKEN_EVENT_COLUMNS} FROM token_event
+             WHERE contract_address = $1 AND chain_id = $2 AND block_number <= $3
+             ORDER BY block_number, event_id
+             LIMIT $4 OFFSET $5"
+        );
+
+        let rows = self
+            .fetch_all(
+                sqlx::query(&q)
+                    .bind(contract_address)
+                    .bind(chain_id)
+                    .bind(block_number as i64)
+                    .bind(pagination.limit as i64 + 1)
+                    .bind(pagination.offset as i64),
+            )
+            .await?;
+
+        page_from_rows(rows, &pagination, transfer_from_row)
+    }
+
+    async fn sales(
+        &self,
+        chain_id: &str,
+        filter: &SaleFilter,
+        pagination: Pagination,
+    ) -> Result<Page<TokenSaleEvent>, StorageError> {
+        let q = format!(
+            "SELECT {TOKEN_SALE_COLUMNS} FROM token_sale
+             WHERE chain_id = $1
+             AND ($2::TEXT IS NULL OR nft_contract_address = $2)
+             AND ($3::TEXT IS NULL OR token_id_hex = $3)
+             AND ($4::BIGINT IS NULL OR block_timestamp >= $4)
+             AND ($5::BIGINT IS NULL OR block_timestamp <= $5)
+             ORDER BY block_timestamp DESC, event_id
+             LIMIT $6 OFFSET $7"
+        );
+
+        let rows = self
+            .fetch_all(
+                sqlx::query(&q)
+                    .bind(chain_id)
+                    .bind(filter.contract_address.clone())
+                    .bind(filter.token_id_hex.clone())
+                    .bind(filter.from_timestamp.map(|ts| ts as i64))
+                    .bind(filter.to_timestamp.map(|ts| ts as i64))
+                    .bind(pagination.limit as i64 + 1)
+                    .bind(pagination.offset as i64),
+            )
+            .await?;
+
+        page_from_rows(rows, &pagination, sale_from_row)
+    }
+
+    async fn collection_stats(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        timestamp: u64,
+    ) -> Result<CollectionStats, StorageError> {
+        let mut stats = CollectionStats {
+            contract_address: contract_address.to_string(),
+            chain_id: chain_id.to_string(),
+            ..Default::default()
+        };
+
+        let q = "SELECT owners_count, supply, sales_count,
+                 volume_usd::FLOAT8 AS volume_usd, mint_count
+                 FROM collection_stats WHERE contract_address = $1 AND chain_id = $2";
+
+        if let Some(row) = self
+            .fetch_optional(sqlx::query(q).bind(contract_address).bind(chain_id))
+            .await?
+        {
+            stats.owners_count = row.try_get::<i64, _>("owners_count")?.max(0) as u64;
+            stats.supply = row.try_get::<i64, _>("supply")?.max(0) as u64;
+            stats.all_time = activity_from_row(&row)?;
+        }
+
+        let current = CollectionStats::bucket(timestamp);
+        let oldest = current.saturating_sub((CollectionStats::PERIODS[2] - 1) * STATS_BUCKET_SECS);
+
+        let q = "SELECT hour_timestamp, sales_count, volume_usd::FLOAT8 AS volume_usd, mint_count
+                 FROM collection_stats_hourly
+                 WHERE contract_address = $1 AND chain_id = $2
+                 AND hour_timestamp >= $3 AND hour_timestamp <= $4";
+
+        let rows = self
+            .fetch_all(
+                sqlx::query(q)
+                    .bind(contract_address)
+                    .bind(chain_id)
+                    .bind(oldest as i64)
+                    .bind(current as i64),
+            )
+            .await?;
+
+        for row in rows {
+            let bucket = row.try_get::<i64, _>("hour_timestamp")? as u64;
+            stats.add_bucket(timestamp, bucket, &activity_from_row(&row)?);
+        }
+
+        Ok(stats)
+    }
+
+    async fn contracts(
+        &self,
+        chain_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<ContractInfo>, StorageError> {
+        let q = format!(
+            "SELECT {CONTRACT_COLUMNS} FROM contract
+             WHERE chain_id = $1
+             ORDER BY contract_address
+             LIMIT $2 OFFSET $3"
+        );
+
+        let rows = self
+            .fetch_all(
+                sqlx::query(&q)
+                    .bind(chain_id)
+                    .bind(pagination.limit as i64 + 1)
+                    .bind(pagination.offset as i64),
+            )
+            .await?;
+
+        page_from_rows(rows, &pagination, contract_from_row)
+    }
+
+    async fn get_contract_info(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+    ) -> Result<ContractInfo, StorageError> {
+        let q = format!(
+            "SELECT {CONTRACT_COLUMNS} FROM contract WHERE contract_address = $1 AND chain_id = $2"
+        );
+
+        match self
+            .fetch_optional(sqlx::query(&q).bind(contract_address).bind(chain_id))
+            .await?
+        {
+            Some(row) => contract_from_row(&row),
+            None => Err(StorageError::NotFound(format!(
+                "contract_address: {contract_address}"
+            ))),
+        }
+    }
+
+    async fn last_block_info(&self) -> Result<Option<BlockInfo>, StorageError> {
+        let q = "SELECT block_number, status, indexer_version, indexer_identifier FROM block
+                 ORDER BY block_number DESC LIMIT 1";
+
+        match self.fetch_optional(sqlx::query(q)).await? {
+            Some(row) => Ok(Some(block_info_from_row(&row)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn blocks_info(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<BlockInfo>, StorageError> {
+        let q = "SELECT block_number, status, indexer_version, indexer_identifier FROM block
+                 WHERE block_number BETWEEN $1 AND $2 ORDER BY block_number";
+
+        let rows = self
+            .fetch_all(sqlx::query(q).bind(from_block as i64).bind(to_block as i64))
+            .await?;
+
+        rows.iter().map(block_info_from_row).collect()
+    }
+}
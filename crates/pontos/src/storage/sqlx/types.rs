@@ -12,6 +12,8 @@ pub struct TokenData {
     pub mint_address: Option<String>,
     pub mint_timestamp: Option<i64>,
     pub mint_transaction_hash: Option<String>,
+    pub mint_price: Option<String>,
+    pub mint_currency_address: Option<String>,
 }
 
 #[derive(Debug, Clone, sqlx::FromRow)]
@@ -56,4 +58,7 @@ pub struct ContractData {
     pub block_timestamp: i64,
     pub contract_address: String,
     pub contract_type: String,
+    pub deployed_block_number: Option<i64>,
+    pub deployer_address: Option<String>,
+    pub class_hash: Option<String>,
 }
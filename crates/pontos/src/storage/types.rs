@@ -1,8 +1,11 @@
+use crate::storage::utils::is_zero_address;
 use serde::{Deserialize, Serialize, Serializer};
 use std::collections::HashMap;
 use std::fmt;
 use std::str::FromStr;
 
+pub use ark_starknet::currency::CurrencyInfo;
+
 #[derive(Debug, Clone)]
 pub enum StorageError {
     DatabaseError(String),
@@ -66,6 +69,52 @@ impl FromStr for EventType {
     }
 }
 
+/// Pattern of a sale suspected to be a wash trade.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WashTradeKind {
+    /// Buyer and seller are the same address.
+    SelfTrade,
+    /// The buyer sold to the seller shortly before.
+    RoundTrip,
+    /// The seller sent funds to the buyer shortly before, or in the same transaction.
+    SellerFunded,
+    /// Price far from the recent prices of the collection.
+    PriceOutlier,
+}
+
+impl fmt::Display for WashTradeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WashTradeKind::SelfTrade => write!(f, "SELF_TRADE"),
+            WashTradeKind::RoundTrip => write!(f, "ROUND_TRIP"),
+            WashTradeKind::SellerFunded => write!(f, "SELLER_FUNDED"),
+            WashTradeKind::PriceOutlier => write!(f, "PRICE_OUTLIER"),
+        }
+    }
+}
+
+impl FromStr for WashTradeKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "SELF_TRADE" => Ok(WashTradeKind::SelfTrade),
+            "ROUND_TRIP" => Ok(WashTradeKind::RoundTrip),
+            "SELLER_FUNDED" => Ok(WashTradeKind::SellerFunded),
+            "PRICE_OUTLIER" => Ok(WashTradeKind::PriceOutlier),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Wash trade suspicion on a sale, with the reason of the analyzer.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct WashTradeFlag {
+    pub kind: WashTradeKind,
+    pub reason: String,
+}
+
 impl Serialize for TokenEvent {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -116,6 +165,27 @@ impl Serialize for TokenEvent {
                 }
 
                 map.insert("price", event.price.clone());
+
+                if let Some(currency_symbol) = event.currency_symbol.clone() {
+                    map.insert("currency_symbol", currency_symbol);
+                }
+
+                if let Some(normalized_price) = event.normalized_price.clone() {
+                    map.insert("normalized_price", normalized_price);
+                }
+
+                if let Some(price_usd) = event.price_usd {
+                    map.insert("price_usd", price_usd.to_string());
+                }
+
+                if event.is_wash_trade() {
+                    let kinds: Vec<String> = event
+                        .wash_trade_flags
+                        .iter()
+                        .map(|flag| flag.kind.to_string())
+                        .collect();
+                    map.insert("wash_trade_flags", kinds.join(","));
+                }
                 map.insert(
                     "block_number",
                     event
@@ -172,8 +242,28 @@ pub struct TokenSaleEvent {
     pub updated_at: Option<u64>,
     pub quantity: u64,
     pub currency_address: Option<String>,
+    /// Raw price, hexadecimal representation of the u256 amount
+    /// in the smallest unit of the currency.
     pub price: String,
+    pub currency_symbol: Option<String>,
+    pub currency_decimals: Option<u8>,
+    /// Exact decimal representation of the price divided by
+    /// `10^currency_decimals`, which makes it comparable and
+    /// summable across sales of the same currency.
+    pub normalized_price: Option<String>,
+    /// USD value of the sale at the time of the sale.
+    pub price_usd: Option<f64>,
     pub chain_id: String,
+    /// Set before the registration, the flagged sales are
+    /// excluded from the collection statistics.
+    #[serde(default)]
+    pub wash_trade_flags: Vec<WashTradeFlag>,
+}
+
+impl TokenSaleEvent {
+    pub fn is_wash_trade(&self) -> bool {
+        !self.wash_trade_flags.is_empty()
+    }
 }
 
 impl Default for TokenTransferEvent {
@@ -205,15 +295,69 @@ pub struct TokenInfo {
     pub owner: String,
 }
 
+/// Token of the token table with the block from which it exists: the
+/// block of its mint, or of the backfill which registered it.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct RegisteredToken {
+    pub token: TokenInfo,
+    pub block_number: u64,
+    /// Owner found on chain at `block_number` by the backfill, `None`
+    /// for a minted token.
+    pub backfilled_owner: Option<String>,
+}
+
+/// Approval of a single token (ERC721 `Approval`).
+///
+/// Only the current approval of a token is relevant, an approval
+/// to the address zero means that the approval was revoked.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct TokenApproval {
+    pub contract_address: String,
+    pub contract_type: String,
+    pub token_id: String,
+    pub token_id_hex: String,
+    pub owner: String,
+    pub approved_address: String,
+    pub transaction_hash: String,
+    pub timestamp: u64,
+    pub block_number: Option<u64>,
+    pub chain_id: String,
+}
+
+/// Approval of an operator for all the tokens of an owner
+/// (ERC721 and ERC1155 `ApprovalForAll`).
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct OperatorApproval {
+    pub contract_address: String,
+    pub contract_type: String,
+    pub owner: String,
+    pub operator: String,
+    pub approved: bool,
+    pub transaction_hash: String,
+    pub timestamp: u64,
+    pub block_number: Option<u64>,
+    pub chain_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ApprovalEvent {
+    Token(TokenApproval),
+    Operator(OperatorApproval),
+}
+
 #[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct TokenMintInfo {
     pub address: String,
     pub timestamp: u64,
     pub transaction_hash: String,
     pub block_number: Option<u64>,
+    /// Hexadecimal representation of the u256 price paid by the minter
+    /// for this token, if any payment was found in the mint transaction.
+    pub price: Option<String>,
+    pub currency_address: Option<String>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum BlockIndexingStatus {
     None,
@@ -275,7 +419,7 @@ pub struct BlockIndexing {
     pub indexer_version: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockInfo {
     pub indexer_version: String,
     pub indexer_identifier: String,
@@ -322,6 +466,229 @@ pub struct ContractInfo {
     pub name: Option<String>,
     pub symbol: Option<String>,
     pub image: Option<String>,
+    /// Deployment details, only known for contracts
+    /// discovered from their deployment.
+    pub deployed_block_number: Option<u64>,
+    pub deployer_address: Option<String>,
+    pub class_hash: Option<String>,
+    /// Heuristic spam score, from 0 to 100, see the `spam` module.
+    /// `None` for the contracts never scored.
+    pub spam_score: Option<u8>,
+}
+
+impl ContractInfo {
+    /// Score from which a collection is considered as spam.
+    pub const SPAM_SCORE_THRESHOLD: u8 = 50;
+
+    pub fn is_spam(&self) -> bool {
+        self.spam_score
+            .map_or(false, |score| score >= Self::SPAM_SCORE_THRESHOLD)
+    }
+}
+
+/// Page of items requested to a `QueryStorage`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Pagination {
+    /// Number of items to skip.
+    pub offset: u64,
+    pub limit: u64,
+}
+
+impl Pagination {
+    pub const DEFAULT_LIMIT: u64 = 50;
+    pub const MAX_LIMIT: u64 = 500;
+
+    /// The limit is bounded to `[1, MAX_LIMIT]`.
+    pub fn new(offset: u64, limit: u64) -> Self {
+        Self {
+            offset,
+            limit: limit.clamp(1, Self::MAX_LIMIT),
+        }
+    }
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        Self::new(0, Self::DEFAULT_LIMIT)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Offset of the next page, if there are more items.
+    pub next_offset: Option<u64>,
+}
+
+impl<T> Page<T> {
+    /// Builds a page from the items fetched with a limit of `pagination.limit + 1`,
+    /// the extra item only tells if a next page exists.
+    pub fn from_items(mut items: Vec<T>, pagination: &Pagination) -> Self {
+        let has_more = items.len() as u64 > pagination.limit;
+        items.truncate(pagination.limit as usize);
+
+        Self {
+            items,
+            next_offset: has_more.then_some(pagination.offset + pagination.limit),
+        }
+    }
+}
+
+/// Filter of the sales, all the criteria are optional.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct SaleFilter {
+    pub contract_address: Option<String>,
+    pub token_id_hex: Option<String>,
+    /// Inclusive bounds on the block timestamp.
+    pub from_timestamp: Option<u64>,
+    pub to_timestamp: Option<u64>,
+}
+
+impl SaleFilter {
+    pub fn matches(&self, sale: &TokenSaleEvent) -> bool {
+        self.contract_address
+            .as_ref()
+            .map_or(true, |c| *c == sale.nft_contract_address)
+            && self
+                .token_id_hex
+                .as_ref()
+                .map_or(true, |t| *t == sale.token_id_hex)
+            && self.from_timestamp.map_or(true, |ts| sale.timestamp >= ts)
+            && self.to_timestamp.map_or(true, |ts| sale.timestamp <= ts)
+    }
+}
+
+/// Duration of the buckets of the collection statistics.
+pub const STATS_BUCKET_SECS: u64 = 3600;
+
+/// Activity of a collection over a period.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct CollectionActivity {
+    pub sales_count: u64,
+    /// Sum of the USD prices. The sales without USD price are only counted.
+    pub volume_usd: f64,
+    pub mint_count: u64,
+}
+
+/// Aggregates of a collection, maintained by the storage while
+/// the events are registered and cleaned.
+///
+/// The periods are made of whole hours: the last hour is the hour
+/// containing the requested timestamp, the last day is this hour
+/// and the 23 previous ones.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct CollectionStats {
+    pub contract_address: String,
+    pub chain_id: String,
+    /// Distinct owners of at least one token.
+    pub owners_count: u64,
+    /// Tokens with an owner.
+    pub supply: u64,
+    pub last_hour: CollectionActivity,
+    pub last_day: CollectionActivity,
+    pub last_week: CollectionActivity,
+    pub all_time: CollectionActivity,
+}
+
+impl CollectionStats {
+    /// Length of the last hour, day and week periods, in buckets.
+    pub const PERIODS: [u64; 3] = [1, 24, 24 * 7];
+
+    /// Start of the bucket containing the timestamp.
+    pub fn bucket(timestamp: u64) -> u64 {
+        timestamp - timestamp % STATS_BUCKET_SECS
+    }
+
+    /// Adds the activity of a bucket to the periods containing it,
+    /// relative to the bucket of `timestamp`. Future buckets are ignored.
+    pub fn add_bucket(&mut self, timestamp: u64, bucket: u64, activity: &CollectionActivity) {
+        let current = Self::bucket(timestamp);
+
+        if bucket > current {
+            return;
+        }
+
+        let age = (current - bucket) / STATS_BUCKET_SECS;
+
+        for (buckets, period) in Self::PERIODS.into_iter().zip([
+            &mut self.last_hour,
+            &mut self.last_day,
+            &mut self.last_week,
+        ]) {
+            if age < buckets {
+                period.add(activity);
+            }
+        }
+    }
+}
+
+impl CollectionActivity {
+    pub fn add(&mut self, other: &CollectionActivity) {
+        self.sales_count += other.sales_count;
+        self.volume_usd += other.volume_usd;
+        self.mint_count += other.mint_count;
+    }
+}
+
+/// Kind of the events indexed again by a selective reindex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReindexEventKind {
+    /// NFT transfers, including mints and burns.
+    Transfer,
+    /// `Approval` and `ApprovalForAll`.
+    Approval,
+    /// Sales decoded from the events of the marketplaces.
+    Sale,
+}
+
+/// Events of a selective reindex, by emitting contract and by kind.
+/// An empty list of kinds matches all the kinds.
+///
+/// Transfers and approvals are matched by NFT contract, sales by
+/// marketplace. Without any contract, all the contracts are matched;
+/// otherwise only the listed ones, so a filter with marketplaces only
+/// matches no transfer. The sales detected by the transaction analyzers
+/// have no emitting marketplace, and are never matched.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ReindexFilter {
+    /// NFT contracts, as padded hexadecimal strings.
+    pub nft_contracts: Vec<String>,
+    /// Marketplaces, as padded hexadecimal strings.
+    pub marketplaces: Vec<String>,
+    pub event_kinds: Vec<ReindexEventKind>,
+}
+
+impl ReindexFilter {
+    /// True if no contract is listed, matching all of them.
+    pub fn matches_all_contracts(&self) -> bool {
+        self.nft_contracts.is_empty() && self.marketplaces.is_empty()
+    }
+
+    pub fn matches_nft_contract(&self, contract_address: &str) -> bool {
+        self.matches_all_contracts() || self.nft_contracts.iter().any(|c| c == contract_address)
+    }
+
+    pub fn matches_marketplace(&self, contract_address: &str) -> bool {
+        self.matches_all_contracts() || self.marketplaces.iter().any(|c| c == contract_address)
+    }
+
+    pub fn matches_kind(&self, kind: ReindexEventKind) -> bool {
+        self.event_kinds.is_empty() || self.event_kinds.contains(&kind)
+    }
+
+    pub fn matches_transfer(&self, event: &TokenTransferEvent) -> bool {
+        self.matches_kind(ReindexEventKind::Transfer)
+            && self.matches_nft_contract(&event.contract_address)
+    }
+
+    pub fn matches_sale(&self, sale: &TokenSaleEvent) -> bool {
+        let detected = is_zero_address(&sale.marketplace_contract_address);
+
+        !detected
+            && self.matches_kind(ReindexEventKind::Sale)
+            && self.matches_marketplace(&sale.marketplace_contract_address)
+    }
 }
 
 #[cfg(test)]
@@ -381,4 +748,19 @@ mod tests {
 
         assert_eq!(serialized_value, expected_value, "json are not equal");
     }
+
+    #[test]
+    fn test_page_from_items() {
+        let pagination = Pagination::new(10, 2);
+
+        let page = Page::from_items(vec![1, 2, 3], &pagination);
+        assert_eq!(page.items, vec![1, 2]);
+        assert_eq!(page.next_offset, Some(12));
+
+        let page = Page::from_items(vec![1, 2], &pagination);
+        assert_eq!(page.next_offset, None);
+
+        assert_eq!(Pagination::new(0, 0).limit, 1);
+        assert_eq!(Pagination::new(0, 10_000).limit, Pagination::MAX_LIMIT);
+    }
 }
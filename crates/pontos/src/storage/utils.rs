@@ -1,3 +1,9 @@
 pub fn format_token_id(token_id: String) -> String {
     format!("{:0>width$}", token_id, width = 78)
 }
+
+/// Returns true for the zero address, whatever its padding.
+/// An empty address, like the owner of a burnt token, is also zero.
+pub fn is_zero_address(address: &str) -> bool {
+    address.trim_start_matches("0x").chars().all(|c| c == '0')
+}
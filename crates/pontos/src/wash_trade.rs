@@ -0,0 +1,353 @@
+//! Detection of the wash trades, the sales made to inflate the volume
+//! of a collection.
+//!
+//! Every sale is checked before its registration against the sales and
+//! the ERC20 payments seen recently by this instance. This history is kept
+//! in memory, and expects the blocks in ascending order: after a restart,
+//! the round trips and fundings with older blocks are not detected.
+use crate::analyzers::CurrencyTransferEvent;
+use crate::config::PontosConfig;
+use crate::storage::types::{TokenSaleEvent, WashTradeFlag, WashTradeKind};
+use ark_starknet::format::to_hex_str;
+use starknet::core::types::FieldElement;
+use std::collections::{HashMap, VecDeque};
+
+/// Recent prices kept by collection and currency, for the outliers.
+const PRICE_SAMPLES: usize = 50;
+
+#[derive(Debug, Clone)]
+struct RecentSale {
+    event_id: String,
+    contract_address: String,
+    seller: String,
+    buyer: String,
+    block_number: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct WashTradeDetector {
+    window_blocks: u64,
+    price_ratio: f64,
+    min_price_samples: usize,
+    /// Highest block seen, used for the events of the pending block.
+    latest_block: u64,
+    /// Sales of the window, oldest first.
+    sales: VecDeque<RecentSale>,
+    /// Block of the last payment, keyed by (from, to).
+    fundings: HashMap<(String, String), u64>,
+    /// Recent normalized prices, keyed by (contract_address, currency_address).
+    prices: HashMap<(String, String), VecDeque<f64>>,
+}
+
+impl WashTradeDetector {
+    pub fn new(window_blocks: u64, price_ratio: f64, min_price_samples: usize) -> Self {
+        Self {
+            window_blocks,
+            price_ratio,
+            min_price_samples,
+            latest_block: 0,
+            sales: VecDeque::new(),
+            fundings: HashMap::new(),
+            prices: HashMap::new(),
+        }
+    }
+
+    pub fn from_config(config: &PontosConfig) -> Self {
+        Self::new(
+            config.wash_trade_window_blocks,
+            config.wash_trade_price_ratio,
+            config.wash_trade_min_price_samples,
+        )
+    }
+
+    /// Remembers the payments of a transaction, to flag the sales funded
+    /// by their seller. Must be called before analyzing the sales of
+    /// the same transaction.
+    pub fn record_payments(
+        &mut self,
+        payments: &[CurrencyTransferEvent],
+        block_number: Option<u64>,
+    ) {
+        let block_number = self.advance(block_number);
+
+        for payment in payments {
+            let key = (
+                normalize(&payment.from_address),
+                normalize(&payment.to_address),
+            );
+            self.fundings.insert(key, block_number);
+        }
+    }
+
+    /// Flags of the sale, which is then part of the history used
+    /// to analyze the next sales.
+    pub fn analyze(&mut self, sale: &TokenSaleEvent) -> Vec<WashTradeFlag> {
+        let block_number = self.advance(sale.block_number);
+        let seller = normalize(&sale.from_address);
+        let buyer = normalize(&sale.to_address);
+        let mut flags = vec![];
+
+        if seller == buyer {
+            flags.push(WashTradeFlag {
+                kind: WashTradeKind::SelfTrade,
+                reason: format!("seller {} is the buyer", seller),
+            });
+        }
+
+        if let Some(previous) = self.sales.iter().rev().find(|s| {
+            s.event_id != sale.event_id
+                && s.contract_address == sale.nft_contract_address
+                && s.seller == buyer
+                && s.buyer == seller
+        }) {
+            flags.push(WashTradeFlag {
+                kind: WashTradeKind::RoundTrip,
+                reason: format!(
+                    "buyer {} sold to seller {} at block {}",
+                    buyer, seller, previous.block_number
+                ),
+            });
+        }
+
+        if let Some(funded_at) = self.fundings.get(&(seller.clone(), buyer.clone())) {
+            flags.push(WashTradeFlag {
+                kind: WashTradeKind::SellerFunded,
+                reason: format!(
+                    "seller {} sent funds to buyer {} at block {}",
+                    seller, buyer, funded_at
+                ),
+            });
+        }
+
+        if let Some(flag) = self.price_outlier(sale) {
+            flags.push(flag);
+        }
+
+        // A sale registered again is already in the history.
+        if self.sales.iter().any(|s| s.event_id == sale.event_id) {
+            return flags;
+        }
+
+        self.sales.push_back(RecentSale {
+            event_id: sale.event_id.clone(),
+            contract_address: sale.nft_contract_address.clone(),
+            seller,
+            buyer,
+            block_number,
+        });
+
+        // The prices of the self-dealing sales would shift the distribution.
+        let self_dealing = flags.iter().any(|f| f.kind != WashTradeKind::PriceOutlier);
+        if let Some(price) = normalized_price(sale).filter(|_| !self_dealing) {
+            let samples = self.prices.entry(price_key(sale)).or_default();
+            if samples.len() == PRICE_SAMPLES {
+                samples.pop_front();
+            }
+            samples.push_back(price);
+        }
+
+        flags
+    }
+
+    /// Flags the prices beyond the ratio to the median of the recent
+    /// prices of the collection in the same currency.
+    fn price_outlier(&self, sale: &TokenSaleEvent) -> Option<WashTradeFlag> {
+        let price = normalized_price(sale)?;
+        let samples = self.prices.get(&price_key(sale))?;
+
+        if samples.len() < self.min_price_samples {
+            return None;
+        }
+
+        let median = median(samples)?;
+        if median <= 0.0
+            || (price <= median * self.price_ratio && price * self.price_ratio >= median)
+        {
+            return None;
+        }
+
+        Some(WashTradeFlag {
+            kind: WashTradeKind::PriceOutlier,
+            reason: format!(
+                "price {} is {:.2} times the median {} of the last {} sales",
+                price,
+                price / median,
+                median,
+                samples.len()
+            ),
+        })
+    }
+
+    /// Returns the block of the event, the latest one for the pending
+    /// block, and forgets the sales and payments out of the window.
+    fn advance(&mut self, block_number: Option<u64>) -> u64 {
+        let block_number = block_number.unwrap_or(self.latest_block);
+
+        if block_number > self.latest_block {
+            self.latest_block = block_number;
+
+            let oldest = block_number.saturating_sub(self.window_blocks);
+            self.sales.retain(|s| s.block_number >= oldest);
+            self.fundings.retain(|_, b| *b >= oldest);
+        }
+
+        block_number
+    }
+}
+
+fn normalize(address: &str) -> String {
+    FieldElement::from_hex_be(address)
+        .map(|a| to_hex_str(&a))
+        .unwrap_or_else(|_| address.to_lowercase())
+}
+
+/// The distribution only needs the order of magnitude of the prices.
+fn normalized_price(sale: &TokenSaleEvent) -> Option<f64> {
+    sale.normalized_price.as_deref()?.parse().ok()
+}
+
+fn price_key(sale: &TokenSaleEvent) -> (String, String) {
+    (
+        sale.nft_contract_address.clone(),
+        sale.currency_address.clone().unwrap_or_default(),
+    )
+}
+
+fn median(samples: &VecDeque<f64>) -> Option<f64> {
+    let mut sorted: Vec<f64> = samples.iter().copied().filter(|p| !p.is_nan()).collect();
+    if sorted.is_empty() {
+        return None;
+    }
+    sorted.sort_by(|a, b| a.total_cmp(b));
+
+    let middle = sorted.len() / 2;
+    if sorted.len() % 2 == 0 {
+        Some((sorted[middle - 1] + sorted[middle]) / 2.0)
+    } else {
+        Some(sorted[middle])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::types::EventType;
+
+    fn sale(id: &str, seller: &str, buyer: &str, block_number: u64) -> TokenSaleEvent {
+        TokenSaleEvent {
+            timestamp: 0,
+            from_address: seller.to_string(),
+            to_address: buyer.to_string(),
+            nft_contract_address: "0x1".to_string(),
+            nft_type: None,
+            marketplace_contract_address: "0xm".to_string(),
+            marketplace_name: "test".to_string(),
+            transaction_hash: "0x123".to_string(),
+            token_id: "1".to_string(),
+            token_id_hex: "0x1".to_string(),
+            event_type: EventType::Sale,
+            event_id: id.to_string(),
+            block_number: Some(block_number),
+            updated_at: None,
+            quantity: 1,
+            currency_address: Some("0xe".to_string()),
+            price: "0x1".to_string(),
+            currency_symbol: None,
+            currency_decimals: None,
+            normalized_price: Some("1".to_string()),
+            price_usd: None,
+            chain_id: "SN_TEST".to_string(),
+            wash_trade_flags: vec![],
+        }
+    }
+
+    fn kinds(flags: Vec<WashTradeFlag>) -> Vec<WashTradeKind> {
+        flags.into_iter().map(|f| f.kind).collect()
+    }
+
+    #[test]
+    fn test_self_trade() {
+        let mut detector = WashTradeDetector::new(10, 10.0, 3);
+
+        let flags = kinds(detector.analyze(&sale("0xs1", "0xa", "0x0a", 1)));
+        assert_eq!(flags, vec![WashTradeKind::SelfTrade]);
+        assert!(detector.analyze(&sale("0xs2", "0xa", "0xb", 1)).is_empty());
+    }
+
+    #[test]
+    fn test_round_trip_within_window() {
+        let mut detector = WashTradeDetector::new(10, 10.0, 3);
+
+        assert!(detector.analyze(&sale("0xs1", "0xa", "0xb", 1)).is_empty());
+        let flags = kinds(detector.analyze(&sale("0xs2", "0xb", "0xa", 5)));
+        assert_eq!(flags, vec![WashTradeKind::RoundTrip]);
+
+        // Out of the window of the first sale, but not of the second one.
+        let flags = kinds(detector.analyze(&sale("0xs3", "0xa", "0xb", 12)));
+        assert_eq!(flags, vec![WashTradeKind::RoundTrip]);
+        assert!(detector.analyze(&sale("0xs4", "0xa", "0xb", 30)).is_empty());
+    }
+
+    #[test]
+    fn test_seller_funded() {
+        let mut detector = WashTradeDetector::new(10, 10.0, 3);
+        let payment = CurrencyTransferEvent {
+            currency_address: "0xe".to_string(),
+            from_address: "0xa".to_string(),
+            to_address: "0xb".to_string(),
+            amount: "0x1".to_string(),
+            transaction_hash: "0x122".to_string(),
+        };
+
+        let sale_payment = CurrencyTransferEvent {
+            from_address: "0xd".to_string(),
+            to_address: "0xc".to_string(),
+            ..payment.clone()
+        };
+
+        detector.record_payments(&[payment, sale_payment], Some(2));
+        let flags = kinds(detector.analyze(&sale("0xs1", "0xa", "0xb", 3)));
+        assert_eq!(flags, vec![WashTradeKind::SellerFunded]);
+
+        // A payment from the buyer to the seller is the sale payment.
+        assert!(detector.analyze(&sale("0xs2", "0xc", "0xd", 3)).is_empty());
+        assert!(detector.analyze(&sale("0xs3", "0xa", "0xb", 20)).is_empty());
+    }
+
+    #[test]
+    fn test_price_outlier() {
+        let mut detector = WashTradeDetector::new(10, 10.0, 3);
+        let priced = |id: &str, price: f64| TokenSaleEvent {
+            normalized_price: Some(price.to_string()),
+            ..sale(id, &format!("0xa{id}"), &format!("0xb{id}"), 1)
+        };
+
+        // Not enough samples yet.
+        assert!(detector.analyze(&priced("1", 100.0)).is_empty());
+        for (id, price) in [("2", 1.0), ("3", 2.0), ("4", 1.5)] {
+            detector.analyze(&priced(id, price));
+        }
+
+        let flags = kinds(detector.analyze(&priced("5", 50.0)));
+        assert_eq!(flags, vec![WashTradeKind::PriceOutlier]);
+        let flags = kinds(detector.analyze(&priced("6", 0.01)));
+        assert_eq!(flags, vec![WashTradeKind::PriceOutlier]);
+        assert!(detector.analyze(&priced("7", 5.0)).is_empty());
+
+        // Other currencies have their own distribution.
+        let other_currency = TokenSaleEvent {
+            currency_address: Some("0xf".to_string()),
+            ..priced("8", 50.0)
+        };
+        assert!(detector.analyze(&other_currency).is_empty());
+    }
+
+    #[test]
+    fn test_registered_again() {
+        let mut detector = WashTradeDetector::new(10, 10.0, 3);
+
+        assert!(detector.analyze(&sale("0xs1", "0xa", "0xb", 1)).is_empty());
+        assert!(detector.analyze(&sale("0xs1", "0xa", "0xb", 1)).is_empty());
+        assert_eq!(detector.sales.len(), 1);
+    }
+}
@@ -0,0 +1,179 @@
+//! Tests of the webhook event handler, against a local HTTP stub.
+//!
+//! ```sh
+//! cargo test -p pontos --features webhook --test webhook
+//! ```
+#![cfg(feature = "webhook")]
+
+use pontos::event_handler::webhook::{signature, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+use pontos::event_handler::{EventHandler, WebhookEndpoint, WebhookEventHandler, WebhookRetry};
+use pontos::storage::types::{TokenEvent, TokenTransferEvent};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+#[derive(Debug, Clone)]
+struct Request {
+    headers: HashMap<String, String>,
+    body: String,
+}
+
+/// HTTP server answering the given statuses in order,
+/// and `200` once they are all used.
+struct Stub {
+    url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl Stub {
+    async fn start(statuses: Vec<u16>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let statuses = Arc::new(Mutex::new(statuses.into_iter()));
+
+        let recorded = Arc::clone(&requests);
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let request = read_request(&mut socket).await;
+                recorded.lock().unwrap().push(request);
+
+                let status = statuses.lock().unwrap().next().unwrap_or(200);
+                let response = format!(
+                    "HTTP/1.1 {} Stub\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    status
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+                socket.shutdown().await.ok();
+            }
+        });
+
+        Stub { url, requests }
+    }
+
+    fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn read_request(socket: &mut tokio::net::TcpStream) -> Request {
+    let mut data = vec![];
+    let mut buf = [0_u8; 4096];
+
+    let header_end = loop {
+        let n = socket.read(&mut buf).await.unwrap();
+        data.extend_from_slice(&buf[..n]);
+
+        if let Some(pos) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&data[..header_end]).to_string();
+    let headers: HashMap<String, String> = head
+        .lines()
+        .skip(1)
+        .filter_map(|l| l.split_once(": "))
+        .map(|(k, v)| (k.to_lowercase(), v.to_string()))
+        .collect();
+
+    let length: usize = headers
+        .get("content-length")
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(0);
+
+    while data.len() < header_end + length {
+        let n = socket.read(&mut buf).await.unwrap();
+        data.extend_from_slice(&buf[..n]);
+    }
+
+    Request {
+        headers,
+        body: String::from_utf8_lossy(&data[header_end..header_end + length]).to_string(),
+    }
+}
+
+fn transfer(contract_address: &str) -> TokenEvent {
+    TokenEvent::Transfer(TokenTransferEvent {
+        contract_address: contract_address.to_string(),
+        event_id: "0x1".to_string(),
+        token_id: "1".to_string(),
+        ..Default::default()
+    })
+}
+
+fn fast_retry(max_attempts: u32) -> WebhookRetry {
+    WebhookRetry {
+        max_attempts,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(5),
+    }
+}
+
+#[tokio::test]
+async fn test_signed_and_filtered_delivery() {
+    let stub = Stub::start(vec![]).await;
+    let handler = WebhookEventHandler::new(vec![
+        WebhookEndpoint::new(&stub.url, "secret").with_collection("0x0abc")
+    ]);
+
+    handler.on_event_registered(transfer("0xabc")).await;
+    handler.on_event_registered(transfer("0xdef")).await;
+
+    let requests = stub.requests();
+    assert_eq!(requests.len(), 1);
+
+    let request = &requests[0];
+    let timestamp = &request.headers[&TIMESTAMP_HEADER.to_lowercase()];
+    assert_eq!(
+        request.headers[&SIGNATURE_HEADER.to_lowercase()],
+        signature("secret", timestamp, &request.body)
+    );
+
+    let payload: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+    assert_eq!(payload["type"], "transfer");
+    assert_eq!(payload["id"], "0x1");
+    assert_eq!(payload["event"]["contract_address"], "0xabc");
+}
+
+#[tokio::test]
+async fn test_retries_server_errors() {
+    let stub = Stub::start(vec![500, 503]).await;
+    let handler = WebhookEventHandler::new(vec![WebhookEndpoint::new(&stub.url, "secret")])
+        .with_retry(fast_retry(3));
+
+    handler.on_event_registered(transfer("0xabc")).await;
+
+    assert_eq!(stub.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn test_dead_letters_are_replayed() {
+    let dead_letters = std::env::temp_dir().join(format!(
+        "pontos-webhook-dead-letters-{}.ndjson",
+        std::process::id()
+    ));
+    std::fs::remove_file(&dead_letters).ok();
+
+    // Client errors are not retried.
+    let stub = Stub::start(vec![500, 500, 400]).await;
+    let handler = WebhookEventHandler::new(vec![WebhookEndpoint::new(&stub.url, "secret")])
+        .with_retry(fast_retry(2))
+        .with_dead_letter_file(&dead_letters);
+
+    handler.on_event_registered(transfer("0xabc")).await;
+    handler.on_event_registered(transfer("0xdef")).await;
+    assert_eq!(stub.requests().len(), 3);
+
+    let content = std::fs::read_to_string(&dead_letters).unwrap();
+    assert_eq!(content.lines().count(), 2);
+
+    assert_eq!(handler.replay_dead_letters().await.unwrap(), 2);
+    assert_eq!(std::fs::read_to_string(&dead_letters).unwrap(), "");
+    assert_eq!(stub.requests().len(), 5);
+
+    std::fs::remove_file(&dead_letters).ok();
+}
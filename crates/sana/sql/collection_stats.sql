@@ -0,0 +1,74 @@
+-- Tables of the collection aggregates maintained by `PostgresStorage`.
+-- Sana does not manage its schema: apply this file once on the database,
+-- it is idempotent and fills the aggregates of the data already indexed.
+-- The volumes are summed from `token_event.price_usd`, so `token_event_price_usd.sql`
+-- must be applied first.
+
+-- Owners and all time activity. The volumes are exact decimals, as they
+-- are updated by each sale and each cleaned block.
+CREATE TABLE IF NOT EXISTS collection_stats (
+    contract_address TEXT NOT NULL,
+    chain_id TEXT NOT NULL,
+    owners_count BIGINT NOT NULL DEFAULT 0,
+    supply BIGINT NOT NULL DEFAULT 0,
+    sales_count BIGINT NOT NULL DEFAULT 0,
+    volume_usd NUMERIC NOT NULL DEFAULT 0,
+    mint_count BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (contract_address, chain_id)
+);
+
+-- Activity by hour, summed on read for the rolling periods.
+CREATE TABLE IF NOT EXISTS collection_stats_hourly (
+    contract_address TEXT NOT NULL,
+    chain_id TEXT NOT NULL,
+    hour_timestamp BIGINT NOT NULL,
+    sales_count BIGINT NOT NULL DEFAULT 0,
+    volume_usd NUMERIC NOT NULL DEFAULT 0,
+    mint_count BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (contract_address, chain_id, hour_timestamp)
+);
+
+-- Tokens held by each owner, to count the distinct owners.
+-- The owners whose last token left keep a row with a count of zero.
+CREATE TABLE IF NOT EXISTS collection_owner (
+    contract_address TEXT NOT NULL,
+    chain_id TEXT NOT NULL,
+    owner TEXT NOT NULL,
+    token_count BIGINT NOT NULL,
+    PRIMARY KEY (contract_address, chain_id, owner)
+);
+
+-- Aggregates of the data indexed before.
+-- The zero address, once its "0x" and leading zeros trimmed, is empty.
+INSERT INTO collection_owner (contract_address, chain_id, owner, token_count)
+SELECT contract_address, chain_id, current_owner, COUNT(*)
+FROM token
+WHERE ltrim(current_owner, '0x') <> ''
+GROUP BY contract_address, chain_id, current_owner
+ON CONFLICT DO NOTHING;
+
+INSERT INTO collection_stats (contract_address, chain_id, owners_count, supply, sales_count, volume_usd, mint_count)
+SELECT contract_address, chain_id, SUM(owners_count), SUM(supply), SUM(sales_count), SUM(volume_usd), SUM(mint_count)
+FROM (
+    SELECT contract_address, chain_id, COUNT(*) AS owners_count, SUM(token_count) AS supply,
+    0 AS sales_count, 0 AS volume_usd, 0 AS mint_count
+    FROM collection_owner WHERE token_count > 0 GROUP BY contract_address, chain_id
+    UNION ALL
+    SELECT contract_address, chain_id, 0, 0,
+    COUNT(*) FILTER (WHERE event_type = 'Sale'),
+    COALESCE(SUM(price_usd) FILTER (WHERE event_type = 'Sale'), 0),
+    COUNT(*) FILTER (WHERE event_type = 'Mint')
+    FROM token_event WHERE event_type IN ('Sale', 'Mint') GROUP BY contract_address, chain_id
+) stats
+GROUP BY contract_address, chain_id
+ON CONFLICT DO NOTHING;
+
+INSERT INTO collection_stats_hourly (contract_address, chain_id, hour_timestamp, sales_count, volume_usd, mint_count)
+SELECT contract_address, chain_id, block_timestamp - block_timestamp % 3600,
+COUNT(*) FILTER (WHERE event_type = 'Sale'),
+COALESCE(SUM(price_usd) FILTER (WHERE event_type = 'Sale'), 0),
+COUNT(*) FILTER (WHERE event_type = 'Mint')
+FROM token_event
+WHERE event_type IN ('Sale', 'Mint')
+GROUP BY 1, 2, 3
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,4 @@
+-- USD value of the sales, computed with the price oracle of the indexer.
+-- Sana does not manage its schema: apply this file once on the database,
+-- it is idempotent. The sales indexed before have no USD value.
+ALTER TABLE token_event ADD COLUMN IF NOT EXISTS price_usd NUMERIC;
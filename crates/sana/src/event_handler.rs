@@ -1,6 +1,7 @@
 //! Trait related to any events that Sana can emit to be handled.
-use crate::storage::types::{TokenEvent, TokenInfo};
+use crate::storage::types::{ContractInfo, TokenEvent, TokenInfo, TokenSaleEvent};
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 
 /// A trait to be implemented in order to handle
 /// events emitted by Sana, in an external code.
@@ -29,12 +30,83 @@ pub trait EventHandler {
     /// Invoked when Sana has successfully indexed a range of blocks up to the given block number.
     async fn on_indexation_range_completed(&self) {}
 
-    /// A new token has be registered.
+    /// A token has been registered, after a transfer.
     async fn on_token_registered(&self, token: TokenInfo) {}
 
-    /// A new event has be registered.
+    /// A token event (transfer or sale) has been registered.
     async fn on_event_registered(&self, event: TokenEvent) {}
 
+    /// A marketplace sale has been registered.
+    /// Also emitted by `on_event_registered`.
+    async fn on_sale_registered(&self, sale: TokenSaleEvent) {}
+
+    /// A contract seen for the first time has been identified and registered.
+    async fn on_contract_identified(&self, contract: ContractInfo) {}
+
+    /// A block of the range has not been indexed.
+    async fn on_block_skipped(&self, block_number: u64, reason: BlockSkipReason) {}
+
+    /// An event couldn't be indexed. The other events of
+    /// the block are still indexed.
+    async fn on_event_failed(&self, failure: EventFailure) {}
+
     // A new latest block has been detected.
     async fn on_new_latest_block(&self, block_number: u64) {}
 }
+
+/// A callback of the `EventHandler` for an event of a block,
+/// with its arguments, held until the block is committed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HandlerEvent {
+    TokenRegistered(TokenInfo),
+    EventRegistered(TokenEvent),
+    SaleRegistered(TokenSaleEvent),
+    ContractIdentified(ContractInfo),
+    EventFailed(EventFailure),
+}
+
+impl HandlerEvent {
+    /// Invokes the callback of the handler matching the event.
+    pub async fn dispatch<E: EventHandler + Sync + ?Sized>(self, handler: &E) {
+        match self {
+            HandlerEvent::TokenRegistered(token) => handler.on_token_registered(token).await,
+            HandlerEvent::EventRegistered(event) => handler.on_event_registered(event).await,
+            HandlerEvent::SaleRegistered(sale) => handler.on_sale_registered(sale).await,
+            HandlerEvent::ContractIdentified(contract) => {
+                handler.on_contract_identified(contract).await
+            }
+            HandlerEvent::EventFailed(failure) => handler.on_event_failed(failure).await,
+        }
+    }
+}
+
+/// Why a block has not been indexed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockSkipReason {
+    /// The block is already indexed by this indexer version.
+    AlreadyIndexed,
+    /// The timestamp of the block couldn't be fetched after all the retries.
+    TimestampUnavailable { attempts: u32 },
+}
+
+/// Step of the indexation at which an event failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventStage {
+    MarketplaceSale,
+    Transfer,
+}
+
+/// An event that couldn't be indexed, with its context.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventFailure {
+    pub stage: EventStage,
+    pub chain_id: String,
+    pub block_number: Option<u64>,
+    pub block_timestamp: u64,
+    pub transaction_hash: String,
+    /// Address of the contract emitting the event.
+    pub contract_address: String,
+    pub error: String,
+}
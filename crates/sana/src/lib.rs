@@ -1,19 +1,27 @@
+pub mod config;
 pub mod event_handler;
 pub mod managers;
 pub mod storage;
 
 use crate::storage::types::BlockIndexingStatus;
+pub use ark_starknet::price_oracle;
+pub use config::SanaConfig;
+
 use anyhow::Result;
 use ark_starknet::client::{StarknetClient, StarknetClientError};
 use ark_starknet::format::to_hex_str;
-use event_handler::EventHandler;
-use managers::{BlockManager, ContractManager, EventManager, PendingBlockData, TokenManager};
+use event_handler::{BlockSkipReason, EventFailure, EventHandler, EventStage, HandlerEvent};
+use managers::{
+    BlockManager, ContractManager, CurrencyManager, EventManager, PendingBlockData, TokenManager,
+};
+use price_oracle::PriceOracle;
 use starknet::core::types::*;
+use std::collections::HashMap;
 use std::fmt;
 use std::sync::Arc;
-use storage::types::{ContractType, StorageError};
+use storage::types::{ContractType, StorageError, TokenEvent, TokenSaleEvent};
 use storage::Storage;
-use tokio::sync::RwLock as AsyncRwLock;
+use tokio::sync::{Mutex as AsyncMutex, RwLock as AsyncRwLock};
 use tracing::{debug, error, info, trace, warn};
 
 pub type IndexerResult<T> = Result<T, IndexerError>;
@@ -24,6 +32,10 @@ const ELEMENT_MARKETPLACE_EVENT_HEX: &str =
 const VENTORY_MARKETPLACE_EVENT_HEX: &str =
     "0x1b43f40d55364e989b3a8674460f61ba8f327542298ee6240a54ee2bf7b55bb"; // EventListingBought
 
+/// Ventory sales are always paid in STRK, the currency is not part of the event.
+const VENTORY_MARKETPLACE_CURRENCY_HEX: &str =
+    "0x04718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d";
+
 /// Generic errors for Sana.
 #[derive(Debug)]
 pub enum IndexerError {
@@ -62,11 +74,6 @@ impl fmt::Display for IndexerError {
 
 impl std::error::Error for IndexerError {}
 
-pub struct SanaConfig {
-    pub indexer_version: String,
-    pub indexer_identifier: String,
-}
-
 pub struct Sana<S: Storage, C: StarknetClient, E: EventHandler> {
     client: Arc<C>,
     event_handler: Arc<E>,
@@ -75,30 +82,54 @@ pub struct Sana<S: Storage, C: StarknetClient, E: EventHandler> {
     event_manager: Arc<EventManager<S>>,
     token_manager: Arc<TokenManager<S, C>>,
     contract_manager: Arc<AsyncRwLock<ContractManager<S, C>>>,
+    currency_manager: Arc<AsyncRwLock<CurrencyManager<C>>>,
+    price_oracle: Option<Arc<dyn PriceOracle + Send + Sync>>,
     pending_cache: Arc<AsyncRwLock<PendingBlockData>>,
+    marketplace_contracts: Vec<FieldElement>,
+    /// Callbacks of the block being applied, emitted once its unit of
+    /// work is committed. `None` outside of a unit of work.
+    block_events: Arc<AsyncMutex<Option<Vec<HandlerEvent>>>>,
+    /// Held while events are applied. The unit of work of the storage and
+    /// `block_events` are per instance, so concurrent calls of this instance
+    /// are applied one block at a time instead of sharing a transaction.
+    unit_of_work: Arc<AsyncMutex<()>>,
 }
 
 impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E> {
     ///
     pub fn new(client: Arc<C>, storage: Arc<S>, event_handler: Arc<E>, config: SanaConfig) -> Self {
         Sana {
-            config,
             client: Arc::clone(&client),
             event_handler: Arc::clone(&event_handler),
             block_manager: Arc::new(BlockManager::new(Arc::clone(&storage))),
-            event_manager: Arc::new(EventManager::new(Arc::clone(&storage))),
+            event_manager: Arc::new(
+                EventManager::new(Arc::clone(&storage))
+                    .with_ventory_currency_address(&config.ventory_currency_address),
+            ),
             token_manager: Arc::new(TokenManager::new(Arc::clone(&storage), Arc::clone(&client))),
             // Contract manager has internal cache, so some functions are using `&mut self`.
             // For this reason, we must protect the write operations in order to share
-            // the cache with any possible thread using `index_block_range` of this instance.
+            // the cache between the tasks of this instance.
             contract_manager: Arc::new(AsyncRwLock::new(ContractManager::new(
                 Arc::clone(&storage),
                 Arc::clone(&client),
             ))),
+            currency_manager: Arc::new(AsyncRwLock::new(CurrencyManager::new(Arc::clone(&client)))),
+            price_oracle: None,
             pending_cache: Arc::new(AsyncRwLock::new(PendingBlockData::new())),
+            marketplace_contracts: crate::config::parse_addresses(&config.marketplace_addresses),
+            block_events: Arc::new(AsyncMutex::new(None)),
+            unit_of_work: Arc::new(AsyncMutex::new(())),
+            config,
         }
     }
 
+    /// Sets the oracle used to compute the USD value of the sales.
+    pub fn with_price_oracle(mut self, price_oracle: Arc<dyn PriceOracle + Send + Sync>) -> Self {
+        self.price_oracle = Some(price_oracle);
+        self
+    }
+
     /// Starts a loop to only index the pending block.
     pub async fn index_pending(&self) -> IndexerResult<()> {
         loop {
@@ -112,7 +143,7 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
                 Ok((ts, txs)) => (ts, txs),
                 Err(e) => {
                     error!("Error while fetching pending block txs: {:?}", e);
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                    tokio::time::sleep(self.config.retry_delay()).await;
                     continue;
                 }
             };
@@ -136,7 +167,7 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
                     Ok(n) => n,
                     Err(e) => {
                         error!("Error while fetching latest block number: {:?}", e);
-                        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                        tokio::time::sleep(self.config.retry_delay()).await;
                         continue;
                     }
                 };
@@ -154,8 +185,7 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
                 cache.clear_tx_hashes();
             }
 
-            // TODO: make this configurable?
-            tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+            tokio::time::sleep(self.config.pending_poll_interval()).await;
         }
     }
 
@@ -179,12 +209,11 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
         // Some contracts are causing too much recursion for the Cairo VM.
         // This is restarting the full node (Juno) as it is OOM and is shutdown by the OS.
         // To mitigate this problem before scaling the full node up,
-        // we setup a `max_attempt` to reach the full node before skipping
+        // we setup a `max_block_retries` to reach the full node before skipping
         // the entire block.
         // Currently, we observed that the node almost always reponds after the
         // second attempt.
-        let max_attempt = 5;
-        let mut attempt = 0;
+        let mut attempt: u32 = 0;
 
         loop {
             trace!("Indexing block range: {} {}", current_u64, to_u64);
@@ -195,7 +224,10 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
             }
 
             let block_ts = match self.client.block_time(BlockId::Number(current_u64)).await {
-                Ok(ts) => ts,
+                Ok(ts) => {
+                    attempt = 0;
+                    ts
+                }
                 Err(e) => {
                     error!(
                         "Attempt #{} - Couldn't get timestamp for block {}: {:?}",
@@ -203,14 +235,21 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
                         current_u64,
                         e
                     );
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                    tokio::time::sleep(self.config.retry_delay()).await;
                     attempt += 1;
 
-                    if attempt > max_attempt {
+                    if attempt > self.config.max_block_retries {
                         warn!(
                             "Skipping block {} as timestamp is not available",
                             current_u64
                         );
+                        self.event_handler
+                            .on_block_skipped(
+                                current_u64,
+                                BlockSkipReason::TimestampUnavailable { attempts: attempt },
+                            )
+                            .await;
+                        attempt = 0;
                         current_u64 += 1;
                     }
 
@@ -229,6 +268,9 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
                 .await?
             {
                 info!("Skipping block {}", current_u64);
+                self.event_handler
+                    .on_block_skipped(current_u64, BlockSkipReason::AlreadyIndexed)
+                    .await;
                 current_u64 += 1;
                 continue;
             }
@@ -259,7 +301,7 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
                 Ok(events) => events,
                 Err(e) => {
                     error!("Error while fetching events: {:?}", e);
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                    tokio::time::sleep(self.config.retry_delay()).await;
                     continue;
                 }
             };
@@ -270,19 +312,21 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
                 current_u64, total_events_count
             );
 
-            for (_, events) in blocks_events {
-                self.process_events(events, block_ts, chain_id).await?;
+            // The block is applied in a single unit of work, to never
+            // leave a partially indexed block in the storage.
+            let _unit_of_work = self.unit_of_work.lock().await;
+            self.begin_block().await?;
+
+            if let Err(e) = self
+                .apply_block_events(blocks_events, current_u64, block_ts, chain_id)
+                .await
+            {
+                error!("Error while indexing block {}: {:?}", current_u64, e);
+                self.rollback_block().await?;
+                return Err(e);
             }
 
-            self.block_manager
-                .set_block_info(
-                    current_u64,
-                    block_ts,
-                    self.config.indexer_version.clone(),
-                    self.config.indexer_identifier.clone(),
-                    BlockIndexingStatus::Terminated,
-                )
-                .await?;
+            self.commit_block().await?;
 
             let progress = if to_u64 == from_u64 {
                 if current_u64 == to_u64 {
@@ -306,6 +350,45 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
         Ok(())
     }
 
+    /// Opens the unit of work of a block.
+    async fn begin_block(&self) -> IndexerResult<()> {
+        self.block_manager.begin_unit_of_work().await?;
+        *self.block_events.lock().await = Some(vec![]);
+        Ok(())
+    }
+
+    /// Commits the unit of work of a block, then emits its callbacks.
+    async fn commit_block(&self) -> IndexerResult<()> {
+        let events = self.block_events.lock().await.take().unwrap_or_default();
+        self.block_manager.commit_unit_of_work().await?;
+
+        for event in events {
+            event.dispatch(self.event_handler.as_ref()).await;
+        }
+
+        Ok(())
+    }
+
+    /// Rolls back the unit of work of a block, with the state this instance
+    /// built while applying it: the callbacks and the cached contracts.
+    async fn rollback_block(&self) -> IndexerResult<()> {
+        self.block_events.lock().await.take();
+        self.block_manager.rollback_unit_of_work().await?;
+        self.contract_manager.write().await.reset_caches();
+        Ok(())
+    }
+
+    /// Emits a callback of the event handler, or holds it until the commit
+    /// when the unit of work of a block is open.
+    async fn emit(&self, event: HandlerEvent) {
+        if let Some(events) = self.block_events.lock().await.as_mut() {
+            events.push(event);
+            return;
+        }
+
+        event.dispatch(self.event_handler.as_ref()).await;
+    }
+
     pub async fn index_pending_block(&self, timestamp: u64, chain_id: &str) -> IndexerResult<()> {
         let blocks_events = match self
             .client
@@ -322,6 +405,8 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
         let total_events_count: usize = blocks_events.values().map(|events| events.len()).sum();
         trace!("Number of events: {:?}", total_events_count);
 
+        let _unit_of_work = self.unit_of_work.lock().await;
+
         for (_, events) in blocks_events {
             self.process_events(events, timestamp, chain_id).await?;
         }
@@ -375,8 +460,10 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
         }
 
         token_sale_event.nft_type = Some(contract_type.to_string());
-        self.event_manager
-            .register_sale_event(&token_sale_event, block_timestamp)
+        self.normalize_sale_price(&mut token_sale_event).await;
+        self.compute_sale_usd_price(&mut token_sale_event).await;
+
+        self.register_sale(&token_sale_event, block_timestamp)
             .await?;
 
         Ok(())
@@ -429,13 +516,102 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
         }
 
         token_sale_event.nft_type = Some(contract_type.to_string());
+        self.normalize_sale_price(&mut token_sale_event).await;
+        self.compute_sale_usd_price(&mut token_sale_event).await;
+
+        self.register_sale(&token_sale_event, block_timestamp)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Registers the sale, and emits it to the event handler.
+    async fn register_sale(&self, sale: &TokenSaleEvent, block_timestamp: u64) -> Result<()> {
         self.event_manager
-            .register_sale_event(&token_sale_event, block_timestamp)
+            .register_sale_event(sale, block_timestamp)
             .await?;
 
+        self.emit(HandlerEvent::SaleRegistered(sale.clone())).await;
+        self.emit(HandlerEvent::EventRegistered(TokenEvent::Sale(
+            sale.clone(),
+        )))
+        .await;
+
         Ok(())
     }
 
+    /// Emits the NFT contracts identified since the last call.
+    async fn emit_identified_contracts(&self) {
+        let contracts = self.contract_manager.write().await.take_identified();
+
+        for contract in contracts {
+            self.emit(HandlerEvent::ContractIdentified(contract)).await;
+        }
+    }
+
+    /// Resolves the sale currency and normalizes the price with its decimals.
+    /// A sale is still registered if the currency can't be resolved.
+    async fn normalize_sale_price(&self, token_sale_event: &mut TokenSaleEvent) {
+        let Some(currency_address) = token_sale_event.currency_address.clone() else {
+            warn!(
+                "Couldn't normalize price of sale {}: sale has no currency",
+                token_sale_event.transaction_hash
+            );
+            return;
+        };
+
+        match self
+            .currency_manager
+            .write()
+            .await
+            .normalize_price(&currency_address, &token_sale_event.price)
+            .await
+        {
+            Ok((currency, normalized_price)) => {
+                token_sale_event.currency_symbol = currency.symbol;
+                token_sale_event.currency_decimals = Some(currency.decimals);
+                token_sale_event.normalized_price = Some(normalized_price);
+            }
+            Err(e) => warn!(
+                "Couldn't normalize price of sale {} (currency: {:?}): {:?}",
+                token_sale_event.transaction_hash, currency_address, e
+            ),
+        }
+    }
+
+    /// Computes the USD value of the sale at the time of the sale,
+    /// if a price oracle is configured.
+    async fn compute_sale_usd_price(&self, token_sale_event: &mut TokenSaleEvent) {
+        let oracle = match &self.price_oracle {
+            Some(oracle) => oracle,
+            None => return,
+        };
+
+        // The USD value is an estimate from the rate, a float is enough.
+        if let (Some(currency_address), Some(normalized_price)) = (
+            &token_sale_event.currency_address,
+            token_sale_event
+                .normalized_price
+                .as_deref()
+                .and_then(|price| price.parse::<f64>().ok()),
+        ) {
+            match oracle
+                .get_usd_rate(currency_address, token_sale_event.block_timestamp)
+                .await
+            {
+                Ok(Some(rate)) => token_sale_event.price_usd = Some(normalized_price * rate),
+                Ok(None) => debug!(
+                    "No USD rate for currency {} at {}",
+                    currency_address, token_sale_event.block_timestamp
+                ),
+                Err(e) => warn!(
+                    "Price oracle failed for currency {}: {:?}",
+                    currency_address, e
+                ),
+            }
+        }
+    }
+
     async fn process_marketplace_event(
         &self,
         event: EmittedEvent,
@@ -507,7 +683,8 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
                 err
             })?;
 
-        self.token_manager
+        let token = self
+            .token_manager
             .format_and_register_token(&token_id, &token_event, block_timestamp, event.block_number)
             .await
             .map_err(|err| {
@@ -516,13 +693,45 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
             })?;
 
         self.event_manager
-            .format_and_register_event(token_event)
+            .format_and_register_event(token_event.clone())
             .await
             .map_err(|err| {
                 error!("Error while registering event {:?}\n{:?}", err, event);
                 err
             })?;
 
+        self.emit(HandlerEvent::TokenRegistered(token)).await;
+        self.emit(HandlerEvent::EventRegistered(TokenEvent::Transfer(
+            token_event,
+        )))
+        .await;
+
+        Ok(())
+    }
+
+    /// Processes all the events of a block, and marks the block as terminated.
+    async fn apply_block_events(
+        &self,
+        blocks_events: HashMap<u64, Vec<EmittedEvent>>,
+        block_number: u64,
+        block_timestamp: u64,
+        chain_id: &str,
+    ) -> IndexerResult<()> {
+        for (_, events) in blocks_events {
+            self.process_events(events, block_timestamp, chain_id)
+                .await?;
+        }
+
+        self.block_manager
+            .set_block_info(
+                block_number,
+                block_timestamp,
+                self.config.indexer_version.clone(),
+                self.config.indexer_identifier.clone(),
+                BlockIndexingStatus::Terminated,
+            )
+            .await?;
+
         Ok(())
     }
 
@@ -533,20 +742,19 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
         block_timestamp: u64,
         chain_id: &str,
     ) -> IndexerResult<()> {
-        let marketplace_contracts = [
-            FieldElement::from_hex_be(
-                "0x04d8bb956e6bd7a50fcb8b49d8e9fd8269cfadbeb73f457fd6d3fc1dff4b879e", // Element Marketplace
-            )
-            .unwrap(),
-            FieldElement::from_hex_be(
-                "0x008755a98ccf7d25e69aa90ef3b73b07c470ba4ec6391b0b0c7c598f992c3fee", // Ventory Marketplace
-            )
-            .unwrap(),
-        ];
-
         for e in events {
             let contract_address = e.from_address;
-            let is_marketplace_event = marketplace_contracts.contains(&contract_address);
+            let is_marketplace_event = self.marketplace_contracts.contains(&contract_address);
+
+            let failure = |stage: EventStage, error: anyhow::Error| EventFailure {
+                stage,
+                chain_id: chain_id.to_string(),
+                block_number: e.block_number,
+                block_timestamp,
+                transaction_hash: to_hex_str(&e.transaction_hash),
+                contract_address: to_hex_str(&contract_address),
+                error: format!("{:#}", error),
+            };
 
             if is_marketplace_event {
                 if let Err(err) = self
@@ -554,15 +762,27 @@ impl<S: Storage, C: StarknetClient, E: EventHandler + Send + Sync> Sana<S, C, E>
                     .await
                 {
                     error!("Error while processing marketplace event: {:?}", err);
+                    self.emit(HandlerEvent::EventFailed(failure(
+                        EventStage::MarketplaceSale,
+                        err,
+                    )))
+                    .await;
                 }
             }
 
-            if let Err(e) = self
+            if let Err(err) = self
                 .process_nft_transfers(e.clone(), block_timestamp, contract_address, chain_id)
                 .await
             {
-                error!("Error while processing NFT transfers: {:?}", e);
+                error!("Error while processing NFT transfers: {:?}", err);
+                self.emit(HandlerEvent::EventFailed(failure(
+                    EventStage::Transfer,
+                    err,
+                )))
+                .await;
             }
+
+            self.emit_identified_contracts().await;
         }
 
         Ok(())
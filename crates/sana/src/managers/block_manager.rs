@@ -26,6 +26,19 @@ impl<S: Storage> BlockManager<S> {
             .await
     }
 
+    /// Starts the unit of work in which a block is indexed.
+    pub async fn begin_unit_of_work(&self) -> Result<(), StorageError> {
+        self.storage.begin_unit_of_work().await
+    }
+
+    pub async fn commit_unit_of_work(&self) -> Result<(), StorageError> {
+        self.storage.commit_unit_of_work().await
+    }
+
+    pub async fn rollback_unit_of_work(&self) -> Result<(), StorageError> {
+        self.storage.rollback_unit_of_work().await
+    }
+
     /// Returns false if the given block number must be indexed.
     /// True otherwise.
     pub async fn should_skip_indexing(
@@ -21,6 +21,8 @@ pub struct ContractManager<S: Storage, C: StarknetClient> {
     client: Arc<C>,
     /// A cache with contract address mapped to its type.
     cache: HashMap<FieldElement, ContractType>,
+    /// NFT contracts registered since the last `take_identified`.
+    identified: Vec<ContractInfo>,
 }
 
 impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
@@ -30,6 +32,7 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
             storage,
             client,
             cache: HashMap::new(),
+            identified: vec![],
         }
     }
 
@@ -121,15 +124,17 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
                     image: None,
                 };
 
-                if let Err(e) = self
+                match self
                     .storage
                     .register_contract_info(&info, block_timestamp)
                     .await
                 {
-                    error!(
+                    Ok(()) if contract_type != ContractType::Other => self.identified.push(info),
+                    Ok(()) => {}
+                    Err(e) => error!(
                         "Failed to store contract info for [0x{:064x}]: {:?}",
                         address, e
-                    );
+                    ),
                 }
 
                 Ok(contract_type)
@@ -137,6 +142,18 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
         }
     }
 
+    /// Returns the NFT contracts identified and registered since the last call.
+    pub fn take_identified(&mut self) -> Vec<ContractInfo> {
+        std::mem::take(&mut self.identified)
+    }
+
+    /// Drops the cached contracts when the unit of work of a block is rolled
+    /// back: those of the block are not stored anymore, and are identified again.
+    pub fn reset_caches(&mut self) {
+        self.cache.clear();
+        self.identified.clear();
+    }
+
     /// Verifies if the contract is an ERC721, ERC1155 or an other type.
     /// `owner_of` is specific to ERC721.
     /// `balance_of` is specific to ERC1155 and different from ERC20 as 2 arguments are expected.
@@ -172,6 +189,7 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
                     }
                 }
                 StarknetClientError::EntrypointNotFound(_) => (),
+                e @ StarknetClientError::Provider(_) => return Err(e.into()),
                 _ => return Ok(false),
             },
         };
@@ -191,6 +209,7 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
                     }
                 }
                 StarknetClientError::EntrypointNotFound(_) => Ok(false),
+                e @ StarknetClientError::Provider(_) => Err(e.into()),
                 _ => Ok(false),
             },
         }
@@ -215,6 +234,7 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
             Err(e) => match e {
                 StarknetClientError::EntrypointNotFound(_) => (),
                 StarknetClientError::InputTooLong => return Ok(false), // ERC20.
+                e @ StarknetClientError::Provider(_) => return Err(e.into()),
                 _ => return Ok(false),
             },
         };
@@ -227,6 +247,7 @@ impl<S: Storage, C: StarknetClient> ContractManager<S, C> {
             Err(e) => match e {
                 StarknetClientError::EntrypointNotFound(_) => Ok(false),
                 StarknetClientError::InputTooLong => Ok(false), // ERC20.
+                e @ StarknetClientError::Provider(_) => Err(e.into()),
                 _ => Ok(false),
             },
         }
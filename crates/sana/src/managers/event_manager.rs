@@ -1,6 +1,6 @@
 use crate::storage::types::{EventType, TokenSaleEvent, TokenTransferEvent};
 use crate::storage::Storage;
-use crate::{ContractType, VENTORY_MARKETPLACE_EVENT_HEX};
+use crate::{ContractType, VENTORY_MARKETPLACE_CURRENCY_HEX, VENTORY_MARKETPLACE_EVENT_HEX};
 use anyhow::{anyhow, Result};
 use ark_starknet::{format::to_hex_str, CairoU256};
 use starknet::core::types::{EmittedEvent, FieldElement};
@@ -17,6 +17,7 @@ const ELEMENT_NFT_MARKETPLACE_HEX: &str =
 #[derive(Debug)]
 pub struct EventManager<S: Storage> {
     storage: Arc<S>,
+    ventory_currency_address: String,
 }
 
 impl<S: Storage> EventManager<S> {
@@ -24,9 +25,16 @@ impl<S: Storage> EventManager<S> {
     pub fn new(storage: Arc<S>) -> Self {
         EventManager {
             storage: Arc::clone(&storage),
+            ventory_currency_address: VENTORY_MARKETPLACE_CURRENCY_HEX.to_string(),
         }
     }
 
+    /// Sets the currency of the Ventory sales.
+    pub fn with_ventory_currency_address(mut self, address: &str) -> Self {
+        self.ventory_currency_address = address.to_string();
+        self
+    }
+
     /// Returns the selectors used to filter events.
     pub fn keys_selector(&self) -> Option<Vec<Vec<FieldElement>>> {
         let element_nft_marketplace = FieldElement::from_hex_be(ELEMENT_NFT_MARKETPLACE_HEX)
@@ -88,12 +96,9 @@ impl<S: Storage> EventManager<S> {
             .get(6)
             .ok_or_else(|| anyhow!("Status not found"))?;
 
-        let token_id = CairoU256 {
-            low: (*token_id)
-                .try_into()
-                .map_err(|_| anyhow!("Failed to parse token id"))?,
-            high: 0,
-        };
+        // Ventory emits the token id as a single felt, which may
+        // be greater than a u128.
+        let token_id = CairoU256::from_felt(token_id);
 
         let event_id = Self::get_event_id(&token_id, seller, buyer, block_timestamp, event);
 
@@ -111,12 +116,14 @@ impl<S: Storage> EventManager<S> {
             block_timestamp,
             updated_at: Some(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()),
             quantity: 1,
-            currency_address: Some(
-                "0x04718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d".to_string(),
-            ), // STRK TOKEN
+            currency_address: Some(self.ventory_currency_address.clone()),
             marketplace_contract_address: to_hex_str(&event.from_address),
             marketplace_name: "Ventory".to_string(),
-            price: to_hex_str(price),
+            price: CairoU256::from_felt(price).to_hex(),
+            currency_symbol: None,
+            currency_decimals: None,
+            normalized_price: None,
+            price_usd: None,
             chain_id: chain_id.to_string(),
         })
     }
@@ -229,7 +236,11 @@ impl<S: Storage> EventManager<S> {
             currency_address: Some(to_hex_str(currency_address)),
             marketplace_contract_address: to_hex_str(&event.from_address),
             marketplace_name: "Element".to_string(),
-            price: to_hex_str(price),
+            price: CairoU256::from_felt(price).to_hex(),
+            currency_symbol: None,
+            currency_decimals: None,
+            normalized_price: None,
+            price_usd: None,
             chain_id: chain_id.to_string(),
         })
     }
@@ -9,3 +9,5 @@ pub use token_manager::TokenManager;
 
 pub mod block_manager;
 pub use block_manager::{BlockManager, PendingBlockData};
+
+pub use ark_starknet::currency::CurrencyManager;
@@ -0,0 +1,766 @@
+//! Sana `Storage` backed by hash maps, in place of a Postgres database.
+//!
+//! Follows the rules of `PostgresStorage` (tokens and contracts are
+//! never overwritten, transfers update the current owner), and exposes
+//! some helpers to inspect what was indexed.
+use crate::storage::types::{
+    BlockInfo, CollectionActivity, CollectionStats, ContractInfo, ContractType, EventType, Page,
+    Pagination, SaleFilter, StorageError, TokenEvent, TokenInfo, TokenMintInfo, TokenSaleEvent,
+    TokenTransferEvent,
+};
+use crate::storage::utils::is_zero_address;
+use crate::storage::{QueryStorage, Storage};
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tracing::trace;
+
+/// Token as stored, with its mint info once known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredToken {
+    pub token: TokenInfo,
+    pub block_timestamp: u64,
+    pub mint: Option<TokenMintInfo>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct MemoryState {
+    /// Keyed by (contract_address, chain_id, token_id).
+    tokens: HashMap<(String, String, String), StoredToken>,
+    /// Transfers and sales share the same table, unique by
+    /// token event id, in the order of their registration.
+    events: Vec<TokenEvent>,
+    /// Keyed by (contract_address, chain_id).
+    contracts: HashMap<(String, String), ContractInfo>,
+    /// Keyed by block timestamp.
+    blocks: BTreeMap<u64, BlockInfo>,
+}
+
+impl MemoryState {
+    fn event_mut(&mut self, token_event_id: &str) -> Option<&mut TokenEvent> {
+        self.events.iter_mut().find(|e| match e {
+            TokenEvent::Transfer(t) => t.token_event_id == token_event_id,
+            TokenEvent::Sale(s) => s.token_event_id == token_event_id,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    state: RwLock<MemoryState>,
+    /// Copy of the state when the unit of work started,
+    /// restored on rollback.
+    snapshot: RwLock<Option<MemoryState>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, MemoryState> {
+        self.state.read().expect("memory storage lock poisoned")
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, MemoryState> {
+        self.state.write().expect("memory storage lock poisoned")
+    }
+
+    pub fn tokens(&self) -> Vec<StoredToken> {
+        self.read().tokens.values().cloned().collect()
+    }
+
+    pub fn token(&self, contract_address: &str, token_id: &str) -> Option<StoredToken> {
+        self.read()
+            .tokens
+            .values()
+            .find(|t| t.token.contract_address == contract_address && t.token.token_id == token_id)
+            .cloned()
+    }
+
+    pub fn events(&self) -> Vec<TokenEvent> {
+        self.read().events.clone()
+    }
+
+    pub fn contracts(&self) -> Vec<ContractInfo> {
+        self.read().contracts.values().cloned().collect()
+    }
+
+    /// Block numbers with a block info, in ascending order of timestamp.
+    pub fn block_numbers(&self) -> Vec<u64> {
+        self.read()
+            .blocks
+            .values()
+            .map(|b| b.block_number)
+            .collect()
+    }
+
+    /// Removes everything, including a unit of work in progress.
+    pub fn clear(&self) {
+        *self.write() = MemoryState::default();
+        *self.snapshot.write().expect("memory storage lock poisoned") = None;
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn begin_unit_of_work(&self) -> Result<(), StorageError> {
+        let mut snapshot = self.snapshot.write().expect("memory storage lock poisoned");
+
+        if snapshot.is_some() {
+            return Err(StorageError::DatabaseError(
+                "A unit of work is already in progress".to_string(),
+            ));
+        }
+
+        *snapshot = Some(self.read().clone());
+
+        Ok(())
+    }
+
+    async fn commit_unit_of_work(&self) -> Result<(), StorageError> {
+        match self
+            .snapshot
+            .write()
+            .expect("memory storage lock poisoned")
+            .take()
+        {
+            Some(_) => Ok(()),
+            None => Err(StorageError::DatabaseError(
+                "No unit of work in progress".to_string(),
+            )),
+        }
+    }
+
+    async fn rollback_unit_of_work(&self) -> Result<(), StorageError> {
+        let snapshot = self
+            .snapshot
+            .write()
+            .expect("memory storage lock poisoned")
+            .take();
+
+        if let Some(state) = snapshot {
+            *self.write() = state;
+        }
+
+        Ok(())
+    }
+
+    async fn register_mint(
+        &self,
+        contract_address: &str,
+        _token_id_hex: &str,
+        token_id: &str,
+        info: &TokenMintInfo,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Registering mint {} {} {:?}",
+            contract_address,
+            token_id,
+            info
+        );
+
+        let mut state = self.write();
+        for stored in state.tokens.values_mut().filter(|t| {
+            t.token.contract_address == contract_address && t.token.token_id == token_id
+        }) {
+            stored.mint = Some(info.clone());
+        }
+
+        Ok(())
+    }
+
+    async fn register_token(
+        &self,
+        token: &TokenInfo,
+        block_timestamp: u64,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Registering token {:?} - block_timestamp: {}",
+            token,
+            block_timestamp
+        );
+
+        let key = (
+            token.contract_address.clone(),
+            token.chain_id.clone(),
+            token.token_id.clone(),
+        );
+
+        self.write()
+            .tokens
+            .entry(key)
+            .or_insert_with(|| StoredToken {
+                token: token.clone(),
+                block_timestamp,
+                mint: None,
+            });
+
+        Ok(())
+    }
+
+    async fn register_sale_event(
+        &self,
+        event: &TokenSaleEvent,
+        block_timestamp: u64,
+    ) -> Result<(), StorageError> {
+        trace!("Registering sale event {:?}", event);
+
+        let mut state = self.write();
+        match state.event_mut(&event.token_event_id) {
+            Some(TokenEvent::Sale(existing)) => existing.block_timestamp = block_timestamp,
+            Some(TokenEvent::Transfer(existing)) => existing.block_timestamp = block_timestamp,
+            None => state.events.push(TokenEvent::Sale(event.clone())),
+        }
+
+        Ok(())
+    }
+
+    async fn register_transfer_event(
+        &self,
+        event: &TokenTransferEvent,
+    ) -> Result<(), StorageError> {
+        trace!("Registering transfer event {:?}", event.token_event_id);
+
+        let mut state = self.write();
+        match state.event_mut(&event.token_event_id) {
+            Some(TokenEvent::Sale(existing)) => {
+                existing.block_timestamp = event.block_timestamp;
+                return Ok(());
+            }
+            Some(TokenEvent::Transfer(existing)) => {
+                existing.block_timestamp = event.block_timestamp;
+                return Ok(());
+            }
+            None => state.events.push(TokenEvent::Transfer(event.clone())),
+        }
+
+        // The current owner is the recipient of the latest
+        // ownership change of the token.
+        let current_owner = state
+            .events
+            .iter()
+            .filter_map(|e| match e {
+                TokenEvent::Transfer(t)
+                    if t.contract_address == event.contract_address
+                        && t.token_id == event.token_id
+                        && t.chain_id == event.chain_id
+                        && matches!(
+                            t.event_type,
+                            Some(EventType::Transfer | EventType::Burn | EventType::Mint)
+                        ) =>
+                {
+                    Some(t)
+                }
+                _ => None,
+            })
+            .max_by_key(|t| t.block_timestamp)
+            .map(|t| t.to_address.clone());
+
+        let key = (
+            event.contract_address.clone(),
+            event.chain_id.clone(),
+            event.token_id.clone(),
+        );
+        if let (Some(stored), Some(owner)) = (state.tokens.get_mut(&key), current_owner) {
+            stored.token.owner = owner;
+        }
+
+        Ok(())
+    }
+
+    async fn get_contract_type(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+    ) -> Result<ContractType, StorageError> {
+        trace!("Getting contract info for contract {}", contract_address);
+
+        let key = (contract_address.to_string(), chain_id.to_string());
+        match self.read().contracts.get(&key) {
+            Some(info) => Ok(ContractType::from_str(&info.contract_type).unwrap()),
+            None => Err(StorageError::NotFound(format!(
+                "Contract {} not found",
+                contract_address
+            ))),
+        }
+    }
+
+    async fn register_contract_info(
+        &self,
+        info: &ContractInfo,
+        block_timestamp: u64,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Registering contract info {:?} for contract {} - block_timestamp: {}",
+            info.contract_type,
+            info.contract_address,
+            block_timestamp
+        );
+
+        let key = (info.contract_address.clone(), info.chain_id.clone());
+        self.write()
+            .contracts
+            .entry(key)
+            .or_insert_with(|| info.clone());
+
+        Ok(())
+    }
+
+    async fn set_block_info(
+        &self,
+        block_timestamp: u64,
+        info: BlockInfo,
+    ) -> Result<(), StorageError> {
+        trace!(
+            "Setting block info {:?} for block #{}",
+            info.block_status,
+            info.block_number
+        );
+
+        let mut state = self.write();
+        if state.blocks.contains_key(&block_timestamp)
+            || !state
+                .blocks
+                .values()
+                .any(|b| b.block_number == info.block_number)
+        {
+            state.blocks.insert(block_timestamp, info);
+        }
+
+        Ok(())
+    }
+
+    async fn get_block_info(&self, block_number: u64) -> Result<BlockInfo, StorageError> {
+        trace!("Getting block info for block #{}", block_number);
+
+        self.read()
+            .blocks
+            .values()
+            .find(|b| b.block_number == block_number)
+            .cloned()
+            .ok_or_else(|| StorageError::NotFound(format!("block number {block_number}")))
+    }
+
+    async fn clean_block(
+        &self,
+        block_timestamp: u64,
+        _block_number: Option<u64>,
+    ) -> Result<(), StorageError> {
+        trace!("Cleaning block [ts: {}]", block_timestamp);
+
+        let mut state = self.write();
+        state.blocks.remove(&block_timestamp);
+        state.events.retain(|e| match e {
+            TokenEvent::Transfer(t) => t.block_timestamp != block_timestamp,
+            TokenEvent::Sale(s) => s.block_timestamp != block_timestamp,
+        });
+
+        Ok(())
+    }
+}
+
+/// Page of the already sorted items.
+fn paginate<T>(items: impl Iterator<Item = T>, pagination: &Pagination) -> Page<T> {
+    let items = items
+        .skip(pagination.offset as usize)
+        .take(pagination.limit as usize + 1)
+        .collect();
+
+    Page::from_items(items, pagination)
+}
+
+impl MemoryStorage {
+    /// Tokens matching the predicate, sorted by contract address and token id.
+    fn sorted_tokens<F>(&self, predicate: F) -> Vec<TokenInfo>
+    where
+        F: Fn(&TokenInfo) -> bool,
+    {
+        let mut tokens: Vec<TokenInfo> = self
+            .read()
+            .tokens
+            .values()
+            .map(|t| t.token.clone())
+            .filter(predicate)
+            .collect();
+
+        tokens.sort_by(|a, b| {
+            (&a.contract_address, &a.token_id_hex).cmp(&(&b.contract_address, &b.token_id_hex))
+        });
+        tokens
+    }
+}
+
+#[async_trait]
+impl QueryStorage for MemoryStorage {
+    async fn get_token(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        token_id: &str,
+    ) -> Result<TokenInfo, StorageError> {
+        let key = (
+            contract_address.to_string(),
+            chain_id.to_string(),
+            token_id.to_string(),
+        );
+
+        match self.read().tokens.get(&key) {
+            Some(stored) => Ok(stored.token.clone()),
+            None => Err(StorageError::NotFound(format!(
+                "token {contract_address} {token_id}"
+            ))),
+        }
+    }
+
+    async fn tokens_by_owner(
+        &self,
+        owner: &str,
+        chain_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<TokenInfo>, StorageError> {
+        let tokens = self.sorted_tokens(|t| t.owner == owner && t.chain_id == chain_id);
+        Ok(paginate(tokens.into_iter(), &pagination))
+    }
+
+    async fn collection_tokens(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<TokenInfo>, StorageError> {
+        let tokens = self
+            .sorted_tokens(|t| t.contract_address == contract_address && t.chain_id == chain_id);
+        Ok(paginate(tokens.into_iter(), &pagination))
+    }
+
+    async fn token_history(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        token_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<TokenTransferEvent>, StorageError> {
+        let mut events: Vec<TokenTransferEvent> = self
+            .read()
+            .events
+            .iter()
+            .filter_map(|e| match e {
+                TokenEvent::Transfer(t)
+                    if t.contract_address == contract_address
+                        && t.chain_id == chain_id
+                        && t.token_id == token_id =>
+                {
+                    Some(t.clone())
+                }
+                _ => None,
+            })
+            .collect();
+
+        events.sort_by(|a, b| {
+            b.block_timestamp
+                .cmp(&a.block_timestamp)
+                .then(a.token_event_id.cmp(&b.token_event_id))
+        });
+
+        Ok(paginate(events.into_iter(), &pagination))
+    }
+
+    async fn sales(
+        &self,
+        chain_id: &str,
+        filter: &SaleFilter,
+        pagination: Pagination,
+    ) -> Result<Page<TokenSaleEvent>, StorageError> {
+        let mut sales: Vec<TokenSaleEvent> = self
+            .read()
+            .events
+            .iter()
+            .filter_map(|e| match e {
+                TokenEvent::Sale(s) if s.chain_id == chain_id && filter.matches(s) => {
+                    Some(s.clone())
+                }
+                _ => None,
+            })
+            .collect();
+
+        sales.sort_by(|a, b| {
+            b.block_timestamp
+                .cmp(&a.block_timestamp)
+                .then(a.token_event_id.cmp(&b.token_event_id))
+        });
+
+        Ok(paginate(sales.into_iter(), &pagination))
+    }
+
+    async fn collection_stats(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        timestamp: u64,
+    ) -> Result<CollectionStats, StorageError> {
+        let state = self.read();
+        let mut stats = CollectionStats {
+            contract_address: contract_address.to_string(),
+            chain_id: chain_id.to_string(),
+            ..Default::default()
+        };
+
+        // Derived from the state instead of being maintained,
+        // which gives the same figures as `PostgresStorage`.
+        let holders: Vec<&str> = state
+            .tokens
+            .values()
+            .map(|t| &t.token)
+            .filter(|t| t.contract_address == contract_address && t.chain_id == chain_id)
+            .filter(|t| !is_zero_address(&t.owner))
+            .map(|t| t.owner.as_str())
+            .collect();
+        stats.supply = holders.len() as u64;
+        stats.owners_count = holders.into_iter().collect::<HashSet<_>>().len() as u64;
+
+        for event in &state.events {
+            let (block_timestamp, activity) = match event {
+                TokenEvent::Sale(s)
+                    if s.nft_contract_address == contract_address && s.chain_id == chain_id =>
+                {
+                    let activity = CollectionActivity {
+                        sales_count: 1,
+                        volume_usd: s.price_usd.unwrap_or_default(),
+                        mint_count: 0,
+                    };
+                    (s.block_timestamp, activity)
+                }
+                TokenEvent::Transfer(t)
+                    if t.contract_address == contract_address
+                        && t.chain_id == chain_id
+                        && t.event_type == Some(EventType::Mint) =>
+                {
+                    let activity = CollectionActivity {
+                        mint_count: 1,
+                        ..Default::default()
+                    };
+                    (t.block_timestamp, activity)
+                }
+                _ => continue,
+            };
+
+            stats.all_time.add(&activity);
+            stats.add_bucket(
+                timestamp,
+                CollectionStats::bucket(block_timestamp),
+                &activity,
+            );
+        }
+
+        Ok(stats)
+    }
+
+    async fn contracts(
+        &self,
+        chain_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<ContractInfo>, StorageError> {
+        let mut contracts: Vec<ContractInfo> = self
+            .read()
+            .contracts
+            .values()
+            .filter(|c| c.chain_id == chain_id)
+            .cloned()
+            .collect();
+        contracts.sort_by(|a, b| a.contract_address.cmp(&b.contract_address));
+
+        Ok(paginate(contracts.into_iter(), &pagination))
+    }
+
+    async fn get_contract_info(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+    ) -> Result<ContractInfo, StorageError> {
+        let key = (contract_address.to_string(), chain_id.to_string());
+
+        self.read()
+            .contracts
+            .get(&key)
+            .cloned()
+            .ok_or_else(|| StorageError::NotFound(format!("contract {contract_address}")))
+    }
+
+    async fn last_block_info(&self) -> Result<Option<BlockInfo>, StorageError> {
+        Ok(self
+            .read()
+            .blocks
+            .values()
+            .max_by_key(|b| b.block_number)
+            .cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::types::STATS_BUCKET_SECS;
+
+    fn transfer(id: &str, to: &str, block_timestamp: u64) -> TokenTransferEvent {
+        TokenTransferEvent {
+            token_event_id: id.to_string(),
+            block_timestamp,
+            to_address: to.to_string(),
+            contract_address: "0x1".to_string(),
+            chain_id: "SN_TEST".to_string(),
+            token_id: "1".to_string(),
+            event_type: Some(EventType::Transfer),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transfer_updates_owner() {
+        let storage = MemoryStorage::new();
+        let token = TokenInfo {
+            contract_address: "0x1".to_string(),
+            token_id: "1".to_string(),
+            chain_id: "SN_TEST".to_string(),
+            token_id_hex: "0x1".to_string(),
+            owner: "0xa".to_string(),
+        };
+        storage.register_token(&token, 10).await.unwrap();
+
+        storage
+            .register_transfer_event(&transfer("e2", "0xc", 30))
+            .await
+            .unwrap();
+        storage
+            .register_transfer_event(&transfer("e1", "0xb", 20))
+            .await
+            .unwrap();
+
+        assert_eq!(storage.token("0x1", "1").unwrap().token.owner, "0xc");
+        assert_eq!(storage.events().len(), 2);
+
+        storage.clean_block(30, None).await.unwrap();
+        assert_eq!(storage.events().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_unit_of_work_rollback() {
+        let storage = MemoryStorage::new();
+
+        storage.begin_unit_of_work().await.unwrap();
+        storage
+            .register_transfer_event(&transfer("e1", "0xb", 20))
+            .await
+            .unwrap();
+        storage.rollback_unit_of_work().await.unwrap();
+
+        assert!(storage.events().is_empty());
+        assert!(storage.commit_unit_of_work().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_token_history() {
+        let storage = MemoryStorage::new();
+
+        for (id, ts) in [("e1", 10), ("e2", 30), ("e3", 20)] {
+            storage
+                .register_transfer_event(&transfer(id, "0xb", ts))
+                .await
+                .unwrap();
+        }
+
+        let page = storage
+            .token_history("0x1", "SN_TEST", "1", Pagination::new(0, 2))
+            .await
+            .unwrap();
+        let ids: Vec<&str> = page
+            .items
+            .iter()
+            .map(|e| e.token_event_id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["e2", "e3"]);
+        assert_eq!(page.next_offset, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_collection_stats() {
+        let storage = MemoryStorage::new();
+        let hour = STATS_BUCKET_SECS;
+        let now = 1000 * hour + 10;
+
+        for (token_id, owner) in [("1", "0xa"), ("2", "0xa"), ("3", "0xb"), ("4", "0x0")] {
+            let token = TokenInfo {
+                contract_address: "0x1".to_string(),
+                token_id: token_id.to_string(),
+                chain_id: "SN_TEST".to_string(),
+                token_id_hex: format!("0x{token_id}"),
+                owner: owner.to_string(),
+            };
+            storage.register_token(&token, 10).await.unwrap();
+        }
+
+        let mint = TokenTransferEvent {
+            event_type: Some(EventType::Mint),
+            token_id: "5".to_string(),
+            ..transfer("e1", "0xc", now - 2 * hour)
+        };
+        storage.register_transfer_event(&mint).await.unwrap();
+
+        // Same day, same week and older than a week.
+        for (id, age) in [
+            ("s1", 5 * hour),
+            ("s2", 3 * 24 * hour),
+            ("s3", 8 * 24 * hour),
+        ] {
+            let sale = TokenSaleEvent {
+                token_event_id: id.to_string(),
+                from_address: "0xa".to_string(),
+                to_address: "0xb".to_string(),
+                nft_contract_address: "0x1".to_string(),
+                nft_type: None,
+                marketplace_contract_address: "0xm".to_string(),
+                marketplace_name: "test".to_string(),
+                transaction_hash: "0x123".to_string(),
+                chain_id: "SN_TEST".to_string(),
+                token_id: "1".to_string(),
+                token_id_hex: "0x1".to_string(),
+                event_type: EventType::Sale,
+                block_timestamp: now - age,
+                block_number: None,
+                updated_at: None,
+                quantity: 1,
+                currency_address: None,
+                price: "0x1".to_string(),
+                currency_symbol: None,
+                currency_decimals: None,
+                normalized_price: None,
+                price_usd: Some(10.0),
+            };
+            storage
+                .register_sale_event(&sale, sale.block_timestamp)
+                .await
+                .unwrap();
+        }
+
+        let stats = storage
+            .collection_stats("0x1", "SN_TEST", now)
+            .await
+            .unwrap();
+        assert_eq!((stats.owners_count, stats.supply), (2, 3));
+        assert_eq!(stats.last_hour, CollectionActivity::default());
+        assert_eq!(stats.last_day.sales_count, 1);
+        assert_eq!(stats.last_day.mint_count, 1);
+        assert_eq!(stats.last_week.volume_usd, 20.0);
+        assert_eq!(stats.all_time.sales_count, 3);
+
+        // A cleaned block is no longer counted.
+        storage.clean_block(now - 5 * hour, None).await.unwrap();
+        let stats = storage
+            .collection_stats("0x1", "SN_TEST", now)
+            .await
+            .unwrap();
+        assert_eq!(stats.last_day.sales_count, 0);
+        assert_eq!(stats.all_time.sales_count, 2);
+    }
+}
@@ -1,13 +1,17 @@
+#[cfg(any(test, feature = "memory"))]
+pub mod memory;
 #[cfg(feature = "sqlxdb")]
 pub mod sqlx;
 pub mod types;
 pub mod utils;
 use self::types::TokenSaleEvent;
 use crate::storage::types::{
-    BlockInfo, ContractInfo, ContractType, StorageError, TokenInfo, TokenMintInfo,
-    TokenTransferEvent,
+    BlockInfo, CollectionStats, ContractInfo, ContractType, Page, Pagination, SaleFilter,
+    StorageError, TokenInfo, TokenMintInfo, TokenTransferEvent,
 };
 use async_trait::async_trait;
+#[cfg(any(test, feature = "memory"))]
+pub use memory::MemoryStorage;
 #[cfg(test)]
 use mockall::automock;
 #[cfg(feature = "sqlxdb")]
@@ -16,6 +20,24 @@ pub use sqlx::PostgresStorage;
 #[async_trait]
 #[cfg_attr(test, automock)]
 pub trait Storage {
+    /// Starts a unit of work. All the following operations are applied
+    /// at once on `commit_unit_of_work`, or discarded on `rollback_unit_of_work`.
+    /// Only one unit of work can be in progress at a time.
+    ///
+    /// Storages without transaction support can keep the default
+    /// implementation, where every operation is applied immediately.
+    async fn begin_unit_of_work(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    async fn commit_unit_of_work(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    async fn rollback_unit_of_work(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
     async fn register_mint(
         &self,
         contract_address: &str,
@@ -68,3 +90,76 @@ pub trait Storage {
         block_number: Option<u64>,
     ) -> Result<(), StorageError>;
 }
+
+/// Read side of the indexed data, shared by the applications
+/// instead of querying the storage tables directly.
+///
+/// Tokens are ordered by contract address and token id, events
+/// and sales from the most recent to the oldest.
+#[async_trait]
+#[cfg_attr(test, automock)]
+pub trait QueryStorage {
+    async fn get_token(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        token_id: &str,
+    ) -> Result<TokenInfo, StorageError>;
+
+    async fn tokens_by_owner(
+        &self,
+        owner: &str,
+        chain_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<TokenInfo>, StorageError>;
+
+    async fn collection_tokens(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<TokenInfo>, StorageError>;
+
+    /// Transfers, mints and burns of a token.
+    async fn token_history(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        token_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<TokenTransferEvent>, StorageError>;
+
+    /// Sales registered in the `token_event` table. The marketplace
+    /// of a sale is not stored, and is left empty.
+    async fn sales(
+        &self,
+        chain_id: &str,
+        filter: &SaleFilter,
+        pagination: Pagination,
+    ) -> Result<Page<TokenSaleEvent>, StorageError>;
+
+    /// Aggregates of a collection, with the periods ending at the hour
+    /// containing `timestamp`. A collection without activity has zeros.
+    async fn collection_stats(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        timestamp: u64,
+    ) -> Result<CollectionStats, StorageError>;
+
+    /// Contracts ordered by address.
+    async fn contracts(
+        &self,
+        chain_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<ContractInfo>, StorageError>;
+
+    async fn get_contract_info(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+    ) -> Result<ContractInfo, StorageError>;
+
+    /// Info of the block with the highest number, if any.
+    async fn last_block_info(&self) -> Result<Option<BlockInfo>, StorageError>;
+}
@@ -1,9 +1,14 @@
 use super::types::*;
 use crate::storage::types::*;
+use crate::storage::utils::is_zero_address;
+use crate::storage::QueryStorage;
 use crate::Storage;
 use async_trait::async_trait;
-use sqlx::{postgres::PgPoolOptions, Error as SqlxError, FromRow, PgPool};
+use sqlx::postgres::{PgArguments, PgPoolOptions, PgQueryResult, PgRow, Postgres};
+use sqlx::query::Query;
+use sqlx::{Acquire, Error as SqlxError, FromRow, PgPool, Row, Transaction};
 use std::str::FromStr;
+use tokio::sync::Mutex;
 use tracing::{error, info, trace};
 
 impl From<SqlxError> for StorageError {
@@ -14,6 +19,9 @@ impl From<SqlxError> for StorageError {
 
 pub struct PostgresStorage {
     pool: PgPool,
+    /// Transaction of the unit of work in progress, if any.
+    /// All the queries are executed in this transaction while it is set.
+    transaction: Mutex<Option<Transaction<'static, Postgres>>>,
 }
 
 impl PostgresStorage {
@@ -29,9 +37,53 @@ impl PostgresStorage {
                 .max_connections(10)
                 .connect(db_url)
                 .await?,
+            transaction: Mutex::new(None),
         })
     }
 
+    /// Executes the query in the unit of work in progress, or directly on the pool.
+    async fn execute<'q>(
+        &self,
+        query: Query<'q, Postgres, PgArguments>,
+    ) -> Result<PgQueryResult, StorageError> {
+        let mut transaction = self.transaction.lock().await;
+
+        let result = match transaction.as_mut() {
+            Some(tx) => query.execute(&mut **tx).await?,
+            None => query.execute(&self.pool).await?,
+        };
+
+        Ok(result)
+    }
+
+    async fn fetch_all<'q>(
+        &self,
+        query: Query<'q, Postgres, PgArguments>,
+    ) -> Result<Vec<PgRow>, StorageError> {
+        let mut transaction = self.transaction.lock().await;
+
+        let rows = match transaction.as_mut() {
+            Some(tx) => query.fetch_all(&mut **tx).await?,
+            None => query.fetch_all(&self.pool).await?,
+        };
+
+        Ok(rows)
+    }
+
+    async fn fetch_optional<'q>(
+        &self,
+        query: Query<'q, Postgres, PgArguments>,
+    ) -> Result<Option<PgRow>, StorageError> {
+        let mut transaction = self.transaction.lock().await;
+
+        let row = match transaction.as_mut() {
+            Some(tx) => query.fetch_optional(&mut **tx).await?,
+            None => query.fetch_optional(&self.pool).await?,
+        };
+
+        Ok(row)
+    }
+
     pub async fn dump_tables(&self) -> Result<(), StorageError> {
         let q = "SELECT * FROM token";
         let rows = sqlx::query(q).fetch_all(&self.pool).await?;
@@ -52,11 +104,13 @@ impl PostgresStorage {
         let q =
             "SELECT contract_address, chain_id, token_id FROM token WHERE contract_address = $1 AND chain_id = $2 AND token_id = $3";
 
-        match sqlx::query(q)
-            .bind(contract_address)
-            .bind(chain_id)
-            .bind(token_id)
-            .fetch_all(&self.pool)
+        match self
+            .fetch_all(
+                sqlx::query(q)
+                    .bind(contract_address)
+                    .bind(chain_id)
+                    .bind(token_id),
+            )
             .await
         {
             Ok(rows) => {
@@ -66,7 +120,7 @@ impl PostgresStorage {
                     Ok(Some(TokenData::from_row(&rows[0])?))
                 }
             }
-            Err(e) => Err(StorageError::DatabaseError(e.to_string())),
+            Err(e) => Err(e),
         }
     }
 
@@ -76,11 +130,7 @@ impl PostgresStorage {
     ) -> Result<Option<EventData>, StorageError> {
         let q = "SELECT token_event_id, contract_address, chain_id, broker_id, order_hash, token_id, event_type, block_timestamp, transaction_hash, to_address, from_address, amount, canceled_reason FROM token_event WHERE token_event_id = $1";
 
-        match sqlx::query(q)
-            .bind(token_event_id)
-            .fetch_all(&self.pool)
-            .await
-        {
+        match self.fetch_all(sqlx::query(q).bind(token_event_id)).await {
             Ok(rows) => {
                 if rows.is_empty() {
                     Ok(None)
@@ -88,7 +138,7 @@ impl PostgresStorage {
                     Ok(Some(EventData::from_row(&rows[0])?))
                 }
             }
-            Err(e) => Err(StorageError::DatabaseError(e.to_string())),
+            Err(e) => Err(e),
         }
     }
 
@@ -107,10 +157,12 @@ impl PostgresStorage {
     ) -> Result<Option<ContractData>, StorageError> {
         let q = "SELECT contract_address, updated_timestamp, contract_address, contract_type FROM contract WHERE contract_address = $1 AND chain_id = $2";
 
-        match sqlx::query(q)
-            .bind(contract_address.to_string())
-            .bind(chain_id.to_string())
-            .fetch_all(&self.pool)
+        match self
+            .fetch_all(
+                sqlx::query(q)
+                    .bind(contract_address.to_string())
+                    .bind(chain_id.to_string()),
+            )
             .await
         {
             Ok(rows) => {
@@ -120,7 +172,7 @@ impl PostgresStorage {
                     Ok(Some(ContractData::from_row(&rows[0])?))
                 }
             }
-            Err(e) => Err(StorageError::DatabaseError(e.to_string())),
+            Err(e) => Err(e),
         }
     }
 
@@ -130,7 +182,7 @@ impl PostgresStorage {
         INNER JOIN indexer as i ON i.indexer_identifier = b.indexer_identifier
         WHERE block_timestamp = $1";
 
-        match sqlx::query(q).bind(ts as i64).fetch_all(&self.pool).await {
+        match self.fetch_all(sqlx::query(q).bind(ts as i64)).await {
             Ok(rows) => {
                 if rows.is_empty() {
                     Ok(None)
@@ -138,7 +190,7 @@ impl PostgresStorage {
                     Ok(Some(BlockData::from_row(&rows[0])?))
                 }
             }
-            Err(e) => Err(StorageError::DatabaseError(e.to_string())),
+            Err(e) => Err(e),
         }
     }
 
@@ -176,21 +228,22 @@ impl PostgresStorage {
                 indexer_version = EXCLUDED.indexer_version
         "#;
 
-        sqlx::query(query)
-            .bind(indexer_identifier)
-            .bind(status)
-            .bind(progression)
-            .bind(current_block_number)
-            .bind(force_mode)
-            .bind(start_block_number)
-            .bind(end_block_number)
-            .bind(indexer_version)
-            .execute(&self.pool)
-            .await
-            .map_err(|e| {
-                error!("Failed to update indexer progress: {}", e);
-                StorageError::DatabaseError(e.to_string())
-            })?;
+        self.execute(
+            sqlx::query(query)
+                .bind(indexer_identifier)
+                .bind(status)
+                .bind(progression)
+                .bind(current_block_number)
+                .bind(force_mode)
+                .bind(start_block_number)
+                .bind(end_block_number)
+                .bind(indexer_version),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to update indexer progress: {}", e);
+            e
+        })?;
 
         Ok(())
     }
@@ -198,6 +251,37 @@ impl PostgresStorage {
 
 #[async_trait]
 impl Storage for PostgresStorage {
+    async fn begin_unit_of_work(&self) -> Result<(), StorageError> {
+        let mut transaction = self.transaction.lock().await;
+
+        if transaction.is_some() {
+            return Err(StorageError::DatabaseError(
+                "A unit of work is already in progress".to_string(),
+            ));
+        }
+
+        *transaction = Some(self.pool.begin().await?);
+
+        Ok(())
+    }
+
+    async fn commit_unit_of_work(&self) -> Result<(), StorageError> {
+        match self.transaction.lock().await.take() {
+            Some(tx) => Ok(tx.commit().await?),
+            None => Err(StorageError::DatabaseError(
+                "No unit of work in progress".to_string(),
+            )),
+        }
+    }
+
+    async fn rollback_unit_of_work(&self) -> Result<(), StorageError> {
+        if let Some(tx) = self.transaction.lock().await.take() {
+            tx.rollback().await?;
+        }
+
+        Ok(())
+    }
+
     async fn register_mint(
         &self,
         contract_address: &str,
@@ -214,12 +298,14 @@ impl Storage for PostgresStorage {
 
         let q = "UPDATE token SET mint_address = $1, mint_timestamp = $2, mint_transaction_hash = $3 WHERE token_id = $4";
 
-        let _r = sqlx::query(q)
-            .bind(info.address.clone())
-            .bind(info.block_timestamp.to_string())
-            .bind(info.transaction_hash.clone())
-            .bind(token_id)
-            .execute(&self.pool)
+        let _r = self
+            .execute(
+                sqlx::query(q)
+                    .bind(info.address.clone())
+                    .bind(info.block_timestamp.to_string())
+                    .bind(info.transaction_hash.clone())
+                    .bind(token_id),
+            )
             .await?;
 
         Ok(())
@@ -237,16 +323,28 @@ impl Storage for PostgresStorage {
         );
 
         let q = "INSERT INTO token (contract_address, chain_id, token_id, token_id_hex, current_owner, block_timestamp) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (contract_address, chain_id, token_id) DO NOTHING";
-        let _r = sqlx::query(q)
-            .bind(token.contract_address.clone())
-            .bind(token.chain_id.clone())
-            .bind(token.token_id.clone())
-            .bind(token.token_id_hex.clone())
-            .bind(token.owner.clone())
-            .bind(block_timestamp as i64)
-            .execute(&self.pool)
+        let r = self
+            .execute(
+                sqlx::query(q)
+                    .bind(token.contract_address.clone())
+                    .bind(token.chain_id.clone())
+                    .bind(token.token_id.clone())
+                    .bind(token.token_id_hex.clone())
+                    .bind(token.owner.clone())
+                    .bind(block_timestamp as i64),
+            )
             .await?;
 
+        if r.rows_affected() == 1 && !is_zero_address(&token.owner) {
+            self.execute(move_collection_token_query(
+                &token.contract_address,
+                &token.chain_id,
+                None,
+                Some(&token.owner),
+            ))
+            .await?;
+        }
+
         Ok(())
     }
 
@@ -259,35 +357,61 @@ impl Storage for PostgresStorage {
 
         if (self.get_event_by_id(&event.token_event_id).await?).is_some() {
             let q = "UPDATE token_event SET block_timestamp = $1 WHERE token_event_id = $2";
-            sqlx::query(q)
-                .bind(block_timestamp as i64)
-                .bind(event.token_event_id.clone())
-                .execute(&self.pool)
-                .await?;
+            self.execute(
+                sqlx::query(q)
+                    .bind(block_timestamp as i64)
+                    .bind(event.token_event_id.clone()),
+            )
+            .await?;
 
             return Ok(());
         }
 
-        let q = "INSERT INTO token_event (token_event_id, contract_address, chain_id, token_id, token_id_hex, event_type, block_timestamp, transaction_hash, to_address, from_address, amount, currency_address)
-                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) ON CONFLICT (token_event_id) DO NOTHING";
+        let q = "INSERT INTO token_event (token_event_id, contract_address, chain_id, token_id, token_id_hex, event_type, block_timestamp, transaction_hash, to_address, from_address, amount, currency_address, price_usd)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13::NUMERIC) ON CONFLICT (token_event_id) DO NOTHING";
 
         let event_type = self.to_title_case(&event.event_type.to_string().to_lowercase());
 
-        let _r = sqlx::query(q)
-            .bind(event.token_event_id.clone())
-            .bind(event.nft_contract_address.clone())
-            .bind(event.chain_id.clone())
-            .bind(event.token_id.clone())
-            .bind(event.token_id_hex.clone())
-            .bind(event_type)
-            .bind(event.block_timestamp as i64)
-            .bind(event.transaction_hash.clone())
-            .bind(event.to_address.clone())
-            .bind(event.from_address.clone())
-            .bind(event.price.clone())
-            .bind(event.currency_address.clone())
-            .execute(&self.pool)
+        // The same decimal representation is stored and added to the
+        // volumes, so that removing the sale gives the volume back exactly.
+        let price_usd = event
+            .price_usd
+            .filter(|price| price.is_finite())
+            .map(|price| price.to_string());
+
+        let r = self
+            .execute(
+                sqlx::query(q)
+                    .bind(event.token_event_id.clone())
+                    .bind(event.nft_contract_address.clone())
+                    .bind(event.chain_id.clone())
+                    .bind(event.token_id.clone())
+                    .bind(event.token_id_hex.clone())
+                    .bind(event_type)
+                    .bind(event.block_timestamp as i64)
+                    .bind(event.transaction_hash.clone())
+                    .bind(event.to_address.clone())
+                    .bind(event.from_address.clone())
+                    .bind(event.price.clone())
+                    .bind(event.currency_address.clone())
+                    .bind(price_usd.clone()),
+            )
+            .await?;
+
+        if r.rows_affected() == 1 {
+            let delta = ActivityDelta {
+                sales_count: 1,
+                volume_usd: price_usd.unwrap_or_else(|| "0".to_string()),
+                mint_count: 0,
+            };
+            self.execute(add_collection_activity_query(
+                &event.nft_contract_address,
+                &event.chain_id,
+                event.block_timestamp,
+                &delta,
+            ))
             .await?;
+        }
 
         Ok(())
     }
@@ -297,9 +421,15 @@ impl Storage for PostgresStorage {
         event: &TokenTransferEvent,
     ) -> Result<(), StorageError> {
         info!("Registering transfer event {:?}", event.token_event_id);
-        let mut transaction = self.pool.begin().await?;
         let existing_transfer_event = self.get_event_by_id(&event.token_event_id).await?.is_some();
 
+        // Nested in the unit of work in progress, if any.
+        let mut unit_of_work = self.transaction.lock().await;
+        let mut transaction = match unit_of_work.as_mut() {
+            Some(tx) => tx.begin().await?,
+            None => self.pool.begin().await?,
+        };
+
         if existing_transfer_event {
             let q = "UPDATE token_event SET block_timestamp = $1 WHERE token_event_id = $2";
             sqlx::query(q)
@@ -323,7 +453,7 @@ impl Storage for PostgresStorage {
 
             info!("Inserting transfer event... {:?}", event_type);
 
-            sqlx::query(insert_query)
+            let inserted = sqlx::query(insert_query)
                 .bind(event.token_event_id.clone())
                 .bind(event.contract_address.clone())
                 .bind(event.chain_id.clone())
@@ -335,10 +465,30 @@ impl Storage for PostgresStorage {
                 .bind(event.to_address.clone())
                 .bind(event.from_address.clone())
                 .execute(&mut *transaction)
-                .await?;
+                .await?
+                .rows_affected()
+                == 1;
 
             info!("Inserted transfer event: {:?}", event.token_event_id);
 
+            if inserted && event.event_type == Some(EventType::Mint) {
+                let delta = ActivityDelta {
+                    sales_count: 0,
+                    volume_usd: "0".to_string(),
+                    mint_count: 1,
+                };
+                add_collection_activity_query(
+                    &event.contract_address,
+                    &event.chain_id,
+                    event.block_timestamp,
+                    &delta,
+                )
+                .execute(&mut *transaction)
+                .await?;
+            }
+
+            // The previous owner is returned to move the token
+            // in the owners of the collection.
             let update_query = "UPDATE token t
                 SET current_owner = (
                   SELECT te.to_address
@@ -354,16 +504,36 @@ impl Storage for PostgresStorage {
                 listing_start_date = null,
                 listing_end_date = null,
                 listing_start_amount = null, listing_end_amount = null
-                WHERE t.contract_address = $1 AND t.token_id = $2 AND t.chain_id = $3 RETURNING t.current_owner";
+                FROM (
+                  SELECT current_owner AS previous_owner FROM token
+                  WHERE contract_address = $1 AND token_id = $2 AND chain_id = $3
+                ) p
+                WHERE t.contract_address = $1 AND t.token_id = $2 AND t.chain_id = $3
+                RETURNING t.current_owner, p.previous_owner";
 
-            let current_owner: Option<String> = sqlx::query_scalar(update_query)
+            let row = sqlx::query(update_query)
                 .bind(event.contract_address.clone())
                 .bind(event.token_id.clone())
                 .bind(event.chain_id.clone())
                 .fetch_one(&mut *transaction)
                 .await?;
+            let current_owner: Option<String> = row.try_get("current_owner")?;
+            let previous_owner: Option<String> = row.try_get("previous_owner")?;
 
             info!("Current owner updated to: {:?}", current_owner);
+
+            let holder = |owner: Option<String>| owner.filter(|o| !is_zero_address(o));
+            let (previous_owner, current_owner) = (holder(previous_owner), holder(current_owner));
+            if previous_owner != current_owner {
+                move_collection_token_query(
+                    &event.contract_address,
+                    &event.chain_id,
+                    previous_owner.as_deref(),
+                    current_owner.as_deref(),
+                )
+                .execute(&mut *transaction)
+                .await?;
+            }
         }
 
         transaction.commit().await?;
@@ -418,16 +588,18 @@ impl Storage for PostgresStorage {
         let q = "INSERT INTO contract (contract_address, chain_id, contract_type, updated_timestamp, contract_symbol, contract_image, contract_name, metadata_ok, deployed_timestamp)
                 VALUES ($1, $2, $3, EXTRACT(epoch FROM now())::bigint, $4, $5, $6, $7, $8) ON CONFLICT (contract_address, chain_id) DO NOTHING";
 
-        let _r = sqlx::query(q)
-            .bind(info.contract_address.clone())
-            .bind(info.chain_id.clone())
-            .bind(info.contract_type.to_string())
-            .bind(info.symbol.clone().unwrap_or_default())
-            .bind(info.image.clone().unwrap_or_default())
-            .bind(info.name.clone().unwrap_or_default())
-            .bind(false)
-            .bind(block_timestamp as i64)
-            .execute(&self.pool)
+        let _r = self
+            .execute(
+                sqlx::query(q)
+                    .bind(info.contract_address.clone())
+                    .bind(info.chain_id.clone())
+                    .bind(info.contract_type.to_string())
+                    .bind(info.symbol.clone().unwrap_or_default())
+                    .bind(info.image.clone().unwrap_or_default())
+                    .bind(info.name.clone().unwrap_or_default())
+                    .bind(false)
+                    .bind(block_timestamp as i64),
+            )
             .await?;
 
         Ok(())
@@ -438,21 +610,24 @@ impl Storage for PostgresStorage {
         block_timestamp: u64,
         info: BlockInfo,
     ) -> Result<(), StorageError> {
-        let exists = sqlx::query("SELECT 1 FROM indexer WHERE indexer_identifier = $1")
-            .bind(info.indexer_identifier.clone())
-            .fetch_optional(&self.pool)
+        let exists = self
+            .fetch_optional(
+                sqlx::query("SELECT 1 FROM indexer WHERE indexer_identifier = $1")
+                    .bind(info.indexer_identifier.clone()),
+            )
             .await?
             .is_some();
 
         if !exists {
             let q = "INSERT INTO indexer (indexer_identifier, indexer_version, indexer_status, current_block_number) VALUES ($1, $2, $3, $4)";
-            sqlx::query(q)
-                .bind(info.indexer_identifier.clone())
-                .bind(info.indexer_version.clone())
-                .bind(info.block_status.to_string())
-                .bind(info.block_number as i64)
-                .execute(&self.pool)
-                .await?;
+            self.execute(
+                sqlx::query(q)
+                    .bind(info.indexer_identifier.clone())
+                    .bind(info.indexer_version.clone())
+                    .bind(info.block_status.to_string())
+                    .bind(info.block_number as i64),
+            )
+            .await?;
         }
         let _r = if (self.get_block_by_timestamp(block_timestamp).await?).is_some() {
             let q = r#"
@@ -460,22 +635,24 @@ impl Storage for PostgresStorage {
                 SET block_number = $1, block_status = $2, indexer_identifier = $3
                 WHERE block_timestamp = $4;
             "#;
-            sqlx::query(q)
-                .bind(info.block_number as i64)
-                .bind(info.block_status.to_string())
-                .bind(info.indexer_identifier.clone())
-                .bind(block_timestamp as i64)
-                .execute(&self.pool)
-                .await?
+            self.execute(
+                sqlx::query(q)
+                    .bind(info.block_number as i64)
+                    .bind(info.block_status.to_string())
+                    .bind(info.indexer_identifier.clone())
+                    .bind(block_timestamp as i64),
+            )
+            .await?
         } else {
             let q = "INSERT INTO block (block_timestamp, block_number, block_status, indexer_identifier) VALUES ($1, $2, $3, $4) ON CONFLICT (block_number) DO NOTHING";
-            sqlx::query(q)
-                .bind(block_timestamp as i64)
-                .bind(info.block_number as i64)
-                .bind(info.block_status.to_string())
-                .bind(info.indexer_identifier.clone())
-                .execute(&self.pool)
-                .await?
+            self.execute(
+                sqlx::query(q)
+                    .bind(block_timestamp as i64)
+                    .bind(info.block_number as i64)
+                    .bind(info.block_status.to_string())
+                    .bind(info.indexer_identifier.clone()),
+            )
+            .await?
         };
 
         Ok(())
@@ -489,9 +666,8 @@ impl Storage for PostgresStorage {
         LEFT JOIN indexer as i ON i.indexer_identifier = b.indexer_identifier
         WHERE block_number = $1";
 
-        match sqlx::query_as::<_, BlockData>(q)
-            .bind(block_number as i64)
-            .fetch_all(&self.pool)
+        match self
+            .fetch_all(sqlx::query(q).bind(block_number as i64))
             .await
         {
             Ok(rows) => {
@@ -500,7 +676,7 @@ impl Storage for PostgresStorage {
                         "block number {block_number}"
                     )))
                 } else {
-                    let d = rows[0].clone();
+                    let d = BlockData::from_row(&rows[0])?;
                     Ok(BlockInfo {
                         indexer_identifier: d.indexer_identifier.clone(),
                         indexer_version: d.indexer_version.clone(),
@@ -511,7 +687,7 @@ impl Storage for PostgresStorage {
             }
             Err(e) => {
                 error!("Database error: {:?}", e);
-                Err(StorageError::DatabaseError(e.to_string()))
+                Err(e)
             }
         }
     }
@@ -523,19 +699,484 @@ impl Storage for PostgresStorage {
     ) -> Result<(), StorageError> {
         trace!("Cleaning block [ts: {}]", block_timestamp.to_string());
         let q = "DELETE FROM block WHERE block_timestamp = $1::bigint";
-        sqlx::query(q)
-            .bind(block_timestamp.to_string())
-            .fetch_all(&self.pool)
+        self.fetch_all(sqlx::query(q).bind(block_timestamp.to_string()))
+            .await?;
+
+        // The negated activity of the deleted events is added to the aggregates.
+        let q = "WITH deleted AS (
+                DELETE FROM token_event WHERE block_timestamp = $1::bigint
+                RETURNING contract_address, chain_id, event_type, price_usd
+            )
+            SELECT contract_address, chain_id,
+            -COUNT(*) FILTER (WHERE event_type = 'Sale') AS sales_count,
+            (-COALESCE(SUM(price_usd) FILTER (WHERE event_type = 'Sale'), 0))::TEXT AS volume_usd,
+            -COUNT(*) FILTER (WHERE event_type = 'Mint') AS mint_count
+            FROM deleted WHERE event_type IN ('Sale', 'Mint')
+            GROUP BY contract_address, chain_id";
+        let deleted = self
+            .fetch_all(sqlx::query(q).bind(block_timestamp.to_string()))
             .await?;
 
-        let q = "DELETE FROM token_event WHERE block_timestamp = $1::bigint";
-        sqlx::query(q)
-            .bind(block_timestamp.to_string())
-            .fetch_all(&self.pool)
+        for row in deleted {
+            let contract_address: String = row.try_get("contract_address")?;
+            let chain_id: String = row.try_get("chain_id")?;
+            let delta = ActivityDelta {
+                sales_count: row.try_get("sales_count")?,
+                volume_usd: row.try_get("volume_usd")?,
+                mint_count: row.try_get("mint_count")?,
+            };
+
+            self.execute(add_collection_activity_query(
+                &contract_address,
+                &chain_id,
+                block_timestamp,
+                &delta,
+            ))
             .await?;
+        }
 
         trace!("Block {} cleaned", block_timestamp.to_string());
 
         Ok(())
     }
 }
+
+/// Change of the activity of a collection, negative when events are cleaned.
+struct ActivityDelta {
+    sales_count: i64,
+    /// Decimal representation, added as a `NUMERIC` to keep the volumes exact.
+    volume_usd: String,
+    mint_count: i64,
+}
+
+/// Adds the activity to the all time and hourly aggregates of a collection.
+fn add_collection_activity_query(
+    contract_address: &str,
+    chain_id: &str,
+    block_timestamp: u64,
+    delta: &ActivityDelta,
+) -> Query<'static, Postgres, PgArguments> {
+    let q = "WITH all_time AS (
+            INSERT INTO collection_stats (contract_address, chain_id, sales_count, volume_usd, mint_count)
+            VALUES ($1, $2, $4, $5::NUMERIC, $6)
+            ON CONFLICT (contract_address, chain_id) DO UPDATE SET
+            sales_count = collection_stats.sales_count + EXCLUDED.sales_count,
+            volume_usd = collection_stats.volume_usd + EXCLUDED.volume_usd,
+            mint_count = collection_stats.mint_count + EXCLUDED.mint_count
+        )
+        INSERT INTO collection_stats_hourly (contract_address, chain_id, hour_timestamp, sales_count, volume_usd, mint_count)
+        VALUES ($1, $2, $3, $4, $5::NUMERIC, $6)
+        ON CONFLICT (contract_address, chain_id, hour_timestamp) DO UPDATE SET
+        sales_count = collection_stats_hourly.sales_count + EXCLUDED.sales_count,
+        volume_usd = collection_stats_hourly.volume_usd + EXCLUDED.volume_usd,
+        mint_count = collection_stats_hourly.mint_count + EXCLUDED.mint_count";
+
+    sqlx::query(q)
+        .bind(contract_address.to_string())
+        .bind(chain_id.to_string())
+        .bind(CollectionStats::bucket(block_timestamp) as i64)
+        .bind(delta.sales_count)
+        .bind(delta.volume_usd.clone())
+        .bind(delta.mint_count)
+}
+
+/// Moves a token from its previous owner to its new owner in the owners
+/// of the collection, `None` being the zero address, which holds nothing.
+fn move_collection_token_query(
+    contract_address: &str,
+    chain_id: &str,
+    previous_owner: Option<&str>,
+    owner: Option<&str>,
+) -> Query<'static, Postgres, PgArguments> {
+    let q = "WITH removed AS (
+            UPDATE collection_owner SET token_count = token_count - 1
+            WHERE contract_address = $1 AND chain_id = $2 AND owner = $3 AND token_count > 0
+            RETURNING token_count
+        ), added AS (
+            INSERT INTO collection_owner (contract_address, chain_id, owner, token_count)
+            SELECT $1, $2, $4, 1 WHERE $4::TEXT IS NOT NULL
+            ON CONFLICT (contract_address, chain_id, owner)
+            DO UPDATE SET token_count = collection_owner.token_count + 1
+            RETURNING token_count
+        )
+        INSERT INTO collection_stats (contract_address, chain_id, owners_count, supply)
+        SELECT $1, $2,
+        (SELECT COUNT(*) FROM added WHERE token_count = 1) - (SELECT COUNT(*) FROM removed WHERE token_count = 0),
+        (SELECT COUNT(*) FROM added) - (SELECT COUNT(*) FROM removed)
+        ON CONFLICT (contract_address, chain_id) DO UPDATE SET
+        owners_count = collection_stats.owners_count + EXCLUDED.owners_count,
+        supply = collection_stats.supply + EXCLUDED.supply";
+
+    sqlx::query(q)
+        .bind(contract_address.to_string())
+        .bind(chain_id.to_string())
+        .bind(previous_owner.map(str::to_string))
+        .bind(owner.map(str::to_string))
+}
+
+fn activity_from_row(row: &PgRow) -> Result<CollectionActivity, StorageError> {
+    Ok(CollectionActivity {
+        sales_count: row.try_get::<i64, _>("sales_count")?.max(0) as u64,
+        volume_usd: row.try_get::<f64, _>("volume_usd")?.max(0.0),
+        mint_count: row.try_get::<i64, _>("mint_count")?.max(0) as u64,
+    })
+}
+
+const TOKEN_COLUMNS: &str = "contract_address, chain_id, token_id, token_id_hex, current_owner";
+
+const TOKEN_EVENT_COLUMNS: &str = "token_event_id, contract_address, chain_id, token_id,
+    token_id_hex, event_type, block_timestamp, transaction_hash, to_address, from_address,
+    amount, currency_address, price_usd::FLOAT8 AS price_usd";
+
+const CONTRACT_COLUMNS: &str =
+    "contract_address, chain_id, contract_type, contract_name, contract_symbol, contract_image";
+
+fn token_from_row(row: &PgRow) -> Result<TokenInfo, StorageError> {
+    Ok(TokenInfo {
+        contract_address: row.try_get("contract_address")?,
+        chain_id: row.try_get("chain_id")?,
+        token_id: row.try_get("token_id")?,
+        token_id_hex: row.try_get("token_id_hex")?,
+        owner: row
+            .try_get::<Option<String>, _>("current_owner")?
+            .unwrap_or_default(),
+    })
+}
+
+/// Event types are stored in title case ("Transfer", "Collection_offer").
+fn event_type_from_row(row: &PgRow) -> Result<EventType, StorageError> {
+    let event_type: String = row.try_get("event_type")?;
+    EventType::from_str(&event_type.to_uppercase())
+        .map_err(|_| StorageError::DatabaseError(format!("Invalid event type {}", event_type)))
+}
+
+fn transfer_from_row(row: &PgRow) -> Result<TokenTransferEvent, StorageError> {
+    Ok(TokenTransferEvent {
+        token_event_id: row.try_get("token_event_id")?,
+        block_timestamp: row.try_get::<i64, _>("block_timestamp")? as u64,
+        from_address: row.try_get("from_address")?,
+        to_address: row.try_get("to_address")?,
+        contract_address: row.try_get("contract_address")?,
+        chain_id: row.try_get("chain_id")?,
+        contract_type: String::new(),
+        transaction_hash: row.try_get("transaction_hash")?,
+        token_id: row.try_get("token_id")?,
+        token_id_hex: row.try_get("token_id_hex")?,
+        event_type: Some(event_type_from_row(row)?),
+        block_number: None,
+        updated_at: None,
+    })
+}
+
+fn sale_from_row(row: &PgRow) -> Result<TokenSaleEvent, StorageError> {
+    Ok(TokenSaleEvent {
+        token_event_id: row.try_get("token_event_id")?,
+        from_address: row.try_get("from_address")?,
+        to_address: row.try_get("to_address")?,
+        nft_contract_address: row.try_get("contract_address")?,
+        nft_type: None,
+        marketplace_contract_address: String::new(),
+        marketplace_name: String::new(),
+        transaction_hash: row.try_get("transaction_hash")?,
+        chain_id: row.try_get("chain_id")?,
+        token_id: row.try_get("token_id")?,
+        token_id_hex: row.try_get("token_id_hex")?,
+        event_type: event_type_from_row(row)?,
+        block_timestamp: row.try_get::<i64, _>("block_timestamp")? as u64,
+        block_number: None,
+        updated_at: None,
+        quantity: 1,
+        currency_address: row.try_get("currency_address")?,
+        price: row
+            .try_get::<Option<String>, _>("amount")?
+            .unwrap_or_default(),
+        currency_symbol: None,
+        currency_decimals: None,
+        normalized_price: None,
+        price_usd: row.try_get("price_usd")?,
+    })
+}
+
+fn contract_from_row(row: &PgRow) -> Result<ContractInfo, StorageError> {
+    Ok(ContractInfo {
+        contract_address: row.try_get("contract_address")?,
+        chain_id: row.try_get("chain_id")?,
+        contract_type: row.try_get("contract_type")?,
+        name: row.try_get("contract_name")?,
+        symbol: row.try_get("contract_symbol")?,
+        image: row.try_get("contract_image")?,
+    })
+}
+
+/// Maps the rows fetched with a limit of `pagination.limit + 1`.
+fn page_from_rows<T>(
+    rows: Vec<PgRow>,
+    pagination: &Pagination,
+    from_row: fn(&PgRow) -> Result<T, StorageError>,
+) -> Result<Page<T>, StorageError> {
+    let items = rows
+        .iter()
+        .map(from_row)
+        .collect::<Result<Vec<T>, StorageError>>()?;
+
+    Ok(Page::from_items(items, pagination))
+}
+
+#[async_trait]
+impl QueryStorage for PostgresStorage {
+    async fn get_token(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        token_id: &str,
+    ) -> Result<TokenInfo, StorageError> {
+        let q = format!(
+            "SELECT {TOKEN_COLUMNS} FROM token
+             WHERE contract_address = $1 AND chain_id = $2 AND token_id = $3"
+        );
+
+        match self
+            .fetch_optional(
+                sqlx::query(&q)
+                    .bind(contract_address)
+                    .bind(chain_id)
+                    .bind(token_id),
+            )
+            .await?
+        {
+            Some(row) => token_from_row(&row),
+            None => Err(StorageError::NotFound(format!(
+                "token {contract_address} {token_id}"
+            ))),
+        }
+    }
+
+    async fn tokens_by_owner(
+        &self,
+        owner: &str,
+        chain_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<TokenInfo>, StorageError> {
+        let q = format!(
+            "SELECT {TOKEN_COLUMNS} FROM token
+             WHERE current_owner = $1 AND chain_id = $2
+             ORDER BY contract_address, token_id_hex
+             LIMIT $3 OFFSET $4"
+        );
+
+        let rows = self
+            .fetch_all(
+                sqlx::query(&q)
+                    .bind(owner)
+                    .bind(chain_id)
+                    .bind(pagination.limit as i64 + 1)
+                    .bind(pagination.offset as i64),
+            )
+            .await?;
+
+        page_from_rows(rows, &pagination, token_from_row)
+    }
+
+    async fn collection_tokens(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<TokenInfo>, StorageError> {
+        let q = format!(
+            "SELECT {TOKEN_COLUMNS} FROM token
+             WHERE contract_address = $1 AND chain_id = $2
+             ORDER BY token_id_hex
+             LIMIT $3 OFFSET $4"
+        );
+
+        let rows = self
+            .fetch_all(
+                sqlx::query(&q)
+                    .bind(contract_address)
+                    .bind(chain_id)
+                    .bind(pagination.limit as i64 + 1)
+                    .bind(pagination.offset as i64),
+            )
+            .await?;
+
+        page_from_rows(rows, &pagination, token_from_row)
+    }
+
+    async fn token_history(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        token_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<TokenTransferEvent>, StorageError> {
+        let q = format!(
+            "SELECT {TOKEN_EVENT_COLUMNS} FROM token_event
+             WHERE contract_address = $1 AND chain_id = $2 AND token_id = $3
+             AND event_type IN ('Transfer', 'Mint', 'Burn')
+             ORDER BY block_timestamp DESC, token_event_id
+             LIMIT $4 OFFSET $5"
+        );
+
+        let rows = self
+            .fetch_all(
+                sqlx::query(&q)
+                    .bind(contract_address)
+                    .bind(chain_id)
+                    .bind(token_id)
+                    .bind(pagination.limit as i64 + 1)
+                    .bind(pagination.offset as i64),
+            )
+            .await?;
+
+        page_from_rows(rows, &pagination, transfer_from_row)
+    }
+
+    async fn sales(
+        &self,
+        chain_id: &str,
+        filter: &SaleFilter,
+        pagination: Pagination,
+    ) -> Result<Page<TokenSaleEvent>, StorageError> {
+        let q = format!(
+            "SELECT {TOKEN_EVENT_COLUMNS} FROM token_event
+             WHERE chain_id = $1 AND event_type = 'Sale'
+             AND ($2::TEXT IS NULL OR contract_address = $2)
+             AND ($3::TEXT IS NULL OR token_id_hex = $3)
+             AND ($4::BIGINT IS NULL OR block_timestamp >= $4)
+             AND ($5::BIGINT IS NULL OR block_timestamp <= $5)
+             ORDER BY block_timestamp DESC, token_event_id
+             LIMIT $6 OFFSET $7"
+        );
+
+        let rows = self
+            .fetch_all(
+                sqlx::query(&q)
+                    .bind(chain_id)
+                    .bind(filter.contract_address.clone())
+                    .bind(filter.token_id_hex.clone())
+                    .bind(filter.from_timestamp.map(|ts| ts as i64))
+                    .bind(filter.to_timestamp.map(|ts| ts as i64))
+                    .bind(pagination.limit as i64 + 1)
+                    .bind(pagination.offset as i64),
+            )
+            .await?;
+
+        page_from_rows(rows, &pagination, sale_from_row)
+    }
+
+    async fn collection_stats(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+        timestamp: u64,
+    ) -> Result<CollectionStats, StorageError> {
+        let mut stats = CollectionStats {
+            contract_address: contract_address.to_string(),
+            chain_id: chain_id.to_string(),
+            ..Default::default()
+        };
+
+        let q = "SELECT owners_count, supply, sales_count,
+                 volume_usd::FLOAT8 AS volume_usd, mint_count
+                 FROM collection_stats WHERE contract_address = $1 AND chain_id = $2";
+
+        if let Some(row) = self
+            .fetch_optional(sqlx::query(q).bind(contract_address).bind(chain_id))
+            .await?
+        {
+            stats.owners_count = row.try_get::<i64, _>("owners_count")?.max(0) as u64;
+            stats.supply = row.try_get::<i64, _>("supply")?.max(0) as u64;
+            stats.all_time = activity_from_row(&row)?;
+        }
+
+        let current = CollectionStats::bucket(timestamp);
+        let oldest = current.saturating_sub((CollectionStats::PERIODS[2] - 1) * STATS_BUCKET_SECS);
+
+        let q = "SELECT hour_timestamp, sales_count, volume_usd::FLOAT8 AS volume_usd, mint_count
+                 FROM collection_stats_hourly
+                 WHERE contract_address = $1 AND chain_id = $2
+                 AND hour_timestamp >= $3 AND hour_timestamp <= $4";
+
+        let rows = self
+            .fetch_all(
+                sqlx::query(q)
+                    .bind(contract_address)
+                    .bind(chain_id)
+                    .bind(oldest as i64)
+                    .bind(current as i64),
+            )
+            .await?;
+
+        for row in rows {
+            let bucket = row.try_get::<i64, _>("hour_timestamp")? as u64;
+            stats.add_bucket(timestamp, bucket, &activity_from_row(&row)?);
+        }
+
+        Ok(stats)
+    }
+
+    async fn contracts(
+        &self,
+        chain_id: &str,
+        pagination: Pagination,
+    ) -> Result<Page<ContractInfo>, StorageError> {
+        let q = format!(
+            "SELECT {CONTRACT_COLUMNS} FROM contract
+             WHERE chain_id = $1
+             ORDER BY contract_address
+             LIMIT $2 OFFSET $3"
+        );
+
+        let rows = self
+            .fetch_all(
+                sqlx::query(&q)
+                    .bind(chain_id)
+                    .bind(pagination.limit as i64 + 1)
+                    .bind(pagination.offset as i64),
+            )
+            .await?;
+
+        page_from_rows(rows, &pagination, contract_from_row)
+    }
+
+    async fn get_contract_info(
+        &self,
+        contract_address: &str,
+        chain_id: &str,
+    ) -> Result<ContractInfo, StorageError> {
+        let q = format!(
+            "SELECT {CONTRACT_COLUMNS} FROM contract WHERE contract_address = $1 AND chain_id = $2"
+        );
+
+        match self
+            .fetch_optional(sqlx::query(&q).bind(contract_address).bind(chain_id))
+            .await?
+        {
+            Some(row) => contract_from_row(&row),
+            None => Err(StorageError::NotFound(format!(
+                "contract_address: {contract_address}"
+            ))),
+        }
+    }
+
+    async fn last_block_info(&self) -> Result<Option<BlockInfo>, StorageError> {
+        let q = "SELECT b.block_number, b.block_status, b.block_timestamp, b.indexer_identifier, i.indexer_version
+        FROM block as b
+        LEFT JOIN indexer as i ON i.indexer_identifier = b.indexer_identifier
+        ORDER BY b.block_number DESC LIMIT 1";
+
+        match self.fetch_optional(sqlx::query(q)).await? {
+            Some(row) => {
+                let d = BlockData::from_row(&row)?;
+                Ok(Some(BlockInfo {
+                    indexer_identifier: d.indexer_identifier.clone(),
+                    indexer_version: d.indexer_version.clone(),
+                    block_status: BlockIndexingStatus::from_str(&d.block_status).unwrap(),
+                    block_number: d.block_number as u64,
+                }))
+            }
+            None => Ok(None),
+        }
+    }
+}
@@ -3,6 +3,8 @@ use std::collections::HashMap;
 use std::fmt;
 use std::str::FromStr;
 
+pub use ark_starknet::currency::CurrencyInfo;
+
 #[derive(Debug, Clone)]
 pub enum StorageError {
     DatabaseError(String),
@@ -135,6 +137,18 @@ impl Serialize for TokenEvent {
                 }
 
                 map.insert("price", event.price.clone());
+
+                if let Some(currency_symbol) = event.currency_symbol.clone() {
+                    map.insert("currency_symbol", currency_symbol);
+                }
+
+                if let Some(normalized_price) = event.normalized_price.clone() {
+                    map.insert("normalized_price", normalized_price);
+                }
+
+                if let Some(price_usd) = event.price_usd {
+                    map.insert("price_usd", price_usd.to_string());
+                }
                 map.insert(
                     "block_number",
                     event
@@ -192,7 +206,17 @@ pub struct TokenSaleEvent {
     pub updated_at: Option<u64>,
     pub quantity: u64,
     pub currency_address: Option<String>,
+    /// Raw price, hexadecimal representation of the u256 amount
+    /// in the smallest unit of the currency.
     pub price: String,
+    pub currency_symbol: Option<String>,
+    pub currency_decimals: Option<u8>,
+    /// Exact decimal representation of the price divided by
+    /// `10^currency_decimals`, which makes it comparable and
+    /// summable across sales of the same currency.
+    pub normalized_price: Option<String>,
+    /// USD value of the sale at the time of the sale.
+    pub price_usd: Option<f64>,
 }
 
 impl Default for TokenTransferEvent {
@@ -232,7 +256,7 @@ pub struct TokenMintInfo {
     pub block_number: Option<u64>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum BlockIndexingStatus {
     None,
@@ -293,7 +317,7 @@ pub struct BlockIndexing {
     pub indexer_version: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockInfo {
     pub indexer_version: String,
     pub indexer_identifier: String,
@@ -341,6 +365,154 @@ pub struct ContractInfo {
     pub image: Option<String>,
 }
 
+/// Page of items requested to a `QueryStorage`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Pagination {
+    /// Number of items to skip.
+    pub offset: u64,
+    pub limit: u64,
+}
+
+impl Pagination {
+    pub const DEFAULT_LIMIT: u64 = 50;
+    pub const MAX_LIMIT: u64 = 500;
+
+    /// The limit is bounded to `[1, MAX_LIMIT]`.
+    pub fn new(offset: u64, limit: u64) -> Self {
+        Self {
+            offset,
+            limit: limit.clamp(1, Self::MAX_LIMIT),
+        }
+    }
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        Self::new(0, Self::DEFAULT_LIMIT)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Offset of the next page, if there are more items.
+    pub next_offset: Option<u64>,
+}
+
+impl<T> Page<T> {
+    /// Builds a page from the items fetched with a limit of `pagination.limit + 1`,
+    /// the extra item only tells if a next page exists.
+    pub fn from_items(mut items: Vec<T>, pagination: &Pagination) -> Self {
+        let has_more = items.len() as u64 > pagination.limit;
+        items.truncate(pagination.limit as usize);
+
+        Self {
+            items,
+            next_offset: has_more.then_some(pagination.offset + pagination.limit),
+        }
+    }
+}
+
+/// Filter of the sales, all the criteria are optional.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct SaleFilter {
+    pub contract_address: Option<String>,
+    pub token_id_hex: Option<String>,
+    /// Inclusive bounds on the block timestamp.
+    pub from_timestamp: Option<u64>,
+    pub to_timestamp: Option<u64>,
+}
+
+impl SaleFilter {
+    pub fn matches(&self, sale: &TokenSaleEvent) -> bool {
+        self.contract_address
+            .as_ref()
+            .map_or(true, |c| *c == sale.nft_contract_address)
+            && self
+                .token_id_hex
+                .as_ref()
+                .map_or(true, |t| *t == sale.token_id_hex)
+            && self
+                .from_timestamp
+                .map_or(true, |ts| sale.block_timestamp >= ts)
+            && self
+                .to_timestamp
+                .map_or(true, |ts| sale.block_timestamp <= ts)
+    }
+}
+
+/// Duration of the buckets of the collection statistics.
+pub const STATS_BUCKET_SECS: u64 = 3600;
+
+/// Activity of a collection over a period.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct CollectionActivity {
+    pub sales_count: u64,
+    /// Sum of the USD prices. The sales without USD price are only counted.
+    pub volume_usd: f64,
+    pub mint_count: u64,
+}
+
+impl CollectionActivity {
+    pub fn add(&mut self, other: &CollectionActivity) {
+        self.sales_count += other.sales_count;
+        self.volume_usd += other.volume_usd;
+        self.mint_count += other.mint_count;
+    }
+}
+
+/// Aggregates of a collection, maintained by the storage while
+/// the events are registered and cleaned.
+///
+/// The periods are made of whole hours: the last hour is the hour
+/// containing the requested timestamp, the last day is this hour
+/// and the 23 previous ones.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct CollectionStats {
+    pub contract_address: String,
+    pub chain_id: String,
+    /// Distinct owners of at least one token.
+    pub owners_count: u64,
+    /// Tokens with an owner.
+    pub supply: u64,
+    pub last_hour: CollectionActivity,
+    pub last_day: CollectionActivity,
+    pub last_week: CollectionActivity,
+    pub all_time: CollectionActivity,
+}
+
+impl CollectionStats {
+    /// Length of the last hour, day and week periods, in buckets.
+    pub const PERIODS: [u64; 3] = [1, 24, 24 * 7];
+
+    /// Start of the bucket containing the timestamp.
+    pub fn bucket(timestamp: u64) -> u64 {
+        timestamp - timestamp % STATS_BUCKET_SECS
+    }
+
+    /// Adds the activity of a bucket to the periods containing it,
+    /// relative to the bucket of `timestamp`. Future buckets are ignored.
+    pub fn add_bucket(&mut self, timestamp: u64, bucket: u64, activity: &CollectionActivity) {
+        let current = Self::bucket(timestamp);
+
+        if bucket > current {
+            return;
+        }
+
+        let age = (current - bucket) / STATS_BUCKET_SECS;
+
+        for (buckets, period) in Self::PERIODS.into_iter().zip([
+            &mut self.last_hour,
+            &mut self.last_day,
+            &mut self.last_week,
+        ]) {
+            if age < buckets {
+                period.add(activity);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;